@@ -1,5 +1,10 @@
 use anyhow::Context;
-use bgzip::{read::BGZFMultiThreadReader, write::BGZFMultiThreadWriter, BGZFReader, BGZFWriter};
+use bgzip::csi::CSIIndex;
+use bgzip::tabix::{Region, Tabix, TabixChunk, TabixIndexBuilder, TabixReader, TabixRecords};
+use bgzip::{
+    read::BGZFMultiThreadReader, write::BGZFMultiThreadWriter, BGZFError, BGZFReader, BGZFWriter,
+    VirtualPosition,
+};
 use clap::Parser;
 use is_terminal::IsTerminal;
 use std::fs::File;
@@ -8,13 +13,13 @@ use std::io::prelude::*;
 #[derive(Debug, Parser, PartialEq, Clone)]
 #[command(author, version, about)]
 struct Cli {
-    // #[arg(
-    //     short = 'b',
-    //     long = "offset",
-    //     help = "decompress at virtual file pointer (0-based uncompressed offset)",
-    //     requires = "stdout"
-    // )]
-    // offset: Option<u64>,
+    #[arg(
+        short = 'b',
+        long = "offset",
+        help = "decompress at virtual file pointer (0-based uncompressed offset)",
+        requires = "stdout"
+    )]
+    offset: Option<u64>,
     #[arg(
         short = 'c',
         long = "stdout",
@@ -46,15 +51,20 @@ struct Cli {
         default_value = "-1"
     )]
     compress_level: i32,
-    // #[arg(short = 'r', long = "reindex", help = "(re)index compressed file")]
-    // reindex: bool,
-    // #[arg(
-    //     short = 's',
-    //     long = "size",
-    //     help = "decompress INT bytes (uncompressed size)",
-    //     requires = "offset"
-    // )]
-    // size: Option<u64>,
+    #[arg(short = 'r', long = "reindex", help = "(re)index compressed file")]
+    reindex: bool,
+    #[arg(
+        long = "region",
+        help = "write lines overlapped with REGION (chr, chr:begin or chr:begin-end, 1-based) on standard output, using .tbi or .csi index if exists"
+    )]
+    region: Option<String>,
+    #[arg(
+        short = 's',
+        long = "size",
+        help = "decompress INT bytes (uncompressed size)",
+        requires = "offset"
+    )]
+    size: Option<u64>,
     #[arg(short = 't', long = "test", help = "test integrity of compressed file")]
     test: bool,
     #[arg(
@@ -75,14 +85,32 @@ fn main() -> anyhow::Result<()> {
         .build_global()
         .context("Failed to set number of threads in thread pool")?;
 
+    run(&cli)
+}
+
+fn run(cli: &Cli) -> anyhow::Result<()> {
+    if cli.test {
+        return test_files(cli);
+    }
+
+    // output order matters only if files are written to standard output
+    let parallel = cli.threads.unwrap_or(1) > 1
+        && !cli.stdout
+        && cli.offset.is_none()
+        && cli.region.is_none()
+        && cli.files.len() > 1
+        && cli.files.iter().all(|x| x != "-");
+
     if cli.files.is_empty() {
-        process_file(&cli, None)?;
+        process_file(cli, None, None)?;
+    } else if parallel {
+        process_files_in_parallel(cli)?;
     } else {
         for one in &cli.files {
             if one == "-" {
-                process_file(&cli, None)?;
+                process_file(cli, None, None)?;
             } else {
-                process_file(&cli, Some(one.as_str()))?;
+                process_file(cli, Some(one.as_str()), None)?;
             }
         }
     }
@@ -90,7 +118,61 @@ fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
-fn process_file(cli: &Cli, input_path: Option<&str>) -> anyhow::Result<()> {
+/// Process files with file workers, each of which has its own thread pool.
+///
+/// Threads given by `-@` are divided among the workers, so the number of open files and
+/// compression threads is bounded by `-@`.
+fn process_files_in_parallel(cli: &Cli) -> anyhow::Result<()> {
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let threads = cli.threads.unwrap_or(1);
+    let workers = threads.min(cli.files.len());
+    let next_file = AtomicUsize::new(0);
+    let failed = AtomicBool::new(false);
+
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..workers)
+            .map(|i| {
+                // remaining threads are given to the first workers
+                let pool_size = threads / workers + usize::from(i < threads % workers);
+                let (next_file, failed) = (&next_file, &failed);
+                scope.spawn(move || -> anyhow::Result<()> {
+                    let pool = Arc::new(
+                        rayon::ThreadPoolBuilder::new()
+                            .num_threads(pool_size)
+                            .build()
+                            .context("Failed to create thread pool")?,
+                    );
+                    while !failed.load(Ordering::Relaxed) {
+                        let Some(path) = cli.files.get(next_file.fetch_add(1, Ordering::Relaxed))
+                        else {
+                            break;
+                        };
+                        if let Err(e) = process_file(cli, Some(path.as_str()), Some(&pool)) {
+                            failed.store(true, Ordering::Relaxed);
+                            return Err(e);
+                        }
+                    }
+                    Ok(())
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|x| x.join().expect("File worker panicked"))
+            .collect::<anyhow::Result<Vec<()>>>()
+    })?;
+    Ok(())
+}
+
+/// Compress or decompress one file. Multi-thread reader and writer run in `pool` if it is given,
+/// or in the global thread pool.
+fn process_file(
+    cli: &Cli,
+    input_path: Option<&str>,
+    pool: Option<&std::sync::Arc<rayon::ThreadPool>>,
+) -> anyhow::Result<()> {
     let compression = match cli.compress_level {
         -1 => bgzip::Compression::default(),
         i if i >= 0 && i <= 12 => bgzip::Compression::new(
@@ -101,6 +183,16 @@ fn process_file(cli: &Cli, input_path: Option<&str>) -> anyhow::Result<()> {
         _ => return Err(anyhow::anyhow!("Compression level must be -1 to 12")),
     };
 
+    if cli.reindex {
+        return reindex(cli, input_path);
+    }
+    if let Some(offset) = cli.offset {
+        return decompress_range(cli, input_path, offset);
+    }
+    if let Some(region) = cli.region.as_ref() {
+        return view_region(input_path, region);
+    }
+
     let mut delete_input = !cli.keep;
 
     let mut input: Box<dyn Read> = if let Some(path) = input_path {
@@ -159,16 +251,26 @@ fn process_file(cli: &Cli, input_path: Option<&str>) -> anyhow::Result<()> {
     };
 
     if cli.decompress {
-        if cli.threads.is_some() {
-            let mut reader = BGZFMultiThreadReader::new(&mut input)?;
+        let mut input = std::io::BufReader::new(input);
+        if cli.threads.is_some() && is_bgzf(&mut input)? {
+            let mut reader = match pool {
+                Some(pool) => BGZFMultiThreadReader::with_thread_pool(&mut input, pool.clone())?,
+                None => BGZFMultiThreadReader::new(&mut input)?,
+            };
             std::io::copy(&mut reader, &mut output)?;
         } else {
-            let mut reader = BGZFReader::new(&mut input)?;
+            // plain gzip is decompressed sequentially like bgzip of htslib
+            let mut reader = BGZFReader::new_degradable(&mut input)?;
             std::io::copy(&mut reader, &mut output)?;
         }
     } else {
         if cli.threads.is_some() {
-            let mut writer = BGZFMultiThreadWriter::new(&mut output, compression);
+            let mut writer = match pool {
+                Some(pool) => {
+                    BGZFMultiThreadWriter::with_thread_pool(&mut output, compression, pool.clone())
+                }
+                None => BGZFMultiThreadWriter::new(&mut output, compression),
+            };
             std::io::copy(&mut input, &mut writer)?;
             let index = writer.close()?;
             if let Some(index_out) = index_out {
@@ -192,3 +294,287 @@ fn process_file(cli: &Cli, input_path: Option<&str>) -> anyhow::Result<()> {
 
     Ok(())
 }
+
+/// `true` if the first header of `input` has BGZF extra field
+fn is_bgzf<R: BufRead>(input: &mut R) -> std::io::Result<bool> {
+    let head = input.fill_buf()?;
+    Ok(bgzip::header::BGZFHeader::from_reader(head)
+        .map(|x| x.block_size().is_ok())
+        .unwrap_or(false))
+}
+
+fn reindex(cli: &Cli, input_path: Option<&str>) -> anyhow::Result<()> {
+    let index_path = cli
+        .index_name
+        .clone()
+        .or_else(|| input_path.map(|x| format!("{}.gzi", x)))
+        .context("Use -I to set index name when reading from standard input")?;
+    let input: Box<dyn Read> = if let Some(path) = input_path {
+        Box::new(File::open(path)?)
+    } else {
+        Box::new(std::io::stdin().lock())
+    };
+    let index = bgzip::index::BGZFIndex::from_bgzf_reader(std::io::BufReader::new(input))?;
+    index.write(std::io::BufWriter::new(File::create(index_path)?))?;
+    Ok(())
+}
+
+/// Test all files and report failed files at the end
+fn test_files(cli: &Cli) -> anyhow::Result<()> {
+    use rayon::prelude::*;
+
+    let input_paths: Vec<Option<&str>> = if cli.files.is_empty() {
+        vec![None]
+    } else {
+        cli.files
+            .iter()
+            .map(|x| if x == "-" { None } else { Some(x.as_str()) })
+            .collect()
+    };
+    // files are checked in parallel, and results are printed in the order of arguments
+    let results: Vec<_> = input_paths
+        .par_iter()
+        .map(|input_path| test_integrity(*input_path))
+        .collect();
+    let mut failed = Vec::new();
+    for (input_path, result) in input_paths.iter().zip(results) {
+        let name = input_path.unwrap_or("-");
+        let report = match result {
+            Ok(report) => report,
+            Err(e) => {
+                eprintln!("{}: FAIL: {}", name, e);
+                failed.push(name);
+                continue;
+            }
+        };
+        for one in report.errors() {
+            eprintln!(
+                "{}: block at offset {} (uncompressed offset {}): {}",
+                name,
+                one.compressed_offset,
+                one.uncompressed_offset,
+                one.error.as_ref().unwrap()
+            );
+        }
+        if !report.has_eof_marker {
+            eprintln!("{}: no valid end-of-file marker", name);
+        }
+        if report.is_valid() {
+            eprintln!("{}: OK", name);
+        } else {
+            eprintln!("{}: FAIL", name);
+            failed.push(name);
+        }
+    }
+    if !failed.is_empty() {
+        return Err(anyhow::anyhow!(
+            "integrity check failed: {}",
+            failed.join(", ")
+        ));
+    }
+    Ok(())
+}
+
+/// Verify header structure, CRC32 and ISIZE of all blocks in the thread pool, and presence of an
+/// end-of-file marker.
+fn test_integrity(
+    input_path: Option<&str>,
+) -> Result<bgzip::check::ValidationReport, bgzip::BGZFError> {
+    let input: Box<dyn Read + Send> = if let Some(path) = input_path {
+        Box::new(File::open(path)?)
+    } else {
+        Box::new(std::io::stdin())
+    };
+    bgzip::check::par_validate(input)
+}
+
+fn decompress_range(cli: &Cli, input_path: Option<&str>, offset: u64) -> anyhow::Result<()> {
+    let path = input_path.context("-b option requires a file name")?;
+    let index_path = cli
+        .index_name
+        .clone()
+        .unwrap_or_else(|| format!("{}.gzi", path));
+    let index = if std::path::Path::new(&index_path).exists() {
+        bgzip::index::BGZFIndex::from_reader(std::io::BufReader::new(File::open(index_path)?))?
+    } else {
+        bgzip::index::BGZFIndex::from_bgzf_reader(std::io::BufReader::new(File::open(path)?))?
+    };
+    let mut reader =
+        bgzip::read::IndexedBGZFReader::new(BGZFReader::new(File::open(path)?)?, index)?;
+    reader.seek(std::io::SeekFrom::Start(offset))?;
+    let mut output = std::io::stdout().lock();
+    if let Some(size) = cli.size {
+        std::io::copy(&mut reader.take(size), &mut output)?;
+    } else {
+        std::io::copy(&mut reader, &mut output)?;
+    }
+    Ok(())
+}
+
+/// Parse `chr`, `chr:begin` or `chr:begin-end` (1-based, closed) like tabix
+fn parse_region(region: &str) -> anyhow::Result<Region> {
+    let number = |x: &str| -> anyhow::Result<u64> {
+        x.replace(',', "")
+            .parse()
+            .with_context(|| format!("Invalid position in region: {}", region))
+    };
+    let (sequence, range) = match region.rsplit_once(':') {
+        Some((sequence, range)) if !range.is_empty() => (sequence, range),
+        _ => return Ok(Region::new(region, 0, u64::MAX)),
+    };
+    let (begin, end) = match range.split_once('-') {
+        Some((begin, "")) => (number(begin)?, u64::MAX),
+        Some((begin, end)) => (number(begin)?, number(end)?),
+        None => (number(range)?, u64::MAX),
+    };
+    if begin == 0 || begin > end {
+        return Err(anyhow::anyhow!("Invalid region: {}", region));
+    }
+    Ok(Region::new(sequence, begin - 1, end))
+}
+
+/// Record parser of CSI index, configured with tabix compatible auxiliary data
+fn tabix_from_csi(csi: &CSIIndex) -> anyhow::Result<Tabix> {
+    let header = csi
+        .aux
+        .get(..28)
+        .context("CSI index does not have tabix compatible auxiliary data")?;
+    let value = |i: usize| i32::from_le_bytes(header[(i * 4)..(i * 4 + 4)].try_into().unwrap());
+    let names = csi.names().context("Invalid sequence names in CSI index")?;
+    Ok(Tabix {
+        number_of_references: names.len().try_into()?,
+        format: value(0),
+        column_for_sequence: value(1),
+        column_for_begin: value(2),
+        column_for_end: value(3),
+        meta: header[16..20].try_into().unwrap(),
+        skip: value(5),
+        length_of_concatenated_sequence_names: value(6),
+        names,
+        sequences: Vec::new(),
+        number_of_unplaced_unmapped: None,
+    })
+}
+
+/// Record parser selected with the file name, used to scan a file without index
+fn tabix_from_file_name(path: &str) -> anyhow::Result<Tabix> {
+    let name = path
+        .strip_suffix(".gz")
+        .or_else(|| path.strip_suffix(".bgz"))
+        .unwrap_or(path);
+    let builder = if name.ends_with(".vcf") {
+        TabixIndexBuilder::vcf()
+    } else if name.ends_with(".bed") {
+        TabixIndexBuilder::bed()
+    } else if name.ends_with(".gff") || name.ends_with(".gff3") || name.ends_with(".gtf") {
+        TabixIndexBuilder::gff()
+    } else if name.ends_with(".sam") {
+        TabixIndexBuilder::sam()
+    } else {
+        return Err(anyhow::anyhow!(
+            "{}: unknown file format. Create .tbi or .csi index.",
+            path
+        ));
+    };
+    Ok(builder.finish())
+}
+
+fn view_region(input_path: Option<&str>, region: &str) -> anyhow::Result<()> {
+    let path = input_path.context("--region option requires a file name")?;
+    let region = parse_region(region)?;
+    let tbi_path = format!("{}.tbi", path);
+    let csi_path = format!("{}.csi", path);
+
+    if std::path::Path::new(&tbi_path).exists() {
+        let tabix = Tabix::from_reader(File::open(tbi_path)?)?;
+        let mut reader = TabixReader::new(BGZFReader::new(File::open(path)?)?, tabix);
+        return write_records(reader.query(&region));
+    }
+
+    let mut reader = BGZFReader::new(File::open(path)?)?;
+    if std::path::Path::new(&csi_path).exists() {
+        let csi = CSIIndex::from_reader(File::open(csi_path)?)?;
+        let tabix = tabix_from_csi(&csi)?;
+        let chunks = match tabix.sequence_index(&region.sequence) {
+            Some(i) => csi.query(i, region.begin, region.end)?,
+            None => Vec::new(),
+        };
+        write_records(TabixRecords::with_chunks(
+            &mut reader,
+            &tabix,
+            &region,
+            chunks,
+        ))
+    } else {
+        // scan whole file because records may not be sorted
+        let tabix = tabix_from_file_name(path)?;
+        let chunks = vec![TabixChunk {
+            begin: VirtualPosition::default(),
+            end: VirtualPosition::from(u64::MAX),
+        }];
+        write_records(
+            TabixRecords::with_chunks(&mut reader, &tabix, &region, chunks).with_sorted(false),
+        )
+    }
+}
+
+fn write_records(records: impl Iterator<Item = Result<Vec<u8>, BGZFError>>) -> anyhow::Result<()> {
+    let mut output = std::io::BufWriter::new(std::io::stdout().lock());
+    for record in records {
+        output.write_all(&record?)?;
+        output.write_all(b"\n")?;
+    }
+    output.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_compress_files_in_parallel() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut paths = Vec::new();
+        let mut contents = Vec::new();
+        for i in 0..5 {
+            let path = dir.path().join(format!("file{}.txt", i));
+            let content: Vec<u8> = (0..(30_000 * (i + 1)))
+                .flat_map(|x| format!("file{}\t{}\n", i, x).into_bytes())
+                .collect();
+            std::fs::write(&path, &content)?;
+            paths.push(path.to_str().unwrap().to_string());
+            contents.push(content);
+        }
+
+        let mut args = vec!["bgzip-rs", "-@", "3", "-i"];
+        args.extend(paths.iter().map(|x| x.as_str()));
+        run(&Cli::try_parse_from(args)?)?;
+
+        for (path, content) in paths.iter().zip(contents.iter()) {
+            assert!(!std::path::Path::new(path).exists());
+            let compressed = std::fs::read(format!("{}.gz", path))?;
+            assert!(compressed.ends_with(&bgzip::EOF_MARKER));
+            let mut data = Vec::new();
+            BGZFReader::new(&compressed[..])?.read_to_end(&mut data)?;
+            assert_eq!(&data, content);
+            let index =
+                bgzip::index::BGZFIndex::from_reader(File::open(format!("{}.gz.gzi", path))?)?;
+            assert_eq!(
+                index,
+                bgzip::index::BGZFIndex::from_bgzf_reader(&compressed[..])?
+            );
+        }
+
+        // decompress in parallel
+        let mut args = vec!["bgzip-rs", "-@", "3", "-d"];
+        let compressed_paths: Vec<_> = paths.iter().map(|x| format!("{}.gz", x)).collect();
+        args.extend(compressed_paths.iter().map(|x| x.as_str()));
+        run(&Cli::try_parse_from(args)?)?;
+        for (path, content) in paths.iter().zip(contents.iter()) {
+            assert_eq!(&std::fs::read(path)?, content);
+        }
+
+        Ok(())
+    }
+}
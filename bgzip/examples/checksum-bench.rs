@@ -0,0 +1,52 @@
+//! Compare time spent for DEFLATE compression and CRC32 checksum of BGZF blocks.
+//!
+//! Run with `--features crc32fast` to measure the hardware accelerated checksum.
+
+use bgzip::deflate::{Compress, Crc};
+use bgzip::write::DEFAULT_COMPRESS_UNIT_SIZE;
+use clap::Parser;
+use std::fs::File;
+use std::io::prelude::*;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Parser, PartialEq)]
+struct Cli {
+    /// Uncompressed input file
+    #[command()]
+    input_file: String,
+    #[arg(short, long, default_value = "1")]
+    compress_level: u32,
+}
+
+fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    let mut data = Vec::new();
+    File::open(&cli.input_file)?.read_to_end(&mut data)?;
+
+    let mut compress = Compress::new(bgzip::Compression::new(cli.compress_level)?);
+    let mut compressed = vec![0; DEFAULT_COMPRESS_UNIT_SIZE * 2];
+    let mut compress_time = Duration::ZERO;
+    let mut checksum_time = Duration::ZERO;
+
+    for block in data.chunks(DEFAULT_COMPRESS_UNIT_SIZE) {
+        let start = Instant::now();
+        compress.compress(block, &mut compressed)?;
+        compress_time += start.elapsed();
+
+        let start = Instant::now();
+        let mut crc = Crc::new();
+        crc.update(block);
+        std::hint::black_box(crc.sum());
+        checksum_time += start.elapsed();
+    }
+
+    let total = compress_time + checksum_time;
+    println!("compress: {:?}", compress_time);
+    println!("checksum: {:?}", checksum_time);
+    println!(
+        "checksum share: {:.1}%",
+        checksum_time.as_secs_f64() / total.as_secs_f64() * 100.
+    );
+
+    Ok(())
+}
@@ -75,8 +75,8 @@ fn main() -> anyhow::Result<()> {
                     format!("{}", j),
                     format!("0x{:x}", bin.bin),
                     format!("{}", k),
-                    format!("0x{:x}", x.begin),
-                    format!("0x{:x}", x.end),
+                    format!("0x{:x}", u64::from(x.begin)),
+                    format!("0x{:x}", u64::from(x.end)),
                 ])?;
             }
         }
@@ -0,0 +1,469 @@
+//! Asynchronous BGZF reader and writer based on [tokio](https://tokio.rs)
+//!
+//! [`AsyncBGZFReader`] and [`AsyncBGZFWriter`] read and write the same data as
+//! [`BGZFReader`](crate::BGZFReader) and [`BGZFWriter`](crate::BGZFWriter), but on top of
+//! [`tokio::io::AsyncRead`] and [`tokio::io::AsyncWrite`]. Compression and decompression of a
+//! block run on the task polling the reader or the writer.
+//!
+//! ```rust
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() -> Result<(), bgzip::BGZFError> {
+//! use bgzip::async_io::{AsyncBGZFReader, AsyncBGZFWriter};
+//! use bgzip::Compression;
+//! use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+//!
+//! let mut compressed = Vec::new();
+//! let mut writer = AsyncBGZFWriter::new(&mut compressed, Compression::default());
+//! writer.write_all(b"##fileformat=VCFv4.2\n").await?;
+//! writer.shutdown().await?;
+//!
+//! let mut reader = AsyncBGZFReader::new(&compressed[..]);
+//! let mut line = String::new();
+//! reader.read_line(&mut line).await?;
+//! assert_eq!(line, "##fileformat=VCFv4.2\n");
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::deflate::{Compress, Compression, Decompress};
+use crate::header::BGZFHeader;
+use crate::read::{decompress_block, load_block};
+use crate::write::{
+    check_compress_unit_size, write_block, COMPRESSED_BLOCK_OVERHEAD, DEFAULT_COMPRESS_UNIT_SIZE,
+    FOOTER_SIZE,
+};
+use crate::{BGZFError, VirtualPosition, EOF_MARKER};
+use std::io;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use tokio::io::{AsyncBufRead, AsyncRead, AsyncSeek, AsyncSeekExt, AsyncWrite, ReadBuf};
+
+/// Asynchronous BGZF reader
+///
+/// Empty blocks are skipped, and reading stops at the end of `reader`.
+pub struct AsyncBGZFReader<R: AsyncRead + Unpin> {
+    reader: R,
+    decompress: Decompress,
+    loading_block: Vec<u8>,
+    compressed_data: Vec<u8>,
+    current_buffer: Vec<u8>,
+    current_position_in_block: usize,
+    current_block: u64,
+    next_block: u64,
+    end_of_data: bool,
+}
+
+impl<R: AsyncRead + Unpin> AsyncBGZFReader<R> {
+    /// Create new asynchronous BGZF reader. `reader` should point the beginning of a block.
+    ///
+    /// Unlike [`BGZFReader::new`](crate::BGZFReader::new), no data is read until the first
+    /// read of the returned reader.
+    pub fn new(reader: R) -> Self {
+        AsyncBGZFReader {
+            reader,
+            decompress: Decompress::new(),
+            loading_block: Vec::new(),
+            compressed_data: Vec::new(),
+            current_buffer: Vec::new(),
+            current_position_in_block: 0,
+            current_block: 0,
+            next_block: 0,
+            end_of_data: false,
+        }
+    }
+
+    /// Current virtual position
+    pub fn bgzf_pos(&self) -> VirtualPosition {
+        VirtualPosition::from(
+            self.current_block << 16 | (self.current_position_in_block & 0xffff) as u64,
+        )
+    }
+
+    /// Unwrap the underlying reader. Buffered data is discarded.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Number of bytes to be loaded before the block in `loading_block` can be parsed
+    fn required_block_size(&self) -> Result<usize, BGZFError> {
+        match BGZFHeader::from_reader(&self.loading_block[..]) {
+            Ok(header) => Ok(header.block_size()?.into()),
+            // The header is incomplete. A block has 8 bytes of CRC32 and ISIZE after the header,
+            // so 8 more bytes can be loaded without reading the next block.
+            Err(BGZFError::IoError(e)) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                Ok(self.loading_block.len() + FOOTER_SIZE)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Load and decompress the next block. `end_of_data` is set if `reader` has no more block.
+    fn poll_load_next(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), BGZFError>> {
+        loop {
+            let required = self.required_block_size()?;
+            let loaded = self.loading_block.len();
+            if loaded >= required {
+                break;
+            }
+            self.loading_block.resize(required, 0);
+            let mut buf = ReadBuf::new(&mut self.loading_block[loaded..]);
+            let result = Pin::new(&mut self.reader).poll_read(cx, &mut buf);
+            let read_bytes = buf.filled().len();
+            self.loading_block.truncate(loaded + read_bytes);
+            ready!(result)?;
+            if read_bytes == 0 {
+                if loaded == 0 {
+                    self.end_of_data = true;
+                    self.current_block = self.next_block;
+                    self.current_buffer.clear();
+                    self.current_position_in_block = 0;
+                    return Poll::Ready(Ok(()));
+                }
+                return Poll::Ready(Err(BGZFError::TruncatedFile));
+            }
+        }
+
+        load_block(&self.loading_block[..], &mut self.compressed_data)?;
+        self.current_buffer.clear();
+        decompress_block(
+            &mut self.current_buffer,
+            &self.compressed_data,
+            &mut self.decompress,
+        )?;
+        self.current_block = self.next_block;
+        self.next_block += self.loading_block.len() as u64;
+        self.current_position_in_block = 0;
+        self.loading_block.clear();
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<R: AsyncRead + AsyncSeek + Unpin> AsyncBGZFReader<R> {
+    /// Move to the virtual position
+    pub async fn bgzf_seek<P: Into<VirtualPosition>>(
+        &mut self,
+        position: P,
+    ) -> Result<(), BGZFError> {
+        let position = position.into();
+        self.reader
+            .seek(io::SeekFrom::Start(position.compressed_offset()))
+            .await?;
+        self.next_block = position.compressed_offset();
+        self.loading_block.clear();
+        self.end_of_data = false;
+        let next_block = self.next_block;
+        std::future::poll_fn(|cx| self.poll_load_next(cx))
+            .await
+            .map_err(|e| e.in_block(next_block, None))?;
+        self.current_position_in_block = position.uncompressed_offset_in_block().into();
+        Ok(())
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncBufRead for AsyncBGZFReader<R> {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        let this = self.get_mut();
+        // empty blocks in the middle of data are skipped
+        while this.current_position_in_block >= this.current_buffer.len() && !this.end_of_data {
+            let next_block = this.next_block;
+            ready!(this.poll_load_next(cx))
+                .map_err(|e| e.in_block(next_block, None).into_io_error())?;
+        }
+        Poll::Ready(Ok(this
+            .current_buffer
+            .get(this.current_position_in_block..)
+            .unwrap_or(&[])))
+    }
+
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        let this = self.get_mut();
+        this.current_position_in_block =
+            (this.current_position_in_block + amt).min(this.current_buffer.len());
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for AsyncBGZFReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let data = ready!(self.as_mut().poll_fill_buf(cx))?;
+        let read_bytes = data.len().min(buf.remaining());
+        buf.put_slice(&data[..read_bytes]);
+        self.consume(read_bytes);
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Asynchronous BGZF writer
+///
+/// [`tokio::io::AsyncWriteExt::shutdown`] must be called to write the last block and
+/// end-of-file marker. Unlike [`BGZFWriter`](crate::BGZFWriter), they are not written on drop.
+/// [`tokio::io::AsyncWriteExt::flush`] writes buffered data as a block.
+pub struct AsyncBGZFWriter<W: AsyncWrite + Unpin> {
+    writer: W,
+    original_data: Vec<u8>,
+    compressed_buffer: Vec<u8>,
+    written_bytes: usize,
+    compress_unit_size: usize,
+    compress: Compress,
+    current_compressed_pos: u64,
+    closed: bool,
+}
+
+impl<W: AsyncWrite + Unpin> AsyncBGZFWriter<W> {
+    /// Create new asynchronous BGZF writer
+    pub fn new(writer: W, level: Compression) -> Self {
+        Self::with_compress_unit_size(writer, level, DEFAULT_COMPRESS_UNIT_SIZE)
+            .expect("Unreachable (AsyncBGZFWriter)")
+    }
+
+    /// Create new asynchronous BGZF writer with compress unit size.
+    ///
+    /// [`BGZFError::TooLargeCompressUnit`] is returned if `compress_unit_size` is not smaller
+    /// than [`MAXIMUM_COMPRESS_UNIT_SIZE`](crate::write::MAXIMUM_COMPRESS_UNIT_SIZE).
+    pub fn with_compress_unit_size(
+        writer: W,
+        level: Compression,
+        compress_unit_size: usize,
+    ) -> Result<Self, BGZFError> {
+        check_compress_unit_size(compress_unit_size)?;
+        Ok(AsyncBGZFWriter {
+            writer,
+            original_data: Vec::with_capacity(compress_unit_size),
+            compressed_buffer: Vec::with_capacity(compress_unit_size + COMPRESSED_BLOCK_OVERHEAD),
+            written_bytes: 0,
+            compress_unit_size,
+            compress: Compress::new(level),
+            current_compressed_pos: 0,
+            closed: false,
+        })
+    }
+
+    /// Current virtual position
+    pub fn bgzf_pos(&self) -> VirtualPosition {
+        VirtualPosition::from(
+            self.current_compressed_pos << 16 | (self.original_data.len() & 0xffff) as u64,
+        )
+    }
+
+    /// Unwrap the underlying writer. Buffered data is discarded.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    /// Compress buffered data into `compressed_buffer`
+    fn compress_block(&mut self) -> io::Result<()> {
+        let block_size = write_block(
+            &mut self.compressed_buffer,
+            &self.original_data,
+            &mut self.compress,
+        )
+        .map_err(|e| BGZFError::from(e).into_io_error())?;
+        self.current_compressed_pos += block_size as u64;
+        self.original_data.clear();
+        Ok(())
+    }
+
+    /// Write all of `compressed_buffer` to the underlying writer
+    fn poll_write_compressed(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while self.written_bytes < self.compressed_buffer.len() {
+            let written = ready!(Pin::new(&mut self.writer)
+                .poll_write(cx, &self.compressed_buffer[self.written_bytes..]))?;
+            if written == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.written_bytes += written;
+        }
+        self.compressed_buffer.clear();
+        self.written_bytes = 0;
+        Poll::Ready(Ok(()))
+    }
+
+    /// Write buffered data as a block
+    fn poll_finish_block(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        ready!(self.poll_write_compressed(cx))?;
+        if !self.original_data.is_empty() {
+            self.compress_block()?;
+            ready!(self.poll_write_compressed(cx))?;
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for AsyncBGZFWriter<W> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if this.closed {
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "AsyncBGZFWriter is already shut down",
+            )));
+        }
+        if this.original_data.len() >= this.compress_unit_size {
+            ready!(this.poll_write_compressed(cx))?;
+            this.compress_block()?;
+        }
+        let write_size = buf
+            .len()
+            .min(this.compress_unit_size - this.original_data.len());
+        this.original_data.extend_from_slice(&buf[..write_size]);
+        Poll::Ready(Ok(write_size))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_finish_block(cx))?;
+        Pin::new(&mut this.writer).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if !this.closed {
+            ready!(this.poll_finish_block(cx))?;
+            this.compressed_buffer.extend_from_slice(&EOF_MARKER);
+            this.closed = true;
+        }
+        ready!(this.poll_write_compressed(cx))?;
+        Pin::new(&mut this.writer).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{BGZFReader, BGZFWriter};
+    use std::io::{BufRead, Read, Write};
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_async_reader() -> anyhow::Result<()> {
+        let mut expected_data = Vec::new();
+        BGZFReader::new(std::fs::File::open("testfiles/generated.bed.gz")?)?
+            .read_to_end(&mut expected_data)?;
+
+        let mut reader =
+            AsyncBGZFReader::new(tokio::fs::File::open("testfiles/generated.bed.gz").await?);
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).await?;
+        assert_eq!(data, expected_data);
+
+        // virtual positions and seek
+        let mut sync_reader = BGZFReader::new(std::fs::File::open("testfiles/generated.bed.gz")?)?;
+        let mut reader =
+            AsyncBGZFReader::new(tokio::fs::File::open("testfiles/generated.bed.gz").await?);
+        let mut positions = Vec::new();
+        let mut line = String::new();
+        let mut sync_line = String::new();
+        for _ in 0..3000 {
+            positions.push((reader.bgzf_pos(), line.clone()));
+            line.clear();
+            sync_line.clear();
+            reader.read_line(&mut line).await?;
+            sync_reader.read_line(&mut sync_line)?;
+            assert_eq!(line, sync_line);
+            assert_eq!(reader.bgzf_pos(), sync_reader.bgzf_pos());
+        }
+        for (position, _) in positions.iter().rev().step_by(7) {
+            sync_reader.bgzf_seek(*position)?;
+            reader.bgzf_seek(*position).await?;
+            line.clear();
+            sync_line.clear();
+            reader.read_line(&mut line).await?;
+            sync_reader.read_line(&mut sync_line)?;
+            assert_eq!(line, sync_line);
+        }
+
+        // truncated data
+        let compressed = std::fs::read("testfiles/generated.bed.gz")?;
+        let mut reader = AsyncBGZFReader::new(&compressed[..1000]);
+        assert!(reader.read_to_end(&mut Vec::new()).await.is_err());
+
+        // header with FCOMMENT field
+        let mut compressed = Vec::new();
+        let mut writer = BGZFWriter::new(&mut compressed, Compression::default())
+            .with_file_comment(b"async reader test")?;
+        writer.write_all(b"line with comment\n")?;
+        writer.close()?;
+        let mut reader = AsyncBGZFReader::new(&compressed[..]);
+        line.clear();
+        reader.read_line(&mut line).await?;
+        assert_eq!(line, "line with comment\n");
+
+        // not BGZF
+        let mut reader = AsyncBGZFReader::new(&b"plain text data"[..]);
+        assert!(reader.read_to_end(&mut Vec::new()).await.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_async_writer() -> anyhow::Result<()> {
+        let mut data = Vec::new();
+        std::fs::File::open("testfiles/common_all_20180418_half.vcf.gz")
+            .map(BGZFReader::new)??
+            .read_to_end(&mut data)?;
+        let data = &data[..1_000_000];
+
+        let mut compressed = Vec::new();
+        let mut writer = AsyncBGZFWriter::new(&mut compressed, Compression::default());
+        for chunk in data.chunks(10_000) {
+            writer.write_all(chunk).await?;
+        }
+        assert_eq!(
+            writer.bgzf_pos().uncompressed_offset_in_block() as usize,
+            data.len() % DEFAULT_COMPRESS_UNIT_SIZE
+        );
+        writer.shutdown().await?;
+
+        // same output as the synchronous writer
+        let mut expected = Vec::new();
+        let mut sync_writer = BGZFWriter::new(&mut expected, Compression::default());
+        sync_writer.write_all(data)?;
+        sync_writer.close()?;
+        assert_eq!(compressed, expected);
+        assert!(compressed.ends_with(&EOF_MARKER));
+
+        let mut decompressed = Vec::new();
+        BGZFReader::new(&compressed[..])?.read_to_end(&mut decompressed)?;
+        assert_eq!(decompressed, data);
+
+        // flush writes a block boundary
+        let mut compressed = Vec::new();
+        let mut writer =
+            AsyncBGZFWriter::with_compress_unit_size(&mut compressed, Compression::fast(), 1000)?;
+        writer.write_all(b"first line\n").await?;
+        writer.flush().await?;
+        let second_block = writer.bgzf_pos();
+        assert_eq!(second_block.uncompressed_offset_in_block(), 0);
+        writer.write_all(b"second line\n").await?;
+        writer.shutdown().await?;
+        assert!(writer.write_all(b"after shutdown").await.is_err());
+        drop(writer);
+
+        let mut reader = AsyncBGZFReader::new(std::io::Cursor::new(&compressed));
+        reader.bgzf_seek(second_block).await?;
+        let mut line = String::new();
+        reader.read_line(&mut line).await?;
+        assert_eq!(line, "second line\n");
+
+        let mut reader = BGZFReader::new(&compressed[..])?;
+        line.clear();
+        reader.read_line(&mut line)?;
+        assert_eq!(line, "first line\n");
+
+        assert!(AsyncBGZFWriter::with_compress_unit_size(
+            Vec::new(),
+            Compression::default(),
+            crate::write::MAXIMUM_COMPRESS_UNIT_SIZE
+        )
+        .is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,671 @@
+//! BAI index support
+//!
+//! [`BAIBuilder`] creates a binning and linear index of BAI layout from coordinate-sorted records.
+//! Record coordinates are supplied through [`RecordCoordinate`] trait, so BAM parser is not required.
+
+use crate::csi::{TABIX_DEPTH, TABIX_MIN_SHIFT};
+use crate::tabix::{TabixBin, TabixChunk, TabixSequence};
+use crate::{BGZFError, BinaryReader, VirtualPosition};
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryInto;
+use std::io::{self, Read, Write};
+
+/// Magic number of BAI file
+pub const BAI_MAGIC: [u8; 4] = [b'B', b'A', b'I', 1];
+
+/// Pseudo bin to store metadata of a reference sequence
+pub const BAI_PSEUDO_BIN: u32 = 37450;
+
+/// Maximum coordinate supported by the binning scheme of BAI (2^29)
+pub const BAI_MAX_COORDINATE: u64 = 1 << 29;
+
+/// Minimum distance of compressed offsets to keep a bin separated from the parent bin
+const MIN_MARKER_DIST: u64 = 0x10000;
+
+/// Coordinate of a record to be indexed.
+pub trait RecordCoordinate {
+    /// Index of reference sequence. `None` for unplaced records.
+    fn reference_id(&self) -> Option<usize>;
+    /// Zero-based start position
+    fn begin(&self) -> u64;
+    /// Zero-based exclusive end position
+    fn end(&self) -> u64;
+    /// `false` if a record is placed but unmapped
+    fn is_mapped(&self) -> bool {
+        true
+    }
+}
+
+/// A simple implementation of [`RecordCoordinate`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Coordinate {
+    pub reference_id: Option<usize>,
+    pub begin: u64,
+    pub end: u64,
+    pub mapped: bool,
+}
+
+impl RecordCoordinate for Coordinate {
+    fn reference_id(&self) -> Option<usize> {
+        self.reference_id
+    }
+
+    fn begin(&self) -> u64 {
+        self.begin
+    }
+
+    fn end(&self) -> u64 {
+        self.end
+    }
+
+    fn is_mapped(&self) -> bool {
+        self.mapped
+    }
+}
+
+/// Metadata stored in [`BAI_PSEUDO_BIN`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BAIMetadata {
+    /// Virtual file offset of the first record
+    pub begin: VirtualPosition,
+    /// Virtual file offset of the end of the last record
+    pub end: VirtualPosition,
+    /// Number of mapped records
+    pub mapped: u64,
+    /// Number of unmapped records
+    pub unmapped: u64,
+}
+
+/// Represents .bai index file
+#[derive(Debug, Clone, PartialEq)]
+pub struct BAI {
+    pub sequences: Vec<TabixSequence>,
+    pub number_of_unplaced_unmapped: Option<u64>,
+}
+
+impl BAI {
+    /// Load .bai index file from `reader`
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self, BGZFError> {
+        let mut reader = io::BufReader::new(reader);
+        let mut buf = [0u8; 4];
+        reader.read_exact(&mut buf)?;
+        if buf != BAI_MAGIC {
+            return Err(BGZFError::Other("Not BAI format"));
+        }
+        let number_of_references = reader.read_le_i32()?;
+        let mut sequences = Vec::new();
+        for _ in 0..number_of_references {
+            sequences.push(TabixSequence::from_reader(&mut reader)?);
+        }
+        let number_of_unplaced_unmapped = match reader.read_le_u64() {
+            Ok(x) => Some(x),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => None,
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(BAI {
+            sequences,
+            number_of_unplaced_unmapped,
+        })
+    }
+
+    /// Write .bai index file into `writer`
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(&BAI_MAGIC)?;
+        let number_of_references: i32 = self.sequences.len().try_into().unwrap();
+        writer.write_all(&number_of_references.to_le_bytes())?;
+        for one in &self.sequences {
+            one.write(&mut writer)?;
+        }
+        if let Some(unplaced) = self.number_of_unplaced_unmapped {
+            writer.write_all(&unplaced.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Get metadata of a reference sequence stored in [`BAI_PSEUDO_BIN`]
+    pub fn metadata(&self, reference_id: usize) -> Option<BAIMetadata> {
+        let bin = self
+            .sequences
+            .get(reference_id)?
+            .bins
+            .get(&BAI_PSEUDO_BIN)?;
+        if bin.chunks.len() != 2 {
+            return None;
+        }
+        Some(BAIMetadata {
+            begin: bin.chunks[0].begin,
+            end: bin.chunks[0].end,
+            mapped: bin.chunks[1].begin.into(),
+            unmapped: bin.chunks[1].end.into(),
+        })
+    }
+
+    /// List chunks which may contain records overlapped with region [begin,end) (zero-based).
+    ///
+    /// Returned chunks are sorted and merged.
+    pub fn query(&self, reference_id: usize, begin: u64, end: u64) -> Vec<TabixChunk> {
+        self.sequences
+            .get(reference_id)
+            .map(|x| x.query(begin, end))
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Default)]
+struct SequenceBuilder {
+    bins: BTreeMap<u32, Vec<TabixChunk>>,
+    intervals: Vec<Option<VirtualPosition>>,
+}
+
+impl SequenceBuilder {
+    fn insert_chunk(&mut self, bin: u32, begin: VirtualPosition, end: VirtualPosition) {
+        self.bins
+            .entry(bin)
+            .or_default()
+            .push(TabixChunk { begin, end });
+    }
+
+    fn insert_interval(&mut self, begin: u64, end: u64, offset: VirtualPosition) {
+        let begin: usize = (begin >> TABIX_MIN_SHIFT).try_into().unwrap();
+        let end: usize = ((end - 1) >> TABIX_MIN_SHIFT).try_into().unwrap();
+        if self.intervals.len() < end + 1 {
+            self.intervals.resize(end + 1, None);
+        }
+        for one in &mut self.intervals[begin..=end] {
+            one.get_or_insert(offset);
+        }
+    }
+
+    fn finish(mut self) -> TabixSequence {
+        // fill missing linear index
+        let mut last_offset = self
+            .bins
+            .get(&BAI_PSEUDO_BIN)
+            .and_then(|x| x.first())
+            .map(|x| x.begin)
+            .unwrap_or_default();
+        let intervals: Vec<VirtualPosition> = self
+            .intervals
+            .iter()
+            .map(|x| {
+                if let Some(x) = x {
+                    last_offset = *x;
+                }
+                last_offset
+            })
+            .collect();
+
+        // merge a bin to its parent if the bin is too small
+        for level in (1..=TABIX_DEPTH).rev() {
+            let first_bin = ((1 << (level * 3)) - 1) / 7;
+            let next_level_bin = ((1 << ((level + 1) * 3)) - 1) / 7;
+            let bins: Vec<u32> = self
+                .bins
+                .range(first_bin..next_level_bin)
+                .map(|x| *x.0)
+                .collect();
+            for bin in bins {
+                let chunks = self.bins.get_mut(&bin).unwrap();
+                chunks.sort_by_key(|x| x.begin);
+                let distance = (chunks.last().unwrap().end.compressed_offset())
+                    .saturating_sub(chunks.first().unwrap().begin.compressed_offset());
+                let parent = crate::csi::parent_bin(bin).unwrap();
+                if distance < MIN_MARKER_DIST && self.bins.contains_key(&parent) {
+                    let chunks = self.bins.remove(&bin).unwrap();
+                    self.bins.get_mut(&parent).unwrap().extend(chunks);
+                }
+            }
+        }
+        if let Some(chunks) = self.bins.get_mut(&0) {
+            chunks.sort_by_key(|x| x.begin);
+        }
+
+        // merge adjacent chunks that start from the same BGZF block
+        let bins: HashMap<u32, TabixBin> = self
+            .bins
+            .into_iter()
+            .map(|(bin, chunks)| {
+                let chunks = if bin == BAI_PSEUDO_BIN {
+                    chunks
+                } else {
+                    let mut merged: Vec<TabixChunk> = Vec::new();
+                    for one in chunks {
+                        if let Some(last) = merged.last_mut() {
+                            if last.end.compressed_offset() >= one.begin.compressed_offset() {
+                                last.end = last.end.max(one.end);
+                                continue;
+                            }
+                        }
+                        merged.push(one);
+                    }
+                    merged
+                };
+                (
+                    bin,
+                    TabixBin {
+                        bin,
+                        number_of_chunk: chunks.len().try_into().unwrap(),
+                        chunks,
+                    },
+                )
+            })
+            .collect();
+
+        TabixSequence {
+            number_of_distinct_bin: bins.len().try_into().unwrap(),
+            bins,
+            number_of_intervals: intervals.len().try_into().unwrap(),
+            intervals,
+        }
+    }
+}
+
+/// Pseudo bin number for unplaced records. It is only used to detect change of bins.
+const UNPLACED_BIN: u32 = u32::MAX - 1;
+
+/// BAI index builder
+///
+/// Records must be supplied in coordinate-sorted order with [`BAIBuilder::add_record`],
+/// and unplaced records must be placed at the end. Chunks and the linear index are
+/// calculated in the same way as htslib.
+#[derive(Debug)]
+pub struct BAIBuilder {
+    sequences: Vec<Option<SequenceBuilder>>,
+    started: bool,
+    last_reference: Option<usize>,
+    last_bin: Option<u32>,
+    last_offset: VirtualPosition,
+    last_begin: u64,
+    save_reference: Option<usize>,
+    save_bin: Option<u32>,
+    save_offset: VirtualPosition,
+    reference_begin_offset: VirtualPosition,
+    number_of_mapped: u64,
+    number_of_unmapped: u64,
+    number_of_unplaced_unmapped: u64,
+}
+
+impl BAIBuilder {
+    /// Create new [`BAIBuilder`] for `number_of_references` reference sequences.
+    pub fn new(number_of_references: usize) -> Self {
+        BAIBuilder {
+            sequences: (0..number_of_references).map(|_| None).collect(),
+            started: false,
+            last_reference: None,
+            last_bin: None,
+            last_offset: VirtualPosition::default(),
+            last_begin: 0,
+            save_reference: None,
+            save_bin: None,
+            save_offset: VirtualPosition::default(),
+            reference_begin_offset: VirtualPosition::default(),
+            number_of_mapped: 0,
+            number_of_unmapped: 0,
+            number_of_unplaced_unmapped: 0,
+        }
+    }
+
+    /// Add a reference sequence and return its index
+    pub(crate) fn add_reference(&mut self) -> usize {
+        self.sequences.push(None);
+        self.sequences.len() - 1
+    }
+
+    /// Add a record located between `begin_voffset` and `end_voffset` (BGZF virtual file offsets).
+    ///
+    /// An error is returned if a placed record begins at or after [`BAI_MAX_COORDINATE`], ends after it,
+    /// or ends before its begin.
+    pub fn add_record<C: RecordCoordinate>(
+        &mut self,
+        record: &C,
+        begin_voffset: VirtualPosition,
+        end_voffset: VirtualPosition,
+    ) -> Result<(), BGZFError> {
+        let reference_id = record.reference_id();
+        if let Some(reference_id) = reference_id {
+            if reference_id >= self.sequences.len() {
+                return Err(BGZFError::Other("Invalid reference ID"));
+            }
+            if record.end() < record.begin() {
+                return Err(BGZFError::Other("Record ends before its begin"));
+            }
+            if record.begin() >= BAI_MAX_COORDINATE || record.end() > BAI_MAX_COORDINATE {
+                return Err(BGZFError::Other("Record position is too large for BAI"));
+            }
+        }
+        if !self.started || reference_id != self.last_reference {
+            if let Some(reference_id) = reference_id {
+                if self.number_of_unplaced_unmapped > 0 {
+                    return Err(BGZFError::Other(
+                        "Unplaced records must be placed at the end",
+                    ));
+                }
+                if self.sequences[reference_id].is_some() {
+                    return Err(BGZFError::Other("Records are not sorted by reference"));
+                }
+            }
+        } else if reference_id.is_some() && self.last_begin > record.begin() {
+            return Err(BGZFError::Other("Records are not sorted by position"));
+        }
+        if !self.started {
+            self.started = true;
+            self.reference_begin_offset = begin_voffset;
+            self.save_offset = begin_voffset;
+        }
+        self.last_offset = begin_voffset;
+
+        self.push(
+            reference_id,
+            record.begin(),
+            record.end(),
+            record.is_mapped(),
+            end_voffset,
+        );
+        Ok(())
+    }
+
+    fn push(
+        &mut self,
+        reference_id: Option<usize>,
+        begin: u64,
+        end: u64,
+        mapped: bool,
+        end_voffset: VirtualPosition,
+    ) {
+        if reference_id != self.last_reference {
+            self.last_reference = reference_id;
+            self.last_bin = None;
+        }
+
+        let end = end.max(begin + 1);
+        let bin = if let Some(reference_id) = reference_id {
+            let sequence = self.sequences[reference_id].get_or_insert_with(Default::default);
+            if mapped {
+                sequence.insert_interval(begin, end, self.last_offset);
+            }
+            crate::csi::reg2bin(begin, end, TABIX_MIN_SHIFT, TABIX_DEPTH).expect("Unreachable")
+        } else {
+            self.number_of_unplaced_unmapped += 1;
+            UNPLACED_BIN
+        };
+
+        if self.last_bin != Some(bin) {
+            if let (Some(save_bin), Some(save_reference)) = (self.save_bin, self.save_reference) {
+                let sequence = self.sequences[save_reference].as_mut().unwrap();
+                sequence.insert_chunk(save_bin, self.save_offset, self.last_offset);
+                if self.last_bin.is_none() {
+                    // change of reference sequence
+                    sequence.insert_chunk(
+                        BAI_PSEUDO_BIN,
+                        self.reference_begin_offset,
+                        self.last_offset,
+                    );
+                    sequence.insert_chunk(
+                        BAI_PSEUDO_BIN,
+                        self.number_of_mapped.into(),
+                        self.number_of_unmapped.into(),
+                    );
+                    self.number_of_mapped = 0;
+                    self.number_of_unmapped = 0;
+                    self.reference_begin_offset = self.last_offset;
+                }
+            }
+            self.save_offset = self.last_offset;
+            self.save_bin = Some(bin);
+            self.last_bin = Some(bin);
+            self.save_reference = reference_id;
+        }
+
+        if mapped {
+            self.number_of_mapped += 1;
+        } else {
+            self.number_of_unmapped += 1;
+        }
+        self.last_offset = end_voffset;
+        self.last_begin = begin;
+    }
+
+    /// Finish building and create [`BAI`] index.
+    pub fn finish(mut self) -> BAI {
+        if self.started {
+            // flush chunks of the last reference sequence
+            self.push(None, 0, 0, false, self.last_offset);
+            self.number_of_unplaced_unmapped -= 1;
+        }
+
+        BAI {
+            sequences: self
+                .sequences
+                .into_iter()
+                .map(|x| {
+                    x.map(|x| x.finish()).unwrap_or_else(|| TabixSequence {
+                        number_of_distinct_bin: 0,
+                        bins: HashMap::new(),
+                        number_of_intervals: 0,
+                        intervals: Vec::new(),
+                    })
+                })
+                .collect(),
+            number_of_unplaced_unmapped: Some(self.number_of_unplaced_unmapped),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tabix::Tabix;
+    use crate::BGZFReader;
+    use rand::prelude::*;
+    use std::fs::File;
+    use std::io::BufRead;
+
+    #[test]
+    fn test_bai_builder() -> anyhow::Result<()> {
+        let tabix = Tabix::from_reader(File::open("testfiles/generated.bed.gz.tbi")?)?;
+        let names: HashMap<Vec<u8>, usize> = tabix
+            .names
+            .iter()
+            .enumerate()
+            .map(|(i, x)| (x[..(x.len() - 1)].to_vec(), i))
+            .collect();
+
+        let mut reader = BGZFReader::new(File::open("testfiles/generated.bed.gz")?)?;
+        let mut builder = BAIBuilder::new(names.len());
+        let mut records = Vec::new();
+        let mut record_offsets = HashMap::new();
+        let mut line = Vec::new();
+        loop {
+            reader.fill_buf()?;
+            let begin_voffset = reader.bgzf_pos();
+            line.clear();
+            if BufRead::read_until(&mut reader, b'\n', &mut line)? == 0 {
+                break;
+            }
+            reader.fill_buf()?;
+            let end_voffset = reader.bgzf_pos();
+            let columns: Vec<_> = line.split(|x| *x == b'\t').collect();
+            let coordinate = Coordinate {
+                reference_id: Some(names[columns[0]]),
+                begin: std::str::from_utf8(columns[1])?.parse()?,
+                end: std::str::from_utf8(columns[2])?.parse()?,
+                mapped: true,
+            };
+            builder.add_record(&coordinate, begin_voffset, end_voffset)?;
+            record_offsets.insert(begin_voffset, records.len());
+            records.push((coordinate, begin_voffset, line.clone()));
+        }
+        assert!(builder
+            .add_record(&records[0].0, records[0].1, records[1].1)
+            .is_err());
+        let bai = builder.finish();
+
+        // same as the index created by htslib
+        assert_eq!(bai.sequences, tabix.sequences);
+        assert_eq!(bai.number_of_unplaced_unmapped, Some(0));
+        let metadata = bai.metadata(0).unwrap();
+        assert_eq!(metadata.begin, VirtualPosition::default());
+        assert_eq!(
+            metadata.mapped,
+            records
+                .iter()
+                .filter(|x| x.0.reference_id == Some(0))
+                .count() as u64
+        );
+        assert_eq!(metadata.unmapped, 0);
+
+        // write and read
+        let mut data = Vec::new();
+        bai.write(&mut data)?;
+        assert_eq!(&data[..4], b"BAI\x01");
+        assert_eq!(BAI::from_reader(&data[..])?, bai);
+        assert!(BAI::from_reader(File::open("testfiles/generated.bed.gz.tbi")?).is_err());
+
+        // query
+        let mut rand = rand_pcg::Pcg64Mcg::seed_from_u64(0x9387402451357523);
+        for _ in 0..30 {
+            let reference_id = rand.gen_range(0..names.len());
+            let begin = rand.gen_range(0..100_000_000);
+            let end = begin + rand.gen_range(1..1_000_000);
+            let expected: Vec<_> = records
+                .iter()
+                .filter(|x| {
+                    x.0.reference_id == Some(reference_id) && x.0.begin < end && begin < x.0.end
+                })
+                .collect();
+
+            let mut found = Vec::new();
+            for chunk in bai.query(reference_id, begin, end) {
+                reader.bgzf_seek(chunk.begin)?;
+                while reader.bgzf_pos() < chunk.end {
+                    let voffset = reader.bgzf_pos();
+                    line.clear();
+                    if BufRead::read_until(&mut reader, b'\n', &mut line)? == 0 {
+                        break;
+                    }
+                    reader.fill_buf()?;
+                    if let Some(record) = record_offsets.get(&voffset).map(|x| &records[*x]) {
+                        if record.0.reference_id == Some(reference_id)
+                            && record.0.begin < end
+                            && begin < record.0.end
+                        {
+                            assert_eq!(record.2, line);
+                            found.push(record);
+                        }
+                    }
+                }
+            }
+            assert_eq!(found, expected);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bai_unmapped() -> anyhow::Result<()> {
+        let mut builder = BAIBuilder::new(3);
+        let records = [
+            (Some(0), 100, 200, true),
+            (Some(0), 150, 150, false),
+            (Some(2), 10, 20, true),
+            (None, 0, 0, false),
+            (None, 0, 0, false),
+        ];
+        for (i, one) in records.iter().enumerate() {
+            let coordinate = Coordinate {
+                reference_id: one.0,
+                begin: one.1,
+                end: one.2,
+                mapped: one.3,
+            };
+            builder.add_record(
+                &coordinate,
+                VirtualPosition::from(i as u64 * 100),
+                VirtualPosition::from((i as u64 + 1) * 100),
+            )?;
+        }
+        assert!(builder
+            .add_record(
+                &Coordinate {
+                    reference_id: Some(1),
+                    begin: 0,
+                    end: 1,
+                    mapped: true
+                },
+                500.into(),
+                600.into()
+            )
+            .is_err());
+        let bai = builder.finish();
+        assert_eq!(bai.number_of_unplaced_unmapped, Some(2));
+        assert_eq!(
+            bai.metadata(0),
+            Some(BAIMetadata {
+                begin: 0.into(),
+                end: 200.into(),
+                mapped: 1,
+                unmapped: 1,
+            })
+        );
+        assert_eq!(bai.metadata(1), None);
+        assert_eq!(
+            bai.metadata(2),
+            Some(BAIMetadata {
+                begin: 200.into(),
+                end: 300.into(),
+                mapped: 1,
+                unmapped: 0,
+            })
+        );
+        assert_eq!(
+            bai.query(0, 0, 1000),
+            vec![TabixChunk {
+                begin: 0.into(),
+                end: 200.into()
+            }]
+        );
+        assert!(bai.query(1, 0, 1000).is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bai_coordinate_range() -> anyhow::Result<()> {
+        let mut builder = BAIBuilder::new(1);
+        for (begin, end) in [
+            (BAI_MAX_COORDINATE, BAI_MAX_COORDINATE + 1),
+            (0, BAI_MAX_COORDINATE + 1),
+            (u64::MAX, u64::MAX),
+            (100, 99),
+        ] {
+            let coordinate = Coordinate {
+                reference_id: Some(0),
+                begin,
+                end,
+                mapped: true,
+            };
+            assert!(builder
+                .add_record(&coordinate, 0.into(), 100.into())
+                .is_err());
+        }
+
+        let coordinate = Coordinate {
+            reference_id: Some(0),
+            begin: BAI_MAX_COORDINATE - 1,
+            end: BAI_MAX_COORDINATE,
+            mapped: true,
+        };
+        builder.add_record(&coordinate, 0.into(), 100.into())?;
+        let bai = builder.finish();
+        assert_eq!(
+            bai.query(0, BAI_MAX_COORDINATE - 1, BAI_MAX_COORDINATE),
+            vec![TabixChunk {
+                begin: 0.into(),
+                end: 100.into()
+            }]
+        );
+
+        Ok(())
+    }
+}
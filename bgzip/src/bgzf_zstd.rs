@@ -0,0 +1,499 @@
+//! BGZF-like block format compressed with [zstd](https://facebook.github.io/zstd/)
+//!
+//! Like BGZF, data is split into blocks of up to 64 KiB, and each block is compressed
+//! independently. A block consists of a zstd skippable frame of [`ZSTD_BLOCK_HEADER_SIZE`] bytes,
+//! which records the size of the block and the uncompressed size, followed by a zstd frame with a
+//! content checksum. Because skippable frames are ignored by zstd decoders, the whole file can be
+//! decompressed by the `zstd` command or any other zstd decoder.
+//!
+//! Virtual positions and .gzi index ([`crate::index::BGZFIndex`]) are calculated in the same way as
+//! BGZF. This format is not compatible with htslib.
+//!
+//! ```rust
+//! # fn main() -> Result<(), bgzip::BGZFError> {
+//! use bgzip::bgzf_zstd::{BGZFZstdReader, BGZFZstdWriter};
+//! use std::io::{BufRead, Write};
+//!
+//! let mut compressed = Vec::new();
+//! let mut writer = BGZFZstdWriter::new(&mut compressed, zstd::DEFAULT_COMPRESSION_LEVEL)?;
+//! writer.write_all(b"##fileformat=VCFv4.2\n")?;
+//! let position = writer.bgzf_pos();
+//! writer.write_all(b"#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n")?;
+//! let index = writer.close()?;
+//!
+//! let mut reader = BGZFZstdReader::new(std::io::Cursor::new(compressed))?;
+//! reader.bgzf_seek(position)?;
+//! let mut line = String::new();
+//! reader.read_line(&mut line)?;
+//! assert_eq!(line, "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n");
+//! assert_eq!(index.uncompressed_pos_to_bgzf_pos(21)?, position);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::index::{BGZFIndex, BGZFIndexEntry};
+use crate::read::truncated_error;
+use crate::write::{
+    check_compress_unit_size, DEFAULT_COMPRESS_UNIT_SIZE, MAXIMUM_COMPRESS_UNIT_SIZE,
+};
+use crate::{BGZFError, VirtualPosition};
+use std::convert::TryInto;
+use std::io::{self, BufRead, Read, Seek, Write};
+
+/// Magic number of the skippable frame at the beginning of a block
+pub const ZSTD_BLOCK_MAGIC: u32 = 0x184D_2A5B;
+
+/// Size of the skippable frame at the beginning of a block
+pub const ZSTD_BLOCK_HEADER_SIZE: usize = 16;
+
+/// Length of the skippable frame content
+const SKIPPABLE_FRAME_CONTENT_SIZE: u32 = 8;
+
+/// Reader of zstd compressed BGZF-like blocks
+///
+/// Empty blocks are skipped, and reading stops at the end of `reader`.
+pub struct BGZFZstdReader<R: Read> {
+    reader: R,
+    decompressor: zstd::bulk::Decompressor<'static>,
+    compressed_data: Vec<u8>,
+    current_buffer: Vec<u8>,
+    current_position_in_block: usize,
+    current_block: u64,
+    next_block: u64,
+}
+
+impl<R: Read> BGZFZstdReader<R> {
+    /// Create new reader. `reader` should point the beginning of a block.
+    pub fn new(reader: R) -> Result<Self, BGZFError> {
+        Ok(BGZFZstdReader {
+            reader,
+            decompressor: zstd::bulk::Decompressor::new()?,
+            compressed_data: Vec::new(),
+            current_buffer: Vec::new(),
+            current_position_in_block: 0,
+            current_block: 0,
+            next_block: 0,
+        })
+    }
+
+    /// Current virtual position
+    pub fn bgzf_pos(&self) -> VirtualPosition {
+        VirtualPosition::from(
+            self.current_block << 16 | (self.current_position_in_block & 0xffff) as u64,
+        )
+    }
+
+    /// Unwrap the underlying reader. Buffered data is discarded.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Load and decompress the next block. `false` is returned at the end of `reader`.
+    fn load_next(&mut self) -> Result<bool, BGZFError> {
+        let next_block = self.next_block;
+        self.load_next_block()
+            .map_err(|e| truncated_error(e).in_block(next_block, None))
+    }
+
+    fn load_next_block(&mut self) -> Result<bool, BGZFError> {
+        let mut header = [0u8; ZSTD_BLOCK_HEADER_SIZE];
+        let mut loaded = 0;
+        while loaded < header.len() {
+            match self.reader.read(&mut header[loaded..]) {
+                Ok(0) => break,
+                Ok(read_bytes) => loaded += read_bytes,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        if loaded == 0 {
+            self.current_block = self.next_block;
+            self.current_buffer.clear();
+            self.current_position_in_block = 0;
+            return Ok(false);
+        }
+        if loaded < header.len() {
+            return Err(BGZFError::TruncatedFile);
+        }
+
+        let field = |i: usize| u32::from_le_bytes(header[(i * 4)..(i * 4 + 4)].try_into().unwrap());
+        if field(0) != ZSTD_BLOCK_MAGIC || field(1) != SKIPPABLE_FRAME_CONTENT_SIZE {
+            return Err(BGZFError::NotBGZF);
+        }
+        let block_size: usize = field(2).try_into().unwrap();
+        let uncompressed_size = field(3);
+        if block_size <= ZSTD_BLOCK_HEADER_SIZE {
+            return Err(BGZFError::BlockTooShort {
+                size: block_size as u64,
+            });
+        }
+        if uncompressed_size as usize > MAXIMUM_COMPRESS_UNIT_SIZE {
+            return Err(BGZFError::UncompressedBlockTooLarge {
+                size: uncompressed_size.into(),
+            });
+        }
+
+        self.compressed_data.clear();
+        self.compressed_data
+            .resize(block_size - ZSTD_BLOCK_HEADER_SIZE, 0);
+        self.reader.read_exact(&mut self.compressed_data)?;
+        self.current_buffer.clear();
+        self.current_buffer.resize(uncompressed_size as usize, 0);
+        let decompressed_size = self
+            .decompressor
+            .decompress_to_buffer(&self.compressed_data[..], &mut self.current_buffer[..])?;
+        if decompressed_size != uncompressed_size as usize {
+            self.current_buffer.clear();
+            return Err(BGZFError::SizeMismatch {
+                expected: uncompressed_size,
+                actual: decompressed_size as u32,
+            });
+        }
+
+        self.current_block = self.next_block;
+        self.next_block += block_size as u64;
+        self.current_position_in_block = 0;
+        Ok(true)
+    }
+}
+
+impl<R: Read + Seek> BGZFZstdReader<R> {
+    /// Move to the virtual position.
+    ///
+    /// Use [`BGZFIndex::uncompressed_pos_to_bgzf_pos`] to seek to an uncompressed position.
+    pub fn bgzf_seek<P: Into<VirtualPosition>>(&mut self, position: P) -> Result<(), BGZFError> {
+        let position = position.into();
+        self.next_block = position.compressed_offset();
+        self.reader.seek(io::SeekFrom::Start(self.next_block))?;
+        self.load_next()?;
+        self.current_position_in_block = position.uncompressed_offset_in_block().into();
+        Ok(())
+    }
+}
+
+impl<R: Read> BufRead for BGZFZstdReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        // empty blocks in the middle of data are skipped
+        while self.current_position_in_block >= self.current_buffer.len() {
+            if !self.load_next().map_err(|e| e.into_io_error())? {
+                break;
+            }
+        }
+        Ok(self
+            .current_buffer
+            .get(self.current_position_in_block..)
+            .unwrap_or(&[]))
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.current_position_in_block =
+            (self.current_position_in_block + amt).min(self.current_buffer.len());
+    }
+}
+
+impl<R: Read> Read for BGZFZstdReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let data = self.fill_buf()?;
+        let read_bytes = data.len().min(buf.len());
+        buf[..read_bytes].copy_from_slice(&data[..read_bytes]);
+        self.consume(read_bytes);
+        Ok(read_bytes)
+    }
+}
+
+/// Writer of zstd compressed BGZF-like blocks
+///
+/// An empty block is written as end-of-file marker on close or drop.
+/// [`std::io::Write::flush`] writes buffered data as a block.
+pub struct BGZFZstdWriter<W: Write> {
+    writer: W,
+    compressor: zstd::bulk::Compressor<'static>,
+    original_data: Vec<u8>,
+    compressed_buffer: Vec<u8>,
+    compress_unit_size: usize,
+    current_compressed_pos: u64,
+    current_uncompressed_pos: u64,
+    bgzf_index: BGZFIndex,
+    closed: bool,
+}
+
+impl<W: Write> BGZFZstdWriter<W> {
+    /// Create new writer with zstd compression `level`.
+    ///
+    /// [`BGZFError::InvalidCompressionLevel`] is returned if `level` is out of
+    /// [`zstd::compression_level_range`]. Level 0 is zstd's default level.
+    pub fn new(writer: W, level: i32) -> Result<Self, BGZFError> {
+        Self::with_compress_unit_size(writer, level, DEFAULT_COMPRESS_UNIT_SIZE)
+    }
+
+    /// Create new writer with compress unit size.
+    ///
+    /// Default value of compress unit size is 65280. [`BGZFError::TooLargeCompressUnit`] is
+    /// returned if `compress_unit_size` is not smaller than [`MAXIMUM_COMPRESS_UNIT_SIZE`].
+    pub fn with_compress_unit_size(
+        writer: W,
+        level: i32,
+        compress_unit_size: usize,
+    ) -> Result<Self, BGZFError> {
+        check_compress_unit_size(compress_unit_size)?;
+        if level != 0 && !zstd::compression_level_range().contains(&level) {
+            return Err(BGZFError::InvalidCompressionLevel);
+        }
+        let mut compressor = zstd::bulk::Compressor::new(level)?;
+        compressor.set_parameter(zstd::zstd_safe::CParameter::ChecksumFlag(true))?;
+
+        Ok(BGZFZstdWriter {
+            writer,
+            compressor,
+            original_data: Vec::with_capacity(compress_unit_size),
+            compressed_buffer: Vec::new(),
+            compress_unit_size,
+            current_compressed_pos: 0,
+            current_uncompressed_pos: 0,
+            bgzf_index: BGZFIndex::new(),
+            closed: false,
+        })
+    }
+
+    /// Current virtual position
+    pub fn bgzf_pos(&self) -> VirtualPosition {
+        VirtualPosition::from(
+            self.current_compressed_pos << 16 | (self.original_data.len() & 0xffff) as u64,
+        )
+    }
+
+    /// Current uncompressed position
+    pub fn pos(&self) -> u64 {
+        self.current_uncompressed_pos + self.original_data.len() as u64
+    }
+
+    /// Compress `original_data` into `compressed_buffer`, and return the size of the block.
+    fn encode_block(&mut self) -> io::Result<usize> {
+        let bound = zstd::zstd_safe::compress_bound(self.original_data.len());
+        self.compressed_buffer.clear();
+        self.compressed_buffer
+            .resize(ZSTD_BLOCK_HEADER_SIZE + bound, 0);
+        let compressed_size = self.compressor.compress_to_buffer(
+            &self.original_data[..],
+            &mut self.compressed_buffer[ZSTD_BLOCK_HEADER_SIZE..],
+        )?;
+        let block_size = ZSTD_BLOCK_HEADER_SIZE + compressed_size;
+        let block_size_field: u32 = block_size
+            .try_into()
+            .map_err(|_| BGZFError::BlockTooLarge.into_io_error())?;
+        self.compressed_buffer.truncate(block_size);
+        for (i, value) in [
+            ZSTD_BLOCK_MAGIC,
+            SKIPPABLE_FRAME_CONTENT_SIZE,
+            block_size_field,
+            self.original_data.len() as u32,
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            self.compressed_buffer[(i * 4)..(i * 4 + 4)].copy_from_slice(&value.to_le_bytes());
+        }
+        Ok(block_size)
+    }
+
+    /// Write buffered data as a block
+    fn write_block(&mut self) -> io::Result<()> {
+        let block_size = self.encode_block()?;
+        self.writer.write_all(&self.compressed_buffer)?;
+        self.current_compressed_pos += block_size as u64;
+        self.current_uncompressed_pos += self.original_data.len() as u64;
+        self.original_data.clear();
+        self.bgzf_index.entries.push(BGZFIndexEntry {
+            compressed_offset: self.current_compressed_pos,
+            uncompressed_offset: self.current_uncompressed_pos,
+        });
+        Ok(())
+    }
+
+    /// Write buffered data as a block if any.
+    pub fn finish_block(&mut self) -> io::Result<()> {
+        if !self.original_data.is_empty() {
+            self.write_block()?;
+        }
+        Ok(())
+    }
+
+    /// Write buffered data and end-of-file marker
+    fn write_last_blocks(&mut self) -> io::Result<()> {
+        self.finish_block()?;
+        self.encode_block()?;
+        self.writer.write_all(&self.compressed_buffer)?;
+        self.writer.flush()?;
+        self.closed = true;
+        Ok(())
+    }
+
+    /// Write end-of-file marker and return .gzi index of the written blocks.
+    ///
+    /// Drop trait will write end-of-file marker automatically.
+    /// If you need to handle I/O errors while closing, please use this method.
+    pub fn close(mut self) -> io::Result<BGZFIndex> {
+        if !self.closed {
+            self.write_last_blocks()?;
+        }
+        let mut index = std::mem::take(&mut self.bgzf_index);
+        // the entry after the last block points the end-of-file marker
+        index.entries.pop();
+        Ok(index)
+    }
+}
+
+impl<W: Write> Write for BGZFZstdWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.original_data.len() >= self.compress_unit_size {
+            self.write_block()?;
+        }
+        let write_size = buf
+            .len()
+            .min(self.compress_unit_size - self.original_data.len());
+        self.original_data.extend_from_slice(&buf[..write_size]);
+        Ok(write_size)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.finish_block()?;
+        self.writer.flush()
+    }
+}
+
+impl<W: Write> Drop for BGZFZstdWriter<W> {
+    fn drop(&mut self) {
+        if !self.closed {
+            self.write_last_blocks().unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::BGZFReader;
+
+    fn load_vcf() -> anyhow::Result<Vec<u8>> {
+        let mut data = Vec::new();
+        BGZFReader::new(std::fs::File::open(
+            "testfiles/common_all_20180418_half.vcf.gz",
+        )?)?
+        .read_to_end(&mut data)?;
+        data.truncate(2_000_000);
+        Ok(data)
+    }
+
+    #[test]
+    fn test_zstd_write_read() -> anyhow::Result<()> {
+        let data = load_vcf()?;
+
+        let mut compressed = Vec::new();
+        let mut writer = BGZFZstdWriter::new(&mut compressed, 0)?;
+        let mut line_positions = Vec::new();
+        for line in data.split_inclusive(|x| *x == b'\n') {
+            line_positions.push((writer.bgzf_pos(), writer.pos(), line));
+            writer.write_all(line)?;
+        }
+        let index = writer.close()?;
+        assert_eq!(
+            index.block_count(),
+            data.len().div_ceil(DEFAULT_COMPRESS_UNIT_SIZE)
+        );
+
+        // sequential read
+        let mut reader = BGZFZstdReader::new(&compressed[..])?;
+        let mut decompressed = Vec::new();
+        reader.read_to_end(&mut decompressed)?;
+        assert_eq!(decompressed, data);
+
+        // other zstd decoders can read the output
+        assert_eq!(zstd::decode_all(&compressed[..])?, data);
+
+        // seek with virtual positions and .gzi index
+        let mut reader = BGZFZstdReader::new(io::Cursor::new(&compressed))?;
+        let mut line = Vec::new();
+        for (bgzf_pos, pos, expected_line) in line_positions.iter().rev().step_by(97) {
+            assert_eq!(index.uncompressed_pos_to_bgzf_pos(*pos)?, *bgzf_pos);
+            reader.bgzf_seek(*bgzf_pos)?;
+            assert_eq!(reader.bgzf_pos(), *bgzf_pos);
+            line.clear();
+            reader.read_until(b'\n', &mut line)?;
+            assert_eq!(&line, expected_line);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_zstd_writer_options() -> anyhow::Result<()> {
+        let data = load_vcf()?;
+
+        let mut compressed = Vec::new();
+        let mut writer = BGZFZstdWriter::with_compress_unit_size(&mut compressed, 19, 1000)?;
+        writer.write_all(&data[..10_000])?;
+        writer.write_all(b"x")?;
+        // flush writes a block boundary
+        writer.flush()?;
+        assert_eq!(writer.bgzf_pos().uncompressed_offset_in_block(), 0);
+        writer.write_all(b"y")?;
+        let index = writer.close()?;
+        assert_eq!(index.block_count(), 12);
+        assert_eq!(index.entries()[10].uncompressed_offset, 10_001);
+
+        let mut decompressed = Vec::new();
+        BGZFZstdReader::new(&compressed[..])?.read_to_end(&mut decompressed)?;
+        assert_eq!(decompressed.len(), 10_002);
+
+        // end-of-file marker only
+        let mut compressed = Vec::new();
+        BGZFZstdWriter::new(&mut compressed, 3)?.close()?;
+        assert!(!compressed.is_empty());
+        let mut decompressed = Vec::new();
+        BGZFZstdReader::new(&compressed[..])?.read_to_end(&mut decompressed)?;
+        assert!(decompressed.is_empty());
+
+        assert!(matches!(
+            BGZFZstdWriter::new(Vec::new(), 100),
+            Err(BGZFError::InvalidCompressionLevel)
+        ));
+        assert!(matches!(
+            BGZFZstdWriter::with_compress_unit_size(Vec::new(), 3, MAXIMUM_COMPRESS_UNIT_SIZE),
+            Err(BGZFError::TooLargeCompressUnit)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_zstd_reader_error() -> anyhow::Result<()> {
+        let mut compressed = Vec::new();
+        let mut writer = BGZFZstdWriter::new(&mut compressed, 3)?;
+        writer.write_all(&load_vcf()?[..100_000])?;
+        writer.close()?;
+
+        // truncated data
+        let mut reader = BGZFZstdReader::new(&compressed[..1000])?;
+        let e = reader.read_to_end(&mut Vec::new()).unwrap_err();
+        let e = e.get_ref().and_then(|x| x.downcast_ref::<BGZFError>());
+        assert!(matches!(
+            e,
+            Some(BGZFError::InBlock { source, .. }) if matches!(**source, BGZFError::TruncatedFile)
+        ));
+
+        // broken data
+        let mut broken = compressed.clone();
+        broken[100] ^= 0xff;
+        assert!(BGZFZstdReader::new(&broken[..])?
+            .read_to_end(&mut Vec::new())
+            .is_err());
+
+        // BGZF is not accepted
+        let bgzf = std::fs::read("testfiles/generated.bed.gz")?;
+        assert!(BGZFZstdReader::new(&bgzf[..])?
+            .read_to_end(&mut Vec::new())
+            .is_err());
+
+        Ok(())
+    }
+}
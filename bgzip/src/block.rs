@@ -0,0 +1,389 @@
+//! Block level primitives without `std::io`
+//!
+//! This module parses and decompresses BGZF blocks stored in byte slices. Only `core` and `alloc`
+//! are required, so this module is available without `std` feature for environments like WASM.
+//! Decompression always uses [miniz_oxide](https://crates.io/crates/miniz_oxide) regardless of
+//! the selected backend.
+//!
+//! ```toml
+//! bgzip = { version = "0.4", default-features = false, features = ["block_core"] }
+//! ```
+//!
+//! ```rust
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let data = std::fs::read("testfiles/generated.bed.gz")?;
+//! let block = bgzip::block::split_block(&data)?;
+//! let mut decompressed = Vec::new();
+//! block.decompress(&mut decompressed)?;
+//! assert!(decompressed.starts_with(b"chr1\t"));
+//! let next_block = bgzip::block::split_block(&data[block.block_size..])?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::VirtualPosition;
+use alloc::vec::Vec;
+use core::convert::TryInto;
+use core::fmt;
+
+const GZIP_ID1: u8 = 31;
+const GZIP_ID2: u8 = 139;
+const DEFLATE: u8 = 8;
+const FLAG_FHCRC: u8 = 2;
+const FLAG_FEXTRA: u8 = 4;
+const FLAG_FNAME: u8 = 8;
+const FLAG_FCOMMENT: u8 = 16;
+
+/// Error of block level primitives
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockError {
+    /// Data ends in the middle of the block
+    Truncated,
+    /// Not gzip format
+    NotGzip,
+    /// Gzip header without BGZF extra field
+    NotBGZF,
+    /// BSIZE is smaller than the header and the footer
+    InvalidBlockSize,
+    /// Compressed data is not a valid deflate stream
+    InvalidDeflate,
+    /// CRC32 of decompressed data does not match the footer
+    Crc32Mismatch,
+    /// Length of decompressed data does not match ISIZE in the footer
+    SizeMismatch,
+    /// Virtual file offset does not point to data
+    InvalidPosition,
+}
+
+impl fmt::Display for BlockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlockError::Truncated => write!(f, "Truncated block"),
+            BlockError::NotGzip => write!(f, "not gzip format"),
+            BlockError::NotBGZF => write!(f, "not BGZF format"),
+            BlockError::InvalidBlockSize => write!(f, "Invalid block size"),
+            BlockError::InvalidDeflate => write!(f, "Invalid deflate stream"),
+            BlockError::Crc32Mismatch => write!(f, "Unmatched CRC32"),
+            BlockError::SizeMismatch => write!(f, "Unmatched ISIZE"),
+            BlockError::InvalidPosition => write!(f, "Invalid BGZF position"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for BlockError {}
+
+#[cfg(feature = "std")]
+impl From<BlockError> for crate::BGZFError {
+    fn from(e: BlockError) -> Self {
+        match e {
+            BlockError::NotGzip => crate::BGZFError::NotGzip,
+            BlockError::NotBGZF => crate::BGZFError::NotBGZF,
+            BlockError::Truncated => {
+                std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "Truncated BGZF block")
+                    .into()
+            }
+            BlockError::InvalidBlockSize => crate::BGZFError::Other("Invalid block size"),
+            BlockError::InvalidDeflate => crate::BGZFError::Other("Invalid deflate stream"),
+            BlockError::Crc32Mismatch => crate::BGZFError::Other("Unmatched CRC32"),
+            BlockError::SizeMismatch => crate::BGZFError::Other("Unmatched ISIZE"),
+            BlockError::InvalidPosition => crate::BGZFError::Other("Invalid BGZF position"),
+        }
+    }
+}
+
+/// A BGZF block in a byte slice
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Block<'a> {
+    /// Size of the gzip header
+    pub header_size: usize,
+    /// Size of the block including header and footer
+    pub block_size: usize,
+    /// Raw deflate payload
+    pub payload: &'a [u8],
+    /// CRC32 of uncompressed data
+    pub crc32: u32,
+    /// Uncompressed size (ISIZE)
+    pub uncompressed_size: u32,
+}
+
+impl<'a> Block<'a> {
+    /// Decompress this block and append decompressed data to `output`.
+    ///
+    /// CRC32 and ISIZE are verified.
+    pub fn decompress(&self, output: &mut Vec<u8>) -> Result<(), BlockError> {
+        let original_len = output.len();
+        let uncompressed_size: usize = self.uncompressed_size.try_into().unwrap();
+        output.resize(original_len + uncompressed_size, 0);
+        let result = miniz_oxide::inflate::decompress_slice_iter_to_slice(
+            &mut output[original_len..],
+            core::iter::once(self.payload),
+            false,
+            true,
+        );
+        let result = match result {
+            Ok(len) if len == uncompressed_size => Ok(()),
+            Ok(_) | Err(miniz_oxide::inflate::TINFLStatus::HasMoreOutput) => {
+                Err(BlockError::SizeMismatch)
+            }
+            Err(_) => Err(BlockError::InvalidDeflate),
+        };
+        if let Err(e) = result {
+            output.truncate(original_len);
+            return Err(e);
+        }
+
+        let mut crc = crc32fast::Hasher::new();
+        crc.update(&output[original_len..]);
+        if crc.finalize() != self.crc32 {
+            output.truncate(original_len);
+            return Err(BlockError::Crc32Mismatch);
+        }
+        Ok(())
+    }
+}
+
+fn skip_zero_terminated(data: &[u8], pos: usize) -> Result<usize, BlockError> {
+    data.get(pos..)
+        .and_then(|x| x.iter().position(|y| *y == 0))
+        .map(|x| pos + x + 1)
+        .ok_or(BlockError::Truncated)
+}
+
+fn read_le_u16(data: &[u8], pos: usize) -> Result<u16, BlockError> {
+    data.get(pos..(pos + 2))
+        .map(|x| u16::from_le_bytes(x.try_into().unwrap()))
+        .ok_or(BlockError::Truncated)
+}
+
+/// Parse a BGZF block at the beginning of `data`.
+///
+/// `data` may continue after the block. Use [`Block::block_size`] to find the next block.
+pub fn split_block(data: &[u8]) -> Result<Block<'_>, BlockError> {
+    if data.len() < 10 {
+        return Err(BlockError::Truncated);
+    }
+    if data[0] != GZIP_ID1 || data[1] != GZIP_ID2 || data[2] != DEFLATE {
+        return Err(BlockError::NotGzip);
+    }
+    let flags = data[3];
+    if flags & FLAG_FEXTRA == 0 {
+        return Err(BlockError::NotBGZF);
+    }
+
+    let extra_field_len: usize = read_le_u16(data, 10)?.into();
+    let extra_field = data
+        .get(12..(12 + extra_field_len))
+        .ok_or(BlockError::Truncated)?;
+    let mut block_size = None;
+    let mut field_pos = 0;
+    while field_pos + 4 <= extra_field.len() {
+        let field_len: usize = read_le_u16(extra_field, field_pos + 2)?.into();
+        if extra_field[field_pos] == 66 && extra_field[field_pos + 1] == 67 && field_len == 2 {
+            block_size = Some(usize::from(read_le_u16(extra_field, field_pos + 4)?) + 1);
+        }
+        field_pos += 4 + field_len;
+    }
+    let block_size = block_size.ok_or(BlockError::NotBGZF)?;
+
+    let mut header_size = 12 + extra_field_len;
+    if flags & FLAG_FNAME != 0 {
+        header_size = skip_zero_terminated(data, header_size)?;
+    }
+    if flags & FLAG_FCOMMENT != 0 {
+        header_size = skip_zero_terminated(data, header_size)?;
+    }
+    if flags & FLAG_FHCRC != 0 {
+        header_size += 2;
+    }
+
+    if block_size < header_size + 8 {
+        return Err(BlockError::InvalidBlockSize);
+    }
+    let block = data.get(..block_size).ok_or(BlockError::Truncated)?;
+    let footer = &block[(block_size - 8)..];
+    Ok(Block {
+        header_size,
+        block_size,
+        payload: &block[header_size..(block_size - 8)],
+        crc32: u32::from_le_bytes(footer[..4].try_into().unwrap()),
+        uncompressed_size: u32::from_le_bytes(footer[4..].try_into().unwrap()),
+    })
+}
+
+/// Decompress all blocks in `data` and return concatenated data.
+///
+/// Unlike [`crate::BGZFReader`], empty blocks in the middle of data are skipped.
+pub fn decompress_all(data: &[u8]) -> Result<Vec<u8>, BlockError> {
+    let mut result = Vec::new();
+    let mut offset = 0;
+    while offset < data.len() {
+        let block = split_block(&data[offset..])?;
+        block.decompress(&mut result)?;
+        offset += block.block_size;
+    }
+    Ok(result)
+}
+
+/// Decompress data between two BGZF virtual file offsets in `data`.
+///
+/// `begin` is inclusive and `end` is exclusive. Only blocks in the region are decompressed.
+/// Virtual file offsets can be obtained from tabix, CSI or BAI index, e.g. [`crate::tabix::TabixChunk`].
+/// Decompression stops at the end of `data` if `end` points beyond the end of data.
+pub fn decompress_region(
+    data: &[u8],
+    begin: VirtualPosition,
+    end: VirtualPosition,
+) -> Result<Vec<u8>, BlockError> {
+    if begin > end {
+        return Err(BlockError::InvalidPosition);
+    }
+    let end_block: usize = end
+        .compressed_offset()
+        .try_into()
+        .map_err(|_| BlockError::InvalidPosition)?;
+    let end_position_in_block: usize = end.uncompressed_offset_in_block().into();
+    let mut offset: usize = begin
+        .compressed_offset()
+        .try_into()
+        .map_err(|_| BlockError::InvalidPosition)?;
+    let mut position_in_block: usize = begin.uncompressed_offset_in_block().into();
+    if offset > data.len() {
+        return Err(BlockError::InvalidPosition);
+    }
+
+    let mut result = Vec::new();
+    let mut buffer = Vec::new();
+    while offset < data.len()
+        && (offset < end_block || (offset == end_block && end_position_in_block > 0))
+    {
+        let block = split_block(&data[offset..])?;
+        buffer.clear();
+        block.decompress(&mut buffer)?;
+        let block_end = if offset == end_block {
+            end_position_in_block
+        } else {
+            buffer.len()
+        };
+        if position_in_block > block_end || block_end > buffer.len() {
+            return Err(BlockError::InvalidPosition);
+        }
+        result.extend_from_slice(&buffer[position_in_block..block_end]);
+        position_in_block = 0;
+        offset += block.block_size;
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_split_block() -> anyhow::Result<()> {
+        let data = std::fs::read("testfiles/generated.bed.gz")?;
+        let mut expected_data = Vec::new();
+        flate2::read::MultiGzDecoder::new(&data[..]).read_to_end(&mut expected_data)?;
+
+        let mut decompressed = Vec::new();
+        let mut pos = 0;
+        for expected in crate::read::RawBlocks::new(&data[..]) {
+            let expected = expected?;
+            let block = split_block(&data[pos..])?;
+            assert_eq!(pos as u64, expected.compressed_offset);
+            assert_eq!(block.block_size as u64, expected.compressed_size());
+            assert_eq!(block.header_size as u64, expected.header.header_size());
+            assert_eq!(block.payload, expected.payload());
+            assert_eq!(block.crc32, expected.crc32());
+            block.decompress(&mut decompressed)?;
+            pos += block.block_size;
+        }
+        assert_eq!(pos, data.len());
+        assert_eq!(decompressed, expected_data);
+
+        // header with comment
+        let mut data = Vec::new();
+        let mut writer = crate::BGZFWriter::new(&mut data, crate::Compression::default())
+            .with_file_comment(b"comment")?;
+        std::io::Write::write_all(&mut writer, b"Hello, world")?;
+        writer.close()?;
+        let block = split_block(&data)?;
+        let mut decompressed = Vec::new();
+        block.decompress(&mut decompressed)?;
+        assert_eq!(decompressed, b"Hello, world");
+        assert_eq!(
+            split_block(&data[block.block_size..])?.block_size,
+            crate::EOF_MARKER.len()
+        );
+
+        // broken data
+        assert_eq!(
+            split_block(&data[..(block.block_size - 1)]),
+            Err(BlockError::Truncated)
+        );
+        assert_eq!(split_block(b"not gzip data"), Err(BlockError::NotGzip));
+        let mut broken = data.clone();
+        broken[block.block_size - 8] ^= 0xff;
+        assert_eq!(
+            split_block(&broken)?.decompress(&mut decompressed),
+            Err(BlockError::Crc32Mismatch)
+        );
+        assert_eq!(decompressed, b"Hello, world");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_decompress_region() -> anyhow::Result<()> {
+        let data = std::fs::read("testfiles/common_all_20180418_half.vcf.gz")?;
+        let mut expected_data = Vec::new();
+        flate2::read::MultiGzDecoder::new(&data[..]).read_to_end(&mut expected_data)?;
+        assert_eq!(decompress_all(&data)?, expected_data);
+
+        let mut reader = crate::BGZFReader::new(std::io::Cursor::new(&data))?;
+        let mut expected = Vec::new();
+        let index = crate::index::BGZFIndex::from_reader(std::fs::File::open(
+            "testfiles/common_all_20180418_half.vcf.gz.gzi",
+        )?)?;
+        let block6 = index.entries()[6].compressed_offset;
+        let block12 = index.entries()[12].compressed_offset;
+        let last_block = index.entries().last().unwrap().compressed_offset;
+        for (begin, end) in [
+            ((0, 0), (64251, 65074)),
+            ((64251, 65074), (64251, 65074)),
+            ((64251, 65074), (64251, 65075)),
+            ((64251, 65074), (block6, 100)),
+            ((0, 20), (block12, 0)),
+            ((block6, 30), (block12, 10)),
+            ((last_block, 10), (last_block + 1_000_000, 0)),
+        ] {
+            let begin = VirtualPosition::new(begin.0, begin.1);
+            let end = VirtualPosition::new(end.0, end.1);
+            let result = decompress_region(&data, begin, end)?;
+            expected.clear();
+            crate::read::copy_region(std::io::Cursor::new(&data), begin, end, &mut expected)?;
+            assert_eq!(result, expected);
+            reader.bgzf_seek(begin)?;
+            let mut head = vec![0; result.len().min(100)];
+            reader.read_exact(&mut head)?;
+            assert!(result.starts_with(&head));
+        }
+
+        assert_eq!(
+            decompress_region(&data, 4210818611.into(), 4210818610.into()),
+            Err(BlockError::InvalidPosition)
+        );
+        assert_eq!(
+            decompress_region(
+                &data,
+                VirtualPosition::new(1, 0),
+                VirtualPosition::new(2, 0)
+            ),
+            Err(BlockError::NotGzip)
+        );
+
+        Ok(())
+    }
+}
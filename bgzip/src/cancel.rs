@@ -0,0 +1,44 @@
+//! Cancellation of multi-thread readers and writers
+
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Token to cancel [`crate::read::BGZFMultiThreadReader`] and [`crate::write::BGZFMultiThreadWriter`].
+///
+/// Clones of a token share the same state, so a token can be cancelled from another thread,
+/// e.g. when a client of a server disconnects. After cancellation, tasks in the thread pool stop
+/// processing remaining blocks, and the reader or writer returns [`crate::BGZFError::Cancelled`].
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Create new token which is not cancelled
+    pub fn new() -> Self {
+        CancellationToken::default()
+    }
+
+    /// Cancel all readers and writers sharing this token
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Return `true` if this token is cancelled
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Error returned after cancellation.
+///
+/// [`io::ErrorKind::Interrupted`] is returned only once, because [`std::io::Read::read_to_end`]
+/// and similar functions retry interrupted operations. Following calls return [`io::ErrorKind::Other`].
+pub(crate) fn cancelled_error(reported: &mut bool) -> io::Error {
+    let kind = if *reported {
+        io::ErrorKind::Other
+    } else {
+        io::ErrorKind::Interrupted
+    };
+    *reported = true;
+    io::Error::new(kind, crate::BGZFError::Cancelled)
+}
@@ -0,0 +1,370 @@
+//! Integrity check of BGZF data
+//!
+//! [`validate`] walks all blocks and reports which block is broken, instead of failing with
+//! an I/O error at the first broken block like [`crate::BGZFReader`]. `par_validate` verifies
+//! blocks in parallel with rayon.
+//!
+//! ```rust
+//! let report = bgzip::check::validate(std::fs::File::open("testfiles/generated.bed.gz")?)?;
+//! assert!(report.is_valid());
+//! assert!(report.has_eof_marker);
+//! # Ok::<(), bgzip::BGZFError>(())
+//! ```
+
+use crate::deflate::{Crc, Decompress};
+use crate::header::BGZFHeader;
+use crate::BGZFError;
+use std::convert::TryInto;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read};
+
+/// Problem found in a block by [`validate`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlockError {
+    /// The header cannot be parsed, BC subfield is missing or BSIZE is too small
+    InvalidHeader(String),
+    /// Data ends in the middle of the block
+    Truncated,
+    /// Compressed data is not a valid deflate stream
+    InvalidDeflate(String),
+    /// CRC32 of decompressed data does not match the footer
+    Crc32Mismatch { expected: u32, actual: u32 },
+    /// Length of decompressed data does not match ISIZE in the footer
+    SizeMismatch { expected: u32, actual: u32 },
+}
+
+impl fmt::Display for BlockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlockError::InvalidHeader(e) => write!(f, "Invalid header: {}", e),
+            BlockError::Truncated => write!(f, "Truncated block"),
+            BlockError::InvalidDeflate(e) => write!(f, "Invalid deflate stream: {}", e),
+            BlockError::Crc32Mismatch { expected, actual } => write!(
+                f,
+                "Unmatched CRC32 (expected: {:08x}, actual: {:08x})",
+                expected, actual
+            ),
+            BlockError::SizeMismatch { expected, actual } => write!(
+                f,
+                "Unmatched ISIZE (expected: {}, actual: {})",
+                expected, actual
+            ),
+        }
+    }
+}
+
+/// Result of one block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockReport {
+    /// File offset of the block
+    pub compressed_offset: u64,
+    /// Uncompressed position of the first byte of the block calculated from ISIZE of preceding blocks
+    pub uncompressed_offset: u64,
+    /// Size of the block including header and footer. `0` if the header cannot be parsed.
+    pub compressed_size: u64,
+    /// ISIZE in the footer. `0` if the footer is not available.
+    pub uncompressed_size: u64,
+    /// `None` if the block is valid
+    pub error: Option<BlockError>,
+}
+
+/// Result of [`validate`]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ValidationReport {
+    /// All blocks in file order. Walk stops at the first block whose size is unknown.
+    pub blocks: Vec<BlockReport>,
+    /// The last block is an end-of-file marker
+    pub has_eof_marker: bool,
+}
+
+impl ValidationReport {
+    /// `true` if all blocks are valid and the data ends with an end-of-file marker
+    pub fn is_valid(&self) -> bool {
+        self.has_eof_marker && self.errors().next().is_none()
+    }
+
+    /// Blocks with errors
+    pub fn errors(&self) -> impl Iterator<Item = &BlockReport> {
+        self.blocks.iter().filter(|x| x.error.is_some())
+    }
+}
+
+/// Walk all blocks in `reader` and verify header structure, CRC32 and ISIZE of each block,
+/// and presence of an end-of-file marker.
+///
+/// Broken blocks are recorded in the report and the walk continues, unless the size of the
+/// block is unknown because of a broken header or the data is truncated. Only I/O errors
+/// except unexpected end of file are returned as [`BGZFError`].
+pub fn validate<R: Read>(reader: R) -> Result<ValidationReport, BGZFError> {
+    let mut decompress = Decompress::new();
+    let mut decompressed = vec![0u8; crate::write::MAXIMUM_COMPRESS_UNIT_SIZE];
+    let blocks = LoadedBlocks::new(reader)
+        .map(|block| Ok(verify_block(block?, &mut decompress, &mut decompressed)))
+        .collect::<Result<Vec<_>, BGZFError>>()?;
+    Ok(ValidationReport::from_blocks(blocks))
+}
+
+/// Verify blocks in `reader` like [`validate`] with rayon.
+///
+/// Blocks are loaded sequentially and verified in the rayon thread pool. The returned report is
+/// the same as [`validate`].
+#[cfg(feature = "rayon")]
+pub fn par_validate<R: Read + Send>(reader: R) -> Result<ValidationReport, BGZFError> {
+    use rayon::prelude::*;
+
+    let mut blocks = LoadedBlocks::new(reader)
+        .par_bridge()
+        .map_init(
+            || {
+                (
+                    Decompress::new(),
+                    vec![0u8; crate::write::MAXIMUM_COMPRESS_UNIT_SIZE],
+                )
+            },
+            |(decompress, decompressed), block| Ok(verify_block(block?, decompress, decompressed)),
+        )
+        .collect::<Result<Vec<_>, BGZFError>>()?;
+    blocks.sort_by_key(|x| x.0.compressed_offset);
+    Ok(ValidationReport::from_blocks(blocks))
+}
+
+impl ValidationReport {
+    /// Create a report from verified blocks in file order
+    fn from_blocks(blocks: Vec<(BlockReport, bool)>) -> Self {
+        ValidationReport {
+            has_eof_marker: blocks.last().map(|x| x.1).unwrap_or(false),
+            blocks: blocks.into_iter().map(|x| x.0).collect(),
+        }
+    }
+}
+
+/// Block loaded by [`LoadedBlocks`]
+struct LoadedBlock {
+    report: BlockReport,
+    /// Compressed data, CRC32 and ISIZE. `None` if the block is broken before decompression.
+    data: Option<Vec<u8>>,
+}
+
+/// Iterator of blocks in a reader, which stops after a block whose size is unknown
+struct LoadedBlocks<R: Read> {
+    reader: BufReader<R>,
+    compressed_offset: u64,
+    uncompressed_offset: u64,
+    finished: bool,
+}
+
+impl<R: Read> LoadedBlocks<R> {
+    fn new(reader: R) -> Self {
+        LoadedBlocks {
+            reader: BufReader::new(reader),
+            compressed_offset: 0,
+            uncompressed_offset: 0,
+            finished: false,
+        }
+    }
+
+    fn load_next(&mut self) -> Result<Option<LoadedBlock>, BGZFError> {
+        if self.finished || self.reader.fill_buf()?.is_empty() {
+            return Ok(None);
+        }
+        let mut block = BlockReport {
+            compressed_offset: self.compressed_offset,
+            uncompressed_offset: self.uncompressed_offset,
+            compressed_size: 0,
+            uncompressed_size: 0,
+            error: None,
+        };
+        // the walk stops at a block whose size is unknown
+        self.finished = true;
+
+        let header = match BGZFHeader::from_reader(&mut self.reader) {
+            Ok(header) => header,
+            Err(BGZFError::IoError(e)) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                block.error = Some(BlockError::Truncated);
+                return Ok(Some(LoadedBlock {
+                    report: block,
+                    data: None,
+                }));
+            }
+            Err(BGZFError::IoError(e)) => return Err(e.into()),
+            Err(e) => {
+                block.error = Some(BlockError::InvalidHeader(e.to_string()));
+                return Ok(Some(LoadedBlock {
+                    report: block,
+                    data: None,
+                }));
+            }
+        };
+        let data_size = match header.block_size() {
+            Ok(x) => u64::from(x).checked_sub(header.header_size()),
+            Err(e) => {
+                block.error = Some(BlockError::InvalidHeader(e.to_string()));
+                return Ok(Some(LoadedBlock {
+                    report: block,
+                    data: None,
+                }));
+            }
+        };
+        let data_size = match data_size {
+            Some(x) if x >= 8 => x,
+            _ => {
+                block.error = Some(BlockError::InvalidHeader(
+                    "BSIZE is smaller than the header and the footer".to_string(),
+                ));
+                return Ok(Some(LoadedBlock {
+                    report: block,
+                    data: None,
+                }));
+            }
+        };
+
+        let mut buffer = Vec::new();
+        let read_bytes = (&mut self.reader)
+            .take(data_size)
+            .read_to_end(&mut buffer)?;
+        if TryInto::<u64>::try_into(read_bytes).unwrap() < data_size {
+            block.error = Some(BlockError::Truncated);
+            return Ok(Some(LoadedBlock {
+                report: block,
+                data: None,
+            }));
+        }
+        block.compressed_size = header.header_size() + data_size;
+        let footer = &buffer[(buffer.len() - 8)..];
+        block.uncompressed_size = u32::from_le_bytes(footer[4..].try_into().unwrap()).into();
+
+        self.finished = false;
+        self.compressed_offset += block.compressed_size;
+        self.uncompressed_offset += block.uncompressed_size;
+        Ok(Some(LoadedBlock {
+            report: block,
+            data: Some(buffer),
+        }))
+    }
+}
+
+impl<R: Read> Iterator for LoadedBlocks<R> {
+    type Item = Result<LoadedBlock, BGZFError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.load_next().transpose()
+    }
+}
+
+/// Decompress `block` and verify CRC32 and ISIZE. `true` is returned with the report if the block
+/// is a valid end-of-file marker.
+fn verify_block(
+    block: LoadedBlock,
+    decompress: &mut Decompress,
+    decompressed: &mut [u8],
+) -> (BlockReport, bool) {
+    let mut report = block.report;
+    let Some(buffer) = block.data else {
+        return (report, false);
+    };
+    let footer = &buffer[(buffer.len() - 8)..];
+    let expected_crc = u32::from_le_bytes(footer[..4].try_into().unwrap());
+    let expected_size = u32::from_le_bytes(footer[4..].try_into().unwrap());
+
+    report.error = match decompress.decompress(&buffer[..(buffer.len() - 8)], decompressed) {
+        Ok(len) => {
+            let mut crc = Crc::new();
+            crc.update(&decompressed[..len]);
+            let actual_size: u32 = len.try_into().unwrap();
+            if actual_size != expected_size {
+                Some(BlockError::SizeMismatch {
+                    expected: expected_size,
+                    actual: actual_size,
+                })
+            } else if crc.sum() != expected_crc {
+                Some(BlockError::Crc32Mismatch {
+                    expected: expected_crc,
+                    actual: crc.sum(),
+                })
+            } else {
+                None
+            }
+        }
+        Err(e) => Some(BlockError::InvalidDeflate(e.to_string())),
+    };
+    let is_eof_marker = report.error.is_none() && buffer == crate::read::EOF_BLOCK;
+    (report, is_eof_marker)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs::File;
+
+    /// Run [`validate`], and check that [`par_validate`] returns the same report
+    fn validate(data: &[u8]) -> Result<ValidationReport, BGZFError> {
+        let report = super::validate(data)?;
+        #[cfg(feature = "rayon")]
+        assert_eq!(par_validate(data)?, report);
+        Ok(report)
+    }
+
+    #[test]
+    fn test_validate() -> anyhow::Result<()> {
+        let mut data = Vec::new();
+        File::open("testfiles/generated.bed.gz")?.read_to_end(&mut data)?;
+        let report = validate(&data[..])?;
+        assert!(report.is_valid());
+        let blocks: Vec<_> = crate::read::RawBlocks::new(&data[..]).collect::<Result<_, _>>()?;
+        assert_eq!(report.blocks.len(), blocks.len());
+        for (one, expected) in report.blocks.iter().zip(blocks.iter()) {
+            assert_eq!(one.compressed_offset, expected.compressed_offset);
+            assert_eq!(one.uncompressed_offset, expected.uncompressed_offset);
+            assert_eq!(one.compressed_size, expected.compressed_size());
+            assert_eq!(one.uncompressed_size, expected.uncompressed_size());
+        }
+
+        // broken CRC32 and deflate stream
+        let mut broken = data.clone();
+        let crc_pos = blocks[3].compressed_offset + blocks[3].compressed_size() - 8;
+        broken[crc_pos as usize] ^= 0xff;
+        let deflate_pos = blocks[5].compressed_offset + blocks[5].header.header_size();
+        broken[deflate_pos as usize] = 0xff;
+        let report = validate(&broken[..])?;
+        assert!(!report.is_valid());
+        assert!(report.has_eof_marker);
+        assert_eq!(report.blocks.len(), blocks.len());
+        let errors: Vec<_> = report.errors().collect();
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].compressed_offset, blocks[3].compressed_offset);
+        assert!(matches!(
+            errors[0].error,
+            Some(BlockError::Crc32Mismatch { .. })
+        ));
+        assert_eq!(errors[1].compressed_offset, blocks[5].compressed_offset);
+        assert!(matches!(
+            errors[1].error,
+            Some(BlockError::InvalidDeflate(_)) | Some(BlockError::SizeMismatch { .. })
+        ));
+
+        // missing end-of-file marker
+        let without_eof = &data[..(data.len() - crate::EOF_MARKER.len())];
+        let report = validate(without_eof)?;
+        assert!(!report.has_eof_marker);
+        assert!(!report.is_valid());
+        assert_eq!(report.errors().count(), 0);
+
+        // truncated data
+        let report = validate(&data[..(blocks[2].compressed_offset as usize + 100)])?;
+        assert_eq!(report.blocks.len(), 3);
+        assert_eq!(report.blocks[2].error, Some(BlockError::Truncated));
+        assert!(!report.has_eof_marker);
+
+        // broken header
+        let mut broken = data.clone();
+        broken[blocks[1].compressed_offset as usize] = 0;
+        let report = validate(&broken[..])?;
+        assert_eq!(report.blocks.len(), 2);
+        assert!(matches!(
+            report.blocks[1].error,
+            Some(BlockError::InvalidHeader(_))
+        ));
+
+        Ok(())
+    }
+}
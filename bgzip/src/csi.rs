@@ -1,43 +1,481 @@
+//! Binning scheme of CSI and tabix index
+//!
+//! Binning index divides a sequence into hierarchical bins. The root bin (bin 0) covers
+//! whole sequence, and each bin is split into 8 child bins at the next level.
+//!
+//! * `min_shift`: Bins at the lowest level span `1 << min_shift` bases.
+//! * `depth`: Number of levels below the root bin. Bins at level `l` span `1 << (min_shift + (depth - l) * 3)` bases.
+//!
+//! Positions must be smaller than `1 << (min_shift + depth * 3)` (see [`max_position`]).
+//! Tabix and BAI use `min_shift = 14` and `depth = 5`, which covers 512M bases.
+//! CSI can use larger `depth` to support longer sequences.
+//!
+//! All coordinates are zero-based and half-close-half-open.
+//!
+//! [`CSIIndex`] reads and writes `.csi` index files.
+
+use crate::bai::BAIMetadata;
+use crate::tabix::TabixChunk;
+use crate::{BGZFError, BinaryReader, VirtualPosition};
+use std::collections::HashMap;
 use std::convert::TryInto;
+use std::io::{self, Read, Write};
+
+/// `min_shift` of tabix and BAI index
+pub const TABIX_MIN_SHIFT: u32 = 14;
+/// `depth` of tabix and BAI index
+pub const TABIX_DEPTH: u32 = 5;
+
+/// Return the first position that can not be indexed with `min_shift` and `depth`.
+///
+/// An error is returned if bins cannot be represented with `u32` or the position does not fit into `u64`.
+pub fn max_position(min_shift: u32, depth: u32) -> Result<u64, BGZFError> {
+    bin_count(depth)?;
+    let shift = depth
+        .checked_mul(3)
+        .and_then(|x| x.checked_add(min_shift))
+        .filter(|x| *x < 64)
+        .ok_or(BGZFError::Other("Too large min_shift or depth"))?;
+    Ok(1 << shift)
+}
+
+/// Total number of bins with `depth`.
+pub fn bin_count(depth: u32) -> Result<u32, BGZFError> {
+    depth
+        .checked_add(1)
+        .and_then(|x| x.checked_mul(3))
+        .filter(|x| *x < 64)
+        .and_then(|x| (((1u64 << x) - 1) / 7).try_into().ok())
+        .ok_or(BGZFError::Other("Too large depth"))
+}
+
+/// Return the parent bin of `bin`. `None` is returned for the root bin.
+pub fn parent_bin(bin: u32) -> Option<u32> {
+    if bin == 0 {
+        None
+    } else {
+        Some((bin - 1) >> 3)
+    }
+}
 
 /// calculate bin given an alignment covering [beg,end) (zero-based, half-close-half-open)
-pub fn reg2bin(beg: i64, end: i64, min_shift: u32, depth: u32) -> u32 {
-    let end = end - 1;
+///
+/// Region which is out of range of `min_shift` and `depth` is assigned to the root bin.
+pub fn reg2bin(beg: u64, end: u64, min_shift: u32, depth: u32) -> Result<u32, BGZFError> {
+    let max_position = max_position(min_shift, depth)?;
+    if beg >= max_position || end > max_position {
+        return Ok(0);
+    }
+    let end = end.saturating_sub(1);
     let mut s = min_shift;
-    let mut t = ((1 << (depth * 3)) - 1) / 7;
+    let mut t: u64 = ((1 << (depth * 3)) - 1) / 7;
 
-    for l2 in 0..depth {
-        //eprintln!("depth: {}", l2);
-        let l = depth - l2;
+    for l in (1..=depth).rev() {
         if beg >> s == end >> s {
-            //eprintln!("value: {}", (t + (beg >> s)));
-            return (t + (beg >> s)).try_into().unwrap();
+            return Ok((t + (beg >> s)).try_into().expect("Unreachable"));
         };
         s += 3;
-        //let t2 = t;
         t -= 1 << ((l - 1) * 3);
-        //eprintln!("t : {} -> {} / {} / {}", t2, t, l, 1 << (l * 3));
     }
 
-    0
+    Ok(0)
 }
 
 /// calculate the list of bins that may overlap with region [beg,end) (zero-based)
-pub fn reg2bins(beg: i64, end: i64, min_shift: u32, depth: u32) -> Vec<u32> {
+///
+/// `end` is truncated to [`max_position`]. Only the root bin is returned if `beg` is out of range.
+pub fn reg2bins(beg: u64, end: u64, min_shift: u32, depth: u32) -> Result<Vec<u32>, BGZFError> {
+    let max_position = max_position(min_shift, depth)?;
+    if beg >= max_position {
+        return Ok(vec![0]);
+    }
     let mut bins: Vec<u32> = Vec::new();
-    let end = end - 1;
+    let end = end.min(max_position).saturating_sub(1);
     let mut s = min_shift + depth * 3;
-    let mut t = 0;
+    let mut t: u64 = 0;
 
     for l in 0..=depth {
         let b = t + (beg >> s);
         let e = t + (end >> s);
         for i in b..=e {
-            bins.push(i.try_into().unwrap());
+            bins.push(i.try_into().expect("Unreachable"));
         }
         s -= 3;
         t += 1 << (l * 3);
     }
 
-    bins
+    Ok(bins)
+}
+
+/// Magic number of CSI file
+pub const CSI_MAGIC: [u8; 4] = [b'C', b'S', b'I', 1];
+
+/// Size of tabix compatible header in auxiliary data, excluding sequence names
+const TABIX_AUX_HEADER_SIZE: usize = 28;
+
+/// A bin of CSI index
+#[derive(Debug, Clone, PartialEq)]
+pub struct CSIBin {
+    pub bin: u32,
+    /// Virtual file offset of the first record overlapped with this bin
+    pub loffset: VirtualPosition,
+    pub chunks: Vec<TabixChunk>,
+}
+
+impl CSIBin {
+    fn from_reader<R: Read + BinaryReader>(reader: &mut R) -> io::Result<Self> {
+        let bin = reader.read_le_u32()?;
+        let loffset = reader.read_le_u64()?.into();
+        let number_of_chunks = reader.read_le_i32()?;
+        let mut chunks = Vec::new();
+        for _ in 0..number_of_chunks {
+            chunks.push(TabixChunk::from_reader(reader)?);
+        }
+        Ok(CSIBin {
+            bin,
+            loffset,
+            chunks,
+        })
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let number_of_chunks: i32 = self.chunks.len().try_into().unwrap();
+        writer.write_all(&self.bin.to_le_bytes())?;
+        writer.write_all(&u64::from(self.loffset).to_le_bytes())?;
+        writer.write_all(&number_of_chunks.to_le_bytes())?;
+        for one in &self.chunks {
+            one.write(writer)?;
+        }
+        Ok(())
+    }
+}
+
+/// Bins of a reference sequence. A pseudo bin is included in `bins`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct CSISequence {
+    pub bins: HashMap<u32, CSIBin>,
+}
+
+impl CSISequence {
+    fn from_reader<R: Read + BinaryReader>(reader: &mut R) -> io::Result<Self> {
+        let number_of_bins = reader.read_le_i32()?;
+        let mut bins = HashMap::new();
+        for _ in 0..number_of_bins {
+            let one_bin = CSIBin::from_reader(reader)?;
+            bins.insert(one_bin.bin, one_bin);
+        }
+        Ok(CSISequence { bins })
+    }
+
+    /// Write bins sorted by bin number.
+    fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let number_of_bins: i32 = self.bins.len().try_into().unwrap();
+        writer.write_all(&number_of_bins.to_le_bytes())?;
+        let mut bins: Vec<_> = self.bins.values().collect();
+        bins.sort_by_key(|x| x.bin);
+        for one in bins {
+            one.write(writer)?;
+        }
+        Ok(())
+    }
+}
+
+/// Represents .csi index file
+#[derive(Debug, Clone, PartialEq)]
+pub struct CSIIndex {
+    pub min_shift: u32,
+    pub depth: u32,
+    /// Auxiliary data. Tabix compatible header and sequence names are stored for text formats.
+    pub aux: Vec<u8>,
+    pub sequences: Vec<CSISequence>,
+    pub number_of_unplaced_unmapped: Option<u64>,
+}
+
+impl CSIIndex {
+    /// Load BGZF compressed .csi index file from `reader`
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self, BGZFError> {
+        let mut reader = io::BufReader::new(crate::read::BGZFReader::new(reader)?);
+        let mut buf = [0u8; 4];
+        reader.read_exact(&mut buf)?;
+        if buf != CSI_MAGIC {
+            return Err(BGZFError::Other("Not CSI format"));
+        }
+        let min_shift: u32 = reader
+            .read_le_i32()?
+            .try_into()
+            .map_err(|_| BGZFError::Other("Invalid min_shift"))?;
+        let depth: u32 = reader
+            .read_le_i32()?
+            .try_into()
+            .map_err(|_| BGZFError::Other("Invalid depth"))?;
+        max_position(min_shift, depth)?;
+        let aux_len: usize = reader
+            .read_le_i32()?
+            .try_into()
+            .map_err(|_| BGZFError::Other("Invalid length of auxiliary data"))?;
+        let mut aux = vec![0; aux_len];
+        reader.read_exact(&mut aux)?;
+
+        let number_of_references = reader.read_le_i32()?;
+        let mut sequences = Vec::new();
+        for _ in 0..number_of_references {
+            sequences.push(CSISequence::from_reader(&mut reader)?);
+        }
+        let number_of_unplaced_unmapped = match reader.read_le_u64() {
+            Ok(x) => Some(x),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => None,
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(CSIIndex {
+            min_shift,
+            depth,
+            aux,
+            sequences,
+            number_of_unplaced_unmapped,
+        })
+    }
+
+    /// Write .csi index file into `writer` in BGZF format
+    pub fn write<W: Write>(&self, writer: W) -> io::Result<()> {
+        let mut writer = crate::BGZFWriter::new(writer, crate::Compression::default());
+        writer.write_all(&CSI_MAGIC)?;
+        for one in [self.min_shift, self.depth] {
+            let value: i32 = one.try_into().unwrap();
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        let aux_len: i32 = self.aux.len().try_into().unwrap();
+        writer.write_all(&aux_len.to_le_bytes())?;
+        writer.write_all(&self.aux)?;
+        let number_of_references: i32 = self.sequences.len().try_into().unwrap();
+        writer.write_all(&number_of_references.to_le_bytes())?;
+        for one in &self.sequences {
+            one.write(&mut writer)?;
+        }
+        if let Some(unplaced) = self.number_of_unplaced_unmapped {
+            writer.write_all(&unplaced.to_le_bytes())?;
+        }
+        writer.close()?;
+        Ok(())
+    }
+
+    /// Bin number of the pseudo bin which stores metadata of a reference sequence
+    pub fn pseudo_bin(&self) -> Result<u32, BGZFError> {
+        Ok(bin_count(self.depth)? + 1)
+    }
+
+    /// Get metadata of a reference sequence stored in the pseudo bin
+    pub fn metadata(&self, reference_id: usize) -> Option<BAIMetadata> {
+        let bin = self
+            .sequences
+            .get(reference_id)?
+            .bins
+            .get(&self.pseudo_bin().ok()?)?;
+        if bin.chunks.len() != 2 {
+            return None;
+        }
+        Some(BAIMetadata {
+            begin: bin.chunks[0].begin,
+            end: bin.chunks[0].end,
+            mapped: bin.chunks[1].begin.into(),
+            unmapped: bin.chunks[1].end.into(),
+        })
+    }
+
+    /// Sequence names stored in tabix compatible auxiliary data. Each name has a trailing NUL character.
+    ///
+    /// `None` is returned if auxiliary data is too short.
+    pub fn names(&self) -> Option<Vec<Vec<u8>>> {
+        let header = self.aux.get(..TABIX_AUX_HEADER_SIZE)?;
+        let names_len: usize = i32::from_le_bytes(header[24..28].try_into().unwrap())
+            .try_into()
+            .ok()?;
+        let names = self
+            .aux
+            .get(TABIX_AUX_HEADER_SIZE..TABIX_AUX_HEADER_SIZE.checked_add(names_len)?)?;
+        Some(crate::tabix::split_names(names))
+    }
+
+    /// Find index of sequence `name` in auxiliary data. `name` should not contain a trailing NUL character.
+    pub fn sequence_index(&self, name: &[u8]) -> Option<usize> {
+        self.names()?
+            .iter()
+            .position(|x| x.strip_suffix(&[0]).unwrap_or(x) == name)
+    }
+
+    /// List chunks which may contain records overlapped with region [begin,end) (zero-based) of `reference_id`.
+    ///
+    /// Chunks which end before `loffset` of the smallest bin containing `begin` are skipped.
+    /// Returned chunks are sorted and merged.
+    pub fn query(
+        &self,
+        reference_id: usize,
+        begin: u64,
+        end: u64,
+    ) -> Result<Vec<TabixChunk>, BGZFError> {
+        let sequence = match self.sequences.get(reference_id) {
+            Some(x) => x,
+            None => return Ok(Vec::new()),
+        };
+        let mut bin = Some(reg2bin(
+            begin,
+            begin.saturating_add(1),
+            self.min_shift,
+            self.depth,
+        )?);
+        let mut min_offset = VirtualPosition::default();
+        while let Some(current) = bin {
+            if let Some(found) = sequence.bins.get(&current) {
+                min_offset = found.loffset;
+                break;
+            }
+            bin = parent_bin(current);
+        }
+
+        let chunks: Vec<TabixChunk> = reg2bins(begin, end, self.min_shift, self.depth)?
+            .iter()
+            .filter_map(|x| sequence.bins.get(x))
+            .flat_map(|x| x.chunks.iter())
+            .filter(|x| x.end > min_offset)
+            .cloned()
+            .collect();
+        Ok(crate::tabix::merge_chunks(chunks))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_bin_math() -> anyhow::Result<()> {
+        assert_eq!(max_position(TABIX_MIN_SHIFT, TABIX_DEPTH)?, 1 << 29);
+        assert_eq!(bin_count(TABIX_DEPTH)?, 37449);
+        assert_eq!(bin_count(10)?, 1227133513);
+        assert!(bin_count(11).is_err());
+        assert!(max_position(40, 8).is_err());
+        assert!(reg2bin(0, 1, 14, 11).is_err());
+        assert!(reg2bins(0, 1, 14, 11).is_err());
+
+        assert_eq!(parent_bin(0), None);
+        assert_eq!(parent_bin(4681), Some(585));
+        assert_eq!(parent_bin(585), Some(73));
+        assert_eq!(parent_bin(1), Some(0));
+
+        // large chromosome with CSI
+        let beg = 3_000_000_000;
+        let bin = reg2bin(beg, beg + 10, 14, 6)?;
+        assert_eq!(bin, 37449 + (beg >> 14) as u32);
+        let bins = reg2bins(beg, beg + 10, 14, 6)?;
+        assert_eq!(bins.len(), 7);
+        assert_eq!(*bins.last().unwrap(), bin);
+        let mut bin = bin;
+        for one in bins.iter().rev().skip(1) {
+            bin = parent_bin(bin).unwrap();
+            assert_eq!(*one, bin);
+        }
+
+        // out of range
+        assert_eq!(reg2bin(1 << 29, (1 << 29) + 1, 14, 5)?, 0);
+        assert_eq!(reg2bins(0, u64::MAX, 14, 5)?.len(), 37449);
+        assert_eq!(reg2bins(1 << 29, (1 << 29) + 1, 14, 5)?, vec![0]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_csi_read_write() -> anyhow::Result<()> {
+        let index = CSIIndex::from_reader(std::fs::File::open(
+            "testfiles/common_all_20180418_half.vcf.gz.csi",
+        )?)?;
+        assert_eq!(index.min_shift, 14);
+        assert_eq!(index.depth, 6);
+        assert_eq!(index.pseudo_bin()?, 299594);
+        let tabix = crate::tabix::Tabix::from_reader(std::fs::File::open(
+            "testfiles/common_all_20180418_half.vcf.gz.tbi",
+        )?)?;
+        assert_eq!(index.names(), Some(tabix.names.clone()));
+        assert_eq!(index.sequences.len(), tabix.sequences.len());
+        assert_eq!(index.sequence_index(b"3"), tabix.sequence_index(b"3"));
+        assert_eq!(index.sequence_index(b"unknown"), None);
+        let metadata = index.metadata(0).unwrap();
+        assert!(metadata.begin < metadata.end);
+        assert!(metadata.mapped > 0);
+
+        std::fs::create_dir_all("tmp")?;
+        index.write(std::fs::File::create(
+            "tmp/common_all_20180418_half.vcf.gz.csi",
+        )?)?;
+        let loaded = CSIIndex::from_reader(std::fs::File::open(
+            "tmp/common_all_20180418_half.vcf.gz.csi",
+        )?)?;
+        assert_eq!(loaded, index);
+
+        let mut data = Vec::new();
+        CSIIndex {
+            min_shift: 14,
+            depth: 7,
+            aux: Vec::new(),
+            sequences: vec![CSISequence::default()],
+            number_of_unplaced_unmapped: None,
+        }
+        .write(&mut data)?;
+        let loaded = CSIIndex::from_reader(&data[..])?;
+        assert_eq!(loaded.depth, 7);
+        assert_eq!(loaded.names(), None);
+        assert_eq!(loaded.query(0, 0, 100)?, vec![]);
+        assert_eq!(loaded.query(1, 0, 100)?, vec![]);
+
+        assert!(CSIIndex::from_reader(std::fs::File::open(
+            "testfiles/common_all_20180418_half.vcf.gz.tbi"
+        )?)
+        .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_csi_query() -> anyhow::Result<()> {
+        let path = "testfiles/common_all_20180418_half.vcf.gz";
+        let index = CSIIndex::from_reader(std::fs::File::open(format!("{}.csi", path))?)?;
+        let tabix =
+            crate::tabix::Tabix::from_reader(std::fs::File::open(format!("{}.tbi", path))?)?;
+        let mut reader = crate::tabix::TabixReader::new(
+            crate::BGZFReader::new(std::fs::File::open(path)?)?,
+            tabix.clone(),
+        );
+        let mut bgzf_reader = crate::BGZFReader::new(std::fs::File::open(path)?)?;
+
+        for region in [
+            crate::tabix::Region::new("1", 1_000_000, 1_500_000),
+            crate::tabix::Region::new("3", 10_342_311, 10_342_312),
+        ] {
+            let expected = reader.fetch(&region)?;
+            assert!(!expected.is_empty());
+
+            let reference_id = index.sequence_index(&region.sequence).unwrap();
+            let mut records = Vec::new();
+            for chunk in index.query(reference_id, region.begin, region.end)? {
+                bgzf_reader.bgzf_seek(chunk.begin)?;
+                while bgzf_reader.bgzf_pos() < chunk.end {
+                    let mut line = Vec::new();
+                    if io::BufRead::read_until(&mut bgzf_reader, b'\n', &mut line)? == 0 {
+                        break;
+                    }
+                    line.pop();
+                    if let Some(record) = tabix.record_region(&line)? {
+                        if record.sequence == &region.sequence[..]
+                            && record.begin < region.end
+                            && record.end > region.begin
+                        {
+                            records.push(line);
+                        }
+                    }
+                }
+            }
+            assert_eq!(records, expected);
+        }
+
+        Ok(())
+    }
 }
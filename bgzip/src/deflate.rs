@@ -3,83 +3,355 @@
 //! [libdeflater](https://crates.io/crates/libdeflater) or [flate2](https://crates.io/crates/flate2) is used to compress/decompress data.
 
 use std::convert::TryInto;
+use std::sync::Arc;
 use thiserror::Error;
 
-#[cfg(not(feature = "libdeflater"))]
-use flate2::Status;
-
-#[cfg(not(feature = "libdeflater"))]
+#[cfg(all(not(feature = "libdeflater"), not(feature = "crc32fast")))]
 pub use flate2::Crc;
 
-#[cfg(feature = "libdeflater")]
+#[cfg(all(feature = "libdeflater", not(feature = "crc32fast")))]
 pub use libdeflater::Crc;
 
+/// CRC32 checksum calculated with [crc32fast](https://crates.io/crates/crc32fast).
+///
+/// SSE4.2 and PCLMULQDQ on x86_64 or CRC32 instructions on ARMv8 are used if they are
+/// available at runtime. This checksum is used instead of DEFLATE backends' one if
+/// `crc32fast` feature is enabled.
+#[cfg(feature = "crc32fast")]
+#[derive(Debug, Clone, Default)]
+pub struct Crc {
+    hasher: crc32fast::Hasher,
+    amount: u32,
+}
+
+#[cfg(feature = "crc32fast")]
+impl Crc {
+    /// Create new CRC32 checksum
+    pub fn new() -> Self {
+        Crc::default()
+    }
+
+    /// Update checksum with `data`
+    pub fn update(&mut self, data: &[u8]) {
+        self.hasher.update(data);
+        self.amount = self.amount.wrapping_add(data.len() as u32);
+    }
+
+    /// Current checksum
+    pub fn sum(&self) -> u32 {
+        self.hasher.clone().finalize()
+    }
+
+    /// Length of data modulo 2^32
+    pub fn amount(&self) -> u32 {
+        self.amount
+    }
+
+    /// Reset to the initial state
+    pub fn reset(&mut self) {
+        self.hasher.reset();
+        self.amount = 0;
+    }
+}
+
 use crate::BGZFError;
 
-/// Compression Level
+/// DEFLATE implementation used by a compressor or decompressor.
+///
+/// If both of `flate2` and `libdeflater` features are enabled, a backend can be
+/// selected per reader or writer instance. `libdeflater` is used by default if it is enabled.
+/// Other DEFLATE implementations can be used with [`Backend::Custom`].
+#[derive(Debug, Clone)]
+pub enum Backend {
+    /// [flate2](https://crates.io/crates/flate2)
+    #[cfg(feature = "flate2")]
+    Flate2,
+    /// [libdeflater](https://crates.io/crates/libdeflater)
+    #[cfg(feature = "libdeflater")]
+    Libdeflater,
+    /// Implementation of [`DeflateBackend`] provided by applications
+    Custom(Arc<dyn DeflateBackend>),
+}
+
+impl Backend {
+    /// Create a custom backend from `backend`
+    pub fn custom<B: DeflateBackend + 'static>(backend: B) -> Self {
+        Backend::Custom(Arc::new(backend))
+    }
+
+    fn deflate_backend(&self) -> &dyn DeflateBackend {
+        match self {
+            #[cfg(feature = "flate2")]
+            Backend::Flate2 => &Flate2Backend,
+            #[cfg(feature = "libdeflater")]
+            Backend::Libdeflater => &LibdeflaterBackend,
+            Backend::Custom(backend) => backend.as_ref(),
+        }
+    }
+}
+
+impl Default for Backend {
+    #[cfg(feature = "libdeflater")]
+    fn default() -> Self {
+        Backend::Libdeflater
+    }
+
+    #[cfg(not(feature = "libdeflater"))]
+    fn default() -> Self {
+        Backend::Flate2
+    }
+}
+
+/// Custom backends are equal only if they share the same instance.
+impl PartialEq for Backend {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Backend::Custom(x), Backend::Custom(y)) => Arc::ptr_eq(x, y),
+            (Backend::Custom(_), _) | (_, Backend::Custom(_)) => false,
+            _ => std::mem::discriminant(self) == std::mem::discriminant(other),
+        }
+    }
+}
+
+impl Eq for Backend {}
+
+/// DEFLATE implementation which creates compressors and decompressors.
+///
+/// Implement this trait to use other DEFLATE libraries, such as hardware accelerators, with
+/// [`Backend::Custom`]. Multi-threaded readers and writers create one compressor or decompressor
+/// per block in flight.
+pub trait DeflateBackend: Send + Sync + std::fmt::Debug {
+    /// Create new compressor of `level`
+    fn compressor(&self, level: Compression) -> Box<dyn DeflateCompressor>;
+
+    /// Create new decompressor
+    fn decompressor(&self) -> Box<dyn DeflateDecompressor>;
+}
+
+/// Raw DEFLATE compressor created by [`DeflateBackend`]
+pub trait DeflateCompressor: Send {
+    /// Compress all of `original_data` into `compressed_data` as a complete raw DEFLATE stream,
+    /// and return the size of compressed data. [`CompressError::InsufficientSpace`] must be
+    /// returned if `compressed_data` is too small.
+    fn compress(
+        &mut self,
+        original_data: &[u8],
+        compressed_data: &mut [u8],
+    ) -> Result<usize, CompressError>;
+}
+
+/// Raw DEFLATE decompressor created by [`DeflateBackend`]
+pub trait DeflateDecompressor: Send {
+    /// Decompress a complete raw DEFLATE stream in `compressed_data` into `decompressed_data`,
+    /// and return the size of decompressed data. [`DecompressError::InsufficientSpace`] must be
+    /// returned if `decompressed_data` is too small.
+    fn decompress(
+        &mut self,
+        compressed_data: &[u8],
+        decompressed_data: &mut [u8],
+    ) -> Result<usize, DecompressError>;
+}
+
+/// [`DeflateBackend`] of [flate2](https://crates.io/crates/flate2)
+#[cfg(feature = "flate2")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Flate2Backend;
+
+#[cfg(feature = "flate2")]
+impl DeflateBackend for Flate2Backend {
+    fn compressor(&self, level: Compression) -> Box<dyn DeflateCompressor> {
+        Box::new(flate2::Compress::new(level.to_flate2(), false))
+    }
+
+    fn decompressor(&self) -> Box<dyn DeflateDecompressor> {
+        Box::new(flate2::Decompress::new(false))
+    }
+}
+
+#[cfg(feature = "flate2")]
+impl DeflateCompressor for flate2::Compress {
+    fn compress(
+        &mut self,
+        original_data: &[u8],
+        compressed_data: &mut [u8],
+    ) -> Result<usize, CompressError> {
+        self.reset();
+        let status = flate2::Compress::compress(
+            self,
+            original_data,
+            compressed_data,
+            flate2::FlushCompress::Finish,
+        )
+        .map_err(|e| CompressError::Other(e.message().unwrap_or("Unknown error").to_string()))?;
+        match status {
+            flate2::Status::BufError => Err(CompressError::InsufficientSpace),
+            flate2::Status::Ok => Err(CompressError::InsufficientSpace),
+            flate2::Status::StreamEnd => Ok(self.total_out().try_into().unwrap()),
+        }
+    }
+}
+
+#[cfg(feature = "flate2")]
+impl DeflateDecompressor for flate2::Decompress {
+    fn decompress(
+        &mut self,
+        compressed_data: &[u8],
+        decompressed_data: &mut [u8],
+    ) -> Result<usize, DecompressError> {
+        self.reset(false);
+        match flate2::Decompress::decompress(
+            self,
+            compressed_data,
+            decompressed_data,
+            flate2::FlushDecompress::Finish,
+        )
+        .map_err(|e| DecompressError::Other(e.message().unwrap_or("Unknown Error").to_string()))?
+        {
+            flate2::Status::StreamEnd => Ok(self.total_out().try_into().unwrap()),
+            flate2::Status::Ok => Err(DecompressError::InsufficientSpace),
+            flate2::Status::BufError => Err(DecompressError::InsufficientSpace),
+        }
+    }
+}
+
+/// [`DeflateBackend`] of [libdeflater](https://crates.io/crates/libdeflater)
+#[cfg(feature = "libdeflater")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LibdeflaterBackend;
+
+#[cfg(feature = "libdeflater")]
+impl DeflateBackend for LibdeflaterBackend {
+    fn compressor(&self, level: Compression) -> Box<dyn DeflateCompressor> {
+        Box::new(libdeflater::Compressor::new(level.to_libdeflater()))
+    }
+
+    fn decompressor(&self) -> Box<dyn DeflateDecompressor> {
+        Box::new(libdeflater::Decompressor::new())
+    }
+}
+
+#[cfg(feature = "libdeflater")]
+impl DeflateCompressor for libdeflater::Compressor {
+    fn compress(
+        &mut self,
+        original_data: &[u8],
+        compressed_data: &mut [u8],
+    ) -> Result<usize, CompressError> {
+        self.deflate_compress(original_data, compressed_data)
+            .map_err(|e| match e {
+                libdeflater::CompressionError::InsufficientSpace => {
+                    CompressError::InsufficientSpace
+                }
+            })
+    }
+}
+
+#[cfg(feature = "libdeflater")]
+impl DeflateDecompressor for libdeflater::Decompressor {
+    fn decompress(
+        &mut self,
+        compressed_data: &[u8],
+        decompressed_data: &mut [u8],
+    ) -> Result<usize, DecompressError> {
+        self.deflate_decompress(compressed_data, decompressed_data)
+            .map_err(|e| match e {
+                libdeflater::DecompressionError::BadData => DecompressError::BadData,
+                libdeflater::DecompressionError::InsufficientSpace => {
+                    DecompressError::InsufficientSpace
+                }
+            })
+    }
+}
+
+#[cfg(feature = "libdeflater")]
+const MAX_LEVEL: u32 = 12;
 #[cfg(not(feature = "libdeflater"))]
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub struct Compression(flate2::Compression);
+const MAX_LEVEL: u32 = 9;
 
 /// Compression Level
-#[cfg(feature = "libdeflater")]
+///
+/// Levels larger than 9 are only supported by libdeflater, and they are treated as 9 by flate2.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub struct Compression(libdeflater::CompressionLvl);
+pub struct Compression(u32);
 
-#[cfg(not(feature = "libdeflater"))]
 impl Compression {
+    /// Create compression level.
+    ///
+    /// [`BGZFError::InvalidCompressionLevel`] is returned if `level` is larger than 12 with
+    /// `libdeflater` feature, or larger than 9 without it, regardless of the selected [`Backend`].
     pub const fn new(level: u32) -> Result<Self, BGZFError> {
-        Ok(Compression(flate2::Compression::new(level)))
+        if level > MAX_LEVEL {
+            return Err(BGZFError::InvalidCompressionLevel);
+        }
+        Ok(Compression(level))
     }
 
     pub const fn best() -> Self {
-        Compression(flate2::Compression::best())
+        Compression(MAX_LEVEL)
     }
 
+    /// Fastest level which compresses data. Level 1 is used for all backends.
     pub const fn fast() -> Self {
-        Compression(flate2::Compression::fast())
+        Compression(1)
     }
-}
 
-#[cfg(not(feature = "libdeflater"))]
-impl From<flate2::Compression> for Compression {
-    fn from(value: flate2::Compression) -> Self {
-        Compression(value)
+    /// Default level of BGZF. Same as the default level of `bgzip` command in htslib.
+    ///
+    /// Level 6 is used for all backends. This is a good balance of speed and ratio,
+    /// and equal to [`Compression::default`].
+    pub const fn bgzf_default() -> Self {
+        Compression(6)
     }
-}
 
-#[cfg(feature = "libdeflater")]
-impl Compression {
-    pub fn new(level: u32) -> Result<Self, BGZFError> {
-        Ok(Compression(
-            libdeflater::CompressionLvl::new(level.try_into().unwrap()).map_err(|e| match e {
-                libdeflater::CompressionLvlError::InvalidValue => {
-                    BGZFError::InvalidCompressionLevel
-                }
-            })?,
-        ))
+    /// Preset for long-term storage. Compression is slow, but the output is smallest.
+    ///
+    /// Level 12 is used with `libdeflater`, and level 9 is used with `flate2`.
+    /// Expect several times slower compression than [`Compression::bgzf_default`] for a few percent smaller output.
+    /// Decompression speed is not affected.
+    pub const fn archive() -> Self {
+        Compression(MAX_LEVEL)
     }
 
-    pub fn best() -> Self {
-        Compression(libdeflater::CompressionLvl::best())
+    /// Preset for streaming data produced in real time.
+    ///
+    /// Level 1 is used with `libdeflater`, `rust_backend` and `zlib`. Level 2 is used with `zlib-ng`,
+    /// because level 1 of zlib-ng uses a special strategy which produces much larger output.
+    /// Expect two or three times faster compression than [`Compression::bgzf_default`] for 10-20% larger output.
+    pub const fn realtime() -> Self {
+        if cfg!(all(feature = "zlib-ng", not(feature = "libdeflater"))) {
+            Compression(2)
+        } else {
+            Compression(1)
+        }
     }
 
-    pub fn fast() -> Self {
-        Compression(libdeflater::CompressionLvl::fastest())
+    /// Compression level as integer
+    pub const fn level(&self) -> u32 {
+        self.0
+    }
+
+    #[cfg(feature = "flate2")]
+    fn to_flate2(self) -> flate2::Compression {
+        flate2::Compression::new(self.0.min(9))
+    }
+
+    #[cfg(feature = "libdeflater")]
+    fn to_libdeflater(self) -> libdeflater::CompressionLvl {
+        libdeflater::CompressionLvl::new(self.0.min(MAX_LEVEL).try_into().unwrap())
+            .expect("Unreachable")
     }
 }
 
-#[cfg(not(feature = "libdeflater"))]
-impl Default for Compression {
-    fn default() -> Self {
-        Compression(flate2::Compression::default())
+#[cfg(feature = "flate2")]
+impl From<flate2::Compression> for Compression {
+    fn from(value: flate2::Compression) -> Self {
+        Compression(value.level())
     }
 }
 
-#[cfg(feature = "libdeflater")]
 impl Default for Compression {
     fn default() -> Self {
-        Compression(libdeflater::CompressionLvl::default())
+        Compression::bgzf_default()
     }
 }
 
@@ -92,49 +364,37 @@ pub enum CompressError {
     Other(String),
 }
 
-/// flate2 based compressor
-#[cfg(not(feature = "libdeflater"))]
-#[derive(Debug)]
-pub struct Compress(flate2::Compress);
+/// DEFLATE compressor
+pub struct Compress {
+    inner: Box<dyn DeflateCompressor>,
+    backend: Backend,
+    level: Compression,
+}
 
-#[cfg(not(feature = "libdeflater"))]
 impl Compress {
+    /// Create new compressor with default [`Backend`]
     pub fn new(level: Compression) -> Self {
-        Compress(flate2::Compress::new(level.0, false))
+        Self::with_backend(level, Backend::default())
     }
 
-    pub fn compress(
-        &mut self,
-        original_data: &[u8],
-        compressed_data: &mut [u8],
-    ) -> Result<usize, CompressError> {
-        self.0.reset();
-        let status = self
-            .0
-            .compress(
-                original_data,
-                compressed_data,
-                flate2::FlushCompress::Finish,
-            )
-            .map_err(|e| {
-                CompressError::Other(e.message().unwrap_or("Unknown error").to_string())
-            })?;
-        match status {
-            flate2::Status::BufError => Err(CompressError::InsufficientSpace),
-            flate2::Status::Ok => Err(CompressError::InsufficientSpace),
-            flate2::Status::StreamEnd => Ok(self.0.total_out().try_into().unwrap()),
+    /// Create new compressor with `backend`
+    pub fn with_backend(level: Compression, backend: Backend) -> Self {
+        let inner = backend.deflate_backend().compressor(level);
+        Compress {
+            inner,
+            backend,
+            level,
         }
     }
-}
 
-/// libdeflater based compressor
-#[cfg(feature = "libdeflater")]
-pub struct Compress(libdeflater::Compressor);
+    /// Backend of this compressor
+    pub fn backend(&self) -> Backend {
+        self.backend.clone()
+    }
 
-#[cfg(feature = "libdeflater")]
-impl Compress {
-    pub fn new(level: Compression) -> Self {
-        Compress(libdeflater::Compressor::new(level.0))
+    /// Compression level of this compressor
+    pub fn level(&self) -> Compression {
+        self.level
     }
 
     pub fn compress(
@@ -142,13 +402,16 @@ impl Compress {
         original_data: &[u8],
         compressed_data: &mut [u8],
     ) -> Result<usize, CompressError> {
-        self.0
-            .deflate_compress(original_data, compressed_data)
-            .map_err(|e| match e {
-                libdeflater::CompressionError::InsufficientSpace => {
-                    CompressError::InsufficientSpace
-                }
-            })
+        self.inner.compress(original_data, compressed_data)
+    }
+}
+
+impl std::fmt::Debug for Compress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Compress")
+            .field("backend", &self.backend)
+            .field("level", &self.level)
+            .finish()
     }
 }
 
@@ -163,48 +426,27 @@ pub enum DecompressError {
     Other(String),
 }
 
-/// flate2 based decompressor
-#[cfg(not(feature = "libdeflater"))]
-#[derive(Debug)]
-pub struct Decompress(flate2::Decompress);
+/// DEFLATE decompressor
+pub struct Decompress {
+    inner: Box<dyn DeflateDecompressor>,
+    backend: Backend,
+}
 
-#[cfg(not(feature = "libdeflater"))]
 impl Decompress {
+    /// Create new decompressor with default [`Backend`]
     pub fn new() -> Self {
-        Decompress(flate2::Decompress::new(false))
+        Self::with_backend(Backend::default())
     }
 
-    pub fn decompress(
-        &mut self,
-        compressed_data: &[u8],
-        decompressed_data: &mut [u8],
-    ) -> Result<usize, DecompressError> {
-        self.0.reset(false);
-        match self
-            .0
-            .decompress(
-                compressed_data,
-                decompressed_data,
-                flate2::FlushDecompress::Finish,
-            )
-            .map_err(|e| {
-                DecompressError::Other(e.message().unwrap_or("Unknown Error").to_string())
-            })? {
-            Status::StreamEnd => Ok(self.0.total_out().try_into().unwrap()),
-            Status::Ok => Err(DecompressError::InsufficientSpace),
-            Status::BufError => Err(DecompressError::InsufficientSpace),
-        }
+    /// Create new decompressor with `backend`
+    pub fn with_backend(backend: Backend) -> Self {
+        let inner = backend.deflate_backend().decompressor();
+        Decompress { inner, backend }
     }
-}
 
-/// libdeflater based decompressor
-#[cfg(feature = "libdeflater")]
-pub struct Decompress(libdeflater::Decompressor);
-
-#[cfg(feature = "libdeflater")]
-impl Decompress {
-    pub fn new() -> Self {
-        Decompress(libdeflater::Decompressor::new())
+    /// Backend of this decompressor
+    pub fn backend(&self) -> Backend {
+        self.backend.clone()
     }
 
     pub fn decompress(
@@ -212,14 +454,13 @@ impl Decompress {
         compressed_data: &[u8],
         decompressed_data: &mut [u8],
     ) -> Result<usize, DecompressError> {
-        self.0
-            .deflate_decompress(compressed_data, decompressed_data)
-            .map_err(|e| match e {
-                libdeflater::DecompressionError::BadData => DecompressError::BadData,
-                libdeflater::DecompressionError::InsufficientSpace => {
-                    DecompressError::InsufficientSpace
-                }
-            })
+        self.inner.decompress(compressed_data, decompressed_data)
+    }
+}
+
+impl std::fmt::Debug for Decompress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Decompress").field(&self.backend).finish()
     }
 }
 
@@ -227,6 +468,7 @@ impl Decompress {
 mod test {
     use super::*;
     use rand::prelude::*;
+    use std::io::prelude::*;
 
     const BUF_SIZE: usize = 3000;
 
@@ -264,4 +506,204 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn test_crc() {
+        let mut rand = rand_pcg::Pcg64Mcg::seed_from_u64(0x2f6b1e0c93d87a45);
+        let mut data = vec![0; 100_000];
+        rand.fill_bytes(&mut data);
+
+        let mut expected = flate2::Crc::new();
+        expected.update(&data);
+        let mut crc = Crc::new();
+        for chunk in data.chunks(7_000) {
+            crc.update(chunk);
+        }
+        assert_eq!(crc.sum(), expected.sum());
+        // `libdeflater::Crc` cannot be reset
+        #[cfg(any(feature = "crc32fast", not(feature = "libdeflater")))]
+        {
+            crc.reset();
+            assert_eq!(crc.sum(), 0);
+        }
+    }
+
+    #[test]
+    fn test_compression_level() -> anyhow::Result<()> {
+        assert_eq!(Compression::new(MAX_LEVEL)?, Compression::best());
+        assert!(matches!(
+            Compression::new(MAX_LEVEL + 1),
+            Err(BGZFError::InvalidCompressionLevel)
+        ));
+
+        // fast level compresses data with every backend
+        let original_data = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ".repeat(100);
+        let backends = [
+            #[cfg(feature = "flate2")]
+            Backend::Flate2,
+            #[cfg(feature = "libdeflater")]
+            Backend::Libdeflater,
+        ];
+        for backend in backends {
+            let mut compress = Compress::with_backend(Compression::fast(), backend);
+            let mut compressed_data = vec![0; original_data.len() + 100];
+            let compressed_len = compress.compress(&original_data, &mut compressed_data)?;
+            assert!(compressed_len < original_data.len() / 10);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_backends() -> anyhow::Result<()> {
+        let backends = [
+            #[cfg(feature = "flate2")]
+            Backend::Flate2,
+            #[cfg(feature = "libdeflater")]
+            Backend::Libdeflater,
+        ];
+        let original_data = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ".repeat(100);
+
+        for compress_backend in backends.clone() {
+            let mut compress =
+                Compress::with_backend(Compression::best(), compress_backend.clone());
+            assert_eq!(compress.backend(), compress_backend);
+            assert_eq!(compress.level(), Compression::best());
+            let mut deflated_data = vec![0; BUF_SIZE];
+            let deflate_size = compress.compress(&original_data, &mut deflated_data)?;
+
+            for decompress_backend in backends.clone() {
+                let mut decompress = Decompress::with_backend(decompress_backend.clone());
+                assert_eq!(decompress.backend(), decompress_backend);
+                let mut inflated_data = vec![0; BUF_SIZE];
+                let inflate_size =
+                    decompress.decompress(&deflated_data[..deflate_size], &mut inflated_data)?;
+                assert_eq!(&inflated_data[..inflate_size], &original_data[..]);
+            }
+
+            let mut data = Vec::new();
+            let mut writer = crate::BGZFWriter::new(&mut data, Compression::default())
+                .with_backend(compress_backend);
+            writer.write_all(&original_data)?;
+            writer.close()?;
+            for decompress_backend in backends.clone() {
+                let mut reader =
+                    crate::BGZFReader::new(&data[..])?.with_backend(decompress_backend);
+                let mut read_data = Vec::new();
+                reader.read_to_end(&mut read_data)?;
+                assert_eq!(read_data, original_data);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Default backend counting compressed and decompressed blocks
+    #[derive(Debug, Default)]
+    struct CountingBackend {
+        compressed: Arc<std::sync::atomic::AtomicUsize>,
+        decompressed: Arc<std::sync::atomic::AtomicUsize>,
+    }
+
+    struct Counting<T: ?Sized> {
+        inner: Box<T>,
+        count: Arc<std::sync::atomic::AtomicUsize>,
+    }
+
+    impl DeflateBackend for CountingBackend {
+        fn compressor(&self, level: Compression) -> Box<dyn DeflateCompressor> {
+            Box::new(Counting {
+                inner: Backend::default().deflate_backend().compressor(level),
+                count: self.compressed.clone(),
+            })
+        }
+
+        fn decompressor(&self) -> Box<dyn DeflateDecompressor> {
+            Box::new(Counting {
+                inner: Backend::default().deflate_backend().decompressor(),
+                count: self.decompressed.clone(),
+            })
+        }
+    }
+
+    impl DeflateCompressor for Counting<dyn DeflateCompressor> {
+        fn compress(
+            &mut self,
+            original_data: &[u8],
+            compressed_data: &mut [u8],
+        ) -> Result<usize, CompressError> {
+            self.count
+                .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            self.inner.compress(original_data, compressed_data)
+        }
+    }
+
+    impl DeflateDecompressor for Counting<dyn DeflateDecompressor> {
+        fn decompress(
+            &mut self,
+            compressed_data: &[u8],
+            decompressed_data: &mut [u8],
+        ) -> Result<usize, DecompressError> {
+            self.count
+                .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            self.inner.decompress(compressed_data, decompressed_data)
+        }
+    }
+
+    #[test]
+    fn test_custom_backend() -> anyhow::Result<()> {
+        let counting = CountingBackend::default();
+        let (compressed, decompressed) =
+            (counting.compressed.clone(), counting.decompressed.clone());
+        let backend = Backend::custom(counting);
+        assert_eq!(backend, backend.clone());
+        assert_ne!(backend, Backend::default());
+        assert_ne!(backend, Backend::custom(CountingBackend::default()));
+
+        let original_data = include_bytes!("../testfiles/reg2bin.c").repeat(100);
+        let mut data = Vec::new();
+        let mut writer =
+            crate::BGZFWriter::new(&mut data, Compression::default()).with_backend(backend.clone());
+        writer.write_all(&original_data)?;
+        writer.close()?;
+        let blocks = crate::read::RawBlocks::new(&data[..]).count();
+        // end-of-file marker is not compressed
+        assert_eq!(
+            compressed.load(std::sync::atomic::Ordering::Relaxed),
+            blocks - 1
+        );
+
+        let mut reader = crate::BGZFReader::new(&data[..])?.with_backend(backend);
+        let mut read_data = Vec::new();
+        reader.read_to_end(&mut read_data)?;
+        assert_eq!(read_data, original_data);
+        assert!(decompressed.load(std::sync::atomic::Ordering::Relaxed) >= blocks - 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_presets() -> anyhow::Result<()> {
+        assert_eq!(Compression::bgzf_default(), Compression::default());
+        assert!(Compression::realtime().level() < Compression::bgzf_default().level());
+        assert!(Compression::bgzf_default().level() < Compression::archive().level());
+        assert_eq!(Compression::archive(), Compression::best());
+        assert!(Compression::realtime().level() > 0);
+
+        let original_data = include_bytes!("../testfiles/reg2bin.c").repeat(10);
+        let mut sizes = Vec::new();
+        for level in [
+            Compression::realtime(),
+            Compression::bgzf_default(),
+            Compression::archive(),
+        ] {
+            let mut compress = Compress::new(level);
+            let mut deflated_data = vec![0; original_data.len() + 500];
+            sizes.push(compress.compress(&original_data, &mut deflated_data)?);
+        }
+        assert!(sizes[0] >= sizes[1]);
+        assert!(sizes[1] >= sizes[2]);
+
+        Ok(())
+    }
 }
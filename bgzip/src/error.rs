@@ -36,6 +36,69 @@ pub enum BGZFError {
     /// Invalid compression level
     #[error("Invalid Compression Level")]
     InvalidCompressionLevel,
+    /// Operation is cancelled with `CancellationToken`
+    #[error("Operation is cancelled")]
+    Cancelled,
+    /// CRC32 of decompressed data does not match the block footer
+    #[error(
+        "unmatched CRC32 of decompressed data (expected: {expected:08x}, actual: {actual:08x})"
+    )]
+    Crc32Mismatch { expected: u32, actual: u32 },
+    /// Length of decompressed data does not match ISIZE in the block footer
+    #[error("unmatched length of decompressed data (expected: {expected}, actual: {actual})")]
+    SizeMismatch { expected: u32, actual: u32 },
+    /// Failed to read a block. `block_index` is the number of blocks preceding the block,
+    /// which is unknown after seek.
+    #[error(
+        "{source} in block{} at compressed offset {compressed_offset}",
+        block_index.map(|x| format!(" {}", x)).unwrap_or_default()
+    )]
+    InBlock {
+        compressed_offset: u64,
+        block_index: Option<u64>,
+        source: Box<BGZFError>,
+    },
+    /// Data ends without BGZF end-of-file marker, or in the middle of a block.
+    /// Returned only if the reader is configured to fail on missing end-of-file marker.
+    #[error("data ends without end-of-file marker")]
+    TruncatedFile,
+    /// Data exists after BGZF end-of-file marker.
+    /// Returned only if the reader is configured to fail on trailing data.
+    #[error("trailing data after end-of-file marker at compressed offset {offset}")]
+    TrailingData { offset: u64 },
+    /// Size of a block including header and footer exceeds 65536 bytes.
+    /// Returned only in strict mode.
+    #[error("block size exceeds 65536 bytes")]
+    BlockTooLarge,
+    /// ISIZE of a block exceeds 65536 bytes. Returned only in strict mode.
+    #[error("uncompressed size of block exceeds 65536 bytes (ISIZE: {size})")]
+    UncompressedBlockTooLarge { size: u64 },
+    /// Reserved bits of FLG are set. Returned only in strict header parsing.
+    #[error("reserved flags are set (FLG: {flags:#04x})")]
+    ReservedFlags { flags: u8 },
+    /// XLEN does not match total length of extra subfields. Returned only in strict header parsing.
+    #[error("extra field length does not match subfields at header position: {position}")]
+    ExtraFieldLengthMismatch { position: u64 },
+    /// BC subfield has wrong length or appears twice. Returned only in strict header parsing.
+    #[error("invalid BC subfield at header position: {position}")]
+    InvalidBCField { position: u64 },
+    /// Block is too short to hold CRC32 and ISIZE
+    #[error("block is too short ({size} bytes)")]
+    BlockTooShort { size: u64 },
+    /// .gzi index ends before all entries are read
+    #[error("truncated .gzi index")]
+    TruncatedIndex,
+    /// Offsets of .gzi index entry at `index` are not larger than offsets of the previous entry
+    #[error("unsorted .gzi index entries at entry {index}")]
+    UnsortedIndexEntries { index: u64 },
+    /// A line of text indexed with tabix is not sorted. `line_number` is one-based and
+    /// `begin` is a zero-based position.
+    #[error("line {line_number} is out of order ({sequence}:{begin})")]
+    UnsortedRecord {
+        line_number: u64,
+        sequence: String,
+        begin: u64,
+    },
     /// Other error
     #[error("Error: {0}")]
     Other(&'static str),
@@ -45,6 +108,17 @@ impl Into<std::io::Error> for BGZFError {
     fn into(self) -> std::io::Error {
         match self {
             BGZFError::IoError(e) => e,
+            BGZFError::InBlock { ref source, .. } => {
+                let kind = match source.as_ref() {
+                    BGZFError::IoError(e) => e.kind(),
+                    BGZFError::TruncatedFile => std::io::ErrorKind::UnexpectedEof,
+                    _ => std::io::ErrorKind::Other,
+                };
+                std::io::Error::new(kind, self)
+            }
+            BGZFError::TruncatedFile => {
+                std::io::Error::new(std::io::ErrorKind::UnexpectedEof, self)
+            }
             other => std::io::Error::new(std::io::ErrorKind::Other, other),
         }
     }
@@ -54,4 +128,46 @@ impl BGZFError {
     pub fn into_io_error(self) -> std::io::Error {
         self.into()
     }
+
+    /// Attach the location of the block where this error occurred.
+    ///
+    /// An error which already has a location is returned as is.
+    pub fn in_block(self, compressed_offset: u64, block_index: Option<u64>) -> Self {
+        match self {
+            BGZFError::InBlock { .. } => self,
+            other => BGZFError::InBlock {
+                compressed_offset,
+                block_index,
+                source: Box::new(other),
+            },
+        }
+    }
+
+    /// Compressed offset of the block where this error occurred, if known.
+    ///
+    /// An error converted into [`std::io::Error`] by a reader is also inspected.
+    pub fn compressed_offset(&self) -> Option<u64> {
+        match self {
+            BGZFError::InBlock {
+                compressed_offset, ..
+            } => Some(*compressed_offset),
+            BGZFError::IoError(e) => e
+                .get_ref()
+                .and_then(|x| x.downcast_ref::<BGZFError>())
+                .and_then(BGZFError::compressed_offset),
+            _ => None,
+        }
+    }
+
+    /// Index of the block where this error occurred, if known.
+    pub fn block_index(&self) -> Option<u64> {
+        match self {
+            BGZFError::InBlock { block_index, .. } => *block_index,
+            BGZFError::IoError(e) => e
+                .get_ref()
+                .and_then(|x| x.downcast_ref::<BGZFError>())
+                .and_then(BGZFError::block_index),
+            _ => None,
+        }
+    }
 }
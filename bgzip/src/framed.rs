@@ -0,0 +1,261 @@
+//! Length-prefixed binary records in BGZF streams.
+//!
+//! Each record is written as its length in 32-bit little endian followed by the record data,
+//! like alignment records of BAM. [`FramedWriter`] returns the virtual file offset of each record,
+//! and [`FramedReader`] reads records back from a virtual file offset, for example from a chunk
+//! of BAI or CSI index.
+//!
+//! ```rust
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! use bgzip::framed::{FramedReader, FramedWriter};
+//! use bgzip::{BGZFReader, BGZFWriter, Compression};
+//!
+//! let mut data = Vec::new();
+//! let mut writer = FramedWriter::new(BGZFWriter::new(&mut data, Compression::default()));
+//! let first = writer.write_record(b"first record")?;
+//! let second = writer.write_record(b"second record")?;
+//! let end = writer.write_record(b"third record")?;
+//! writer.into_inner().close()?;
+//!
+//! let mut reader = FramedReader::new(BGZFReader::new(std::io::Cursor::new(data))?);
+//! let records = reader
+//!     .records_between(second, end)?
+//!     .collect::<Result<Vec<_>, _>>()?;
+//! assert_eq!(records, vec![(second, b"second record".to_vec())]);
+//! # assert!(first < second);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{BGZFError, BGZFReader, BGZFWriter, VirtualPosition};
+use std::io::{self, BufRead, Read, Seek, Write};
+
+/// Size of the length prefix of a record
+pub const LENGTH_PREFIX_SIZE: usize = 4;
+
+/// Writer of length-prefixed records
+pub struct FramedWriter<W: Write> {
+    writer: BGZFWriter<W>,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> FramedWriter<W> {
+    /// Create new writer writing records into `writer`
+    pub fn new(writer: BGZFWriter<W>) -> Self {
+        FramedWriter {
+            writer,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Write `record` with its length prefix, and return the virtual file offset of the prefix.
+    ///
+    /// A record is not split across blocks if it fits in a block.
+    /// See [`BGZFWriter::write_record`].
+    pub fn write_record(&mut self, record: &[u8]) -> io::Result<VirtualPosition> {
+        let length: u32 = record.len().try_into().map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "record length does not fit in 32 bits",
+            )
+        })?;
+        self.buffer.clear();
+        self.buffer.extend_from_slice(&length.to_le_bytes());
+        self.buffer.extend_from_slice(record);
+        self.writer.write_record(&self.buffer)
+    }
+
+    /// Virtual file offset of the next record
+    pub fn bgzf_pos(&self) -> VirtualPosition {
+        self.writer.bgzf_pos()
+    }
+
+    /// Get a reference to the underlying writer
+    pub fn get_ref(&self) -> &BGZFWriter<W> {
+        &self.writer
+    }
+
+    /// Get a mutable reference to the underlying writer
+    pub fn get_mut(&mut self) -> &mut BGZFWriter<W> {
+        &mut self.writer
+    }
+
+    /// Unwrap this writer. Call [`BGZFWriter::close`] to write remaining records.
+    pub fn into_inner(self) -> BGZFWriter<W> {
+        self.writer
+    }
+}
+
+/// Reader of length-prefixed records
+pub struct FramedReader<R: Read> {
+    reader: BGZFReader<R>,
+}
+
+impl<R: Read> FramedReader<R> {
+    /// Create new reader reading records from the current position of `reader`
+    pub fn new(reader: BGZFReader<R>) -> Self {
+        FramedReader { reader }
+    }
+
+    /// Read the next record into `buf`, and return the virtual file offset of the record.
+    ///
+    /// `None` is returned at the end of data. Data ending in the middle of a record is
+    /// an error of [`io::ErrorKind::UnexpectedEof`].
+    pub fn read_record(&mut self, buf: &mut Vec<u8>) -> io::Result<Option<VirtualPosition>> {
+        let Some(pos) = self.reader.next_bgzf_pos()? else {
+            return Ok(None);
+        };
+        let mut length = [0; LENGTH_PREFIX_SIZE];
+        self.reader.read_exact(&mut length)?;
+        let length = u32::from_le_bytes(length) as u64;
+        buf.clear();
+        let read_bytes = (&mut self.reader).take(length).read_to_end(buf)?;
+        if read_bytes as u64 != length {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "data ends in the middle of a record",
+            ));
+        }
+        Ok(Some(pos))
+    }
+
+    /// Iterate records from the current position to the end of data
+    pub fn records(&mut self) -> Records<'_, R> {
+        Records {
+            reader: self,
+            end: None,
+        }
+    }
+
+    /// Get a reference to the underlying reader
+    pub fn get_ref(&self) -> &BGZFReader<R> {
+        &self.reader
+    }
+
+    /// Get a mutable reference to the underlying reader
+    pub fn get_mut(&mut self) -> &mut BGZFReader<R> {
+        &mut self.reader
+    }
+
+    /// Unwrap this reader, returning the underlying reader
+    pub fn into_inner(self) -> BGZFReader<R> {
+        self.reader
+    }
+}
+
+impl<R: Read + Seek> FramedReader<R> {
+    /// Seek to `start` and iterate records starting before `end`.
+    ///
+    /// `start` must be the virtual file offset of a record, such as a value returned by
+    /// [`FramedWriter::write_record`] or the start of a chunk in BAI and CSI indexes.
+    pub fn records_between(
+        &mut self,
+        start: VirtualPosition,
+        end: VirtualPosition,
+    ) -> Result<Records<'_, R>, BGZFError> {
+        self.reader.bgzf_seek(start)?;
+        Ok(Records {
+            reader: self,
+            end: Some(end),
+        })
+    }
+}
+
+/// Iterator over records and their virtual file offsets.
+///
+/// Created by [`FramedReader::records`] or [`FramedReader::records_between`].
+pub struct Records<'a, R: Read> {
+    reader: &'a mut FramedReader<R>,
+    end: Option<VirtualPosition>,
+}
+
+impl<'a, R: Read> Iterator for Records<'a, R> {
+    type Item = Result<(VirtualPosition, Vec<u8>), BGZFError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(end) = self.end {
+            if let Err(e) = self.reader.reader.fill_buf() {
+                return Some(Err(e.into()));
+            }
+            if self.reader.reader.bgzf_pos() >= end {
+                return None;
+            }
+        }
+        let mut record = Vec::new();
+        match self.reader.read_record(&mut record) {
+            Ok(Some(pos)) => Some(Ok((pos, record))),
+            Ok(None) => None,
+            Err(e) => Some(Err(e.into())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Compression;
+    use rand::prelude::*;
+
+    #[test]
+    fn test_framed() -> anyhow::Result<()> {
+        let mut rand = rand_pcg::Pcg64Mcg::seed_from_u64(0xc2b2ae35);
+        let records: Vec<Vec<u8>> = (0..3000)
+            .map(|i| {
+                let mut record = vec![
+                    0;
+                    if i == 1000 {
+                        100_000
+                    } else {
+                        rand.gen_range(0..300)
+                    }
+                ];
+                rand.fill_bytes(&mut record);
+                record
+            })
+            .collect();
+
+        let mut data = Vec::new();
+        let mut writer = FramedWriter::new(BGZFWriter::new(&mut data, Compression::default()));
+        let positions = records
+            .iter()
+            .map(|x| writer.write_record(x))
+            .collect::<io::Result<Vec<_>>>()?;
+        assert_eq!(writer.bgzf_pos(), writer.get_ref().bgzf_pos());
+        writer.into_inner().close()?;
+
+        let mut reader = FramedReader::new(BGZFReader::new(io::Cursor::new(&data))?);
+        let all = reader.records().collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(all.len(), records.len());
+        for ((pos, record), (expected_pos, expected)) in
+            all.iter().zip(positions.iter().zip(&records))
+        {
+            assert_eq!(pos, expected_pos);
+            assert_eq!(record, expected);
+        }
+
+        for (start, end) in [(0, 10), (500, 1500), (999, 1001), (2990, 3000)] {
+            let end_pos = positions
+                .get(end)
+                .copied()
+                .unwrap_or(VirtualPosition::new(u64::MAX >> 16, 0));
+            let read = reader
+                .records_between(positions[start], end_pos)?
+                .collect::<Result<Vec<_>, _>>()?;
+            assert_eq!(read.len(), end - start);
+            assert_eq!(read[0].1, records[start]);
+            assert_eq!(read.last().unwrap().1, records[end - 1]);
+        }
+
+        // truncated record
+        let mut data = Vec::new();
+        let mut writer = BGZFWriter::new(&mut data, Compression::default());
+        writer.write_all(&10u32.to_le_bytes())?;
+        writer.write_all(b"short")?;
+        writer.close()?;
+        let mut reader = FramedReader::new(BGZFReader::new(&data[..])?);
+        let e = reader.read_record(&mut Vec::new()).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+
+        Ok(())
+    }
+}
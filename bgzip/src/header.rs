@@ -37,6 +37,11 @@ impl ExtraField {
         &self.data
     }
 
+    /// Return `true` if this is BC subfield holding BGZF block size
+    fn is_bc(&self) -> bool {
+        self.sub_field_id1 == 66 && self.sub_field_id2 == 67
+    }
+
     pub fn field_len(&self) -> u16 {
         TryInto::<u16>::try_into(self.data.len()).unwrap() + 4
     }
@@ -103,6 +108,60 @@ pub const FILESYSTEM_UNIX: u8 = 3;
 pub const FILESYSTEM_NTFS: u8 = 11;
 pub const FILESYSTEM_UNKNOWN: u8 = 255;
 
+/// Limits of variable length fields applied while parsing gzip headers.
+///
+/// [`BGZFError::HeaderParseError`] is returned if a header exceeds these limits.
+/// Position of the error is an offset from the beginning of the header.
+///
+/// If `strict` is set, nonstandard headers are rejected with specific errors:
+/// [`BGZFError::ReservedFlags`] for reserved bits of FLG, [`BGZFError::ExtraFieldLengthMismatch`]
+/// for XLEN not matching subfields, and [`BGZFError::InvalidBCField`] for BC subfield whose
+/// length is not 2 or which appears twice.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeaderLimits {
+    /// Maximum length of original file name including NUL terminator. Default: 1024
+    pub max_file_name_len: usize,
+    /// Maximum length of comment including NUL terminator. Default: 1024
+    pub max_comment_len: usize,
+    /// Maximum length of whole extra field. Default: 65535
+    pub max_extra_field_len: u16,
+    /// Reject nonstandard headers. Default: `false`
+    pub strict: bool,
+}
+
+impl Default for HeaderLimits {
+    fn default() -> Self {
+        HeaderLimits {
+            max_file_name_len: 1024,
+            max_comment_len: 1024,
+            max_extra_field_len: u16::MAX,
+            strict: false,
+        }
+    }
+}
+
+/// Read NUL terminated string up to `max_len` bytes.
+fn read_zero_terminated<R: io::Read>(
+    mut reader: R,
+    max_len: usize,
+    position: u64,
+) -> Result<Vec<u8>, BGZFError> {
+    let mut buf = Vec::new();
+    loop {
+        if buf.len() >= max_len {
+            return Err(BGZFError::HeaderParseError {
+                position: position + TryInto::<u64>::try_into(buf.len()).unwrap(),
+            });
+        }
+        let mut byte = [0u8];
+        reader.read_exact(&mut byte)?;
+        buf.push(byte[0]);
+        if byte[0] == 0 {
+            return Ok(buf);
+        }
+    }
+}
+
 impl BGZFHeader {
     /// Create new BGZF file header
     pub fn new(fast: bool, modified_time: u32, compressed_len: u16) -> Self {
@@ -131,9 +190,10 @@ impl BGZFHeader {
             .map(|x| {
                 let mut bytes: [u8; 2] = [0, 0];
                 bytes.copy_from_slice(&x.data[0..2]);
-                u16::from_le_bytes(bytes) + 1
+                u16::from_le_bytes(bytes).checked_add(1)
             })
-            .ok_or(BGZFError::NotBGZF)
+            .ok_or(BGZFError::NotBGZF)?
+            .ok_or(BGZFError::Other("Too large block size"))
     }
 
     /// Overwrite BGZF block write
@@ -164,8 +224,16 @@ impl BGZFHeader {
             + self.crc16.map(|_| 2).unwrap_or(0)
     }
 
-    /// Load gzip header form `reader`
-    pub fn from_reader<R: io::Read>(mut reader: R) -> Result<Self, BGZFError> {
+    /// Load gzip header form `reader` with default [`HeaderLimits`]
+    pub fn from_reader<R: io::Read>(reader: R) -> Result<Self, BGZFError> {
+        Self::from_reader_with_limits(reader, &HeaderLimits::default())
+    }
+
+    /// Load gzip header form `reader` with `limits`
+    pub fn from_reader_with_limits<R: io::Read>(
+        mut reader: R,
+        limits: &HeaderLimits,
+    ) -> Result<Self, BGZFError> {
         let mut header_data = [0u8; 10];
         reader.read_exact(&mut header_data)?;
 
@@ -180,21 +248,43 @@ impl BGZFHeader {
         }
         let flags = header_data[3];
         if flags | 0x1f != 0x1f {
+            if limits.strict {
+                return Err(BGZFError::ReservedFlags { flags });
+            }
             return Err(BGZFError::Other("Unsupported flag"));
         }
         let modified_time = u32::from_le_bytes(header_data[4..8].try_into().unwrap());
         let extra_flags = header_data[8];
         let operation_system = header_data[9];
+        let mut position = 10;
         let (extra_field_len, extra_field) = if flags & FLAG_FEXTRA != 0 {
             let len = reader.read_le_u16()?;
+            if len > limits.max_extra_field_len {
+                return Err(BGZFError::HeaderParseError { position });
+            }
+            position += 2;
             let mut remain_bytes = len;
-            let mut fields = Vec::new();
-            while remain_bytes > 4 {
+            let mut fields: Vec<ExtraField> = Vec::new();
+            while remain_bytes >= 4 {
                 let mut buf = [0u8; 4];
                 reader.read_exact(&mut buf)?;
                 let sub_field_id1 = buf[0];
                 let sub_field_id2 = buf[1];
                 let sub_field_len = u16::from_le_bytes([buf[2], buf[3]]);
+                remain_bytes = remain_bytes
+                    .checked_sub(4)
+                    .and_then(|x| x.checked_sub(sub_field_len))
+                    .ok_or(if limits.strict {
+                        BGZFError::ExtraFieldLengthMismatch { position }
+                    } else {
+                        BGZFError::HeaderParseError { position }
+                    })?;
+                if limits.strict
+                    && (sub_field_id1, sub_field_id2) == (66, 67)
+                    && (sub_field_len != 2 || fields.iter().any(|x| x.is_bc()))
+                {
+                    return Err(BGZFError::InvalidBCField { position });
+                }
                 let mut buf: Vec<u8> = vec![0; sub_field_len as usize];
                 reader.read_exact(&mut buf)?;
                 fields.push(ExtraField {
@@ -202,9 +292,12 @@ impl BGZFHeader {
                     sub_field_id2,
                     data: buf,
                 });
-                remain_bytes -= 4 + sub_field_len;
+                position += 4 + u64::from(sub_field_len);
             }
             if remain_bytes != 0 {
+                if limits.strict {
+                    return Err(BGZFError::ExtraFieldLengthMismatch { position });
+                }
                 return Err(BGZFError::Other("Invalid extra field"));
             }
 
@@ -214,16 +307,15 @@ impl BGZFHeader {
         };
 
         let file_name = if flags & FLAG_FNAME != 0 {
-            let mut buf = Vec::new();
-            reader.read_until(0, &mut buf)?;
+            let buf = read_zero_terminated(&mut reader, limits.max_file_name_len, position)?;
+            position += TryInto::<u64>::try_into(buf.len()).unwrap();
             Some(buf)
         } else {
             None
         };
 
         let comment = if flags & FLAG_FCOMMENT != 0 {
-            let mut buf = Vec::new();
-            reader.read_until(0, &mut buf)?;
+            let buf = read_zero_terminated(&mut reader, limits.max_comment_len, position)?;
             Some(buf)
         } else {
             None
@@ -345,6 +437,129 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_header_limits() -> Result<(), BGZFError> {
+        let mut header = BGZFHeader::new(false, 0, 0);
+        header.flags |= FLAG_FNAME | FLAG_FCOMMENT;
+        header.file_name = Some(b"name.txt".to_vec());
+        header.comment = Some(vec![b'a'; 2000]);
+        let mut buf = Vec::new();
+        header.write(&mut buf)?;
+
+        assert!(matches!(
+            BGZFHeader::from_reader(&buf[..]),
+            Err(BGZFError::HeaderParseError { position: 1051 })
+        ));
+        let limits = HeaderLimits {
+            max_comment_len: 2001,
+            ..Default::default()
+        };
+        let loaded = BGZFHeader::from_reader_with_limits(&buf[..], &limits)?;
+        assert_eq!(loaded.file_name, Some(b"name.txt\0".to_vec()));
+        assert_eq!(loaded.comment.as_ref().map(|x| x.len()), Some(2001));
+
+        let limits = HeaderLimits {
+            max_file_name_len: 8,
+            max_comment_len: 2001,
+            ..Default::default()
+        };
+        assert!(matches!(
+            BGZFHeader::from_reader_with_limits(&buf[..], &limits),
+            Err(BGZFError::HeaderParseError { position: 26 })
+        ));
+        let limits = HeaderLimits {
+            max_extra_field_len: 5,
+            ..Default::default()
+        };
+        assert!(matches!(
+            BGZFHeader::from_reader_with_limits(&buf[..], &limits),
+            Err(BGZFError::HeaderParseError { position: 10 })
+        ));
+
+        // unterminated file name
+        assert!(BGZFHeader::from_reader(&buf[..30]).is_err());
+
+        // broken extra field length
+        let mut broken = buf.clone();
+        broken[14] = 0xff;
+        assert!(matches!(
+            BGZFHeader::from_reader(&broken[..]),
+            Err(BGZFError::HeaderParseError { position: 12 })
+        ));
+
+        // too small block size
+        let mut broken = crate::EOF_MARKER.to_vec();
+        broken[16] = 10;
+        assert!(matches!(
+            crate::read::load_block(&broken[..], &mut Vec::new()),
+            Err(BGZFError::HeaderParseError { position: 18 })
+        ));
+
+        // too large block size
+        broken[16] = 0xff;
+        broken[17] = 0xff;
+        assert!(crate::read::load_block(&broken[..], &mut Vec::new()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_strict_header() -> Result<(), BGZFError> {
+        let strict = HeaderLimits {
+            strict: true,
+            ..Default::default()
+        };
+        let load = |data: &[u8]| {
+            (
+                BGZFHeader::from_reader(data),
+                BGZFHeader::from_reader_with_limits(data, &strict),
+            )
+        };
+        assert!(BGZFHeader::from_reader_with_limits(&crate::EOF_MARKER[..], &strict).is_ok());
+
+        let mut broken = crate::EOF_MARKER.to_vec();
+        broken[3] |= 0x80;
+        assert!(matches!(
+            load(&broken),
+            (Err(_), Err(BGZFError::ReservedFlags { flags: 0x84 }))
+        ));
+
+        // XLEN is shorter than the subfield
+        let mut broken = crate::EOF_MARKER.to_vec();
+        broken[10] = 5;
+        assert!(matches!(
+            load(&broken),
+            (
+                Err(BGZFError::HeaderParseError { position: 12 }),
+                Err(BGZFError::ExtraFieldLengthMismatch { position: 12 })
+            )
+        ));
+
+        // BC subfield with 3 bytes of data
+        let mut header = BGZFHeader::new(false, 0, 0);
+        header.extra_field = vec![ExtraField::new(66, 67, vec![0, 1, 0])];
+        header.extra_field_len = Some(7);
+        let mut buf = Vec::new();
+        header.write(&mut buf)?;
+        assert!(matches!(
+            load(&buf),
+            (Ok(_), Err(BGZFError::InvalidBCField { position: 12 }))
+        ));
+
+        // BC subfield appears twice
+        let mut header = BGZFHeader::new(false, 0, 0);
+        header.extra_field.push(ExtraField::new(66, 67, vec![0, 1]));
+        header.extra_field_len = Some(12);
+        let mut buf = Vec::new();
+        header.write(&mut buf)?;
+        assert!(matches!(
+            load(&buf),
+            (Ok(_), Err(BGZFError::InvalidBCField { position: 18 }))
+        ));
+
+        Ok(())
+    }
+
     #[test]
     fn load_header2() -> Result<(), BGZFError> {
         let mut reader = io::BufReader::new(File::open(
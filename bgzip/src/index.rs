@@ -1,8 +1,11 @@
 //! .gzi index support
 
 use std::convert::TryInto;
+use std::io::{Read, Seek, SeekFrom};
 
-use crate::{BGZFError, BinaryReader};
+use crate::{BGZFError, BinaryReader, VirtualPosition};
+
+const ENTRY_SIZE: u64 = 16;
 
 /// Represents .gzi index file
 #[derive(Debug, Clone, PartialEq, Default)]
@@ -21,20 +24,91 @@ impl BGZFIndex {
     }
 
     /// Load .gzi index file from `reader`
-    pub fn from_reader<R: std::io::Read>(mut reader: R) -> std::io::Result<Self> {
-        let num_entries = reader.read_le_u64()?;
+    ///
+    /// [`BGZFError::TruncatedIndex`] is returned if `reader` ends before all entries are read,
+    /// and [`BGZFError::UnsortedIndexEntries`] is returned if offsets of entries do not increase.
+    /// .gzi index does not have magic bytes, so other kinds of files cannot be detected.
+    pub fn from_reader<R: std::io::Read>(mut reader: R) -> Result<Self, BGZFError> {
+        let num_entries = reader.read_le_u64().map_err(truncated_index)?;
         let mut result = BGZFIndex::default();
-        for _ in 0..num_entries {
-            let compressed_offset = reader.read_le_u64()?;
-            let uncompressed_offset = reader.read_le_u64()?;
-            result.entries.push(BGZFIndexEntry {
-                compressed_offset,
-                uncompressed_offset,
-            })
+        for i in 0..num_entries {
+            let entry = read_entry(&mut reader).map_err(truncated_index)?;
+            if let Some(last) = result.entries.last() {
+                if entry.compressed_offset <= last.compressed_offset
+                    || entry.uncompressed_offset < last.uncompressed_offset
+                {
+                    return Err(BGZFError::UnsortedIndexEntries { index: i });
+                }
+            }
+            result.entries.push(entry);
         }
         Ok(result)
     }
 
+    /// Create index by scanning BGZF data in `reader`.
+    ///
+    /// Only headers and ISIZE fields of blocks are read, so payloads are not decompressed.
+    /// The same entries are created as [`crate::BGZFWriter`]; the first block and the trailing
+    /// end-of-file marker do not have entries.
+    pub fn from_bgzf_reader<R: Read>(reader: R) -> Result<Self, BGZFError> {
+        let mut index = BGZFIndex::new();
+        let mut last_block_empty = false;
+        for block in crate::read::RawBlocks::new(reader) {
+            let block = block?;
+            if block.compressed_offset > 0 {
+                index.entries.push(BGZFIndexEntry {
+                    compressed_offset: block.compressed_offset,
+                    uncompressed_offset: block.uncompressed_offset,
+                });
+            }
+            last_block_empty = block.uncompressed_size() == 0;
+        }
+        if last_block_empty {
+            index.entries.pop();
+        }
+        Ok(index)
+    }
+
+    /// Cross-check entries with actual blocks of BGZF data in `reader`.
+    ///
+    /// All block headers in `reader` are scanned as [`BGZFIndex::from_bgzf_reader`]. An empty list is
+    /// returned if the index matches the data. Stale index files of regenerated data and truncated
+    /// index files are detected.
+    pub fn validate<R: Read>(&self, reader: R) -> Result<Vec<IndexProblem>, BGZFError> {
+        let actual = BGZFIndex::from_bgzf_reader(reader)?;
+        Ok(self.problems(&actual))
+    }
+
+    /// Rebuild entries from BGZF data in `reader` and return problems found in the old entries.
+    ///
+    /// See [`BGZFIndex::validate`] for the detail of problems.
+    pub fn repair<R: Read>(&mut self, reader: R) -> Result<Vec<IndexProblem>, BGZFError> {
+        let actual = BGZFIndex::from_bgzf_reader(reader)?;
+        let problems = self.problems(&actual);
+        *self = actual;
+        Ok(problems)
+    }
+
+    fn problems(&self, actual: &BGZFIndex) -> Vec<IndexProblem> {
+        let mut problems = Vec::new();
+        let mut actual_entries = actual.entries.iter().peekable();
+        for (i, entry) in self.entries.iter().enumerate() {
+            while let Some(missing) =
+                actual_entries.next_if(|x| x.compressed_offset < entry.compressed_offset)
+            {
+                problems.push(IndexProblem::MissingEntry(*missing));
+            }
+            if actual_entries.next_if(|x| *x == entry).is_none() {
+                problems.push(IndexProblem::InvalidEntry {
+                    index: i,
+                    entry: *entry,
+                });
+            }
+        }
+        problems.extend(actual_entries.map(|x| IndexProblem::MissingEntry(*x)));
+        problems
+    }
+
     /// Write .gzi index file into `writer`
     pub fn write<W: std::io::Write>(&self, mut writer: W) -> std::io::Result<()> {
         let entries: u64 = self.entries.len().try_into().unwrap();
@@ -46,39 +120,209 @@ impl BGZFIndex {
         Ok(())
     }
 
-    /// Convert uncompressed position to bgzf virtual position
-    pub fn uncompressed_pos_to_bgzf_pos(&self, pos: u64) -> Result<u64, BGZFError> {
+    /// Number of blocks except end-of-file markers.
+    ///
+    /// The first block does not have an entry, so this is the number of entries + 1.
+    pub fn block_count(&self) -> usize {
+        self.entries.len() + 1
+    }
+
+    /// Find the entry of the block which contains uncompressed position `pos`.
+    ///
+    /// An entry at offset zero is returned for the first block, which does not have an entry in .gzi.
+    pub fn entry_for_uncompressed_pos(&self, pos: u64) -> BGZFIndexEntry {
         let i = self
             .entries
             .partition_point(|x| x.uncompressed_offset <= pos);
-        let entry = match i {
+        match i {
             0 => BGZFIndexEntry {
                 compressed_offset: 0,
                 uncompressed_offset: 0,
             },
-            i => self.entries[i - 1].clone(),
-        };
-        // eprintln!(
-        //     "[{}/{}] {} / {} ",
-        //     i,
-        //     self.entries().len(),
-        //     pos,
-        //     entry.uncompressed_offset
-        // );
-        Ok((entry.compressed_offset << 16) + ((pos - entry.uncompressed_offset) & ((1 << 16) - 1)))
+            i => self.entries[i - 1],
+        }
+    }
+
+    /// Iterate byte ranges of all blocks in file order.
+    ///
+    /// End of the last block is `None`, because .gzi index does not record the end of the last block.
+    pub fn block_ranges(&self) -> impl Iterator<Item = BGZFPartition> + '_ {
+        let starts = std::iter::once(BGZFIndexEntry {
+            compressed_offset: 0,
+            uncompressed_offset: 0,
+        })
+        .chain(self.entries.iter().copied());
+        starts
+            .zip(self.entries.iter().map(Some).chain(std::iter::once(None)))
+            .map(|(start, end)| BGZFPartition {
+                compressed_offset: start.compressed_offset,
+                compressed_end: end.map(|x| x.compressed_offset),
+                uncompressed_offset: start.uncompressed_offset,
+                uncompressed_end: end.map(|x| x.uncompressed_offset),
+            })
+    }
+
+    /// Calculate uncompressed size of BGZF data in `reader`.
+    ///
+    /// Only headers and ISIZE fields of blocks after the last entry are read, because .gzi index
+    /// does not record the size of the last block.
+    pub fn uncompressed_size<R: Read + Seek>(&self, mut reader: R) -> Result<u64, BGZFError> {
+        let (compressed_offset, mut size) = self
+            .entries
+            .last()
+            .map(|x| (x.compressed_offset, x.uncompressed_offset))
+            .unwrap_or((0, 0));
+        reader.seek(SeekFrom::Start(compressed_offset))?;
+        for block in crate::read::RawBlocks::new(reader) {
+            let block = block?;
+            if block.data == crate::read::EOF_BLOCK {
+                break;
+            }
+            size += block.uncompressed_size();
+        }
+        Ok(size)
+    }
+
+    /// Convert uncompressed position to bgzf virtual position
+    pub fn uncompressed_pos_to_bgzf_pos(&self, pos: u64) -> Result<VirtualPosition, BGZFError> {
+        Ok(self.entry_for_uncompressed_pos(pos).bgzf_pos(pos))
     }
 
     /// Convert bgzf virtual position to uncompressed position
-    pub fn bgzf_pos_to_uncompressed_pos(&self, bgzf_pos: u64) -> Result<u64, BGZFError> {
-        let compressed_pos = bgzf_pos >> 16;
+    pub fn bgzf_pos_to_uncompressed_pos<P: Into<VirtualPosition>>(
+        &self,
+        bgzf_pos: P,
+    ) -> Result<u64, BGZFError> {
+        let bgzf_pos = bgzf_pos.into();
+        let compressed_pos = bgzf_pos.compressed_offset();
         if compressed_pos == 0 {
-            return Ok(bgzf_pos);
+            return Ok(bgzf_pos.uncompressed_offset_in_block().into());
         }
         let i = self
             .entries
             .binary_search_by(|x| x.compressed_offset.cmp(&compressed_pos))
             .map_err(|_| BGZFError::Other("Invalid BGZF position"))?;
-        Ok(self.entries[i].uncompressed_offset + (bgzf_pos & ((1 << 16) - 1)))
+        Ok(
+            self.entries[i].uncompressed_offset
+                + u64::from(bgzf_pos.uncompressed_offset_in_block()),
+        )
+    }
+
+    /// Decompress uncompressed byte `range` of BGZF `data` loaded in memory.
+    ///
+    /// Only blocks in the range are decompressed with [`crate::block`] functions, so neither
+    /// [`std::io::Seek`] nor [`BGZFError`] is required. Returned data is shorter than the range if `data`
+    /// ends before the end of the range.
+    #[cfg(feature = "block_core")]
+    pub fn decompress_range(
+        &self,
+        data: &[u8],
+        range: std::ops::Range<u64>,
+    ) -> Result<Vec<u8>, crate::block::BlockError> {
+        use crate::block::{split_block, BlockError};
+
+        let begin = self
+            .uncompressed_pos_to_bgzf_pos(range.start)
+            .map_err(|_| BlockError::InvalidPosition)?;
+        let length: usize = range
+            .end
+            .saturating_sub(range.start)
+            .try_into()
+            .map_err(|_| BlockError::InvalidPosition)?;
+        let mut offset: usize = begin
+            .compressed_offset()
+            .try_into()
+            .map_err(|_| BlockError::InvalidPosition)?;
+        let skip: usize = begin.uncompressed_offset_in_block().into();
+
+        let mut result = Vec::with_capacity(length + skip);
+        while result.len() < length + skip && offset < data.len() {
+            let block = split_block(&data[offset..])?;
+            block.decompress(&mut result)?;
+            offset += block.block_size;
+        }
+        if result.len() < skip {
+            return Err(BlockError::InvalidPosition);
+        }
+        result.drain(..skip);
+        result.truncate(length);
+        Ok(result)
+    }
+
+    /// Split blocks into at most `n` partitions aligned to block boundaries.
+    ///
+    /// Each partition has nearly the same number of blocks and can be decompressed independently,
+    /// e.g. by seeking a [`crate::BGZFReader`] to [`BGZFPartition::bgzf_pos`] and reading until
+    /// [`BGZFPartition::uncompressed_end`]. Fewer partitions are returned if the file has fewer
+    /// blocks than `n`. The last partition continues to the end of the file, because .gzi index
+    /// does not record the end of the last block.
+    pub fn partition(&self, n: usize) -> Vec<BGZFPartition> {
+        let starts: Vec<BGZFIndexEntry> = std::iter::once(BGZFIndexEntry {
+            compressed_offset: 0,
+            uncompressed_offset: 0,
+        })
+        .chain(self.entries.iter().copied())
+        .collect();
+        let n = n.min(starts.len());
+        (0..n)
+            .map(|i| {
+                let start = starts[i * starts.len() / n];
+                let end = starts.get((i + 1) * starts.len() / n);
+                BGZFPartition {
+                    compressed_offset: start.compressed_offset,
+                    compressed_end: end.map(|x| x.compressed_offset),
+                    uncompressed_offset: start.uncompressed_offset,
+                    uncompressed_end: end.map(|x| x.uncompressed_offset),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Byte range of blocks returned by [`BGZFIndex::partition`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BGZFPartition {
+    /// Compressed offset of the first block
+    pub compressed_offset: u64,
+    /// Compressed offset of the next partition. `None` if this partition continues to the end of the file.
+    pub compressed_end: Option<u64>,
+    /// Uncompressed offset of the first block
+    pub uncompressed_offset: u64,
+    /// Uncompressed offset of the next partition. `None` if this partition continues to the end of the file.
+    pub uncompressed_end: Option<u64>,
+}
+
+impl BGZFPartition {
+    /// Virtual file offset of the beginning of this partition
+    pub fn bgzf_pos(&self) -> VirtualPosition {
+        VirtualPosition::new(self.compressed_offset, 0)
+    }
+}
+
+/// Problem of .gzi index found by [`BGZFIndex::validate`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexProblem {
+    /// Entry at `index` does not point to the start of a block, or its uncompressed offset is wrong
+    InvalidEntry { index: usize, entry: BGZFIndexEntry },
+    /// A block does not have an entry
+    MissingEntry(BGZFIndexEntry),
+}
+
+fn read_entry<R: Read>(reader: &mut R) -> std::io::Result<BGZFIndexEntry> {
+    let compressed_offset = reader.read_le_u64()?;
+    let uncompressed_offset = reader.read_le_u64()?;
+    Ok(BGZFIndexEntry {
+        compressed_offset,
+        uncompressed_offset,
+    })
+}
+
+/// Map unexpected end of .gzi index to [`BGZFError::TruncatedIndex`]
+fn truncated_index(e: std::io::Error) -> BGZFError {
+    if e.kind() == std::io::ErrorKind::UnexpectedEof {
+        BGZFError::TruncatedIndex
+    } else {
+        e.into()
     }
 }
 
@@ -89,6 +333,122 @@ pub struct BGZFIndexEntry {
     pub uncompressed_offset: u64,
 }
 
+impl BGZFIndexEntry {
+    /// Virtual file offset of uncompressed position `pos` in the block of this entry.
+    fn bgzf_pos(&self, pos: u64) -> VirtualPosition {
+        VirtualPosition::new(
+            self.compressed_offset,
+            ((pos - self.uncompressed_offset) & ((1 << 16) - 1)) as u16,
+        )
+    }
+}
+
+/// .gzi index which loads entries from the file on demand.
+///
+/// [`BGZFIndex`] loads all entries into memory. For huge indexes, this type binary-searches
+/// entries in the file instead, so position conversion requires `O(log n)` reads and no memory
+/// for entries.
+#[derive(Debug)]
+pub struct LazyBGZFIndex<R: Read + Seek> {
+    reader: R,
+    len: u64,
+}
+
+impl<R: Read + Seek> LazyBGZFIndex<R> {
+    /// Open .gzi index in `reader`. Only the number of entries is loaded.
+    pub fn new(mut reader: R) -> Result<Self, BGZFError> {
+        reader.seek(SeekFrom::Start(0))?;
+        let len = reader.read_le_u64().map_err(truncated_index)?;
+        let file_size = reader.seek(SeekFrom::End(0))?;
+        if len
+            .checked_mul(ENTRY_SIZE)
+            .and_then(|x| x.checked_add(8))
+            .map(|x| x > file_size)
+            .unwrap_or(true)
+        {
+            return Err(BGZFError::TruncatedIndex);
+        }
+        Ok(LazyBGZFIndex { reader, len })
+    }
+
+    /// Number of entries
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Return `true` if no entries are available
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Load `i`-th entry
+    pub fn entry(&mut self, i: u64) -> Result<BGZFIndexEntry, BGZFError> {
+        if i >= self.len {
+            return Err(BGZFError::Other("Index entry out of range"));
+        }
+        self.reader.seek(SeekFrom::Start(8 + i * ENTRY_SIZE))?;
+        read_entry(&mut self.reader).map_err(truncated_index)
+    }
+
+    /// Load all entries into [`BGZFIndex`]
+    pub fn load(&mut self) -> Result<BGZFIndex, BGZFError> {
+        self.reader.seek(SeekFrom::Start(0))?;
+        BGZFIndex::from_reader(std::io::BufReader::new(&mut self.reader))
+    }
+
+    /// Return the first index `i` where `predicate(entry(i))` is `false`.
+    /// `predicate` must be `true` for entries before `i`, and `false` for other entries.
+    fn partition_point<F: Fn(&BGZFIndexEntry) -> bool>(
+        &mut self,
+        predicate: F,
+    ) -> Result<u64, BGZFError> {
+        let mut left = 0;
+        let mut right = self.len;
+        while left < right {
+            let mid = left + (right - left) / 2;
+            if predicate(&self.entry(mid)?) {
+                left = mid + 1;
+            } else {
+                right = mid;
+            }
+        }
+        Ok(left)
+    }
+
+    /// Convert uncompressed position to bgzf virtual position. See [`BGZFIndex::uncompressed_pos_to_bgzf_pos`].
+    pub fn uncompressed_pos_to_bgzf_pos(&mut self, pos: u64) -> Result<VirtualPosition, BGZFError> {
+        let entry = match self.partition_point(|x| x.uncompressed_offset <= pos)? {
+            0 => BGZFIndexEntry {
+                compressed_offset: 0,
+                uncompressed_offset: 0,
+            },
+            i => self.entry(i - 1)?,
+        };
+        Ok(entry.bgzf_pos(pos))
+    }
+
+    /// Convert bgzf virtual position to uncompressed position. See [`BGZFIndex::bgzf_pos_to_uncompressed_pos`].
+    pub fn bgzf_pos_to_uncompressed_pos<P: Into<VirtualPosition>>(
+        &mut self,
+        bgzf_pos: P,
+    ) -> Result<u64, BGZFError> {
+        let bgzf_pos = bgzf_pos.into();
+        let compressed_pos = bgzf_pos.compressed_offset();
+        if compressed_pos == 0 {
+            return Ok(bgzf_pos.uncompressed_offset_in_block().into());
+        }
+        let i = self.partition_point(|x| x.compressed_offset < compressed_pos)?;
+        if i >= self.len {
+            return Err(BGZFError::Other("Invalid BGZF position"));
+        }
+        let entry = self.entry(i)?;
+        if entry.compressed_offset != compressed_pos {
+            return Err(BGZFError::Other("Invalid BGZF position"));
+        }
+        Ok(entry.uncompressed_offset + u64::from(bgzf_pos.uncompressed_offset_in_block()))
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -105,6 +465,185 @@ mod test {
         index.write(&mut generated_data)?;
         assert_eq!(data, generated_data);
 
+        assert!(matches!(
+            BGZFIndex::from_reader(&data[..(data.len() - 3)]),
+            Err(BGZFError::TruncatedIndex)
+        ));
+        assert!(matches!(
+            BGZFIndex::from_reader(&data[..5]),
+            Err(BGZFError::TruncatedIndex)
+        ));
+        // swap the 10th and 11th entries
+        let mut unsorted = data.clone();
+        let entry_start = 8 + 9 * ENTRY_SIZE as usize;
+        unsorted[entry_start..(entry_start + 32)].rotate_left(16);
+        assert!(matches!(
+            BGZFIndex::from_reader(&unsorted[..]),
+            Err(BGZFError::UnsortedIndexEntries { index: 10 })
+        ));
+
+        Ok(())
+    }
+
+    #[cfg(feature = "block_core")]
+    #[test]
+    fn test_decompress_range() -> anyhow::Result<()> {
+        let data = fs::read("testfiles/generated.bed.gz")?;
+        let index = BGZFIndex::from_reader(fs::File::open("testfiles/generated.bed.gz.gzi")?)?;
+        let mut expected_data = Vec::new();
+        flate2::read::MultiGzDecoder::new(&data[..]).read_to_end(&mut expected_data)?;
+        let length = expected_data.len() as u64;
+
+        for range in [
+            0..0,
+            0..100,
+            10..200_000,
+            65280..65281,
+            (length - 10)..length,
+            (length - 10)..(length + 10),
+            1_000_000..2_000_000,
+        ] {
+            let start = range.start as usize;
+            let end = (range.end.min(length) as usize).max(start);
+            assert_eq!(
+                index.decompress_range(&data, range)?,
+                &expected_data[start..end]
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_summary() -> anyhow::Result<()> {
+        let path = "testfiles/generated.bed.gz";
+        let index = BGZFIndex::from_reader(fs::File::open(format!("{}.gzi", path))?)?;
+        let blocks: Vec<_> = crate::read::RawBlocks::new(fs::File::open(path)?)
+            .filter(|x| !matches!(x, Ok(x) if x.uncompressed_size() == 0))
+            .collect::<Result<_, _>>()?;
+        let mut expected_data = Vec::new();
+        flate2::read::MultiGzDecoder::new(fs::File::open(path)?).read_to_end(&mut expected_data)?;
+
+        assert_eq!(index.block_count(), blocks.len());
+        assert_eq!(
+            index.uncompressed_size(fs::File::open(path)?)?,
+            expected_data.len() as u64
+        );
+        let ranges: Vec<_> = index.block_ranges().collect();
+        assert_eq!(ranges.len(), blocks.len());
+        for (i, (range, block)) in ranges.iter().zip(blocks.iter()).enumerate() {
+            assert_eq!(range.compressed_offset, block.compressed_offset);
+            assert_eq!(range.uncompressed_offset, block.uncompressed_offset);
+            if i + 1 < blocks.len() {
+                assert_eq!(
+                    range.compressed_end,
+                    Some(block.compressed_offset + block.compressed_size())
+                );
+                assert_eq!(
+                    range.uncompressed_end,
+                    Some(block.uncompressed_offset + block.uncompressed_size())
+                );
+            } else {
+                assert_eq!(range.compressed_end, None);
+            }
+
+            for pos in [
+                block.uncompressed_offset,
+                block.uncompressed_offset + block.uncompressed_size() - 1,
+            ] {
+                let entry = index.entry_for_uncompressed_pos(pos);
+                assert_eq!(entry.compressed_offset, block.compressed_offset);
+                assert_eq!(entry.uncompressed_offset, block.uncompressed_offset);
+            }
+        }
+
+        let empty = BGZFIndex::default();
+        assert_eq!(empty.block_count(), 1);
+        assert_eq!(empty.block_ranges().count(), 1);
+        assert_eq!(empty.entry_for_uncompressed_pos(100).compressed_offset, 0);
+        assert_eq!(
+            empty.uncompressed_size(std::io::Cursor::new(crate::EOF_MARKER))?,
+            0
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_validate() -> anyhow::Result<()> {
+        let path = "testfiles/generated.bed.gz";
+        let data = fs::read(path)?;
+        let index = BGZFIndex::from_reader(fs::File::open(format!("{}.gzi", path))?)?;
+        assert!(index.validate(&data[..])?.is_empty());
+
+        // truncated index
+        let mut truncated = index.clone();
+        truncated.entries.truncate(100);
+        let problems = truncated.validate(&data[..])?;
+        assert_eq!(problems.len(), index.entries.len() - 100);
+        assert_eq!(problems[0], IndexProblem::MissingEntry(index.entries[100]));
+        let mut repaired = truncated.clone();
+        assert_eq!(repaired.repair(&data[..])?, problems);
+        assert_eq!(repaired, index);
+
+        // stale index of regenerated data
+        let mut regenerated = Vec::new();
+        let mut writer = BGZFWriter::with_compress_unit_size(
+            &mut regenerated,
+            Compression::best(),
+            30000,
+            true,
+        )?;
+        let mut reader = crate::BGZFReader::new(&data[..])?;
+        std::io::copy(&mut reader, &mut writer)?;
+        let expected = writer.close()?.unwrap();
+        let problems = index.validate(&regenerated[..])?;
+        assert!(problems
+            .iter()
+            .any(|x| matches!(x, IndexProblem::InvalidEntry { index: 0, .. })));
+        assert!(problems
+            .iter()
+            .any(|x| matches!(x, IndexProblem::MissingEntry(_))));
+        let mut repaired = index.clone();
+        repaired.repair(&regenerated[..])?;
+        assert_eq!(repaired, expected);
+        assert!(repaired.validate(&regenerated[..])?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_from_bgzf_reader() -> anyhow::Result<()> {
+        for path in [
+            "testfiles/generated.bed.gz",
+            "testfiles/common_all_20180418_half.vcf.gz",
+        ] {
+            let expected = BGZFIndex::from_reader(fs::File::open(format!("{}.gzi", path))?)?;
+            let index =
+                BGZFIndex::from_bgzf_reader(std::io::BufReader::new(fs::File::open(path)?))?;
+            assert_eq!(index, expected);
+        }
+
+        let data = crate::testutil::SyntheticBGZF::new(29)
+            .with_block_count(4)
+            .generate();
+        assert_eq!(
+            BGZFIndex::from_bgzf_reader(&data.compressed[..])?,
+            data.index
+        );
+        let no_eof = crate::testutil::SyntheticBGZF::new(29)
+            .with_block_count(4)
+            .with_eof_marker(false)
+            .generate();
+        assert_eq!(
+            BGZFIndex::from_bgzf_reader(&no_eof.compressed[..])?,
+            data.index
+        );
+        assert!(BGZFIndex::from_bgzf_reader(&crate::EOF_MARKER[..])?
+            .entries
+            .is_empty());
+        assert!(BGZFIndex::from_bgzf_reader(&b"not bgzf data"[..]).is_err());
+
         Ok(())
     }
 
@@ -146,4 +685,88 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn test_lazy_index() -> anyhow::Result<()> {
+        let data = fs::read("testfiles/generated.bed.gz.gzi")?;
+        let index = BGZFIndex::from_reader(&data[..])?;
+        let mut lazy_index = LazyBGZFIndex::new(fs::File::open("testfiles/generated.bed.gz.gzi")?)?;
+        assert_eq!(lazy_index.len(), 295);
+        assert_eq!(lazy_index.entry(10)?, index.entries()[10]);
+        assert!(lazy_index.entry(295).is_err());
+        assert_eq!(lazy_index.load()?, index);
+
+        let last = index.entries().last().unwrap().uncompressed_offset;
+        for pos in [0, 1, 65279, 65280, 100_000, last - 1, last, last + 100] {
+            let bgzf_pos = index.uncompressed_pos_to_bgzf_pos(pos)?;
+            assert_eq!(lazy_index.uncompressed_pos_to_bgzf_pos(pos)?, bgzf_pos);
+            assert_eq!(
+                lazy_index.bgzf_pos_to_uncompressed_pos(bgzf_pos)?,
+                index.bgzf_pos_to_uncompressed_pos(bgzf_pos)?
+            );
+        }
+        assert!(lazy_index
+            .bgzf_pos_to_uncompressed_pos((index.entries()[3].compressed_offset + 1) << 16)
+            .is_err());
+        assert!(lazy_index.bgzf_pos_to_uncompressed_pos(u64::MAX).is_err());
+
+        assert!(LazyBGZFIndex::new(std::io::Cursor::new(&data[..(data.len() - 1)])).is_err());
+        let mut empty = LazyBGZFIndex::new(std::io::Cursor::new(&[0u8; 8][..]))?;
+        assert!(empty.is_empty());
+        assert_eq!(
+            empty.uncompressed_pos_to_bgzf_pos(100)?,
+            VirtualPosition::from(100)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_partition() -> anyhow::Result<()> {
+        let index = BGZFIndex::from_reader(fs::File::open("testfiles/generated.bed.gz.gzi")?)?;
+        let mut expected = Vec::new();
+        crate::BGZFReader::new(fs::File::open("testfiles/generated.bed.gz")?)?
+            .read_to_end(&mut expected)?;
+
+        let partitions = index.partition(7);
+        assert_eq!(partitions.len(), 7);
+        assert_eq!(partitions[0].compressed_offset, 0);
+        assert_eq!(partitions[6].compressed_end, None);
+        for pair in partitions.windows(2) {
+            assert_eq!(pair[0].compressed_end, Some(pair[1].compressed_offset));
+            assert_eq!(pair[0].uncompressed_end, Some(pair[1].uncompressed_offset));
+        }
+
+        // decode each partition independently
+        let mut concatenated = Vec::new();
+        for one in &partitions {
+            let mut reader = crate::BGZFReader::new(fs::File::open("testfiles/generated.bed.gz")?)?;
+            reader.bgzf_seek(one.bgzf_pos())?;
+            match one.uncompressed_end {
+                Some(end) => {
+                    let mut buf = vec![0; (end - one.uncompressed_offset) as usize];
+                    reader.read_exact(&mut buf)?;
+                    concatenated.extend_from_slice(&buf);
+                }
+                None => {
+                    reader.read_to_end(&mut concatenated)?;
+                }
+            }
+        }
+        assert_eq!(concatenated, expected);
+
+        assert_eq!(index.partition(1000).len(), 296);
+        assert!(index.partition(0).is_empty());
+        assert_eq!(
+            BGZFIndex::new().partition(3),
+            vec![BGZFPartition {
+                compressed_offset: 0,
+                compressed_end: None,
+                uncompressed_offset: 0,
+                uncompressed_end: None,
+            }]
+        );
+
+        Ok(())
+    }
 }
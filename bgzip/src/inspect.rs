@@ -0,0 +1,502 @@
+//! Format inspection of compressed files
+//!
+//! [`report`] detects format of a file, collects block statistics and checks consistency of
+//! `.gzi`, `.tbi` and `.csi` indexes placed next to the file in one call.
+//! [`strict_check`] verifies that every block follows the canonical BGZF layout.
+
+use crate::bai::BAI_PSEUDO_BIN;
+use crate::deflate::Decompress;
+use crate::header::{BGZFHeader, FLAG_FEXTRA, GZIP_ID1, GZIP_ID2};
+use crate::index::BGZFIndex;
+use crate::read::RawBlocks;
+use crate::tabix::Tabix;
+use crate::{BGZFError, VirtualPosition};
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+/// Detected file format
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Format {
+    /// BGZF
+    Bgzf,
+    /// Standard gzip which is not BGZF
+    Gzip,
+    /// Not compressed
+    Plain,
+}
+
+/// Statistics of BGZF blocks
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BlockStats {
+    /// Number of data blocks. End-of-file markers are not counted.
+    pub blocks: u64,
+    /// Number of end-of-file markers including markers in the middle of the file
+    pub eof_markers: u64,
+    /// Total size of blocks including headers
+    pub compressed_size: u64,
+    /// Total size of uncompressed data
+    pub uncompressed_size: u64,
+    /// Largest uncompressed size of a block
+    pub max_block_size: u64,
+}
+
+/// Consistency of an index file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexReport {
+    /// Path to the index file
+    pub path: PathBuf,
+    /// Description of the first problem found in the index. `None` if the index is consistent.
+    pub error: Option<String>,
+}
+
+impl IndexReport {
+    /// Return `true` if no problem is found.
+    pub fn is_consistent(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Result of [`report`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatReport {
+    /// Detected format
+    pub format: Format,
+    /// Block statistics. Available only for BGZF.
+    pub block_stats: Option<BlockStats>,
+    /// `true` if the file ends with [`crate::EOF_MARKER`]
+    pub has_eof_marker: bool,
+    /// Error found while scanning blocks, such as truncated or corrupted blocks
+    pub scan_error: Option<String>,
+    /// Report of `.gzi` index if exists
+    pub gzi: Option<IndexReport>,
+    /// Report of `.tbi` index if exists
+    pub tbi: Option<IndexReport>,
+    /// Report of `.csi` index if exists
+    pub csi: Option<IndexReport>,
+}
+
+/// Start offsets of blocks in the compressed and uncompressed data
+#[derive(Debug, Default)]
+struct BlockMap {
+    /// compressed offset, uncompressed offset and uncompressed size of each data block
+    blocks: Vec<(u64, u64, u64)>,
+    /// Compressed offset of the end of data
+    end: u64,
+}
+
+impl BlockMap {
+    fn check_virtual_offset(&self, bgzf_pos: VirtualPosition) -> Result<(), String> {
+        let compressed_offset = bgzf_pos.compressed_offset();
+        let position_in_block = u64::from(bgzf_pos.uncompressed_offset_in_block());
+        match self
+            .blocks
+            .binary_search_by_key(&compressed_offset, |x| x.0)
+        {
+            Ok(i) if position_in_block <= self.blocks[i].2 => Ok(()),
+            Err(_) if compressed_offset == self.end && position_in_block == 0 => Ok(()),
+            _ => Err(format!(
+                "Virtual offset {} does not point to data",
+                bgzf_pos
+            )),
+        }
+    }
+}
+
+/// Inspect a file at `path` and indexes next to it.
+///
+/// Index files are searched at `path` + `.gzi`, `.tbi` and `.csi`.
+/// Errors in the data or indexes are recorded in the report, and only I/O errors while
+/// opening `path` are returned as [`BGZFError`].
+pub fn report<P: AsRef<Path>>(path: P) -> Result<FormatReport, BGZFError> {
+    let path = path.as_ref();
+    let mut reader = BufReader::new(File::open(path)?);
+    let format = detect_format(&mut reader)?;
+
+    let mut result = FormatReport {
+        format,
+        block_stats: None,
+        has_eof_marker: false,
+        scan_error: None,
+        gzi: None,
+        tbi: None,
+        csi: None,
+    };
+
+    let mut block_map = BlockMap::default();
+    if format == Format::Bgzf {
+        let mut stats = BlockStats::default();
+        let mut last_is_eof = false;
+        for block in RawBlocks::new(&mut reader) {
+            let block = match block {
+                Ok(block) => block,
+                Err(e) => {
+                    result.scan_error = Some(e.to_string());
+                    break;
+                }
+            };
+            stats.compressed_size += block.compressed_size();
+            block_map.end = block.compressed_offset + block.compressed_size();
+            last_is_eof = block.data == crate::read::EOF_BLOCK;
+            if last_is_eof {
+                stats.eof_markers += 1;
+                block_map.end = block.compressed_offset;
+                continue;
+            }
+            stats.blocks += 1;
+            stats.uncompressed_size += block.uncompressed_size();
+            stats.max_block_size = stats.max_block_size.max(block.uncompressed_size());
+            block_map.blocks.push((
+                block.compressed_offset,
+                block.uncompressed_offset,
+                block.uncompressed_size(),
+            ));
+        }
+        result.has_eof_marker = last_is_eof && result.scan_error.is_none();
+        result.block_stats = Some(stats);
+    }
+
+    result.gzi = check_index(path, "gzi", format, |x| check_gzi(x, &block_map));
+    result.tbi = check_index(path, "tbi", format, |x| check_tbi(x, &block_map));
+    result.csi = check_index(path, "csi", format, |x| check_csi(x, &block_map));
+
+    Ok(result)
+}
+
+/// A violation of canonical BGZF layout found by [`strict_check`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// Compressed offset of the block
+    pub offset: u64,
+    /// Description of the violation
+    pub message: String,
+}
+
+/// Verify that all blocks read from `reader` follow the canonical BGZF layout.
+///
+/// Each header must have only FEXTRA flag and XLEN=6 with `BC` subfield of SLEN=2, BSIZE must
+/// match the size of the block, uncompressed data must be at most 64KiB, and the data must end
+/// with byte-exact [`crate::EOF_MARKER`]. Scan stops at the first block which cannot be parsed.
+/// Violations are returned as a list, and only I/O errors except unexpected end of file are
+/// returned as [`BGZFError`].
+pub fn strict_check<R: Read>(reader: R) -> Result<Vec<Violation>, BGZFError> {
+    let mut reader = BufReader::new(reader);
+    let mut violations = Vec::new();
+    let mut decompress = Decompress::new();
+    let mut buffer = Vec::new();
+    let mut decompressed = Vec::new();
+    let mut offset = 0;
+    let mut last_block = None;
+
+    while !reader.fill_buf()?.is_empty() {
+        let mut violation = |message: &str| {
+            violations.push(Violation {
+                offset,
+                message: message.to_string(),
+            })
+        };
+        let header = match BGZFHeader::from_reader(&mut reader) {
+            Ok(header) => header,
+            Err(BGZFError::IoError(e)) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                violation("Truncated header");
+                return Ok(violations);
+            }
+            Err(BGZFError::IoError(e)) => return Err(e.into()),
+            Err(e) => {
+                violation(&format!("Invalid header: {}", e));
+                return Ok(violations);
+            }
+        };
+        if header.flags != FLAG_FEXTRA {
+            violation("FLG must be FEXTRA only");
+        }
+        if header.extra_field_len != Some(6) {
+            violation("XLEN must be 6");
+        }
+        match header.extra_field.first() {
+            Some(x) if x.id1() == b'B' && x.id2() == b'C' && x.data().len() == 2 => (),
+            _ => violation("The first subfield must be BC with SLEN=2"),
+        }
+        let block_size: u64 = match header.block_size() {
+            Ok(x) => x.into(),
+            Err(_) => {
+                violation("BC subfield is missing");
+                return Ok(violations);
+            }
+        };
+        let data_size = match block_size.checked_sub(header.header_size()) {
+            Some(x) if x >= 8 => x,
+            _ => {
+                violation("BSIZE is smaller than the header and the footer");
+                return Ok(violations);
+            }
+        };
+
+        buffer.clear();
+        let read_bytes = (&mut reader).take(data_size).read_to_end(&mut buffer)?;
+        if u64::try_from(read_bytes).unwrap() < data_size {
+            violation("Truncated block");
+            return Ok(violations);
+        }
+        let isize = u32::from_le_bytes(buffer[(buffer.len() - 4)..].try_into().unwrap());
+        if isize > 65536 {
+            violation("ISIZE must be at most 65536");
+        } else {
+            decompressed.clear();
+            if let Err(e) =
+                crate::read::decompress_block(&mut decompressed, &buffer, &mut decompress)
+            {
+                violation(&format!("Broken block data: {}", e));
+            }
+        }
+
+        let is_eof_marker = if buffer == crate::read::EOF_BLOCK {
+            let mut raw = Vec::new();
+            header.write(&mut raw)?;
+            raw.extend_from_slice(&buffer);
+            raw == crate::EOF_MARKER
+        } else {
+            false
+        };
+        last_block = Some((offset, is_eof_marker));
+        offset += block_size;
+    }
+
+    match last_block {
+        Some((_, true)) => (),
+        Some((last_offset, false)) => violations.push(Violation {
+            offset: last_offset,
+            message: "The last block must be the end-of-file marker".to_string(),
+        }),
+        None => violations.push(Violation {
+            offset: 0,
+            message: "Empty data".to_string(),
+        }),
+    }
+
+    Ok(violations)
+}
+
+pub(crate) fn detect_format<R: io::BufRead>(reader: &mut R) -> io::Result<Format> {
+    let magics = reader.fill_buf()?;
+    if magics.len() < 2 || magics[0] != GZIP_ID1 || magics[1] != GZIP_ID2 {
+        return Ok(Format::Plain);
+    }
+    match BGZFHeader::from_reader(magics) {
+        Ok(header) if header.block_size().is_ok() => Ok(Format::Bgzf),
+        _ => Ok(Format::Gzip),
+    }
+}
+
+fn check_index<F>(path: &Path, extension: &str, format: Format, check: F) -> Option<IndexReport>
+where
+    F: FnOnce(File) -> Result<(), String>,
+{
+    let mut index_path = path.as_os_str().to_os_string();
+    index_path.push(".");
+    index_path.push(extension);
+    let index_path = PathBuf::from(index_path);
+    let file = File::open(&index_path).ok()?;
+    let error = if format == Format::Bgzf {
+        check(file).err()
+    } else {
+        Some("Data is not BGZF".to_string())
+    };
+    Some(IndexReport {
+        path: index_path,
+        error,
+    })
+}
+
+fn check_gzi(file: File, block_map: &BlockMap) -> Result<(), String> {
+    let index = BGZFIndex::from_reader(BufReader::new(file)).map_err(|e| e.to_string())?;
+    for entry in index.entries() {
+        match block_map
+            .blocks
+            .binary_search_by_key(&entry.compressed_offset, |x| x.0)
+        {
+            Ok(i) if block_map.blocks[i].1 == entry.uncompressed_offset => (),
+            Ok(_) => {
+                return Err(format!(
+                    "Uncompressed offset {} of block at {} is not matched",
+                    entry.uncompressed_offset, entry.compressed_offset
+                ))
+            }
+            // The last entry may point to the end of data
+            Err(_) if entry.compressed_offset == block_map.end => (),
+            Err(_) => {
+                return Err(format!(
+                    "Compressed offset {} does not point to a block",
+                    entry.compressed_offset
+                ))
+            }
+        }
+    }
+    Ok(())
+}
+
+fn check_tbi(file: File, block_map: &BlockMap) -> Result<(), String> {
+    let tabix = Tabix::from_reader(file).map_err(|e| e.to_string())?;
+    for sequence in &tabix.sequences {
+        for bin in sequence.bins.values() {
+            // chunks in the pseudo bin store numbers of records except the first one
+            let chunk_num = if bin.bin == BAI_PSEUDO_BIN {
+                1
+            } else {
+                usize::MAX
+            };
+            for chunk in bin.chunks.iter().take(chunk_num) {
+                block_map.check_virtual_offset(chunk.begin)?;
+                block_map.check_virtual_offset(chunk.end)?;
+            }
+        }
+        for one in &sequence.intervals {
+            // empty intervals are filled with zero
+            if *one != VirtualPosition::default() {
+                block_map.check_virtual_offset(*one)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn check_csi(file: File, block_map: &BlockMap) -> Result<(), String> {
+    csi_virtual_offsets(file)
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .try_for_each(|x| block_map.check_virtual_offset(x))
+}
+
+/// List virtual offsets in CSI index except metadata in pseudo bins.
+fn csi_virtual_offsets<R: Read>(reader: R) -> Result<Vec<VirtualPosition>, BGZFError> {
+    let index = crate::csi::CSIIndex::from_reader(reader)?;
+    let pseudo_bin = index.pseudo_bin()?;
+    let mut offsets = Vec::new();
+    for one_bin in index.sequences.iter().flat_map(|x| x.bins.values()) {
+        if one_bin.bin != pseudo_bin {
+            offsets.push(one_bin.loffset);
+        }
+        for (i, one) in one_bin.chunks.iter().enumerate() {
+            if one_bin.bin != pseudo_bin || i == 0 {
+                offsets.push(one.begin);
+                offsets.push(one.end);
+            }
+        }
+    }
+    Ok(offsets)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_report() -> anyhow::Result<()> {
+        let result = report("testfiles/common_all_20180418_half.vcf.gz")?;
+        assert_eq!(result.format, Format::Bgzf);
+        assert!(result.has_eof_marker);
+        assert_eq!(result.scan_error, None);
+        let stats = result.block_stats.unwrap();
+        assert_eq!(stats.eof_markers, 1);
+        assert_eq!(
+            stats.compressed_size,
+            fs::metadata("testfiles/common_all_20180418_half.vcf.gz")?.len()
+        );
+        assert!(stats.max_block_size <= 65536);
+        for one in [result.gzi, result.tbi, result.csi] {
+            assert!(one.unwrap().is_consistent());
+        }
+
+        let result = report("testfiles/generated.bed.gz")?;
+        assert_eq!(result.format, Format::Bgzf);
+        assert!(result.gzi.unwrap().is_consistent());
+        assert!(result.tbi.unwrap().is_consistent());
+        assert_eq!(result.csi, None);
+
+        let result = report("testfiles/common_all_20180418_half.vcf.nobgzip.gz")?;
+        assert_eq!(result.format, Format::Gzip);
+        assert_eq!(result.block_stats, None);
+        assert!(!result.has_eof_marker);
+
+        let result = report("testfiles/reg2bin.c")?;
+        assert_eq!(result.format, Format::Plain);
+
+        // truncated data with index of other file
+        let data = fs::read("testfiles/generated.bed.gz")?;
+        fs::write("tmp/inspect-truncated.bed.gz", &data[..(data.len() - 100)])?;
+        fs::copy(
+            "testfiles/common_all_20180418_half.vcf.gz.gzi",
+            "tmp/inspect-truncated.bed.gz.gzi",
+        )?;
+        let result = report("tmp/inspect-truncated.bed.gz")?;
+        assert_eq!(result.format, Format::Bgzf);
+        assert!(!result.has_eof_marker);
+        assert!(result.scan_error.is_some());
+        assert!(!result.gzi.unwrap().is_consistent());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_strict_check() -> anyhow::Result<()> {
+        assert_eq!(
+            strict_check(fs::File::open("testfiles/common_all_20180418_half.vcf.gz")?)?,
+            vec![]
+        );
+
+        let data = crate::testutil::SyntheticBGZF::new(7)
+            .with_block_count(3)
+            .generate();
+        assert_eq!(strict_check(&data.compressed[..])?, vec![]);
+
+        let no_eof = crate::testutil::SyntheticBGZF::new(7)
+            .with_block_count(3)
+            .with_eof_marker(false)
+            .generate();
+        let violations = strict_check(&no_eof.compressed[..])?;
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].offset, no_eof.block_offsets[2]);
+
+        let broken = crate::testutil::SyntheticBGZF::new(7)
+            .with_block_count(3)
+            .with_corruption(crate::testutil::Corruption::BadCrc { block: 1 })
+            .with_corruption(crate::testutil::Corruption::Truncate { block: 2 })
+            .generate();
+        let violations = strict_check(&broken.compressed[..])?;
+        let offsets: Vec<_> = violations.iter().map(|x| x.offset).collect();
+        assert_eq!(
+            offsets,
+            vec![broken.block_offsets[1], broken.block_offsets[2]]
+        );
+        assert_eq!(violations[1].message, "Truncated block");
+
+        // an extra subfield before BC
+        let mut header = BGZFHeader::new(false, 0, 2);
+        header
+            .extra_field
+            .insert(0, crate::header::ExtraField::new(b'X', b'Y', vec![0]));
+        header.extra_field_len = Some(11);
+        header.update_block_size(u16::try_from(header.header_size()).unwrap() + 10)?;
+        let mut data = Vec::new();
+        header.write(&mut data)?;
+        data.extend_from_slice(&crate::read::EOF_BLOCK);
+        data.extend_from_slice(&crate::EOF_MARKER);
+        let messages: Vec<_> = strict_check(&data[..])?
+            .into_iter()
+            .map(|x| x.message)
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                "XLEN must be 6",
+                "The first subfield must be BC with SLEN=2"
+            ]
+        );
+
+        assert_eq!(strict_check(&b""[..])?.len(), 1);
+
+        Ok(())
+    }
+}
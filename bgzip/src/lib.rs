@@ -13,6 +13,7 @@
 //! -------------
 //!
 //! * `rayon`: Enable [rayon](https://github.com/rayon-rs/rayon) based multi-threaded writer. This is default feature.
+//! * `threads`: Enable multi-threaded reader/writer running on a pool of `std::thread` workers, without rayon. This feature is enabled by `rayon`.
 //! * `log`: Enable [log](https://github.com/rust-lang/log) crate to log warnings. This is default feature.
 //! * `rust_backend`: use use [miniz_oxide](https://crates.io/crates/miniz_oxide) crate for [flate2](https://github.com/rust-lang/flate2-rs) backend. This is default feature.
 //! * `zlib`: use `zlib` for flate2 backend. Please read [flate2](https://github.com/rust-lang/flate2-rs) description for the detail.
@@ -20,6 +21,15 @@
 //! * `zlib-ng-compat`: Please read [flate2](https://github.com/rust-lang/flate2-rs) description for the detail.
 //! * `cloudflare_zlib`: Please read [flate2](https://github.com/rust-lang/flate2-rs) description for the detail.
 //! * `libdeflater`: use [libdeflater](https://github.com/adamkewley/libdeflater) instead of [flate2](https://github.com/rust-lang/flate2-rs) crate.
+//! * `crc32fast`: calculate CRC32 of blocks with [crc32fast](https://crates.io/crates/crc32fast), which uses SSE4.2/PCLMULQDQ or ARMv8 CRC instructions if available. Run `checksum-bench` example to compare the time spent for checksum and compression.
+//! * `testutil`: Enable `testutil` module to generate synthetic BGZF data for tests.
+//! * `metrics`: Enable `metrics` module to record durations of compression and decompression of each block.
+//! * `mmap`: Enable `BGZFReader::from_mmap` to decompress blocks directly from a memory mapped file with [memmap2](https://crates.io/crates/memmap2).
+//! * `async`: Enable `async_io` module, which provides `AsyncBGZFReader` and `AsyncBGZFWriter` based on [tokio](https://tokio.rs) `AsyncRead`/`AsyncWrite`.
+//! * `zstd`: Enable `bgzf_zstd` module, which provides BGZF-like blocks compressed with [zstd](https://crates.io/crates/zstd). This format is not compatible with htslib.
+//! * `std`: Enable readers, writers and index parsers based on `std::io`. This feature is enabled by all features above.
+//! * `block_core`: Enable [`block`] module, which parses and decompresses BGZF blocks in memory. This module works
+//!   without `std` feature (`no_std` + `alloc`). This is default feature.
 //!
 //! Write Examples
 //! --------
@@ -36,7 +46,7 @@
 //! }
 //! ```
 //!
-//! Multi-thread support is available via [`write::BGZFMultiThreadWriter`]. `rayon` flag is required to use this feature.
+//! Multi-thread support is available via [`write::BGZFMultiThreadWriter`]. `rayon` or `threads` flag is required to use this feature.
 //!
 //! Read Examples
 //! --------
@@ -59,27 +69,79 @@
 //! }
 //! ```
 
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(feature = "block_core")]
+extern crate alloc;
+
+#[cfg(feature = "std")]
 mod error;
 
-pub(crate) mod csi;
+#[cfg(feature = "async")]
+pub mod async_io;
+#[cfg(feature = "std")]
+pub mod bai;
+#[cfg(feature = "zstd")]
+pub mod bgzf_zstd;
+#[cfg(feature = "block_core")]
+pub mod block;
+#[cfg(feature = "threads")]
+pub mod cancel;
+#[cfg(feature = "std")]
+pub mod check;
+#[cfg(feature = "std")]
+pub mod csi;
+#[cfg(feature = "std")]
 pub mod deflate;
+#[cfg(feature = "std")]
+pub mod framed;
 /// BGZ header parser
+#[cfg(feature = "std")]
 pub mod header;
+#[cfg(feature = "std")]
 pub mod index;
-#[cfg(feature = "rayon")]
-pub(crate) mod rayon;
+#[cfg(feature = "std")]
+pub mod inspect;
+#[cfg(feature = "std")]
+pub mod line_index;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(all(feature = "std", not(feature = "metrics")))]
+mod metrics;
+#[cfg(feature = "threads")]
+pub(crate) mod pool;
+mod position;
+#[cfg(feature = "std")]
 pub mod read;
+#[cfg(feature = "std")]
+pub mod recompress;
+#[cfg(feature = "std")]
+pub mod sniff;
 
-pub use deflate::Compression;
+#[cfg(feature = "std")]
+pub use deflate::{Backend, Compression};
 /// Tabix file parser. (This module is alpha state.)
+#[cfg(feature = "std")]
 pub mod tabix;
+#[cfg(any(test, feature = "testutil"))]
+pub mod testutil;
+#[cfg(feature = "std")]
 pub mod write;
+#[cfg(feature = "std")]
 pub use error::BGZFError;
+pub use position::VirtualPosition;
+#[cfg(feature = "std")]
 pub use read::BGZFReader;
-pub use read::{new_reader, open};
+#[cfg(feature = "std")]
+pub use read::{copy_range, new_reader, open};
+#[cfg(feature = "std")]
+pub use recompress::recompress;
+#[cfg(feature = "std")]
 pub use write::create;
+#[cfg(feature = "std")]
 pub use write::BGZFWriter;
 
+#[cfg(feature = "std")]
 use std::io;
 
 /// End-of-file maker.
@@ -90,6 +152,12 @@ pub const EOF_MARKER: [u8; 28] = [
     0x1b, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
 ];
 
+/// Return `true` if `block` is byte-exact [`EOF_MARKER`].
+pub fn is_eof_block(block: &[u8]) -> bool {
+    block == EOF_MARKER
+}
+
+#[cfg(feature = "std")]
 pub(crate) trait BinaryReader: io::Read {
     fn read_le_u8(&mut self) -> io::Result<u8> {
         let mut buf: [u8; 1] = [0];
@@ -135,6 +203,7 @@ pub(crate) trait BinaryReader: io::Read {
     }
 }
 
+#[cfg(feature = "std")]
 impl<R: io::Read> BinaryReader for R {}
 
 #[cfg(test)]
@@ -0,0 +1,237 @@
+//! Line index of bgzipped text
+//!
+//! [`LineIndex`] keeps virtual file offsets of every `interval`-th line, so any line of large text
+//! files without genomic coordinates, such as TSV, can be reached by reading at most `interval - 1`
+//! lines after a seek. Line numbers are zero-based.
+//!
+//! ```rust
+//! # fn main() -> Result<(), bgzip::BGZFError> {
+//! use bgzip::line_index::LineIndex;
+//! use bgzip::BGZFReader;
+//! use std::io::BufRead;
+//!
+//! let mut reader = BGZFReader::new(std::fs::File::open("testfiles/generated.bed.gz")?)?;
+//! let index = LineIndex::build(&mut reader, 100)?;
+//! index.seek_to_line(&mut reader, 1234)?;
+//! let mut line = String::new();
+//! reader.read_line(&mut line)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{BGZFError, BGZFReader, BinaryReader, VirtualPosition};
+use std::io::{self, BufRead, Read, Seek, Write};
+
+/// Magic bytes of line index files
+pub const LINE_INDEX_MAGIC: [u8; 4] = [b'B', b'L', b'I', 1];
+
+/// Virtual file offsets of every `interval`-th line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineIndex {
+    interval: u64,
+    line_count: u64,
+    offsets: Vec<VirtualPosition>,
+}
+
+impl LineIndex {
+    /// Scan text from the current position of `reader` to the end, and keep virtual file offsets
+    /// of lines whose numbers are multiples of `interval`.
+    ///
+    /// The line at the current position is the line 0. The last line without a trailing new line
+    /// is counted.
+    pub fn build<R: Read>(reader: &mut BGZFReader<R>, interval: u64) -> Result<Self, BGZFError> {
+        if interval == 0 {
+            return Err(BGZFError::Other("Line index interval must be positive"));
+        }
+        let mut offsets = Vec::new();
+        let mut line_count = 0;
+        let mut at_line_start = true;
+        while let Some(pos) = reader.next_bgzf_pos()? {
+            if at_line_start {
+                if line_count % interval == 0 {
+                    offsets.push(pos);
+                }
+                at_line_start = false;
+            }
+            let buf = reader.fill_buf()?;
+            match buf.iter().position(|x| *x == b'\n') {
+                Some(i) => {
+                    reader.consume(i + 1);
+                    line_count += 1;
+                    at_line_start = true;
+                }
+                None => {
+                    let len = buf.len();
+                    reader.consume(len);
+                }
+            }
+        }
+        if !at_line_start {
+            line_count += 1;
+        }
+        Ok(LineIndex {
+            interval,
+            line_count,
+            offsets,
+        })
+    }
+
+    /// Load line index file from `reader`
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self, BGZFError> {
+        let mut reader = io::BufReader::new(reader);
+        let mut buf = [0u8; 4];
+        reader.read_exact(&mut buf)?;
+        if buf != LINE_INDEX_MAGIC {
+            return Err(BGZFError::Other("Not line index format"));
+        }
+        let interval = reader.read_le_u64()?;
+        let line_count = reader.read_le_u64()?;
+        if interval == 0 {
+            return Err(BGZFError::Other("Line index interval must be positive"));
+        }
+        let mut offsets = Vec::new();
+        for i in 0..line_count.div_ceil(interval) {
+            let offset = VirtualPosition::from(reader.read_le_u64()?);
+            if offsets.last().is_some_and(|x| *x >= offset) {
+                return Err(BGZFError::UnsortedIndexEntries { index: i });
+            }
+            offsets.push(offset);
+        }
+        Ok(LineIndex {
+            interval,
+            line_count,
+            offsets,
+        })
+    }
+
+    /// Write line index file into `writer`
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(&LINE_INDEX_MAGIC)?;
+        writer.write_all(&self.interval.to_le_bytes())?;
+        writer.write_all(&self.line_count.to_le_bytes())?;
+        for one in &self.offsets {
+            writer.write_all(&u64::from(*one).to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Interval of indexed lines
+    pub fn interval(&self) -> u64 {
+        self.interval
+    }
+
+    /// Number of lines
+    pub fn line_count(&self) -> u64 {
+        self.line_count
+    }
+
+    /// Virtual file offsets of lines `0`, `interval`, `2 * interval`, ...
+    pub fn offsets(&self) -> &[VirtualPosition] {
+        &self.offsets
+    }
+
+    /// Move `reader` to the beginning of the line `line`.
+    ///
+    /// `reader` is moved to the nearest indexed line and following lines are skipped.
+    /// An error is returned if `line` is not smaller than [`LineIndex::line_count`].
+    pub fn seek_to_line<R: Read + Seek>(
+        &self,
+        reader: &mut BGZFReader<R>,
+        line: u64,
+    ) -> Result<(), BGZFError> {
+        if line >= self.line_count {
+            return Err(BGZFError::Other("Line number out of range"));
+        }
+        let index: usize = (line / self.interval).try_into().unwrap();
+        reader.bgzf_seek(self.offsets[index])?;
+        for _ in 0..(line % self.interval) {
+            skip_line(reader)?;
+        }
+        Ok(())
+    }
+}
+
+/// Consume bytes to the next new line character
+fn skip_line<R: BufRead>(reader: &mut R) -> io::Result<()> {
+    loop {
+        let buf = reader.fill_buf()?;
+        if buf.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "data ends before the indexed line",
+            ));
+        }
+        match buf.iter().position(|x| *x == b'\n') {
+            Some(i) => {
+                reader.consume(i + 1);
+                return Ok(());
+            }
+            None => {
+                let len = buf.len();
+                reader.consume(len);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs::File;
+
+    #[test]
+    fn test_line_index() -> anyhow::Result<()> {
+        let mut expected_data = String::new();
+        flate2::read::MultiGzDecoder::new(File::open("testfiles/generated.bed.gz")?)
+            .read_to_string(&mut expected_data)?;
+        let expected: Vec<&str> = expected_data.lines().collect();
+
+        let mut reader = BGZFReader::new(File::open("testfiles/generated.bed.gz")?)?;
+        for interval in [1, 7, 1000] {
+            reader.bgzf_seek(0)?;
+            let index = LineIndex::build(&mut reader, interval)?;
+            assert_eq!(index.line_count(), expected.len() as u64);
+            assert_eq!(
+                index.offsets().len() as u64,
+                index.line_count().div_ceil(interval)
+            );
+
+            let mut serialized = Vec::new();
+            index.write(&mut serialized)?;
+            assert_eq!(serialized.len(), 20 + 8 * index.offsets().len());
+            let loaded = LineIndex::from_reader(&serialized[..])?;
+            assert_eq!(loaded, index);
+
+            let mut line = String::new();
+            for n in [0, 1, 6, 7, 999, 1000, 1001, expected.len() - 1] {
+                loaded.seek_to_line(&mut reader, n as u64)?;
+                line.clear();
+                reader.read_line(&mut line)?;
+                assert_eq!(line.trim_end(), expected[n]);
+            }
+            assert!(loaded
+                .seek_to_line(&mut reader, expected.len() as u64)
+                .is_err());
+        }
+
+        // last line without new line
+        let mut compressed = Vec::new();
+        let mut writer = crate::BGZFWriter::new(&mut compressed, crate::Compression::default());
+        writer.write_all(b"a\nb\n\nc")?;
+        writer.close()?;
+        let mut reader = BGZFReader::new(io::Cursor::new(compressed))?;
+        let index = LineIndex::build(&mut reader, 2)?;
+        assert_eq!(index.line_count(), 4);
+        index.seek_to_line(&mut reader, 3)?;
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        assert_eq!(line, "c");
+
+        assert!(LineIndex::from_reader(&b"TBI\x01"[..]).is_err());
+        let mut serialized = Vec::new();
+        index.write(&mut serialized)?;
+        assert!(LineIndex::from_reader(&serialized[..(serialized.len() - 1)]).is_err());
+
+        Ok(())
+    }
+}
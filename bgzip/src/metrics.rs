@@ -0,0 +1,166 @@
+//! Latency instrumentation of block operations
+//!
+//! This module is available with `metrics` feature. Pass a [`HistogramSink`] to `with_histogram`
+//! methods of readers and writers to record the duration of compression or decompression of
+//! each BGZF block.
+//!
+//! ```rust
+//! # #[cfg(feature = "metrics")]
+//! # fn main() -> std::io::Result<()> {
+//! use bgzip::metrics::{BlockOperation, HistogramSink};
+//! use std::sync::{Arc, Mutex};
+//! use std::time::Duration;
+//!
+//! #[derive(Default)]
+//! struct Durations(Mutex<Vec<(BlockOperation, Duration)>>);
+//!
+//! impl HistogramSink for Durations {
+//!     fn record(&self, operation: BlockOperation, duration: Duration) {
+//!         self.0.lock().unwrap().push((operation, duration));
+//!     }
+//! }
+//!
+//! let durations = Arc::new(Durations::default());
+//! let mut data = Vec::new();
+//! let mut writer = bgzip::BGZFWriter::new(&mut data, bgzip::Compression::default())
+//!     .with_histogram(durations.clone());
+//! std::io::Write::write_all(&mut writer, b"hello")?;
+//! writer.close()?;
+//! assert_eq!(durations.0.lock().unwrap()[0].0, BlockOperation::Compress);
+//! # Ok(())
+//! # }
+//! # #[cfg(not(feature = "metrics"))]
+//! # fn main() {}
+//! ```
+
+#[cfg(feature = "metrics")]
+use std::sync::Arc;
+#[cfg(feature = "metrics")]
+use std::time::{Duration, Instant};
+
+/// Kind of recorded operation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BlockOperation {
+    /// Compression of a block
+    Compress,
+    /// Decompression of a block
+    Decompress,
+}
+
+/// Destination of recorded durations, such as a histogram.
+///
+/// `record` is called from worker threads of multi-thread readers and writers, so implementations
+/// should be cheap and thread safe.
+#[cfg(feature = "metrics")]
+pub trait HistogramSink: Send + Sync {
+    /// Record `duration` of `operation` for one block
+    fn record(&self, operation: BlockOperation, duration: Duration);
+}
+
+/// Measure durations if a sink is set. This is a no-op without `metrics` feature.
+#[cfg(feature = "metrics")]
+#[derive(Clone)]
+pub(crate) struct Recorder(Option<Arc<dyn HistogramSink>>);
+
+#[cfg(not(feature = "metrics"))]
+#[derive(Clone)]
+pub(crate) struct Recorder;
+
+impl Recorder {
+    /// Create a recorder without a sink
+    pub(crate) fn disabled() -> Self {
+        #[cfg(feature = "metrics")]
+        return Recorder(None);
+        #[cfg(not(feature = "metrics"))]
+        Recorder
+    }
+
+    #[cfg(feature = "metrics")]
+    pub(crate) fn new(sink: Arc<dyn HistogramSink>) -> Self {
+        Recorder(Some(sink))
+    }
+
+    /// Run `f` and record its duration as `operation`
+    #[inline]
+    pub(crate) fn record<T, F: FnOnce() -> T>(&self, operation: BlockOperation, f: F) -> T {
+        #[cfg(feature = "metrics")]
+        if let Some(sink) = self.0.as_ref() {
+            let start = Instant::now();
+            let result = f();
+            sink.record(operation, start.elapsed());
+            return result;
+        }
+        #[cfg(not(feature = "metrics"))]
+        let _ = operation;
+        f()
+    }
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod test {
+    use super::*;
+    use crate::{BGZFReader, BGZFWriter, Compression};
+    use std::io::{Read, Write};
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct Counter(Mutex<Vec<BlockOperation>>);
+
+    impl HistogramSink for Counter {
+        fn record(&self, operation: BlockOperation, _duration: Duration) {
+            self.0.lock().unwrap().push(operation);
+        }
+    }
+
+    impl Counter {
+        fn take(&self) -> Vec<BlockOperation> {
+            std::mem::take(&mut self.0.lock().unwrap())
+        }
+    }
+
+    #[test]
+    fn test_histogram() -> anyhow::Result<()> {
+        let data = crate::testutil::SyntheticBGZF::new(5)
+            .with_block_count(4)
+            .generate();
+        let counter = Arc::new(Counter::default());
+
+        let mut compressed = Vec::new();
+        let mut writer = BGZFWriter::new(&mut compressed, Compression::default())
+            .with_histogram(counter.clone());
+        writer.write_all(&data.uncompressed)?;
+        writer.close()?;
+        assert_eq!(counter.take(), vec![BlockOperation::Compress; 4]);
+
+        // the first block is loaded before setting the sink
+        let mut reader = BGZFReader::new(&compressed[..])?.with_histogram(counter.clone());
+        let mut decompressed = Vec::new();
+        reader.read_to_end(&mut decompressed)?;
+        assert_eq!(decompressed, data.uncompressed);
+        assert_eq!(counter.take(), vec![BlockOperation::Decompress; 3]);
+
+        #[cfg(feature = "threads")]
+        {
+            let mut compressed = Vec::new();
+            let mut writer =
+                crate::write::BGZFMultiThreadWriter::new(&mut compressed, Compression::default())
+                    .with_histogram(counter.clone());
+            writer.write_all(&data.uncompressed)?;
+            writer.close()?;
+            assert_eq!(counter.take(), vec![BlockOperation::Compress; 4]);
+
+            let mut reader =
+                crate::read::BGZFMultiThreadReader::with_process_block_num(&compressed[..], 1)?
+                    .with_histogram(counter.clone());
+            let mut decompressed = Vec::new();
+            reader.read_to_end(&mut decompressed)?;
+            assert_eq!(decompressed, data.uncompressed);
+            assert!(counter
+                .take()
+                .iter()
+                .all(|x| *x == BlockOperation::Decompress));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,177 @@
+use crate::BGZFError;
+use std::sync::mpsc::{channel, Receiver, RecvError, Sender};
+#[cfg(feature = "rayon")]
+use std::sync::mpsc::{RecvTimeoutError, TryRecvError};
+use std::sync::{Arc, Mutex, OnceLock};
+
+#[cfg(feature = "rayon")]
+const TIMEOUT_DURATION: std::time::Duration = std::time::Duration::from_millis(10);
+
+#[cfg(feature = "rayon")]
+pub(crate) fn receive_or_yield<R>(receiver: &Receiver<R>) -> std::result::Result<R, RecvError> {
+    loop {
+        match receiver.try_recv() {
+            Ok(t) => return Ok(t),
+            Err(TryRecvError::Empty) => match rayon::yield_now() {
+                None => return receiver.recv(),
+                Some(rayon::Yield::Executed) => continue,
+                Some(rayon::Yield::Idle) => match receiver.recv_timeout(TIMEOUT_DURATION) {
+                    Ok(t) => return Ok(t),
+                    Err(RecvTimeoutError::Timeout) => {
+                        //dbg!("receive idle");
+                        continue;
+                    }
+                    Err(RecvTimeoutError::Disconnected) => return Err(RecvError),
+                },
+            },
+            Err(TryRecvError::Disconnected) => return Err(RecvError),
+        }
+    }
+}
+
+#[cfg(not(feature = "rayon"))]
+pub(crate) fn receive_or_yield<R>(receiver: &Receiver<R>) -> std::result::Result<R, RecvError> {
+    receiver.recv()
+}
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// User-supplied function which schedules a job
+pub(crate) type Executor = Arc<dyn Fn(Job) + Send + Sync>;
+
+/// Thread pool built on [`std::thread`] and a channel
+pub(crate) struct ThreadPool {
+    sender: Mutex<Sender<Job>>,
+    num_threads: usize,
+}
+
+impl ThreadPool {
+    /// Spawn `num_threads` worker threads. Workers exit when the pool is dropped.
+    pub(crate) fn new(num_threads: usize) -> Result<Self, BGZFError> {
+        let num_threads = num_threads.max(1);
+        let (sender, receiver) = channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        for i in 0..num_threads {
+            let receiver = receiver.clone();
+            std::thread::Builder::new()
+                .name(format!("bgzip-{}", i))
+                .spawn(move || loop {
+                    let job = match receiver.lock().unwrap().recv() {
+                        Ok(job) => job,
+                        Err(_) => return,
+                    };
+                    // same as rayon, a panic in a task aborts the process
+                    if std::panic::catch_unwind(std::panic::AssertUnwindSafe(job)).is_err() {
+                        std::process::abort();
+                    }
+                })?;
+        }
+        Ok(ThreadPool {
+            sender: Mutex::new(sender),
+            num_threads,
+        })
+    }
+
+    fn spawn(&self, job: Job) {
+        // workers exit only after the sender is dropped
+        self.sender.lock().unwrap().send(job).unwrap();
+    }
+}
+
+/// Thread pool to run compression and decompression tasks
+#[derive(Clone)]
+pub(crate) enum Pool {
+    /// rayon's global thread pool
+    #[cfg(feature = "rayon")]
+    Global,
+    /// Dedicated thread pool
+    #[cfg(feature = "rayon")]
+    Local(Arc<rayon::ThreadPool>),
+    /// Thread pool built on [`std::thread`]
+    Threads(Arc<ThreadPool>),
+    /// Jobs are scheduled by a user-supplied executor, which runs up to `num_jobs` jobs at a time
+    Executor { executor: Executor, num_jobs: usize },
+}
+
+impl Pool {
+    /// rayon's global thread pool, or the pool of `available_parallelism` without `rayon` feature.
+    pub(crate) fn global() -> Result<Self, BGZFError> {
+        #[cfg(feature = "rayon")]
+        return Ok(Pool::Global);
+        #[cfg(not(feature = "rayon"))]
+        Self::available_parallelism()
+    }
+
+    /// Pool sized with [`std::thread::available_parallelism`], shared by all readers and writers
+    /// created with `with_available_parallelism`.
+    #[cfg(feature = "rayon")]
+    pub(crate) fn available_parallelism() -> Result<Self, BGZFError> {
+        static POOL: OnceLock<Arc<rayon::ThreadPool>> = OnceLock::new();
+        if let Some(pool) = POOL.get() {
+            return Ok(Pool::Local(pool.clone()));
+        }
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(num_available_threads())
+            .thread_name(|i| format!("bgzip-{}", i))
+            .build()
+            .map_err(|_| BGZFError::Other("Failed to create thread pool"))?;
+        Ok(Pool::Local(POOL.get_or_init(|| Arc::new(pool)).clone()))
+    }
+
+    /// Pool sized with [`std::thread::available_parallelism`], shared by all readers and writers
+    /// created with `with_available_parallelism`.
+    #[cfg(not(feature = "rayon"))]
+    pub(crate) fn available_parallelism() -> Result<Self, BGZFError> {
+        static POOL: OnceLock<Arc<ThreadPool>> = OnceLock::new();
+        if let Some(pool) = POOL.get() {
+            return Ok(Pool::Threads(pool.clone()));
+        }
+        let pool = ThreadPool::new(num_available_threads())?;
+        Ok(Pool::Threads(POOL.get_or_init(|| Arc::new(pool)).clone()))
+    }
+
+    /// Dedicated pool of `num_threads` [`std::thread`] workers
+    pub(crate) fn std_threads(num_threads: usize) -> Result<Self, BGZFError> {
+        Ok(Pool::Threads(Arc::new(ThreadPool::new(num_threads)?)))
+    }
+
+    pub(crate) fn current_num_threads(&self) -> usize {
+        match self {
+            #[cfg(feature = "rayon")]
+            Pool::Global => rayon::current_num_threads(),
+            #[cfg(feature = "rayon")]
+            Pool::Local(pool) => pool.current_num_threads(),
+            Pool::Threads(pool) => pool.num_threads,
+            Pool::Executor { num_jobs, .. } => *num_jobs,
+        }
+    }
+
+    pub(crate) fn spawn<F: FnOnce() + Send + 'static>(&self, f: F) {
+        match self {
+            #[cfg(feature = "rayon")]
+            Pool::Global => rayon::spawn(f),
+            #[cfg(feature = "rayon")]
+            Pool::Local(pool) => pool.spawn(f),
+            Pool::Threads(pool) => pool.spawn(Box::new(f)),
+            Pool::Executor { executor, .. } => executor(Box::new(f)),
+        }
+    }
+
+    pub(crate) fn spawn_fifo<F: FnOnce() + Send + 'static>(&self, f: F) {
+        match self {
+            #[cfg(feature = "rayon")]
+            Pool::Global => rayon::spawn_fifo(f),
+            #[cfg(feature = "rayon")]
+            Pool::Local(pool) => pool.spawn_fifo(f),
+            // jobs are taken from the channel in order
+            Pool::Threads(pool) => pool.spawn(Box::new(f)),
+            Pool::Executor { executor, .. } => executor(Box::new(f)),
+        }
+    }
+}
+
+fn num_available_threads() -> usize {
+    std::thread::available_parallelism()
+        .map(|x| x.get())
+        .unwrap_or(1)
+}
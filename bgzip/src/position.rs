@@ -0,0 +1,72 @@
+//! BGZF virtual file offset
+
+use core::fmt;
+
+/// BGZF virtual file offset
+///
+/// Upper 48 bits are the file offset of the start of a BGZF block, and lower 16 bits are
+/// the offset within the uncompressed data of the block. See "4.1.1 Random access" of
+/// [SAM specification](https://samtools.github.io/hts-specs/SAMv1.pdf).
+///
+/// Raw `u64` values stored in index files can be converted with [`From`]. Virtual positions are
+/// ordered in the same order as the data in the file.
+///
+/// ```rust
+/// use bgzip::VirtualPosition;
+///
+/// let position = VirtualPosition::new(64251, 65074);
+/// assert_eq!(position, VirtualPosition::from(4210818610));
+/// assert_eq!(position.compressed_offset(), 64251);
+/// assert_eq!(position.uncompressed_offset_in_block(), 65074);
+/// assert_eq!(u64::from(position), 4210818610);
+/// assert_eq!(position.to_string(), "64251:65074");
+/// assert!(position < VirtualPosition::new(64252, 0));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct VirtualPosition(u64);
+
+impl VirtualPosition {
+    /// Create a virtual position from the file offset of a block and the offset in the block.
+    ///
+    /// Panics if `compressed_offset` does not fit into 48 bits.
+    pub fn new(compressed_offset: u64, uncompressed_offset_in_block: u16) -> Self {
+        assert!(
+            compressed_offset < 1 << 48,
+            "Too large compressed offset for virtual position"
+        );
+        VirtualPosition(compressed_offset << 16 | u64::from(uncompressed_offset_in_block))
+    }
+
+    /// File offset of the start of the block
+    pub fn compressed_offset(self) -> u64 {
+        self.0 >> 16
+    }
+
+    /// Offset in uncompressed data of the block
+    pub fn uncompressed_offset_in_block(self) -> u16 {
+        (self.0 & 0xffff) as u16
+    }
+}
+
+impl From<u64> for VirtualPosition {
+    fn from(value: u64) -> Self {
+        VirtualPosition(value)
+    }
+}
+
+impl From<VirtualPosition> for u64 {
+    fn from(value: VirtualPosition) -> Self {
+        value.0
+    }
+}
+
+impl fmt::Display for VirtualPosition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}",
+            self.compressed_offset(),
+            self.uncompressed_offset_in_block()
+        )
+    }
+}
@@ -0,0 +1,505 @@
+use super::{decompress_block, load_block};
+use crate::deflate::Decompress;
+use crate::header::BGZFHeader;
+use crate::{BGZFError, VirtualPosition};
+use std::convert::TryInto;
+use std::io::{self, prelude::*};
+
+/// A compressed BGZF block with its position in the file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RawBlock {
+    /// File offset of the block
+    pub compressed_offset: u64,
+    /// Uncompressed position of the first byte of the block
+    pub uncompressed_offset: u64,
+    /// Header of the block
+    pub header: BGZFHeader,
+    /// Raw deflate payload followed by CRC32 and ISIZE
+    pub data: Vec<u8>,
+}
+
+impl RawBlock {
+    /// Raw deflate payload without CRC32 and ISIZE.
+    pub fn payload(&self) -> &[u8] {
+        &self.data[..(self.data.len() - 8)]
+    }
+
+    /// CRC32 of uncompressed data stored in this block.
+    pub fn crc32(&self) -> u32 {
+        let len = self.data.len();
+        u32::from_le_bytes(self.data[(len - 8)..(len - 4)].try_into().unwrap())
+    }
+
+    /// Uncompressed size of this block stored in ISIZE field.
+    pub fn uncompressed_size(&self) -> u64 {
+        let len = self.data.len();
+        u32::from_le_bytes(self.data[(len - 4)..].try_into().unwrap()).into()
+    }
+
+    /// Compressed size of this block including header and footer.
+    pub fn compressed_size(&self) -> u64 {
+        self.header.header_size() + TryInto::<u64>::try_into(self.data.len()).unwrap()
+    }
+
+    /// Write this block as is, including the header.
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        self.header.write(&mut writer)?;
+        writer.write_all(&self.data)
+    }
+
+    /// Decompress this block.
+    ///
+    /// An error is returned with the compressed offset of this block. See [`BGZFError::in_block`].
+    pub fn decompress(self, decompress: &mut Decompress) -> Result<DecompressedBlock, BGZFError> {
+        let mut data = Vec::new();
+        decompress_block(&mut data, &self.data, decompress)
+            .map_err(|e| e.in_block(self.compressed_offset, None))?;
+        Ok(DecompressedBlock {
+            compressed_offset: self.compressed_offset,
+            uncompressed_offset: self.uncompressed_offset,
+            data,
+        })
+    }
+}
+
+/// Iterator of compressed blocks.
+///
+/// All blocks until the end of stream are returned without decompression, including end-of-file markers.
+/// This is useful to pass BGZF data through to another container without recompression.
+///
+/// Each [`RawBlock`] provides the file offset, the parsed header, the deflate payload, CRC32 and ISIZE,
+/// so block level tools do not have to parse blocks themselves.
+///
+/// ```rust
+/// use std::collections::HashSet;
+///
+/// let mut seen = HashSet::new();
+/// let mut duplicated = 0;
+/// for block in bgzip::read::RawBlocks::new(std::fs::File::open("testfiles/generated.bed.gz")?) {
+///     let block = block?;
+///     println!(
+///         "offset: {}, header size: {}, payload: {} bytes, crc32: {:08x}, isize: {}",
+///         block.compressed_offset,
+///         block.header.header_size(),
+///         block.payload().len(),
+///         block.crc32(),
+///         block.uncompressed_size()
+///     );
+///     if !seen.insert((block.crc32(), block.uncompressed_size())) {
+///         duplicated += 1;
+///     }
+/// }
+/// assert_eq!(duplicated, 0);
+/// # Ok::<(), bgzip::BGZFError>(())
+/// ```
+pub struct RawBlocks<R: Read> {
+    reader: io::BufReader<R>,
+    compressed_offset: u64,
+    uncompressed_offset: u64,
+    finished: bool,
+}
+
+impl<R: Read> RawBlocks<R> {
+    /// Create new [`RawBlocks`] from `reader`
+    pub fn new(reader: R) -> Self {
+        RawBlocks {
+            reader: io::BufReader::new(reader),
+            compressed_offset: 0,
+            uncompressed_offset: 0,
+            finished: false,
+        }
+    }
+
+    fn load_next(&mut self) -> Result<Option<RawBlock>, BGZFError> {
+        if self.reader.fill_buf()?.is_empty() {
+            return Ok(None);
+        }
+        let mut data = Vec::new();
+        let header = load_block(&mut self.reader, &mut data)?;
+        let block = RawBlock {
+            compressed_offset: self.compressed_offset,
+            uncompressed_offset: self.uncompressed_offset,
+            header,
+            data,
+        };
+        self.compressed_offset += block.compressed_size();
+        self.uncompressed_offset += block.uncompressed_size();
+        Ok(Some(block))
+    }
+}
+
+impl<R: Read> Iterator for RawBlocks<R> {
+    type Item = Result<RawBlock, BGZFError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        let result = self.load_next().transpose();
+        if !matches!(result, Some(Ok(_))) {
+            self.finished = true;
+        }
+        result
+    }
+}
+
+/// Iterator over decompressed data of each block. Created by [`super::BGZFReader::into_block_iter`].
+pub struct BlockIter<R: Read> {
+    reader: super::BGZFReader<R>,
+    /// File offset of the last returned block
+    compressed_offset: u64,
+    finished: bool,
+}
+
+impl<R: Read> BlockIter<R> {
+    pub(crate) fn new(reader: super::BGZFReader<R>) -> Self {
+        BlockIter {
+            compressed_offset: reader.current_block,
+            reader,
+            finished: false,
+        }
+    }
+
+    /// File offset of the block returned last
+    pub fn compressed_offset(&self) -> u64 {
+        self.compressed_offset
+    }
+
+    /// Unwrap this iterator, returning the underlying reader at the beginning of the next block
+    pub fn into_inner(self) -> super::BGZFReader<R> {
+        self.reader
+    }
+
+    fn load_next(&mut self) -> Result<Option<Vec<u8>>, BGZFError> {
+        let reader = &mut self.reader;
+        loop {
+            if reader.current_position_in_block < reader.current_buffer.len() {
+                let mut data = std::mem::take(&mut reader.current_buffer);
+                data.drain(..reader.current_position_in_block);
+                reader.current_position_in_block = 0;
+                self.compressed_offset = reader.current_block;
+                return Ok(Some(data));
+            }
+            reader.load_next()?;
+            // empty blocks in the middle of data are skipped
+            if reader.current_buffer.is_empty()
+                && (reader.next_block >= reader.eof_pos || reader.is_degraded())
+            {
+                return Ok(None);
+            }
+        }
+    }
+}
+
+impl<R: Read> Iterator for BlockIter<R> {
+    type Item = Result<Vec<u8>, BGZFError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        let result = self.load_next().transpose();
+        if !matches!(result, Some(Ok(_))) {
+            self.finished = true;
+        }
+        result
+    }
+}
+
+/// A decompressed BGZF block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecompressedBlock {
+    /// File offset of the block
+    pub compressed_offset: u64,
+    /// Uncompressed position of the first byte of the block
+    pub uncompressed_offset: u64,
+    /// Decompressed data
+    pub data: Vec<u8>,
+}
+
+/// Decompress all blocks in `reader` with rayon.
+///
+/// Blocks are loaded sequentially and decompressed in the rayon thread pool via
+/// [`rayon::iter::ParallelBridge`]. Blocks are not returned in file order;
+/// use [`DecompressedBlock::uncompressed_offset`] to restore the order if required.
+#[cfg(feature = "rayon")]
+pub fn par_blocks<R: Read + Send>(
+    reader: R,
+) -> impl rayon::iter::ParallelIterator<Item = Result<DecompressedBlock, BGZFError>> {
+    use rayon::prelude::*;
+
+    RawBlocks::new(reader)
+        .par_bridge()
+        .map_init(Decompress::new, |decompress, block| {
+            block?.decompress(decompress)
+        })
+}
+
+/// Decompress whole BGZF data in `reader` with rayon and return concatenated data.
+///
+/// This is a batch version of [`super::BGZFMultiThreadReader`]. Blocks are decompressed with
+/// [`par_blocks`] and concatenated in file order.
+///
+/// ```rust
+/// # #[cfg(feature = "rayon")]
+/// # fn main() -> Result<(), bgzip::BGZFError> {
+/// let data = bgzip::read::decompress_all_parallel(std::fs::File::open("testfiles/generated.bed.gz")?)?;
+/// assert!(data.starts_with(b"chr1\t"));
+/// # Ok(())
+/// # }
+/// # #[cfg(not(feature = "rayon"))]
+/// # fn main() {}
+/// ```
+#[cfg(feature = "rayon")]
+pub fn decompress_all_parallel<R: Read + Send>(reader: R) -> Result<Vec<u8>, BGZFError> {
+    use rayon::prelude::*;
+
+    let mut blocks = par_blocks(reader).collect::<Result<Vec<_>, BGZFError>>()?;
+    blocks.sort_by_key(|x| x.compressed_offset);
+
+    let mut data = Vec::with_capacity(blocks.iter().map(|x| x.data.len()).sum());
+    for block in blocks {
+        data.extend_from_slice(&block.data);
+    }
+    Ok(data)
+}
+
+/// A line found by [`scan_lines`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScannedLine {
+    /// BGZF virtual file offset of the first byte of the line
+    pub bgzf_pos: VirtualPosition,
+    /// Line content without trailing new line
+    pub line: Vec<u8>,
+}
+
+#[cfg(feature = "rayon")]
+struct ScannedBlock {
+    compressed_offset: u64,
+    /// Bytes before the first new line, or the whole block if the block has no new line
+    head: Vec<u8>,
+    has_new_line: bool,
+    /// Lines which start and end in this block
+    matches: Vec<ScannedLine>,
+    /// Position and bytes of the line continued to the next block
+    tail_pos: usize,
+    tail: Vec<u8>,
+}
+
+/// Scan all lines in BGZF text and return lines matched with `predicate`.
+///
+/// Blocks are decompressed and scanned in parallel with [`par_blocks`]. Lines across block boundaries
+/// are joined before `predicate` is called. Matched lines are returned in file order with
+/// their virtual file offsets, which can be passed to [`super::BGZFReader::bgzf_seek`].
+#[cfg(feature = "rayon")]
+pub fn scan_lines<R, F>(reader: R, predicate: F) -> Result<Vec<ScannedLine>, BGZFError>
+where
+    R: Read + Send,
+    F: Fn(&[u8]) -> bool + Sync + Send,
+{
+    use rayon::prelude::*;
+
+    let mut blocks = par_blocks(reader)
+        .map(|block| {
+            let block = block?;
+            let first_new_line = block.data.iter().position(|x| *x == b'\n');
+            let last_new_line = block.data.iter().rposition(|x| *x == b'\n');
+            let mut scanned = ScannedBlock {
+                compressed_offset: block.compressed_offset,
+                head: Vec::new(),
+                has_new_line: first_new_line.is_some(),
+                matches: Vec::new(),
+                tail_pos: 0,
+                tail: Vec::new(),
+            };
+            if let (Some(first), Some(last)) = (first_new_line, last_new_line) {
+                scanned.head = block.data[..first].to_vec();
+                let mut line_start = first + 1;
+                for line in block.data[line_start..last].split_inclusive(|x| *x == b'\n') {
+                    let content = line.strip_suffix(b"\n").unwrap_or(line);
+                    if predicate(content) {
+                        scanned.matches.push(ScannedLine {
+                            bgzf_pos: VirtualPosition::new(
+                                block.compressed_offset,
+                                line_start.try_into().unwrap(),
+                            ),
+                            line: content.to_vec(),
+                        });
+                    }
+                    line_start += line.len();
+                }
+                scanned.tail_pos = last + 1;
+                scanned.tail = block.data[(last + 1)..].to_vec();
+            } else {
+                scanned.head = block.data;
+            }
+            Ok(scanned)
+        })
+        .collect::<Result<Vec<_>, BGZFError>>()?;
+    blocks.sort_by_key(|x| x.compressed_offset);
+
+    let mut result = Vec::new();
+    let mut partial_line: Option<ScannedLine> = None;
+    for block in blocks {
+        if block.head.is_empty() && !block.has_new_line {
+            continue;
+        }
+        let mut line = partial_line.take().unwrap_or_else(|| ScannedLine {
+            bgzf_pos: VirtualPosition::new(block.compressed_offset, 0),
+            line: Vec::new(),
+        });
+        line.line.extend_from_slice(&block.head);
+        if !block.has_new_line {
+            partial_line = Some(line);
+            continue;
+        }
+        if predicate(&line.line) {
+            result.push(line);
+        }
+        result.extend(block.matches);
+        if !block.tail.is_empty() {
+            partial_line = Some(ScannedLine {
+                bgzf_pos: VirtualPosition::new(
+                    block.compressed_offset,
+                    block.tail_pos.try_into().unwrap(),
+                ),
+                line: block.tail,
+            });
+        }
+    }
+    if let Some(line) = partial_line {
+        if predicate(&line.line) {
+            result.push(line);
+        }
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[cfg(feature = "rayon")]
+    use rayon::prelude::*;
+    use std::fs::File;
+
+    #[test]
+    fn test_raw_blocks() -> anyhow::Result<()> {
+        let mut original_data = Vec::new();
+        File::open("testfiles/generated.bed.gz")?.read_to_end(&mut original_data)?;
+        let mut expected_data = Vec::new();
+        flate2::read::MultiGzDecoder::new(&original_data[..]).read_to_end(&mut expected_data)?;
+
+        let mut reframed_data = Vec::new();
+        let mut uncompressed_data = Vec::new();
+        for block in RawBlocks::new(&original_data[..]) {
+            let block = block?;
+            assert_eq!(block.compressed_offset, reframed_data.len() as u64);
+            assert_eq!(block.uncompressed_offset, uncompressed_data.len() as u64);
+            block.write(&mut reframed_data)?;
+
+            let mut data = Vec::new();
+            flate2::read::DeflateDecoder::new(block.payload()).read_to_end(&mut data)?;
+            assert_eq!(data.len() as u64, block.uncompressed_size());
+            let mut crc = flate2::Crc::new();
+            crc.update(&data);
+            assert_eq!(crc.sum(), block.crc32());
+            uncompressed_data.extend_from_slice(&data);
+        }
+        assert_eq!(reframed_data, original_data);
+        assert_eq!(uncompressed_data, expected_data);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_par_blocks() -> anyhow::Result<()> {
+        let mut expected_data = Vec::new();
+        flate2::read::MultiGzDecoder::new(File::open("testfiles/common_all_20180418_half.vcf.gz")?)
+            .read_to_end(&mut expected_data)?;
+
+        let mut blocks = par_blocks(File::open("testfiles/common_all_20180418_half.vcf.gz")?)
+            .collect::<Result<Vec<_>, _>>()?;
+        blocks.sort_by_key(|x| x.compressed_offset);
+
+        let mut data = Vec::new();
+        for one in blocks {
+            assert_eq!(one.uncompressed_offset, data.len() as u64);
+            data.extend_from_slice(&one.data);
+        }
+        assert_eq!(data, expected_data);
+
+        let line_count: usize =
+            par_blocks(File::open("testfiles/common_all_20180418_half.vcf.gz")?)
+                .map(|x| x.map(|x| x.data.iter().filter(|y| **y == b'\n').count()))
+                .sum::<Result<usize, _>>()?;
+        assert_eq!(
+            line_count,
+            expected_data.iter().filter(|x| **x == b'\n').count()
+        );
+
+        Ok(())
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_decompress_all_parallel() -> anyhow::Result<()> {
+        let mut expected_data = Vec::new();
+        flate2::read::MultiGzDecoder::new(File::open("testfiles/common_all_20180418_half.vcf.gz")?)
+            .read_to_end(&mut expected_data)?;
+        let data =
+            decompress_all_parallel(File::open("testfiles/common_all_20180418_half.vcf.gz")?)?;
+        assert_eq!(data, expected_data);
+
+        assert!(decompress_all_parallel(&crate::EOF_MARKER[..])?.is_empty());
+        assert!(decompress_all_parallel(&b"not bgzf data"[..]).is_err());
+
+        Ok(())
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_scan_lines() -> anyhow::Result<()> {
+        let mut expected_data = Vec::new();
+        flate2::read::MultiGzDecoder::new(File::open("testfiles/generated.bed.gz")?)
+            .read_to_end(&mut expected_data)?;
+        let predicate = |line: &[u8]| line.windows(3).any(|x| x == b"_77");
+        let expected_lines: Vec<_> = expected_data
+            .split(|x| *x == b'\n')
+            .filter(|x| predicate(x))
+            .collect();
+
+        let scanned = scan_lines(File::open("testfiles/generated.bed.gz")?, predicate)?;
+        assert_eq!(scanned.len(), expected_lines.len());
+
+        let mut reader = crate::BGZFReader::new(File::open("testfiles/generated.bed.gz")?)?;
+        let mut line = Vec::new();
+        for (one, expected) in scanned.iter().zip(expected_lines) {
+            assert_eq!(one.line, expected);
+            reader.bgzf_seek(one.bgzf_pos)?;
+            line.clear();
+            reader.read_until(b'\n', &mut line)?;
+            assert_eq!(&line[..(line.len() - 1)], expected);
+        }
+
+        // a line without new line at end of file
+        let mut data = Vec::new();
+        let mut writer = crate::BGZFWriter::with_compress_unit_size(
+            &mut data,
+            crate::Compression::default(),
+            100,
+            false,
+        )?;
+        for i in 0..100 {
+            write!(writer, "line {}\nsecond line", i)?;
+        }
+        writer.close()?;
+        let scanned = scan_lines(&data[..], |x| x.starts_with(b"second"))?;
+        assert_eq!(scanned.len(), 100);
+        assert_eq!(scanned[99].line, b"second line");
+        assert_eq!(scanned[0].line, b"second lineline 1");
+
+        Ok(())
+    }
+}
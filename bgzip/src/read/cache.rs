@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+
+/// Decompressed blocks keyed on compressed offset.
+///
+/// Pinned blocks are created by warming and kept until [`BlockCache::clear`] is called.
+/// Recently read blocks are kept up to `capacity` blocks and the least recently used block
+/// is evicted first.
+#[derive(Debug, Default)]
+pub(crate) struct BlockCache {
+    pinned: HashMap<u64, Vec<u8>>,
+    recent: HashMap<u64, (u64, Vec<u8>)>,
+    capacity: usize,
+    tick: u64,
+}
+
+impl BlockCache {
+    /// Set maximum number of recently read blocks. Extra blocks are evicted.
+    pub(crate) fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.recent.len() > capacity {
+            self.evict();
+        }
+    }
+
+    /// Get decompressed data of the block at `offset`
+    pub(crate) fn get(&mut self, offset: u64) -> Option<&[u8]> {
+        if let Some(data) = self.pinned.get(&offset) {
+            return Some(data);
+        }
+        self.tick += 1;
+        let tick = self.tick;
+        self.recent.get_mut(&offset).map(|(last_used, data)| {
+            *last_used = tick;
+            &data[..]
+        })
+    }
+
+    /// Keep `data` as a recently read block if capacity is not zero
+    pub(crate) fn insert_recent(&mut self, offset: u64, data: &[u8]) {
+        if self.capacity == 0 || self.pinned.contains_key(&offset) {
+            return;
+        }
+        let mut buffer = if self.recent.len() >= self.capacity {
+            self.evict().unwrap_or_default()
+        } else {
+            Vec::new()
+        };
+        buffer.clear();
+        buffer.extend_from_slice(data);
+        self.tick += 1;
+        self.recent.insert(offset, (self.tick, buffer));
+    }
+
+    /// Keep `data` until the cache is cleared
+    pub(crate) fn pin(&mut self, offset: u64, data: Vec<u8>) {
+        self.recent.remove(&offset);
+        self.pinned.insert(offset, data);
+    }
+
+    /// Pin the block at `offset` if it is cached. Return `false` if the block is not cached.
+    pub(crate) fn pin_existing(&mut self, offset: u64) -> bool {
+        if self.pinned.contains_key(&offset) {
+            return true;
+        }
+        match self.recent.remove(&offset) {
+            Some((_, data)) => {
+                self.pinned.insert(offset, data);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Remove all blocks
+    pub(crate) fn clear(&mut self) {
+        self.pinned.clear();
+        self.recent.clear();
+    }
+
+    /// Remove the least recently used block and return its buffer
+    fn evict(&mut self) -> Option<Vec<u8>> {
+        let offset = *self
+            .recent
+            .iter()
+            .min_by_key(|(_, (last_used, _))| *last_used)?
+            .0;
+        self.recent.remove(&offset).map(|(_, data)| data)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn contains(&self, offset: u64) -> bool {
+        self.pinned.contains_key(&offset) || self.recent.contains_key(&offset)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_block_cache() {
+        let mut cache = BlockCache::default();
+        cache.insert_recent(0, b"a");
+        assert_eq!(cache.get(0), None);
+
+        cache.set_capacity(2);
+        cache.insert_recent(0, b"a");
+        cache.insert_recent(10, b"b");
+        assert_eq!(cache.get(0), Some(&b"a"[..]));
+        // block 10 is the least recently used
+        cache.insert_recent(20, b"c");
+        assert!(!cache.contains(10));
+        assert_eq!(cache.get(0), Some(&b"a"[..]));
+        assert_eq!(cache.get(20), Some(&b"c"[..]));
+
+        // pinned blocks are not evicted
+        cache.pin(30, b"d".to_vec());
+        assert!(cache.pin_existing(0));
+        assert!(!cache.pin_existing(10));
+        cache.insert_recent(40, b"e");
+        cache.insert_recent(50, b"f");
+        assert_eq!(cache.get(0), Some(&b"a"[..]));
+        assert_eq!(cache.get(30), Some(&b"d"[..]));
+        assert!(!cache.contains(20));
+
+        cache.set_capacity(1);
+        assert!(cache.contains(50));
+        assert!(!cache.contains(40));
+
+        cache.clear();
+        assert_eq!(cache.get(0), None);
+        assert_eq!(cache.get(50), None);
+    }
+}
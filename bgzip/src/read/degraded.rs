@@ -0,0 +1,117 @@
+use crate::header::BGZFHeader;
+use crate::BGZFError;
+use std::convert::TryInto;
+use std::io::{self, Read};
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Sequential decoder of multi-member gzip stream without BGZF block sizes.
+pub(crate) struct DegradedStream {
+    inflate: flate2::Decompress,
+    input: Vec<u8>,
+    input_pos: usize,
+    crc: flate2::Crc,
+    finished: bool,
+}
+
+impl DegradedStream {
+    /// Create a decoder. The header of the first member must be already consumed.
+    pub(crate) fn new() -> Self {
+        DegradedStream {
+            inflate: flate2::Decompress::new(false),
+            input: Vec::with_capacity(CHUNK_SIZE),
+            input_pos: 0,
+            crc: flate2::Crc::new(),
+            finished: false,
+        }
+    }
+
+    /// Fill `input` with new data. Return `false` at end of file.
+    fn fill_input<R: Read>(&mut self, reader: &mut R) -> io::Result<bool> {
+        self.input.resize(CHUNK_SIZE, 0);
+        self.input_pos = 0;
+        loop {
+            match reader.read(&mut self.input) {
+                Ok(read_bytes) => {
+                    self.input.truncate(read_bytes);
+                    return Ok(read_bytes > 0);
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    self.input.clear();
+                    return Err(e);
+                }
+            }
+        }
+    }
+
+    /// Decompress next data into `output`. `output` is empty at end of file.
+    pub(crate) fn read_next<R: Read>(
+        &mut self,
+        reader: &mut R,
+        output: &mut Vec<u8>,
+    ) -> Result<(), BGZFError> {
+        output.clear();
+        output.resize(CHUNK_SIZE, 0);
+        let mut wrote_bytes = 0;
+        while wrote_bytes == 0 && !self.finished {
+            if self.input_pos == self.input.len() && !self.fill_input(reader)? {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+            }
+            let total_in = self.inflate.total_in();
+            let total_out = self.inflate.total_out();
+            let status = self
+                .inflate
+                .decompress(
+                    &self.input[self.input_pos..],
+                    &mut output[wrote_bytes..],
+                    flate2::FlushDecompress::None,
+                )
+                .map_err(|_| BGZFError::Other("Invalid deflate stream"))?;
+            let consumed: usize = (self.inflate.total_in() - total_in).try_into().unwrap();
+            let produced: usize = (self.inflate.total_out() - total_out).try_into().unwrap();
+            self.crc
+                .update(&output[wrote_bytes..(wrote_bytes + produced)]);
+            self.input_pos += consumed;
+            wrote_bytes += produced;
+            if status == flate2::Status::StreamEnd {
+                self.finish_member(reader)?;
+            }
+        }
+        output.truncate(wrote_bytes);
+        Ok(())
+    }
+
+    /// Check footer of current member and load header of the next member
+    fn finish_member<R: Read>(&mut self, reader: &mut R) -> Result<(), BGZFError> {
+        let mut footer = [0u8; 8];
+        let mut cursor = io::Cursor::new(&self.input[self.input_pos..]);
+        (&mut cursor).chain(&mut *reader).read_exact(&mut footer)?;
+        self.input_pos += TryInto::<usize>::try_into(cursor.position()).unwrap();
+        let expected_crc = u32::from_le_bytes(footer[0..4].try_into().unwrap());
+        if expected_crc != self.crc.sum() {
+            return Err(BGZFError::Crc32Mismatch {
+                expected: expected_crc,
+                actual: self.crc.sum(),
+            });
+        }
+        let expected_len = u32::from_le_bytes(footer[4..8].try_into().unwrap());
+        if expected_len != self.crc.amount() {
+            return Err(BGZFError::SizeMismatch {
+                expected: expected_len,
+                actual: self.crc.amount(),
+            });
+        }
+        self.crc.reset();
+        self.inflate.reset(false);
+
+        if self.input_pos == self.input.len() && !self.fill_input(reader)? {
+            self.finished = true;
+            return Ok(());
+        }
+        let mut cursor = io::Cursor::new(&self.input[self.input_pos..]);
+        BGZFHeader::from_reader((&mut cursor).chain(&mut *reader))?;
+        self.input_pos += TryInto::<usize>::try_into(cursor.position()).unwrap();
+        Ok(())
+    }
+}
@@ -0,0 +1,104 @@
+use crate::{BGZFError, VirtualPosition};
+use std::io::{self, BufRead};
+
+/// Iterator over lines and their virtual file offsets.
+///
+/// Created by [`super::BGZFReader::lines_with_pos`] or
+/// [`super::BGZFMultiThreadReader::lines_with_pos`]. Each item is a line without trailing new line
+/// characters and the virtual file offset of its first byte, which can be passed to `bgzf_seek`.
+pub struct LinesWithPos<'a, B: BufRead> {
+    reader: &'a mut B,
+    next_bgzf_pos: fn(&mut B) -> io::Result<Option<VirtualPosition>>,
+}
+
+impl<'a, B: BufRead> LinesWithPos<'a, B> {
+    pub(crate) fn new(
+        reader: &'a mut B,
+        next_bgzf_pos: fn(&mut B) -> io::Result<Option<VirtualPosition>>,
+    ) -> Self {
+        LinesWithPos {
+            reader,
+            next_bgzf_pos,
+        }
+    }
+}
+
+impl<'a, B: BufRead> Iterator for LinesWithPos<'a, B> {
+    type Item = Result<(VirtualPosition, String), BGZFError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let pos = match (self.next_bgzf_pos)(self.reader) {
+            Ok(Some(pos)) => pos,
+            Ok(None) => return None,
+            Err(e) => return Some(Err(e.into())),
+        };
+        let mut line = String::new();
+        match self.reader.read_line(&mut line) {
+            Ok(_) => {
+                if line.ends_with('\n') {
+                    line.pop();
+                    if line.ends_with('\r') {
+                        line.pop();
+                    }
+                }
+                Some(Ok((pos, line)))
+            }
+            Err(e) => Some(Err(e.into())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{BGZFReader, VirtualPosition};
+    use std::fs::File;
+    use std::io::{BufRead, Read, Write};
+
+    #[test]
+    fn test_lines_with_pos() -> anyhow::Result<()> {
+        let mut expected_data = String::new();
+        flate2::read::MultiGzDecoder::new(File::open("testfiles/generated.bed.gz")?)
+            .read_to_string(&mut expected_data)?;
+        let expected: Vec<&str> = expected_data.lines().collect();
+
+        let mut reader = BGZFReader::new(File::open("testfiles/generated.bed.gz")?)?;
+        let lines = reader.lines_with_pos().collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(
+            lines.iter().map(|x| x.1.as_str()).collect::<Vec<_>>(),
+            expected
+        );
+        for (pos, line) in lines.iter().step_by(97) {
+            reader.bgzf_seek(*pos)?;
+            let mut read_line = String::new();
+            reader.read_line(&mut read_line)?;
+            assert_eq!(read_line.trim_end(), line);
+        }
+
+        #[cfg(feature = "threads")]
+        {
+            let mut reader = crate::read::BGZFMultiThreadReader::with_process_block_num(
+                File::open("testfiles/generated.bed.gz")?,
+                3,
+            )?;
+            let thread_lines = reader.lines_with_pos().collect::<Result<Vec<_>, _>>()?;
+            assert_eq!(thread_lines, lines);
+        }
+
+        let mut data = Vec::new();
+        let mut writer = crate::BGZFWriter::new(&mut data, crate::Compression::default());
+        writer.write_all(b"a\r\n\nb")?;
+        writer.close()?;
+        let mut reader = BGZFReader::new(&data[..])?;
+        let lines = reader.lines_with_pos().collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(
+            lines,
+            vec![
+                (VirtualPosition::new(0, 0), "a".to_string()),
+                (VirtualPosition::new(0, 3), "".to_string()),
+                (VirtualPosition::new(0, 4), "b".to_string()),
+            ]
+        );
+
+        Ok(())
+    }
+}
@@ -1,25 +1,121 @@
 //! BGZF reader
 
-#[cfg(feature = "rayon")]
+mod block;
+mod cache;
+#[cfg(feature = "flate2")]
+mod degraded;
+mod lines;
+mod read_at;
+#[cfg(feature = "threads")]
+mod readahead;
+mod recover;
+mod take;
+#[cfg(feature = "threads")]
 mod thread;
 
 #[cfg(feature = "rayon")]
+pub use block::{decompress_all_parallel, par_blocks, scan_lines};
+pub use block::{BlockIter, DecompressedBlock, RawBlock, RawBlocks, ScannedLine};
+pub use lines::LinesWithPos;
+pub use read_at::{ReadAt, ReadAtReader};
+#[cfg(feature = "threads")]
+pub use readahead::Readahead;
+pub use take::TakeUntil;
+#[cfg(feature = "threads")]
 pub use thread::BGZFMultiThreadReader;
 
 use crate::deflate::*;
+use crate::header::{BGZFHeader, HeaderLimits};
 use crate::index::BGZFIndex;
-use crate::{header::BGZFHeader, BGZFError};
+use crate::metrics::{BlockOperation, Recorder};
+use crate::write::MAXIMUM_COMPRESS_UNIT_SIZE;
+use crate::{BGZFError, VirtualPosition};
 use std::convert::TryInto;
 use std::io::{self, prelude::*};
+use std::ops::Range;
 use std::path::Path;
 
-enum AdaptiveReader<R: BufRead> {
+/// Reader of BGZF, plain gzip or uncompressed data.
+///
+/// Created by [`AdaptiveReader::new`], [`new_reader`] or [`open`]. The format is detected by the header
+/// of the data, not by file extension. BGZF data can be seeked with [`AdaptiveReader::bgzf_seek`],
+/// while multi-member gzip and uncompressed data can be read only sequentially.
+///
+/// ```rust
+/// use bgzip::read::AdaptiveReader;
+/// use std::io::BufRead;
+///
+/// let mut reader = AdaptiveReader::new(std::io::BufReader::new(std::fs::File::open(
+///     "testfiles/common_all_20180418_half.vcf.gz",
+/// )?))?;
+/// assert!(reader.is_bgzf());
+/// reader.bgzf_seek(4210818610)?;
+/// let mut line = String::new();
+/// reader.read_line(&mut line)?;
+/// assert!(line.starts_with("1\t72700625"));
+///
+/// let mut reader = AdaptiveReader::new(std::io::Cursor::new(b"plain text"))?;
+/// assert!(!reader.is_bgzf());
+/// assert!(reader.bgzf_seek(0).is_err());
+/// # Ok::<(), bgzip::BGZFError>(())
+/// ```
+pub enum AdaptiveReader<R: BufRead> {
+    /// Uncompressed data
     Plain(R),
+    /// Gzip data which is not BGZF
     #[cfg(feature = "flate2")]
     Gzip(io::BufReader<flate2::read::MultiGzDecoder<R>>),
+    /// BGZF data
     Bgzip(BGZFReader<R>),
 }
 
+impl<R: BufRead> AdaptiveReader<R> {
+    /// Detect format of `reader` and create suitable reader.
+    pub fn new(mut reader: R) -> Result<Self, BGZFError> {
+        let magics = reader.fill_buf()?;
+        if magics.starts_with(&[crate::header::GZIP_ID1, crate::header::GZIP_ID2]) {
+            if let Ok(header) = crate::header::BGZFHeader::from_reader(magics) {
+                if header.block_size().is_ok() {
+                    return Ok(AdaptiveReader::Bgzip(BGZFReader::new(reader)?));
+                }
+            }
+            #[cfg(feature = "flate2")]
+            return Ok(AdaptiveReader::Gzip(io::BufReader::new(
+                flate2::read::MultiGzDecoder::new(reader),
+            )));
+            #[cfg(not(feature = "flate2"))]
+            return Err(crate::error::BGZFError::Other(
+                "Standard gzip is not supported",
+            ));
+        } else {
+            Ok(AdaptiveReader::Plain(reader))
+        }
+    }
+
+    /// `true` if the data is BGZF
+    pub fn is_bgzf(&self) -> bool {
+        matches!(self, AdaptiveReader::Bgzip(_))
+    }
+
+    /// BGZF virtual file offset of the next byte. `None` if the data is not BGZF.
+    pub fn bgzf_pos(&self) -> Option<VirtualPosition> {
+        match self {
+            AdaptiveReader::Bgzip(reader) => Some(reader.bgzf_pos()),
+            _ => None,
+        }
+    }
+}
+
+impl<R: BufRead + Seek> AdaptiveReader<R> {
+    /// Move to BGZF virtual file offset. An error is returned if the data is not BGZF.
+    pub fn bgzf_seek<P: Into<VirtualPosition>>(&mut self, position: P) -> Result<(), BGZFError> {
+        match self {
+            AdaptiveReader::Bgzip(reader) => reader.bgzf_seek(position),
+            _ => Err(BGZFError::Other("Only BGZF data can be seeked")),
+        }
+    }
+}
+
 impl<R: BufRead> Read for AdaptiveReader<R> {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         match self {
@@ -55,7 +151,7 @@ impl<R: BufRead> BufRead for AdaptiveReader<R> {
 ///
 /// This function automatically detect input file format from gzip, bgzip and plain text, and return suitable reader.
 /// File format is detected by header of file, not by file extension.
-pub fn open<P: AsRef<Path>>(path: P) -> io::Result<impl BufRead> {
+pub fn open<P: AsRef<Path>>(path: P) -> io::Result<AdaptiveReader<io::BufReader<std::fs::File>>> {
     let reader = io::BufReader::new(std::fs::File::open(path)?);
     new_reader(reader).map_err(|e| e.into_io_error())
 }
@@ -64,39 +160,85 @@ pub fn open<P: AsRef<Path>>(path: P) -> io::Result<impl BufRead> {
 ///
 /// This function automatically detect input file format from gzip, bgzip and plain text, and return suitable reader.
 /// File format is detected by header of file, not by file extension.
-pub fn new_reader<R: BufRead>(mut reader: R) -> Result<impl BufRead, BGZFError> {
-    let magics = reader.fill_buf()?;
-    if magics[0] == crate::header::GZIP_ID1 && magics[1] == crate::header::GZIP_ID2 {
-        if let Ok(header) = crate::header::BGZFHeader::from_reader(&magics[..]) {
-            if header.block_size().is_ok() {
-                return Ok(AdaptiveReader::Bgzip(BGZFReader::new(reader)?));
-            }
-        }
-        #[cfg(feature = "flate2")]
-        return Ok(AdaptiveReader::Gzip(io::BufReader::new(
-            flate2::read::MultiGzDecoder::new(reader),
-        )));
-        #[cfg(not(feature = "flate2"))]
-        return Err(crate::error::BGZFError::Other(
-            "Standard gzip is not supported",
-        ));
-    } else {
-        Ok(AdaptiveReader::Plain(reader))
-    }
+pub fn new_reader<R: BufRead>(reader: R) -> Result<AdaptiveReader<R>, BGZFError> {
+    AdaptiveReader::new(reader)
 }
 
+/// Compressed payload, CRC32 and ISIZE of an empty block such as [`crate::EOF_MARKER`].
+pub(crate) const EOF_BLOCK: [u8; 10] = [3, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+
 /// Load single block from reader.
 ///
 /// This function is useful when writing your own parallelized BGZF reader.
 /// Loaded buffer can be decompress with [`decompress_block`] function.
-pub fn load_block<R: Read>(mut reader: R, buffer: &mut Vec<u8>) -> Result<BGZFHeader, BGZFError> {
-    let header = BGZFHeader::from_reader(&mut reader)?;
+pub fn load_block<R: Read>(reader: R, buffer: &mut Vec<u8>) -> Result<BGZFHeader, BGZFError> {
+    load_block_with_limits(reader, buffer, &HeaderLimits::default())
+}
+
+pub(crate) fn load_block_with_limits<R: Read>(
+    mut reader: R,
+    buffer: &mut Vec<u8>,
+    limits: &HeaderLimits,
+) -> Result<BGZFHeader, BGZFError> {
+    let header = BGZFHeader::from_reader_with_limits(&mut reader, limits)?;
+    load_block_data(reader, &header, buffer)?;
+    Ok(header)
+}
+
+/// Load single block like [`load_block_with_limits`], or return `None` if `reader` ends
+/// before the first byte of the block.
+pub(crate) fn load_block_or_end<R: Read>(
+    mut reader: R,
+    buffer: &mut Vec<u8>,
+    limits: &HeaderLimits,
+) -> Result<Option<BGZFHeader>, BGZFError> {
+    let mut first_byte = [0u8; 1];
+    loop {
+        match reader.read(&mut first_byte) {
+            Ok(0) => return Ok(None),
+            Ok(_) => break,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    load_block_with_limits(io::Cursor::new(first_byte).chain(reader), buffer, limits).map(Some)
+}
+
+/// Replace unexpected end of data in the middle of a block with [`BGZFError::TruncatedFile`]
+pub(crate) fn truncated_error(e: BGZFError) -> BGZFError {
+    match e {
+        BGZFError::IoError(ref x) if x.kind() == io::ErrorKind::UnexpectedEof => {
+            BGZFError::TruncatedFile
+        }
+        e => e,
+    }
+}
+
+/// Remove the terminating NUL of a header string
+fn strip_nul(mut value: Vec<u8>) -> Vec<u8> {
+    if value.last() == Some(&0) {
+        value.pop();
+    }
+    value
+}
+
+/// Load compressed data, CRC32 and ISIZE of the block with `header`
+fn load_block_data<R: Read>(
+    mut reader: R,
+    header: &BGZFHeader,
+    buffer: &mut Vec<u8>,
+) -> Result<(), BGZFError> {
     let block_size: u64 = header.block_size()?.into();
+    let data_size = block_size
+        .checked_sub(header.header_size())
+        .filter(|x| *x >= 8)
+        .ok_or(BGZFError::HeaderParseError {
+            position: header.header_size(),
+        })?;
     buffer.clear();
-    buffer.resize((block_size - header.header_size()).try_into().unwrap(), 0);
+    buffer.resize(data_size.try_into().unwrap(), 0);
     reader.read_exact(buffer)?;
-
-    Ok(header)
+    Ok(())
 }
 
 /// Decompress single BGZF block from buffer. The buffer should be loaded with [`load_block`] function.
@@ -108,21 +250,42 @@ pub fn decompress_block(
     decompress: &mut Decompress,
 ) -> Result<(), BGZFError> {
     let original_decompress_data_len = decompressed_data.len();
-    let mut crc = Crc::new();
-
-    let expected_len_data = [
-        compressed_block[compressed_block.len() - 4],
-        compressed_block[compressed_block.len() - 3],
-        compressed_block[compressed_block.len() - 2],
-        compressed_block[compressed_block.len() - 1],
-    ];
-    let expected_len: usize = u32::from_le_bytes(expected_len_data).try_into().unwrap();
+    let expected_len = uncompressed_block_size(compressed_block)?;
     decompressed_data.resize(original_decompress_data_len + expected_len, 0);
-
-    decompress.decompress(
-        compressed_block,
+    if let Err(e) = decompress_block_into(
         &mut decompressed_data[original_decompress_data_len..],
-    )?;
+        compressed_block,
+        decompress,
+    ) {
+        decompressed_data.truncate(original_decompress_data_len);
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// Decompress single BGZF block into `decompressed_data`, and return the size of decompressed data.
+///
+/// Unlike [`decompress_block`], no memory is allocated. [`DecompressError::InsufficientSpace`]
+/// is returned if `decompressed_data` is smaller than ISIZE in the footer of the block.
+///
+/// [`DecompressError::InsufficientSpace`]: crate::deflate::DecompressError::InsufficientSpace
+pub fn decompress_block_into(
+    decompressed_data: &mut [u8],
+    compressed_block: &[u8],
+    decompress: &mut Decompress,
+) -> Result<usize, BGZFError> {
+    let expected_len = uncompressed_block_size(compressed_block)?;
+    let decompressed_data = decompressed_data
+        .get_mut(..expected_len)
+        .ok_or(crate::deflate::DecompressError::InsufficientSpace)?;
+
+    let actual_len = decompress.decompress(compressed_block, decompressed_data)?;
+    if actual_len != expected_len {
+        return Err(BGZFError::SizeMismatch {
+            expected: expected_len.try_into().unwrap(),
+            actual: actual_len.try_into().unwrap(),
+        });
+    }
 
     let expected_crc_data = [
         compressed_block[compressed_block.len() - 8],
@@ -132,12 +295,223 @@ pub fn decompress_block(
     ];
 
     let expected_crc = u32::from_le_bytes(expected_crc_data);
-    crc.update(&decompressed_data[original_decompress_data_len..]);
+    let mut crc = Crc::new();
+    crc.update(decompressed_data);
     if expected_crc != crc.sum() {
-        return Err(BGZFError::Other("unmatched CRC32 of decompressed data"));
+        return Err(BGZFError::Crc32Mismatch {
+            expected: expected_crc,
+            actual: crc.sum(),
+        });
     }
 
-    Ok(())
+    Ok(expected_len)
+}
+
+/// ISIZE in the footer of a block loaded with [`load_block`]
+fn uncompressed_block_size(compressed_block: &[u8]) -> Result<usize, BGZFError> {
+    if compressed_block.len() < 8 {
+        return Err(BGZFError::BlockTooShort {
+            size: compressed_block.len() as u64,
+        });
+    }
+    let expected_len_data = [
+        compressed_block[compressed_block.len() - 4],
+        compressed_block[compressed_block.len() - 3],
+        compressed_block[compressed_block.len() - 2],
+        compressed_block[compressed_block.len() - 1],
+    ];
+    Ok(u32::from_le_bytes(expected_len_data).try_into().unwrap())
+}
+
+/// Decompress single BGZF block as [`decompress_block`], and reject blocks violating
+/// the SAM specification.
+///
+/// [`BGZFError::UncompressedBlockTooLarge`] is returned before decompression if ISIZE exceeds
+/// 65536 bytes. Use with [`crate::header::HeaderLimits::strict`] to classify malformed blocks.
+pub fn decompress_block_strict(
+    decompressed_data: &mut Vec<u8>,
+    compressed_block: &[u8],
+    decompress: &mut Decompress,
+) -> Result<(), BGZFError> {
+    // short blocks are rejected by `decompress_block`
+    if let Some(isize_data) = compressed_block
+        .last_chunk::<4>()
+        .filter(|_| compressed_block.len() >= 8)
+    {
+        let size = u32::from_le_bytes(*isize_data);
+        if size as usize > MAXIMUM_COMPRESS_UNIT_SIZE {
+            return Err(BGZFError::UncompressedBlockTooLarge { size: size.into() });
+        }
+    }
+    decompress_block(decompressed_data, compressed_block, decompress)
+}
+
+/// List compressed offsets of end-of-file markers found in the middle of a BGZF stream.
+///
+/// A file made by concatenating several BGZF files contains an end-of-file marker at the end of
+/// each original file. This function scans all blocks without decompression and returns the
+/// offsets of those markers, excluding the marker at the end of the stream.
+/// The next member starts at `offset + EOF_MARKER.len()`.
+pub fn member_offsets<R: Read>(reader: R) -> Result<Vec<u64>, BGZFError> {
+    let mut offsets = Vec::new();
+    let mut last_eof_offset = None;
+
+    for block in block::RawBlocks::new(reader) {
+        let block = block?;
+        if let Some(offset) = last_eof_offset.take() {
+            offsets.push(offset);
+        }
+        if block.data == EOF_BLOCK {
+            last_eof_offset = Some(block.compressed_offset);
+        }
+    }
+
+    Ok(offsets)
+}
+
+/// Copy uncompressed data between two BGZF virtual file offsets into `writer`.
+///
+/// `start_voffset` is inclusive and `end_voffset` is exclusive. Only blocks in the region are loaded,
+/// and they are decompressed one by one into a single buffer, so memory usage is bounded by the block size.
+/// Copy stops at the end-of-file marker if `end_voffset` points beyond the end of data.
+/// Return the number of written bytes.
+pub fn copy_region<R: Read + Seek, W: Write>(
+    mut reader: R,
+    start_voffset: VirtualPosition,
+    end_voffset: VirtualPosition,
+    writer: &mut W,
+) -> Result<u64, BGZFError> {
+    if start_voffset > end_voffset {
+        return Err(BGZFError::Other("Start offset is larger than end offset"));
+    }
+    let start_block = start_voffset.compressed_offset();
+    let end_block = end_voffset.compressed_offset();
+    let end_position_in_block: usize = end_voffset.uncompressed_offset_in_block().into();
+    let mut position_in_block: usize = start_voffset.uncompressed_offset_in_block().into();
+
+    reader.seek(io::SeekFrom::Start(start_block))?;
+    let mut decompress = Decompress::new();
+    let mut buffer = Vec::with_capacity(crate::write::MAXIMUM_COMPRESS_UNIT_SIZE);
+    let mut written_bytes = 0;
+
+    for block in block::RawBlocks::new(&mut reader) {
+        let block = block?;
+        let block_offset = start_block + block.compressed_offset;
+        if block_offset > end_block
+            || (block_offset == end_block && end_position_in_block == 0)
+            || block.data == EOF_BLOCK
+        {
+            break;
+        }
+
+        buffer.clear();
+        decompress_block(&mut buffer, &block.data, &mut decompress)?;
+        let end = if block_offset == end_block {
+            end_position_in_block
+        } else {
+            buffer.len()
+        };
+        if position_in_block > end || end > buffer.len() {
+            return Err(BGZFError::Other("Invalid BGZF position"));
+        }
+        writer.write_all(&buffer[position_in_block..end])?;
+        written_bytes += TryInto::<u64>::try_into(end - position_in_block).unwrap();
+        position_in_block = 0;
+    }
+
+    Ok(written_bytes)
+}
+
+/// Copy uncompressed data between two BGZF virtual file offsets into BGZF `writer`.
+///
+/// `start_voffset` is inclusive and `end_voffset` is exclusive. Only the partial leading and trailing blocks
+/// are decompressed and compressed again, and blocks between them are copied without recompression.
+/// Copied blocks start at block boundaries of `writer`, so data buffered in `writer` is written as a block first.
+/// Copy stops at the end-of-file marker if `end_voffset` points beyond the end of data.
+/// Return the number of copied uncompressed bytes.
+pub fn copy_range<R: Read + Seek, W: Write>(
+    mut reader: R,
+    writer: &mut crate::BGZFWriter<W>,
+    start_voffset: VirtualPosition,
+    end_voffset: VirtualPosition,
+) -> Result<u64, BGZFError> {
+    if start_voffset > end_voffset {
+        return Err(BGZFError::Other("Start offset is larger than end offset"));
+    }
+    let start_block = start_voffset.compressed_offset();
+    let end_block = end_voffset.compressed_offset();
+    let end_position_in_block: usize = end_voffset.uncompressed_offset_in_block().into();
+    let mut position_in_block: usize = start_voffset.uncompressed_offset_in_block().into();
+
+    reader.seek(io::SeekFrom::Start(start_block))?;
+    let mut decompress = Decompress::new();
+    let mut buffer = Vec::with_capacity(crate::write::MAXIMUM_COMPRESS_UNIT_SIZE);
+    let mut copied_bytes = 0;
+    writer.finish_block()?;
+
+    for block in block::RawBlocks::new(&mut reader) {
+        let block = block?;
+        let block_offset = start_block + block.compressed_offset;
+        if block_offset > end_block
+            || (block_offset == end_block && end_position_in_block == 0)
+            || block.data == EOF_BLOCK
+        {
+            break;
+        }
+
+        if position_in_block == 0 && block_offset < end_block {
+            if block.uncompressed_size() == 0 {
+                continue;
+            }
+            copied_bytes += block.uncompressed_size();
+            writer.append_raw_block(block)?;
+            continue;
+        }
+
+        buffer.clear();
+        decompress_block(&mut buffer, &block.data, &mut decompress)?;
+        let end = if block_offset == end_block {
+            end_position_in_block
+        } else {
+            buffer.len()
+        };
+        if position_in_block > end || end > buffer.len() {
+            return Err(BGZFError::Other("Invalid BGZF position"));
+        }
+        writer.write_all(&buffer[position_in_block..end])?;
+        writer.finish_block()?;
+        copied_bytes += TryInto::<u64>::try_into(end - position_in_block).unwrap();
+        position_in_block = 0;
+    }
+
+    Ok(copied_bytes)
+}
+
+/// Copy whole BGZF stream from `reader` into `writer` without recompression.
+///
+/// The first block header and the end-of-file marker are verified before copy, and then data is copied as is
+/// with [`std::io::copy`]. If both of `reader` and `writer` are [`std::fs::File`], the copy is done in the kernel
+/// (e.g. `copy_file_range` on Linux). Return the number of copied bytes.
+pub fn copy_all<R: Read + Seek, W: Write>(mut reader: R, mut writer: W) -> Result<u64, BGZFError> {
+    reader.seek(io::SeekFrom::Start(0))?;
+    let header = BGZFHeader::from_reader(&mut reader).map_err(|_| BGZFError::NotBGZF)?;
+    header.block_size().map_err(|_| BGZFError::NotBGZF)?;
+
+    let length = reader.seek(io::SeekFrom::End(0))?;
+    let marker_length: u64 = crate::EOF_MARKER.len().try_into().unwrap();
+    if length < marker_length {
+        return Err(BGZFError::Other("End-of-file marker is not found"));
+    }
+    reader.seek(io::SeekFrom::Start(length - marker_length))?;
+    let mut marker = [0u8; 28];
+    reader.read_exact(&mut marker)?;
+    if marker != crate::EOF_MARKER {
+        return Err(BGZFError::Other("End-of-file marker is not found"));
+    }
+
+    reader.seek(io::SeekFrom::Start(0))?;
+    let copied = io::copy(&mut reader, &mut writer)?;
+    Ok(copied)
 }
 
 /// A BGZF reader
@@ -150,122 +524,807 @@ pub struct BGZFReader<R: Read> {
     current_buffer: Vec<u8>,
     current_block: u64,
     next_block: u64,
+    /// Number of blocks preceding `next_block`. Unknown after seek.
+    next_block_index: Option<u64>,
     current_position_in_block: usize,
     eof_pos: u64,
+    /// Return [`BGZFError::TruncatedFile`] instead of ending at data without end-of-file marker
+    fail_on_missing_eof: bool,
+    /// End of end-of-file marker block if the marker has been read
+    eof_marker_end: Option<u64>,
+    /// Return [`BGZFError::TrailingData`] if data exists after end-of-file marker
+    fail_on_trailing_data: bool,
+    trailing_data_checked: bool,
+    /// Skip end-of-file markers followed by more blocks
+    concatenated: bool,
+    /// Enforce block size constraints of the SAM specification
+    strict: bool,
+    header_limits: HeaderLimits,
+    /// Decompressed blocks keyed on compressed offset
+    cache: cache::BlockCache,
+    recorder: Recorder,
+    /// FCOMMENT of the first block without the terminating NUL
+    file_comment: Option<Vec<u8>>,
+    /// Index and uncompressed length attached with [`BGZFReader::with_index`]
+    index: Option<(BGZFIndex, u64)>,
+    /// Sequential decoder used when the data is gzip but not BGZF
+    #[cfg(feature = "flate2")]
+    degraded: Option<Box<degraded::DegradedStream>>,
+    /// Memory map to borrow compressed blocks from, set with [`BGZFReader::from_mmap`]
+    #[cfg(feature = "mmap")]
+    mapped: Option<std::sync::Arc<memmap2::Mmap>>,
+    /// Skip broken blocks, set with [`BGZFReader::with_recover`]
+    recover: Option<Box<recover::Recover<R>>>,
 }
 
 impl<R: Read + Seek> BGZFReader<R> {
+    /// Skip broken blocks instead of returning an error.
+    ///
+    /// If a block cannot be parsed or decompressed, data after the block is scanned for the next
+    /// gzip magic bytes followed by a block which is decompressed successfully, and reading resumes
+    /// at the block. `callback` is called with the compressed range of skipped data and the error
+    /// of the broken block. Data of skipped blocks are lost, so this mode is useful to salvage
+    /// partially corrupted archives. Errors of the underlying reader are returned as is.
+    /// The first block is loaded by constructors, so it is not recovered.
+    ///
+    /// ```rust
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use std::io::Read;
+    ///
+    /// let mut data = std::fs::read("testfiles/generated.bed.gz")?;
+    /// data[100_000] ^= 0xff;
+    /// let mut reader = bgzip::BGZFReader::new(std::io::Cursor::new(data))?.with_recover(
+    ///     |range, error| eprintln!("skipped {:?}: {}", range, error),
+    /// );
+    /// let mut salvaged = Vec::new();
+    /// reader.read_to_end(&mut salvaged)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_recover<F: FnMut(Range<u64>, &BGZFError) + Send + 'static>(
+        mut self,
+        callback: F,
+    ) -> Self {
+        self.recover = Some(Box::new(recover::Recover::new(Box::new(callback))));
+        self
+    }
+
     /// Seek BGZF with position. This position is not equal to real file offset,
     /// but equal to virtual file offset described in [BGZF format](https://samtools.github.io/hts-specs/SAMv1.pdf).
     /// Please read "4.1.1 Random access" to learn more.
-    pub fn bgzf_seek(&mut self, position: u64) -> Result<(), BGZFError> {
-        self.next_block = position >> 16;
+    ///
+    /// Raw `u64` offsets stored in index files are also accepted.
+    pub fn bgzf_seek<P: Into<VirtualPosition>>(&mut self, position: P) -> Result<(), BGZFError> {
+        if self.is_degraded() {
+            return Err(BGZFError::NotBGZF);
+        }
+        let position = position.into();
+        self.next_block = position.compressed_offset();
+        self.next_block_index = if self.next_block == 0 { Some(0) } else { None };
         self.reader.seek(io::SeekFrom::Start(self.next_block))?;
         self.load_next()?;
-        self.current_position_in_block = (position & 0xffff) as usize;
+        self.current_position_in_block = position.uncompressed_offset_in_block().into();
 
         Ok(())
     }
+
+    /// Attach `index` to enable [`std::io::Seek`] with uncompressed positions.
+    ///
+    /// The uncompressed length is calculated from the last index entry and ISIZE fields of
+    /// the following blocks to support [`std::io::SeekFrom::End`]. The current position is kept.
+    pub fn with_index(mut self, index: BGZFIndex) -> Result<Self, BGZFError> {
+        if self.is_degraded() {
+            return Err(BGZFError::NotBGZF);
+        }
+        let uncompressed_size = index.uncompressed_size(&mut self.reader);
+        // restore stream position of the reader
+        self.reader.seek(io::SeekFrom::Start(self.next_block))?;
+        self.index = Some((index, uncompressed_size?));
+        Ok(self)
+    }
+
+    /// Index attached with [`BGZFReader::with_index`]
+    pub fn index(&self) -> Option<&BGZFIndex> {
+        self.index.as_ref().map(|x| &x.0)
+    }
+
+    /// Uncompressed position calculated with the attached index
+    fn uncompressed_pos(&self) -> Result<u64, BGZFError> {
+        let (index, uncompressed_size) = self.index.as_ref().ok_or(BGZFError::Other(
+            "Index is required to seek uncompressed position",
+        ))?;
+        if self.current_block >= self.eof_pos {
+            return Ok(*uncompressed_size);
+        }
+        index.bgzf_pos_to_uncompressed_pos(self.bgzf_pos())
+    }
+}
+
+impl<R: Read + Seek> Seek for BGZFReader<R> {
+    /// Seek to an uncompressed position. An index must be attached with [`BGZFReader::with_index`].
+    ///
+    /// Seeking beyond the end of data fails with [`std::io::ErrorKind::InvalidInput`].
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        let current_pos = self.uncompressed_pos().map_err(|x| x.into_io_error())?;
+        let uncompressed_size = self.index.as_ref().unwrap().1;
+        let new_pos = match pos {
+            io::SeekFrom::Start(p) => Some(p),
+            io::SeekFrom::Current(p) => add_signed(current_pos, p),
+            io::SeekFrom::End(p) => add_signed(uncompressed_size, p),
+        }
+        .filter(|x| *x <= uncompressed_size)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative position or beyond the end",
+            )
+        })?;
+        if new_pos != current_pos {
+            let bgzf_pos = self
+                .index
+                .as_ref()
+                .unwrap()
+                .0
+                .uncompressed_pos_to_bgzf_pos(new_pos)
+                .map_err(|x| x.into_io_error())?;
+            self.bgzf_seek(bgzf_pos).map_err(|x| x.into_io_error())?;
+        }
+        Ok(new_pos)
+    }
+}
+
+fn add_signed(value: u64, offset: i64) -> Option<u64> {
+    if offset >= 0 {
+        value.checked_add(offset.unsigned_abs())
+    } else {
+        value.checked_sub(offset.unsigned_abs())
+    }
+}
+
+impl<T: ReadAt> BGZFReader<ReadAtReader<T>> {
+    /// Create a new BGZF reader from random access `source`, such as a file shared by many readers
+    /// or a client of an object storage.
+    pub fn from_read_at(source: T) -> Result<Self, BGZFError> {
+        Self::new(ReadAtReader::new(source))
+    }
+}
+
+#[cfg(feature = "mmap")]
+impl BGZFReader<ReadAtReader<std::sync::Arc<memmap2::Mmap>>> {
+    /// Create a new BGZF reader of a memory mapped file at `path`.
+    ///
+    /// Compressed blocks are decompressed directly from the memory map without copying them into
+    /// an intermediate buffer. The file must not be modified while the reader is alive.
+    pub fn from_mmap<P: AsRef<Path>>(path: P) -> Result<Self, BGZFError> {
+        let file = std::fs::File::open(path)?;
+        // SAFETY: the file is expected not to be modified by other processes while it is mapped
+        let mapped = std::sync::Arc::new(unsafe { memmap2::Mmap::map(&file)? });
+        let mut reader = Self::from_read_at(mapped.clone())?;
+        reader.mapped = Some(mapped);
+        Ok(reader)
+    }
+}
+
+#[cfg(feature = "threads")]
+impl<R: Read + Seek + Send + 'static> BGZFReader<Readahead<R>> {
+    /// Create a new BGZF reader which loads up to `num_blocks` blocks ahead in a background thread.
+    ///
+    /// See [`Readahead`] for details.
+    pub fn with_readahead(reader: R, num_blocks: usize) -> Result<Self, BGZFError> {
+        Self::new(Readahead::new(reader, num_blocks)?)
+    }
 }
 
 impl<R: Read> BGZFReader<R> {
     /// Create a new BGZF reader from [`std::io::Read`]
     pub fn new(mut reader: R) -> Result<Self, BGZFError> {
-        let mut decompress = Decompress::new();
-        let mut compressed_buffer = Vec::new();
-        load_block(&mut reader, &mut compressed_buffer)?;
-        let mut buffer = Vec::new();
-        decompress_block(&mut buffer, &compressed_buffer, &mut decompress)?;
+        let header = BGZFHeader::from_reader(&mut reader)?;
+        let mut result = Self::empty(reader);
+        result.load_first_block(&header)?;
+        result.file_comment = header.comment.map(strip_nul);
+        Ok(result)
+    }
 
-        Ok(BGZFReader {
+    /// Create a new reader from [`std::io::Read`] which accepts gzip data that is not BGZF.
+    ///
+    /// If the first header does not have BGZF block size, the data is decoded as multi-member
+    /// gzip in sequential-only mode. [`BGZFReader::is_degraded`] returns `true` in this mode,
+    /// and [`BGZFReader::bgzf_seek`] fails with [`BGZFError::NotBGZF`].
+    #[cfg(feature = "flate2")]
+    pub fn new_degradable(mut reader: R) -> Result<Self, BGZFError> {
+        let header = BGZFHeader::from_reader(&mut reader)?;
+        let mut result = Self::empty(reader);
+        result.file_comment = header.comment.clone().map(strip_nul);
+        if header.block_size().is_ok() {
+            result.load_first_block(&header)?;
+        } else {
+            result.degraded = Some(Box::new(degraded::DegradedStream::new()));
+            result.load_next()?;
+        }
+        Ok(result)
+    }
+
+    fn empty(reader: R) -> Self {
+        BGZFReader {
             reader,
-            decompress,
-            current_buffer: buffer,
+            decompress: Decompress::new(),
+            compressed_buffer: Vec::new(),
+            current_buffer: Vec::new(),
             current_block: 0,
-            next_block: compressed_buffer.len().try_into().unwrap(),
+            next_block: 0,
+            next_block_index: Some(0),
             current_position_in_block: 0,
             eof_pos: u64::MAX,
-            compressed_buffer,
-        })
+            fail_on_missing_eof: false,
+            eof_marker_end: None,
+            fail_on_trailing_data: false,
+            trailing_data_checked: false,
+            concatenated: false,
+            strict: false,
+            header_limits: HeaderLimits::default(),
+            cache: cache::BlockCache::default(),
+            recorder: Recorder::disabled(),
+            file_comment: None,
+            index: None,
+            #[cfg(feature = "flate2")]
+            degraded: None,
+            #[cfg(feature = "mmap")]
+            mapped: None,
+            recover: None,
+        }
+    }
+
+    fn load_first_block(&mut self, header: &BGZFHeader) -> Result<(), BGZFError> {
+        // data without BGZF block size is not BGZF rather than a broken block
+        header.block_size()?;
+        load_block_data(&mut self.reader, header, &mut self.compressed_buffer)
+            .and_then(|_| {
+                decompress_block(
+                    &mut self.current_buffer,
+                    &self.compressed_buffer,
+                    &mut self.decompress,
+                )
+            })
+            .map_err(|e| e.in_block(0, Some(0)))?;
+        self.next_block_index = Some(1);
+        self.next_block =
+            header.header_size() + TryInto::<u64>::try_into(self.compressed_buffer.len()).unwrap();
+        if self.compressed_buffer == EOF_BLOCK {
+            self.eof_pos = 0;
+            self.eof_marker_end = Some(self.next_block);
+        }
+        Ok(())
+    }
+
+    /// FCOMMENT field of the first block, such as a comment written with
+    /// [`crate::write::BGZFWriter::with_file_comment`].
+    pub fn file_comment(&self) -> Option<&[u8]> {
+        self.file_comment.as_deref()
+    }
+
+    /// Return `true` if the data is not BGZF and decoded in sequential-only mode.
+    ///
+    /// See [`BGZFReader::new_degradable`].
+    pub fn is_degraded(&self) -> bool {
+        #[cfg(feature = "flate2")]
+        return self.degraded.is_some();
+        #[cfg(not(feature = "flate2"))]
+        false
+    }
+
+    /// Select DEFLATE [`Backend`] to decompress blocks.
+    pub fn with_backend(mut self, backend: Backend) -> Self {
+        self.decompress = Decompress::with_backend(backend);
+        self
+    }
+
+    /// Return [`BGZFError::TruncatedFile`] if data ends without end-of-file marker
+    /// or in the middle of a block.
+    ///
+    /// By default, data ending at a block boundary without end-of-file marker is read to the end
+    /// like `bgzip` command of htslib, and data ending in the middle of a block is an I/O error.
+    pub fn with_fail_on_missing_eof(mut self, fail_on_missing_eof: bool) -> Self {
+        self.fail_on_missing_eof = fail_on_missing_eof;
+        self
+    }
+
+    /// Return [`BGZFError::TrailingData`] if any byte exists after end-of-file marker.
+    ///
+    /// By default, data after end-of-file marker is ignored.
+    pub fn with_fail_on_trailing_data(mut self, fail_on_trailing_data: bool) -> Self {
+        self.fail_on_trailing_data = fail_on_trailing_data;
+        self
+    }
+
+    /// Read concatenated BGZF files, such as output of `cat a.gz b.gz`, to the end.
+    ///
+    /// By default, reading ends at the first end-of-file marker. With this option, end-of-file
+    /// markers followed by more blocks are skipped as empty blocks, and reading ends at the
+    /// physical end of data. Data ending without end-of-file marker is handled as configured with
+    /// [`BGZFReader::with_fail_on_missing_eof`].
+    pub fn with_concatenated(mut self, concatenated: bool) -> Self {
+        self.concatenated = concatenated;
+        if concatenated && self.eof_pos == 0 {
+            // the first block is end-of-file marker
+            self.eof_pos = u64::MAX;
+        }
+        self
+    }
+
+    /// Return `true` if end-of-file marker has been read.
+    ///
+    /// With [`BGZFReader::with_concatenated`], this is `true` only if the last read block is
+    /// end-of-file marker.
+    ///
+    /// Data ending at a block boundary without the marker is read to the end unless
+    /// [`BGZFReader::with_fail_on_missing_eof`] is set, so check this after reading all data
+    /// to distinguish a cleanly terminated file from a truncated one.
+    pub fn reached_eof_marker(&self) -> bool {
+        self.eof_marker_end.is_some()
+    }
+
+    /// Enforce block constraints of the SAM specification.
+    ///
+    /// In strict mode, [`BGZFError::UncompressedBlockTooLarge`] is returned if ISIZE of a block
+    /// exceeds 65536 bytes, and [`BGZFError::NotBGZF`] is returned for gzip data without BC field
+    /// even if the reader is created with [`BGZFReader::new_degradable`]. A block cannot exceed
+    /// 65536 bytes because its size is stored in BC field.
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Set [`HeaderLimits`] to parse headers of following blocks.
+    ///
+    /// The first block is already loaded with default limits in [`BGZFReader::new`].
+    pub fn with_header_limits(mut self, header_limits: HeaderLimits) -> Self {
+        self.header_limits = header_limits;
+        self
+    }
+
+    /// Keep up to `cache_size` recently decompressed blocks.
+    ///
+    /// Blocks are keyed on compressed offset, and the least recently used block is evicted first.
+    /// Reading a cached block after [`BGZFReader::bgzf_seek`] skips decompression, so alternating
+    /// access to a few regions becomes fast. The cache is disabled by default.
+    pub fn with_cache_size(mut self, cache_size: usize) -> Self {
+        self.cache.set_capacity(cache_size);
+        self
+    }
+
+    /// Record decompression duration of each block into `sink`.
+    ///
+    /// The first block is already loaded in [`BGZFReader::new`] and is not recorded.
+    #[cfg(feature = "metrics")]
+    pub fn with_histogram(
+        mut self,
+        sink: std::sync::Arc<dyn crate::metrics::HistogramSink>,
+    ) -> Self {
+        self.recorder = Recorder::new(sink);
+        self
     }
 
     /// Get BGZF virtual file offset. This position is not equal to real file offset,
     /// but equal to virtual file offset described in [BGZF format](https://samtools.github.io/hts-specs/SAMv1.pdf).
     /// Please read "4.1.1 Random access" to learn more.    
-    pub fn bgzf_pos(&self) -> u64 {
-        self.current_block << 16 | (self.current_position_in_block & 0xffff) as u64
+    pub fn bgzf_pos(&self) -> VirtualPosition {
+        VirtualPosition::from(
+            self.current_block << 16 | (self.current_position_in_block & 0xffff) as u64,
+        )
     }
 
-    fn load_next(&mut self) -> Result<(), BGZFError> {
-        if self.next_block >= self.eof_pos {
-            return Ok(());
+    /// Get BGZF virtual file offset of the next byte to be read, or `None` at the end of data.
+    ///
+    /// If the current block is consumed, [`BGZFReader::bgzf_pos`] points the end of the block,
+    /// while this method loads the next block and returns the offset of its first byte.
+    /// Use this method to record the position of a record or to compare it with a chunk end.
+    pub fn next_bgzf_pos(&mut self) -> io::Result<Option<VirtualPosition>> {
+        if self.fill_buf()?.is_empty() {
+            return Ok(None);
         }
+        Ok(Some(self.bgzf_pos()))
+    }
 
-        self.compressed_buffer.clear();
-        let header = load_block(&mut self.reader, &mut self.compressed_buffer)?;
-        let header_size = header.header_size();
-        if self.compressed_buffer == crate::EOF_MARKER {
-            self.eof_pos = self.next_block;
-            self.current_buffer.clear();
-            self.current_block = self.next_block;
-            self.current_position_in_block = 0;
-            return Ok(());
-        }
+    /// Compressed offset of the current block in the underlying reader.
+    ///
+    /// After reading all data, this is the offset of the end-of-file marker.
+    pub fn current_block_file_offset(&self) -> u64 {
+        self.current_block
+    }
 
-        self.current_buffer.clear();
-        decompress_block(
-            &mut self.current_buffer,
-            &self.compressed_buffer,
-            &mut self.decompress,
-        )?;
-        self.current_block = self.next_block;
-        let current_block_size: u64 = self.compressed_buffer.len().try_into().unwrap();
-        self.next_block += current_block_size + header_size;
-        self.current_position_in_block = 0;
+    /// Size of the current block including the header and the footer.
+    ///
+    /// Zero after reading all data.
+    pub fn current_block_compressed_size(&self) -> u64 {
+        self.next_block.saturating_sub(self.current_block)
+    }
 
-        Ok(())
+    /// Size of decompressed data of the current block.
+    pub fn current_block_uncompressed_size(&self) -> usize {
+        self.current_buffer.len()
     }
-}
 
-impl<R: Read> BufRead for BGZFReader<R> {
-    fn fill_buf(&mut self) -> io::Result<&[u8]> {
-        if self.current_position_in_block >= self.current_buffer.len() {
-            self.load_next().map_err(|e| e.into_io_error())?;
+    /// Compressed size of the current block divided by its uncompressed size.
+    /// `None` if the current block is empty.
+    pub fn current_block_compression_ratio(&self) -> Option<f64> {
+        if self.current_buffer.is_empty() {
+            return None;
         }
+        Some(self.current_block_compressed_size() as f64 / self.current_buffer.len() as f64)
+    }
 
-        let remain_bytes = self.current_buffer.len() - self.current_position_in_block;
+    /// Convert into an iterator over owned decompressed data of each block.
+    ///
+    /// Decompressed buffers are moved out instead of being copied through [`BufRead`].
+    /// If the current block is partially consumed, only the rest of the block is returned first.
+    /// Empty blocks are skipped.
+    pub fn into_block_iter(self) -> BlockIter<R> {
+        BlockIter::new(self)
+    }
 
-        if remain_bytes > 0 {
-            return Ok(&self.current_buffer[self.current_position_in_block..]);
-        }
-        Ok(&[])
+    /// Create a reader which reads data from the current position and reaches the end of data at
+    /// `end`.
+    ///
+    /// This is useful to read a chunk of BAI, CSI or tabix index, which ends at a virtual file
+    /// offset possibly in the middle of a block.
+    pub fn take_until<P: Into<VirtualPosition>>(&mut self, end: P) -> TakeUntil<'_, R> {
+        TakeUntil::new(self, end.into())
     }
 
-    fn consume(&mut self, amt: usize) {
-        let remain_bytes = self.current_buffer.len() - self.current_position_in_block;
-        if amt <= remain_bytes {
-            self.current_position_in_block += amt;
-        } else {
-            unreachable!()
-        }
+    /// Read all data from the current position to `end` into `buf`, and return the number of
+    /// read bytes. See [`BGZFReader::take_until`].
+    pub fn read_until_voffset<P: Into<VirtualPosition>>(
+        &mut self,
+        end: P,
+        buf: &mut Vec<u8>,
+    ) -> io::Result<usize> {
+        self.take_until(end).read_to_end(buf)
     }
-}
 
-impl<R: Read> Read for BGZFReader<R> {
-    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        //eprintln!("read start: {}", buf.len());
-        let internal_buf = self.fill_buf()?;
-        let bytes_to_copy = buf.len().min(internal_buf.len());
-        buf[0..bytes_to_copy].copy_from_slice(&internal_buf[0..bytes_to_copy]);
-        self.consume(bytes_to_copy);
-        //eprintln!("read end: {}", bytes_to_copy);
-        Ok(bytes_to_copy)
+    /// Iterate lines with virtual file offsets of their first bytes.
+    ///
+    /// Trailing new line characters are removed. This is useful to build an index of records.
+    pub fn lines_with_pos(&mut self) -> LinesWithPos<'_, Self> {
+        LinesWithPos::new(self, BGZFReader::next_bgzf_pos)
     }
-}
 
-/// Seekable BGZF reader.
-pub struct IndexedBGZFReader<R: Read + Seek> {
-    reader: BGZFReader<R>,
+    fn load_next(&mut self) -> Result<(), BGZFError> {
+        #[cfg(feature = "flate2")]
+        if let Some(degraded) = self.degraded.as_mut() {
+            if self.strict {
+                return Err(BGZFError::NotBGZF);
+            }
+            self.current_position_in_block = 0;
+            return degraded.read_next(&mut self.reader, &mut self.current_buffer);
+        }
+        if self.next_block >= self.eof_pos {
+            return self.check_trailing_data();
+        }
+        let (next_block, mut next_block_index) = (self.next_block, self.next_block_index);
+        let fail_on_missing_eof = self.fail_on_missing_eof;
+        let result = match self.load_next_block() {
+            Err(e) if self.recover.is_some() && recover::is_recoverable(&e) => {
+                // the number of skipped blocks is unknown
+                next_block_index = None;
+                self.skip_broken_blocks(e)
+            }
+            result => result,
+        };
+        result
+            .map_err(|e| {
+                if fail_on_missing_eof {
+                    truncated_error(e)
+                } else {
+                    e
+                }
+            })
+            .map_err(|e| e.in_block(next_block, next_block_index))?;
+        self.next_block_index = next_block_index.map(|x| x + 1);
+        self.check_trailing_data()
+    }
+
+    /// Return [`BGZFError::TrailingData`] if data follows end-of-file marker which has just been read
+    fn check_trailing_data(&mut self) -> Result<(), BGZFError> {
+        let eof_marker_end = match self.eof_marker_end {
+            Some(end)
+                if self.fail_on_trailing_data
+                    && !self.trailing_data_checked
+                    && self.next_block >= self.eof_pos =>
+            {
+                end
+            }
+            _ => return Ok(()),
+        };
+        self.trailing_data_checked = true;
+        #[cfg(feature = "mmap")]
+        if let Some(mapped) = self.mapped.as_ref() {
+            return if mapped.len() as u64 > eof_marker_end {
+                Err(BGZFError::TrailingData {
+                    offset: eof_marker_end,
+                })
+            } else {
+                Ok(())
+            };
+        }
+        // the reader is just after end-of-file marker
+        let mut buf = [0; 1];
+        if self.reader.read(&mut buf)? > 0 {
+            return Err(BGZFError::TrailingData {
+                offset: eof_marker_end,
+            });
+        }
+        Ok(())
+    }
+
+    fn load_next_block(&mut self) -> Result<(), BGZFError> {
+        #[cfg(feature = "mmap")]
+        if let Some(mapped) = self.mapped.clone() {
+            return self.load_mapped_block(&mapped);
+        }
+
+        self.compressed_buffer.clear();
+        let header = load_block_or_end(
+            &mut self.reader,
+            &mut self.compressed_buffer,
+            &self.header_limits,
+        )?;
+        let header = match header {
+            Some(header) => header,
+            None => return self.missing_eof(),
+        };
+        let compressed_data = std::mem::take(&mut self.compressed_buffer);
+        let result = self.set_current_block(header.header_size(), &compressed_data);
+        self.compressed_buffer = compressed_data;
+        result
+    }
+
+    /// Skip data from `next_block` until a block is loaded successfully
+    fn skip_broken_blocks(&mut self, mut error: BGZFError) -> Result<(), BGZFError> {
+        loop {
+            let recover = self.recover.as_mut().unwrap();
+            let broken_block = self.next_block;
+            let resume_pos =
+                (recover.find_next_block)(&mut self.reader, broken_block + 1, &self.header_limits)?;
+            (recover.callback)(broken_block..resume_pos, &error);
+            self.next_block = resume_pos;
+            match self.load_next_block() {
+                Err(e) if recover::is_recoverable(&e) => error = e,
+                result => return result,
+            }
+        }
+    }
+
+    /// Load the block at `next_block` from memory map without copying compressed data
+    #[cfg(feature = "mmap")]
+    fn load_mapped_block(&mut self, mapped: &[u8]) -> Result<(), BGZFError> {
+        let data = usize::try_from(self.next_block)
+            .ok()
+            .and_then(|x| mapped.get(x..))
+            .unwrap_or_default();
+        if data.is_empty() {
+            return self.missing_eof();
+        }
+        let header = BGZFHeader::from_reader_with_limits(data, &self.header_limits)?;
+        let header_size: usize = header.header_size().try_into().unwrap();
+        let block_size = usize::from(header.block_size()?);
+        if block_size < header_size + 8 {
+            return Err(BGZFError::HeaderParseError {
+                position: header.header_size(),
+            });
+        }
+        let compressed_data = data
+            .get(header_size..block_size)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "Truncated BGZF block"))?;
+        self.set_current_block(header.header_size(), compressed_data)
+    }
+
+    /// Handle the end of data without end-of-file marker
+    fn missing_eof(&mut self) -> Result<(), BGZFError> {
+        if self.concatenated && self.eof_marker_end == Some(self.next_block) {
+            // data ends just after end-of-file marker
+            self.eof_pos = self.current_block;
+            self.next_block = self.current_block;
+            self.current_buffer.clear();
+            self.current_position_in_block = 0;
+            return Ok(());
+        }
+        if self.fail_on_missing_eof {
+            return Err(BGZFError::TruncatedFile);
+        }
+        #[cfg(feature = "log")]
+        log::warn!(
+            "BGZF data ends without end-of-file marker at compressed offset {}",
+            self.next_block
+        );
+        // same as end-of-file marker
+        self.eof_pos = self.next_block;
+        self.current_buffer.clear();
+        self.current_block = self.next_block;
+        self.current_position_in_block = 0;
+        Ok(())
+    }
+
+    /// Decompress `compressed_data` of the block at `next_block`, and advance to the block
+    fn set_current_block(
+        &mut self,
+        header_size: u64,
+        compressed_data: &[u8],
+    ) -> Result<(), BGZFError> {
+        if compressed_data == EOF_BLOCK {
+            let eof_marker_end = self.next_block + header_size + compressed_data.len() as u64;
+            self.eof_marker_end = Some(eof_marker_end);
+            if self.concatenated {
+                // skipped as an empty block unless data ends here
+                self.current_buffer.clear();
+                self.current_block = self.next_block;
+                self.next_block = eof_marker_end;
+                self.current_position_in_block = 0;
+                return Ok(());
+            }
+            self.eof_pos = self.next_block;
+            self.current_buffer.clear();
+            self.current_block = self.next_block;
+            self.current_position_in_block = 0;
+            return Ok(());
+        }
+
+        if self
+            .eof_marker_end
+            .is_some_and(|end| self.concatenated && self.next_block >= end)
+        {
+            // more blocks follow end-of-file marker
+            self.eof_marker_end = None;
+        }
+        self.current_buffer.clear();
+        if let Some(cached) = self.cache.get(self.next_block) {
+            self.current_buffer.extend_from_slice(cached);
+        } else {
+            self.recorder.record(BlockOperation::Decompress, || {
+                decompress_block(
+                    &mut self.current_buffer,
+                    compressed_data,
+                    &mut self.decompress,
+                )
+            })?;
+            self.cache
+                .insert_recent(self.next_block, &self.current_buffer);
+        }
+        self.current_block = self.next_block;
+        let current_block_size: u64 = compressed_data.len().try_into().unwrap();
+        self.next_block += current_block_size + header_size;
+        self.current_position_in_block = 0;
+
+        Ok(())
+    }
+}
+
+impl<R: Read> BufRead for BGZFReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        while self.current_position_in_block >= self.current_buffer.len() {
+            self.load_next().map_err(|e| e.into_io_error())?;
+            // empty blocks in the middle of data are skipped
+            if !self.current_buffer.is_empty()
+                || self.next_block >= self.eof_pos
+                || self.is_degraded()
+            {
+                break;
+            }
+        }
+        if self.strict && self.current_buffer.len() > MAXIMUM_COMPRESS_UNIT_SIZE {
+            // ISIZE is checked at decompression
+            return Err(BGZFError::UncompressedBlockTooLarge {
+                size: self.current_buffer.len() as u64,
+            }
+            .in_block(self.current_block, None)
+            .into_io_error());
+        }
+
+        let remain_bytes = self.current_buffer.len() - self.current_position_in_block;
+
+        if remain_bytes > 0 {
+            return Ok(&self.current_buffer[self.current_position_in_block..]);
+        }
+        Ok(&[])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        let remain_bytes = self.current_buffer.len() - self.current_position_in_block;
+        if amt <= remain_bytes {
+            self.current_position_in_block += amt;
+        } else {
+            unreachable!()
+        }
+    }
+}
+
+impl<R: Read> Read for BGZFReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        //eprintln!("read start: {}", buf.len());
+        let internal_buf = self.fill_buf()?;
+        let bytes_to_copy = buf.len().min(internal_buf.len());
+        buf[0..bytes_to_copy].copy_from_slice(&internal_buf[0..bytes_to_copy]);
+        self.consume(bytes_to_copy);
+        //eprintln!("read end: {}", bytes_to_copy);
+        Ok(bytes_to_copy)
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [io::IoSliceMut<'_>]) -> io::Result<usize> {
+        read_vectored_from_buf(self, bufs)
+    }
+}
+
+/// Fill `bufs` in order directly from decompressed data of `reader`.
+///
+/// Reading stops at the end of data. An error is returned only if nothing is read.
+pub(crate) fn read_vectored_from_buf<R: BufRead>(
+    reader: &mut R,
+    bufs: &mut [io::IoSliceMut<'_>],
+) -> io::Result<usize> {
+    let mut read_bytes = 0;
+    for buf in bufs.iter_mut() {
+        let mut filled = 0;
+        while filled < buf.len() {
+            let internal_buf = match reader.fill_buf() {
+                Ok(x) => x,
+                // the error is returned again by the next read
+                Err(_) if read_bytes + filled > 0 => return Ok(read_bytes + filled),
+                Err(e) => return Err(e),
+            };
+            if internal_buf.is_empty() {
+                return Ok(read_bytes + filled);
+            }
+            let bytes_to_copy = (buf.len() - filled).min(internal_buf.len());
+            buf[filled..(filled + bytes_to_copy)].copy_from_slice(&internal_buf[..bytes_to_copy]);
+            reader.consume(bytes_to_copy);
+            filled += bytes_to_copy;
+        }
+        read_bytes += filled;
+    }
+    Ok(read_bytes)
+}
+
+/// Readable and seekable stream.
+///
+/// This trait is implemented for all types implementing [`Read`] and [`Seek`].
+/// Use `Box<dyn ReadSeek>` to pass heterogeneous sources (file, memory map, HTTP range reader, etc.)
+/// to [`BGZFReader`] without wrapper enums.
+pub trait ReadSeek: Read + Seek {}
+
+impl<T: Read + Seek + ?Sized> ReadSeek for T {}
+
+/// Object safe interface of BGZF readers supporting random access with virtual file offset.
+///
+/// Readers with different types can be stored in one collection as `Box<dyn BGZFReadSeek>`.
+pub trait BGZFReadSeek: BufRead {
+    /// Get BGZF virtual file offset. See [`BGZFReader::bgzf_pos`].
+    fn bgzf_pos(&self) -> VirtualPosition;
+    /// Seek BGZF with virtual file offset. See [`BGZFReader::bgzf_seek`].
+    fn bgzf_seek(&mut self, position: VirtualPosition) -> Result<(), BGZFError>;
+}
+
+impl<R: Read + Seek> BGZFReadSeek for BGZFReader<R> {
+    fn bgzf_pos(&self) -> VirtualPosition {
+        BGZFReader::bgzf_pos(self)
+    }
+
+    fn bgzf_seek(&mut self, position: VirtualPosition) -> Result<(), BGZFError> {
+        BGZFReader::bgzf_seek(self, position)
+    }
+}
+
+impl<T: BGZFReadSeek + ?Sized> BGZFReadSeek for Box<T> {
+    fn bgzf_pos(&self) -> VirtualPosition {
+        (**self).bgzf_pos()
+    }
+
+    fn bgzf_seek(&mut self, position: VirtualPosition) -> Result<(), BGZFError> {
+        (**self).bgzf_seek(position)
+    }
+}
+
+/// Seekable BGZF reader.
+pub struct IndexedBGZFReader<R: Read + Seek> {
+    reader: BGZFReader<R>,
     index: BGZFIndex,
     current_pos: u64,
     end_pos: u64,
@@ -273,35 +1332,159 @@ pub struct IndexedBGZFReader<R: Read + Seek> {
 
 impl<R: Read + Seek> IndexedBGZFReader<R> {
     /// Create new [`IndexedBGZFReader`] from [`BGZFReader`] and [`BGZFIndex`].
+    ///
+    /// The uncompressed length is calculated from the last index entry and
+    /// ISIZE fields of the following blocks, so whole data is not decompressed.
     pub fn new(mut reader: BGZFReader<R>, index: BGZFIndex) -> Result<Self, BGZFError> {
-        let last_entry = index
-            .entries
-            .last()
-            .ok_or(BGZFError::Other("Invalid index file"))?
-            .clone();
-        reader.bgzf_seek(last_entry.compressed_offset << 16)?;
-        let mut buf = Vec::new();
-        reader.read_to_end(&mut buf)?;
+        let end_pos = index.uncompressed_size(&mut reader.reader)?;
         reader.bgzf_seek(0)?;
 
         Ok(IndexedBGZFReader {
             reader,
             index,
             current_pos: 0,
-            end_pos: last_entry.uncompressed_offset + TryInto::<u64>::try_into(buf.len()).unwrap(),
+            end_pos,
         })
     }
+
+    /// Get the index of this reader.
+    pub fn index(&self) -> &BGZFIndex {
+        &self.index
+    }
+
+    /// Get the uncompressed position of end of file.
+    pub fn end_pos(&self) -> u64 {
+        self.end_pos
+    }
+
+    /// Get the uncompressed length of data. This value is equal to [`IndexedBGZFReader::end_pos`].
+    pub fn len(&self) -> u64 {
+        self.end_pos
+    }
+
+    /// Return `true` if uncompressed data is empty.
+    pub fn is_empty(&self) -> bool {
+        self.end_pos == 0
+    }
+
+    /// Decompress all blocks overlapping with `range` of uncompressed positions into the block cache.
+    ///
+    /// Following reads from warmed blocks skip decompression. This is useful to prefetch a region
+    /// which will be read soon. Warmed blocks are kept until [`IndexedBGZFReader::clear_cache`] is called.
+    /// Number of newly decompressed blocks is returned.
+    pub fn warm(&mut self, range: Range<u64>) -> Result<usize, BGZFError> {
+        let blocks = self.uncached_blocks(range)?;
+        let block_num = blocks.len();
+        for block in blocks {
+            let block = block.decompress(&mut self.reader.decompress)?;
+            self.reader.cache.pin(block.compressed_offset, block.data);
+        }
+        Ok(block_num)
+    }
+
+    /// Same as [`IndexedBGZFReader::warm`], but blocks are decompressed in the rayon thread pool.
+    #[cfg(feature = "rayon")]
+    pub fn par_warm(&mut self, range: Range<u64>) -> Result<usize, BGZFError> {
+        use rayon::prelude::*;
+
+        let blocks = self.uncached_blocks(range)?;
+        let block_num = blocks.len();
+        let backend = self.reader.decompress.backend();
+        let decompressed = blocks
+            .into_par_iter()
+            .map_init(
+                || Decompress::with_backend(backend.clone()),
+                |decompress, block| block.decompress(decompress),
+            )
+            .collect::<Result<Vec<_>, _>>()?;
+        for block in decompressed {
+            self.reader.cache.pin(block.compressed_offset, block.data);
+        }
+        Ok(block_num)
+    }
+
+    /// Remove all blocks from the block cache.
+    pub fn clear_cache(&mut self) {
+        self.reader.cache.clear();
+    }
+
+    /// Load compressed blocks overlapping with `range`, except blocks in the cache.
+    fn uncached_blocks(&mut self, range: Range<u64>) -> Result<Vec<RawBlock>, BGZFError> {
+        let end = range.end.min(self.end_pos);
+        if range.start >= end {
+            return Ok(Vec::new());
+        }
+        let bgzf_pos = self.index.uncompressed_pos_to_bgzf_pos(range.start)?;
+        let compressed_offset = bgzf_pos.compressed_offset();
+        let uncompressed_offset = range.start - u64::from(bgzf_pos.uncompressed_offset_in_block());
+
+        let mut blocks = Vec::new();
+        self.reader
+            .reader
+            .seek(io::SeekFrom::Start(compressed_offset))?;
+        for block in RawBlocks::new(&mut self.reader.reader) {
+            let mut block = block?;
+            block.compressed_offset += compressed_offset;
+            block.uncompressed_offset += uncompressed_offset;
+            if block.uncompressed_offset >= end || block.data == EOF_BLOCK {
+                break;
+            }
+            if !self.reader.cache.pin_existing(block.compressed_offset) {
+                blocks.push(block);
+            }
+        }
+        // restore stream position of the reader
+        self.reader
+            .reader
+            .seek(io::SeekFrom::Start(self.reader.next_block))?;
+
+        Ok(blocks)
+    }
+}
+
+impl<T: ReadAt> IndexedBGZFReader<ReadAtReader<T>> {
+    /// Create new [`IndexedBGZFReader`] from random access `source` and [`BGZFIndex`].
+    pub fn from_read_at(source: T, index: BGZFIndex) -> Result<Self, BGZFError> {
+        Self::new(BGZFReader::from_read_at(source)?, index)
+    }
 }
 
 impl IndexedBGZFReader<std::fs::File> {
     /// Create new [`IndexedBGZFReader`] from file path.
+    ///
+    /// Index is loaded from `path` + `.gzi`. If the index file does not exist, index is created
+    /// by scanning block headers of `path` with [`BGZFIndex::from_bgzf_reader`].
     pub fn from_path<P: AsRef<std::path::Path>>(path: P) -> Result<Self, BGZFError> {
+        let mut index_path = path.as_ref().as_os_str().to_os_string();
+        index_path.push(".gzi");
+        match std::fs::File::open(&index_path) {
+            Ok(index_file) => {
+                let reader = BGZFReader::new(std::fs::File::open(path.as_ref())?)?;
+                IndexedBGZFReader::new(
+                    reader,
+                    BGZFIndex::from_reader(io::BufReader::new(index_file))?,
+                )
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                let index = BGZFIndex::from_bgzf_reader(io::BufReader::new(std::fs::File::open(
+                    path.as_ref(),
+                )?))?;
+                let reader = BGZFReader::new(std::fs::File::open(path.as_ref())?)?;
+                IndexedBGZFReader::new(reader, index)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Create new [`IndexedBGZFReader`] from paths of BGZF file and .gzi index file.
+    pub fn from_paths<P: AsRef<std::path::Path>, Q: AsRef<std::path::Path>>(
+        path: P,
+        index_path: Q,
+    ) -> Result<Self, BGZFError> {
         let reader = BGZFReader::new(std::fs::File::open(path.as_ref())?)?;
-        let index = BGZFIndex::from_reader(std::fs::File::open(
-            path.as_ref()
-                .to_str()
-                .ok_or(BGZFError::PathConvertionError)?,
-        )?)?;
+        let index = BGZFIndex::from_reader(io::BufReader::new(std::fs::File::open(
+            index_path.as_ref(),
+        )?))?;
         IndexedBGZFReader::new(reader, index)
     }
 }
@@ -330,6 +1513,20 @@ impl<R: Read + Seek> Seek for IndexedBGZFReader<R> {
     }
 }
 
+impl<R: Read + Seek> BGZFReadSeek for IndexedBGZFReader<R> {
+    fn bgzf_pos(&self) -> VirtualPosition {
+        self.reader.bgzf_pos()
+    }
+
+    /// Seek with virtual file offset. `position` must point to a block listed in the index.
+    fn bgzf_seek(&mut self, position: VirtualPosition) -> Result<(), BGZFError> {
+        let uncompressed_pos = self.index.bgzf_pos_to_uncompressed_pos(position)?;
+        self.reader.bgzf_seek(position)?;
+        self.current_pos = uncompressed_pos;
+        Ok(())
+    }
+}
+
 impl<R: Read + Seek> BufRead for IndexedBGZFReader<R> {
     fn fill_buf(&mut self) -> io::Result<&[u8]> {
         self.reader.fill_buf()
@@ -400,6 +1597,189 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_decompress_block_into() -> anyhow::Result<()> {
+        let data = fs::read("testfiles/generated.bed.gz")?;
+        let mut expected_data = Vec::new();
+        flate2::read::MultiGzDecoder::new(&data[..]).read_to_end(&mut expected_data)?;
+
+        let mut reader = &data[..];
+        let mut block_data = Vec::new();
+        let mut decompress = Decompress::new();
+        let mut buffer = vec![0u8; crate::write::MAXIMUM_COMPRESS_UNIT_SIZE];
+        let mut decompressed_data = Vec::new();
+        while !reader.is_empty() {
+            load_block(&mut reader, &mut block_data)?;
+            let size = decompress_block_into(&mut buffer, &block_data, &mut decompress)?;
+            decompressed_data.extend_from_slice(&buffer[..size]);
+
+            if size > 0 {
+                let result =
+                    decompress_block_into(&mut buffer[..(size - 1)], &block_data, &mut decompress);
+                assert!(matches!(
+                    result,
+                    Err(BGZFError::DecompressionError(
+                        crate::deflate::DecompressError::InsufficientSpace
+                    ))
+                ));
+            }
+        }
+        assert_eq!(decompressed_data, expected_data);
+
+        // output is not grown on errors
+        load_block(&data[..], &mut block_data)?;
+        let block_len = block_data.len();
+        block_data[block_len - 8] ^= 0xff;
+        let mut output = b"abc".to_vec();
+        assert!(decompress_block(&mut output, &block_data, &mut decompress).is_err());
+        assert_eq!(output, b"abc");
+        assert!(decompress_block_into(&mut buffer, &[0; 7], &mut decompress).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_member_offsets() -> anyhow::Result<()> {
+        let first = fs::read("testfiles/generated.bed.gz")?;
+        let second = fs::read("testfiles/common_all_20180418_half.vcf.gz")?;
+        assert_eq!(member_offsets(&first[..])?, Vec::<u64>::new());
+
+        let mut concatenated = first.clone();
+        concatenated.extend_from_slice(&second);
+        concatenated.extend_from_slice(&first);
+        let first_len: u64 = first.len().try_into()?;
+        let second_len: u64 = second.len().try_into()?;
+        let eof_len: u64 = crate::EOF_MARKER.len().try_into()?;
+        assert_eq!(
+            member_offsets(&concatenated[..])?,
+            vec![first_len - eof_len, first_len + second_len - eof_len]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_copy_region() -> anyhow::Result<()> {
+        let mut expected_data = Vec::new();
+        flate2::read::MultiGzDecoder::new(File::open("testfiles/generated.bed.gz")?)
+            .read_to_end(&mut expected_data)?;
+
+        let mut reader = BGZFReader::new(File::open("testfiles/generated.bed.gz")?)?;
+        let mut offsets = vec![(VirtualPosition::default(), 0)];
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            if reader.read_until(b'\n', &mut line)? == 0 {
+                break;
+            }
+            offsets.push((reader.bgzf_pos(), offsets.last().unwrap().1 + line.len()));
+        }
+
+        let mut rand = rand_pcg::Pcg64Mcg::seed_from_u64(0x1287402456157523);
+        let mut copied = Vec::new();
+        for _ in 0..100 {
+            let start = rand.gen_range(0..offsets.len());
+            let end = rand.gen_range(start..offsets.len());
+            copied.clear();
+            let size = copy_region(
+                File::open("testfiles/generated.bed.gz")?,
+                offsets[start].0,
+                offsets[end].0,
+                &mut copied,
+            )?;
+            assert_eq!(size, copied.len() as u64);
+            assert_eq!(copied, &expected_data[offsets[start].1..offsets[end].1]);
+        }
+
+        // whole data
+        copied.clear();
+        copy_region(
+            File::open("testfiles/generated.bed.gz")?,
+            VirtualPosition::default(),
+            VirtualPosition::from(u64::MAX),
+            &mut copied,
+        )?;
+        assert_eq!(copied, expected_data);
+
+        assert!(copy_region(
+            File::open("testfiles/generated.bed.gz")?,
+            offsets[10].0,
+            offsets[5].0,
+            &mut copied,
+        )
+        .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_copy_range() -> anyhow::Result<()> {
+        let mut expected_data = Vec::new();
+        flate2::read::MultiGzDecoder::new(File::open("testfiles/generated.bed.gz")?)
+            .read_to_end(&mut expected_data)?;
+        let original_blocks: Vec<_> =
+            block::RawBlocks::new(File::open("testfiles/generated.bed.gz")?)
+                .collect::<Result<_, _>>()?;
+
+        let mut reader = BGZFReader::new(File::open("testfiles/generated.bed.gz")?)?;
+        let mut offsets = vec![(VirtualPosition::default(), 0)];
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            if reader.read_until(b'\n', &mut line)? == 0 {
+                break;
+            }
+            offsets.push((reader.bgzf_pos(), offsets.last().unwrap().1 + line.len()));
+        }
+
+        let mut rand = rand_pcg::Pcg64Mcg::seed_from_u64(0x6287402456157524);
+        for _ in 0..30 {
+            let start = rand.gen_range(0..offsets.len());
+            let end = rand.gen_range(start..offsets.len());
+            let mut copied = Vec::new();
+            let mut writer = crate::BGZFWriter::new(&mut copied, Compression::default());
+            writer.write_all(b"header\n")?;
+            let size = copy_range(
+                File::open("testfiles/generated.bed.gz")?,
+                &mut writer,
+                offsets[start].0,
+                offsets[end].0,
+            )?;
+            writer.close()?;
+            assert_eq!(size, (offsets[end].1 - offsets[start].1) as u64);
+
+            let mut data = Vec::new();
+            BGZFReader::new(&copied[..])?.read_to_end(&mut data)?;
+            assert_eq!(&data[..7], b"header\n");
+            assert_eq!(&data[7..], &expected_data[offsets[start].1..offsets[end].1]);
+
+            // blocks between partial blocks are copied as is
+            let copied_blocks: Vec<_> =
+                block::RawBlocks::new(&copied[..]).collect::<Result<_, _>>()?;
+            let start_block = offsets[start].0.compressed_offset();
+            let end_block = offsets[end].0.compressed_offset();
+            for one in original_blocks.iter().filter(|x| {
+                x.compressed_offset > start_block
+                    && x.compressed_offset < end_block
+                    && x.uncompressed_size() > 0
+            }) {
+                assert!(copied_blocks.iter().any(|x| x.data == one.data));
+            }
+        }
+
+        let mut copied = Vec::new();
+        let mut writer = crate::BGZFWriter::new(&mut copied, Compression::default());
+        assert!(copy_range(
+            File::open("testfiles/generated.bed.gz")?,
+            &mut writer,
+            offsets[10].0,
+            offsets[5].0,
+        )
+        .is_err());
+
+        Ok(())
+    }
+
     #[test]
     fn test_read() -> Result<(), BGZFError> {
         let mut expected_reader = io::BufReader::new(flate2::read::MultiGzDecoder::new(
@@ -429,10 +1809,10 @@ mod test {
         let mut buffer = [0; 30];
 
         reader.bgzf_seek(0)?;
-        assert_eq!(reader.bgzf_pos(), 0);
+        assert_eq!(u64::from(reader.bgzf_pos()), 0);
 
         reader.bgzf_seek(35973)?;
-        assert_eq!(reader.bgzf_pos(), 35973);
+        assert_eq!(u64::from(reader.bgzf_pos()), 35973);
         reader.read_exact(&mut buffer)?;
         assert!(
             buffer.starts_with(b"1\t4008153"),
@@ -441,16 +1821,16 @@ mod test {
         );
         //reader.bgzf_seek(reader.cache.get(&0).unwrap().next_block_position() << 16)?;
         reader.bgzf_seek(4210818610)?;
-        assert_eq!(reader.bgzf_pos(), 4210818610);
+        assert_eq!(u64::from(reader.bgzf_pos()), 4210818610);
         reader.read_exact(&mut buffer)?;
         assert!(buffer.starts_with(b"1\t72700625"));
         //eprintln!("data: {}", String::from_utf8_lossy(&buffer));
         reader.bgzf_seek(9618658636)?;
-        assert_eq!(reader.bgzf_pos(), 9618658636);
+        assert_eq!(u64::from(reader.bgzf_pos()), 9618658636);
         reader.read_exact(&mut buffer)?;
         assert!(buffer.starts_with(b"1\t"));
         reader.bgzf_seek(135183301012)?;
-        assert_eq!(reader.bgzf_pos(), 135183301012);
+        assert_eq!(u64::from(reader.bgzf_pos()), 135183301012);
         reader.read_exact(&mut buffer)?;
         assert!(buffer.starts_with(b"11\t"));
 
@@ -458,7 +1838,7 @@ mod test {
         reader.bgzf_seek(0)?;
         reader.read_exact(&mut tmp_buf)?;
         //eprintln!("data: {}", String::from_utf8_lossy(&buffer));
-        assert_eq!(reader.bgzf_pos(), 4210818610);
+        assert_eq!(u64::from(reader.bgzf_pos()), 4210818610);
         reader.read_exact(&mut buffer)?;
         assert!(
             buffer.starts_with(b"1\t72700625"),
@@ -469,6 +1849,55 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    #[cfg(feature = "flate2")]
+    fn test_adaptive_reader() -> anyhow::Result<()> {
+        let mut expected_data = Vec::new();
+        flate2::read::MultiGzDecoder::new(File::open("testfiles/generated.bed.gz")?)
+            .read_to_end(&mut expected_data)?;
+        let (first, second) = expected_data.split_at(expected_data.len() / 2);
+        let mut gzip_data = Vec::new();
+        for one in [first, second] {
+            let mut encoder =
+                flate2::write::GzEncoder::new(&mut gzip_data, flate2::Compression::default());
+            encoder.write_all(one)?;
+            encoder.finish()?;
+        }
+
+        let mut reader = open("testfiles/generated.bed.gz")?;
+        assert!(reader.is_bgzf());
+        assert_eq!(reader.bgzf_pos(), Some(VirtualPosition::default()));
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        assert_eq!(data, expected_data);
+
+        for (input, bgzf) in [
+            (&gzip_data[..], false),
+            (&expected_data[..], false),
+            (&fs::read("testfiles/generated.bed.gz")?[..], true),
+        ] {
+            let mut reader = new_reader(input)?;
+            assert_eq!(reader.is_bgzf(), bgzf);
+            assert_eq!(reader.bgzf_pos().is_some(), bgzf);
+            data.clear();
+            reader.read_to_end(&mut data)?;
+            assert_eq!(data, expected_data);
+        }
+
+        let mut reader = new_reader(io::Cursor::new(gzip_data))?;
+        assert!(reader.bgzf_seek(0).is_err());
+
+        for input in [&b""[..], &[0x1f][..]] {
+            let mut reader = new_reader(input)?;
+            assert!(!reader.is_bgzf());
+            data.clear();
+            reader.read_to_end(&mut data)?;
+            assert_eq!(data, input);
+        }
+
+        Ok(())
+    }
+
     #[test]
     fn test_read_all() -> anyhow::Result<()> {
         let mut expected_data_reader =
@@ -510,6 +1939,7 @@ mod test {
             line_list.push((bgzf_pos, uncompressed_pos, line.clone()));
         }
         let index = writer.close()?.unwrap();
+        let index_copy = index.clone();
 
         let mut rand = rand_pcg::Pcg64Mcg::seed_from_u64(0x9387402456157523);
         let mut reader = IndexedBGZFReader::new(
@@ -529,7 +1959,763 @@ mod test {
             assert_eq!(line, line_list[i].2);
         }
 
-        assert_eq!(TryInto::<u64>::try_into(total_len).unwrap(), reader.end_pos);
+        assert_eq!(
+            TryInto::<u64>::try_into(total_len).unwrap(),
+            reader.end_pos()
+        );
+        assert_eq!(reader.len(), reader.end_pos());
+        assert_eq!(reader.index(), &index_copy);
+
+        // index is created if .gzi does not exist
+        let _ = fs::remove_file("tmp/test-indexed-reader.bed.gz.gzi");
+        let reader = IndexedBGZFReader::from_path("tmp/test-indexed-reader.bed.gz")?;
+        assert_eq!(reader.index(), &index_copy);
+        index_copy.write(fs::File::create("tmp/test-indexed-reader.bed.gz.gzi")?)?;
+        let reader = IndexedBGZFReader::from_path("tmp/test-indexed-reader.bed.gz")?;
+        assert_eq!(reader.index(), &index_copy);
+        let mut reader = IndexedBGZFReader::from_paths(
+            "testfiles/generated.bed.gz",
+            "testfiles/generated.bed.gz.gzi",
+        )?;
+        reader.seek(std::io::SeekFrom::Start(line_list[100].1))?;
+        line.clear();
+        reader.read_line(&mut line)?;
+        assert_eq!(line, line_list[100].2);
+        assert!(
+            IndexedBGZFReader::from_paths("testfiles/generated.bed.gz", "tmp/not-found.gzi")
+                .is_err()
+        );
+
+        // single block without index entries
+        let mut writer = BGZFWriter::new(
+            fs::File::create("tmp/test-indexed-reader-small.txt.gz")?,
+            Compression::default(),
+        );
+        writer.write_all(b"small data")?;
+        let index = writer.close()?.unwrap();
+        assert!(index.entries().is_empty());
+        let mut reader = IndexedBGZFReader::new(
+            BGZFReader::new(fs::File::open("tmp/test-indexed-reader-small.txt.gz")?)?,
+            index,
+        )?;
+        assert_eq!(reader.len(), 10);
+        assert!(!reader.is_empty());
+        reader.seek(std::io::SeekFrom::End(-4))?;
+        line.clear();
+        reader.read_line(&mut line)?;
+        assert_eq!(line, "data");
+
+        // end-of-file marker only
+        let mut reader = IndexedBGZFReader::new(
+            BGZFReader::new(io::Cursor::new(crate::EOF_MARKER))?,
+            BGZFIndex::default(),
+        )?;
+        assert_eq!(reader.len(), 0);
+        assert!(reader.is_empty());
+        assert_eq!(reader.seek(std::io::SeekFrom::End(0))?, 0);
+        line.clear();
+        assert_eq!(reader.read_line(&mut line)?, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_dyn_readers() -> anyhow::Result<()> {
+        let path = "testfiles/generated.bed.gz";
+        let compressed = fs::read(path)?;
+        let index = crate::index::BGZFIndex::from_reader(File::open(format!("{}.gzi", path))?)?;
+
+        let mut readers: Vec<Box<dyn BGZFReadSeek>> = vec![
+            Box::new(BGZFReader::new(File::open(path)?)?),
+            Box::new(BGZFReader::new(io::Cursor::new(compressed.clone()))?),
+            Box::new(BGZFReader::new(
+                Box::new(File::open(path)?) as Box<dyn ReadSeek>
+            )?),
+            Box::new(IndexedBGZFReader::new(
+                BGZFReader::new(io::Cursor::new(&compressed[..]))?,
+                index,
+            )?),
+        ];
+
+        let mut expected_line = String::new();
+        let mut line = String::new();
+        for reader in readers.iter_mut() {
+            line.clear();
+            reader.read_line(&mut line)?;
+            reader.fill_buf()?;
+            let pos = reader.bgzf_pos();
+            expected_line.clear();
+            reader.read_line(&mut expected_line)?;
+            for _ in 0..3000 {
+                line.clear();
+                reader.read_line(&mut line)?;
+            }
+            assert_ne!(line, expected_line);
+
+            reader.bgzf_seek(pos)?;
+            line.clear();
+            reader.read_line(&mut line)?;
+            assert_eq!(line, expected_line);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_copy_all() -> anyhow::Result<()> {
+        let path = "testfiles/generated.bed.gz";
+        let output_path = "tmp/test_copy_all.bed.gz";
+        let copied = copy_all(File::open(path)?, File::create(output_path)?)?;
+        assert_eq!(copied, fs::metadata(path)?.len());
+        assert_eq!(fs::read(output_path)?, fs::read(path)?);
+
+        let data = fs::read(path)?;
+        let mut output = Vec::new();
+        assert!(copy_all(io::Cursor::new(&data[..(data.len() - 1)]), &mut output).is_err());
+        assert!(copy_all(io::Cursor::new(&data[..10]), &mut output).is_err());
+        assert!(matches!(
+            copy_all(
+                File::open("testfiles/common_all_20180418_half.vcf.nobgzip.gz")?,
+                &mut output
+            ),
+            Err(BGZFError::NotBGZF)
+        ));
+        assert!(matches!(
+            copy_all(File::open("testfiles/reg2bin.c")?, &mut output),
+            Err(BGZFError::NotBGZF)
+        ));
+        assert!(output.is_empty());
+
+        assert_eq!(
+            copy_all(io::Cursor::new(&crate::EOF_MARKER[..]), &mut output)?,
+            28
+        );
+        assert_eq!(output, crate::EOF_MARKER);
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "flate2")]
+    fn test_degraded() -> anyhow::Result<()> {
+        use std::io::Write;
+
+        let mut rand = rand_pcg::Pcg64Mcg::seed_from_u64(0x61c8864680b583eb);
+        let mut expected = vec![0; 300_000];
+        rand.fill_bytes(&mut expected[..100_000]);
+
+        // gzip members with FEXTRA but without BC subfield
+        let mut data = Vec::new();
+        for one in expected.chunks(120_000) {
+            let mut writer = flate2::GzBuilder::new()
+                .extra(vec![b'X', b'Y', 2, 0, 1, 2])
+                .write(&mut data, flate2::Compression::default());
+            writer.write_all(one)?;
+            writer.finish()?;
+        }
+        assert!(matches!(
+            BGZFReader::new(&data[..]),
+            Err(BGZFError::NotBGZF)
+        ));
+
+        let mut reader = BGZFReader::new_degradable(io::Cursor::new(&data))?;
+        assert!(reader.is_degraded());
+        let mut read_data = Vec::new();
+        reader.read_to_end(&mut read_data)?;
+        assert_eq!(read_data, expected);
+        assert!(matches!(reader.bgzf_seek(0), Err(BGZFError::NotBGZF)));
+
+        // broken CRC32 in the last member
+        let position = data.len() - 8;
+        data[position] ^= 0xff;
+        let mut reader = BGZFReader::new_degradable(&data[..])?;
+        assert!(reader.read_to_end(&mut Vec::new()).is_err());
+        assert!(BGZFReader::new_degradable(&data[..(data.len() - 100)])?
+            .read_to_end(&mut Vec::new())
+            .is_err());
+
+        // BGZF is read as usual
+        let mut reader =
+            BGZFReader::new_degradable(File::open("testfiles/common_all_20180418_half.vcf.gz")?)?;
+        assert!(!reader.is_degraded());
+        reader.bgzf_seek(4210818610)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_seek_with_index() -> anyhow::Result<()> {
+        let path = "testfiles/common_all_20180418_half.vcf.gz";
+        let mut expected = Vec::new();
+        flate2::read::MultiGzDecoder::new(File::open(path)?).read_to_end(&mut expected)?;
+        let index = BGZFIndex::from_reader(File::open(format!("{}.gzi", path))?)?;
+
+        let mut reader = BGZFReader::new(File::open(path)?)?;
+        assert!(reader.seek(io::SeekFrom::Start(0)).is_err());
+        let mut buf = [0u8; 10];
+        reader.read_exact(&mut buf)?;
+        let mut reader = reader.with_index(index)?;
+        assert_eq!(reader.stream_position()?, 10);
+        assert_eq!(reader.seek(io::SeekFrom::End(0))?, expected.len() as u64);
+        assert_eq!(reader.read(&mut buf)?, 0);
+        assert!(reader.seek(io::SeekFrom::End(1)).is_err());
+        assert!(reader
+            .seek(io::SeekFrom::Current(-(expected.len() as i64) - 1))
+            .is_err());
+
+        // generic code which requires Read + Seek
+        fn read_at<R: Read + Seek>(mut reader: R, pos: io::SeekFrom) -> io::Result<Vec<u8>> {
+            reader.seek(pos)?;
+            let mut data = vec![0u8; 1000];
+            reader.read_exact(&mut data)?;
+            Ok(data)
+        }
+
+        let mut rand = rand_pcg::Pcg64Mcg::seed_from_u64(0x5e3a7c19d40b8f26);
+        for _ in 0..100 {
+            let pos = rand.gen_range(0..(expected.len() - 1000));
+            let data = match rand.gen_range(0..3) {
+                0 => read_at(&mut reader, io::SeekFrom::Start(pos as u64))?,
+                1 => read_at(
+                    &mut reader,
+                    io::SeekFrom::End(pos as i64 - expected.len() as i64),
+                )?,
+                _ => {
+                    let current = reader.stream_position()?;
+                    read_at(
+                        &mut reader,
+                        io::SeekFrom::Current(pos as i64 - current as i64),
+                    )?
+                }
+            };
+            assert_eq!(data, &expected[pos..(pos + 1000)]);
+            assert_eq!(reader.stream_position()?, pos as u64 + 1000);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_block_error_position() -> anyhow::Result<()> {
+        let mut data = Vec::new();
+        File::open("testfiles/generated.bed.gz")?.read_to_end(&mut data)?;
+        let blocks: Vec<_> = RawBlocks::new(&data[..]).collect::<Result<_, _>>()?;
+        let crc_pos = blocks[3].compressed_offset + blocks[3].compressed_size() - 8;
+        data[crc_pos as usize] ^= 0xff;
+
+        let mut reader = BGZFReader::new(io::Cursor::new(&data))?;
+        let e: BGZFError = reader.read_to_end(&mut Vec::new()).unwrap_err().into();
+        assert_eq!(e.compressed_offset(), Some(blocks[3].compressed_offset));
+        assert_eq!(e.block_index(), Some(3));
+        assert!(e.to_string().contains(&format!(
+            "block 3 at compressed offset {}",
+            blocks[3].compressed_offset
+        )));
+
+        let e = reader
+            .bgzf_seek(blocks[3].compressed_offset << 16)
+            .unwrap_err();
+        assert!(matches!(
+            e,
+            BGZFError::InBlock { block_index: None, ref source, .. }
+                if matches!(source.as_ref(), BGZFError::Crc32Mismatch { .. })
+        ));
+        assert_eq!(e.compressed_offset(), Some(blocks[3].compressed_offset));
+
+        // broken header
+        let mut data = Vec::new();
+        File::open("testfiles/generated.bed.gz")?.read_to_end(&mut data)?;
+        data[blocks[2].compressed_offset as usize] = 0;
+        let mut reader = BGZFReader::new(&data[..])?;
+        let e: BGZFError = reader.read_to_end(&mut Vec::new()).unwrap_err().into();
+        assert_eq!(e.compressed_offset(), Some(blocks[2].compressed_offset));
+        assert_eq!(e.block_index(), Some(2));
+
+        // broken first block
+        let mut data = Vec::new();
+        File::open("testfiles/generated.bed.gz")?.read_to_end(&mut data)?;
+        data[(blocks[0].compressed_size() - 8) as usize] ^= 0xff;
+        let e = BGZFReader::new(&data[..]).err().unwrap();
+        assert_eq!(e.compressed_offset(), Some(0));
+        assert_eq!(e.block_index(), Some(0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_next_bgzf_pos() -> anyhow::Result<()> {
+        let data = fs::read("testfiles/generated.bed.gz")?;
+        let blocks: Vec<_> = RawBlocks::new(&data[..]).collect::<Result<_, _>>()?;
+        let mut reader = BGZFReader::new(&data[..])?;
+        let len = reader.fill_buf()?.len();
+        reader.consume(len);
+        // the first block is consumed
+        assert_eq!(
+            reader.bgzf_pos(),
+            VirtualPosition::new(blocks[0].compressed_offset, len as u16)
+        );
+        assert_eq!(
+            reader.next_bgzf_pos()?,
+            Some(VirtualPosition::new(blocks[1].compressed_offset, 0))
+        );
+        assert_eq!(
+            reader.bgzf_pos(),
+            VirtualPosition::new(blocks[1].compressed_offset, 0)
+        );
+        io::copy(&mut reader, &mut io::sink())?;
+        assert_eq!(reader.next_bgzf_pos()?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_current_block() -> anyhow::Result<()> {
+        let data = fs::read("testfiles/generated.bed.gz")?;
+        let blocks: Vec<_> = RawBlocks::new(&data[..]).collect::<Result<_, _>>()?;
+        let mut reader = BGZFReader::new(&data[..])?;
+        for block in &blocks[..(blocks.len() - 1)] {
+            let len = reader.fill_buf()?.len();
+            // offsets of blocks after the first block include the header of the first block
+            assert_eq!(
+                reader.bgzf_pos(),
+                VirtualPosition::new(block.compressed_offset, 0)
+            );
+            assert_eq!(reader.current_block_file_offset(), block.compressed_offset);
+            assert_eq!(
+                reader.current_block_compressed_size(),
+                block.compressed_size()
+            );
+            assert_eq!(
+                reader.current_block_uncompressed_size() as u64,
+                block.uncompressed_size()
+            );
+            assert_eq!(
+                reader.current_block_compression_ratio(),
+                Some(block.compressed_size() as f64 / block.uncompressed_size() as f64)
+            );
+            reader.consume(len);
+        }
+        assert!(reader.fill_buf()?.is_empty());
+        assert_eq!(
+            reader.current_block_file_offset(),
+            blocks.last().unwrap().compressed_offset
+        );
+        assert_eq!(reader.current_block_compressed_size(), 0);
+        assert_eq!(reader.current_block_compression_ratio(), None);
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "mmap")]
+    fn test_mmap() -> anyhow::Result<()> {
+        let path = "testfiles/common_all_20180418_half.vcf.gz";
+        let mut expected = Vec::new();
+        BGZFReader::new(File::open(path)?)?.read_to_end(&mut expected)?;
+
+        let mut reader = BGZFReader::from_mmap(path)?;
+        let mut read_data = Vec::new();
+        reader.read_to_end(&mut read_data)?;
+        assert_eq!(read_data, expected);
+
+        let index = BGZFIndex::from_reader(File::open(format!("{}.gzi", path))?)?;
+        let mut reader = IndexedBGZFReader::new(BGZFReader::from_mmap(path)?, index)?;
+        let mut buf = [0; 100];
+        reader.seek(io::SeekFrom::Start(2_000_000))?;
+        reader.read_exact(&mut buf)?;
+        assert_eq!(buf, expected[2_000_000..2_000_100]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_into_block_iter() -> anyhow::Result<()> {
+        let data = fs::read("testfiles/generated.bed.gz")?;
+        let mut decompress = Decompress::new();
+        let expected: Vec<_> = RawBlocks::new(&data[..])
+            .map(|x| x?.decompress(&mut decompress))
+            .filter(|x| !matches!(x, Ok(block) if block.data.is_empty()))
+            .collect::<Result<_, _>>()?;
+
+        let mut blocks = BGZFReader::new(&data[..])?.into_block_iter();
+        for one in &expected {
+            assert_eq!(blocks.next().transpose()?, Some(one.data.clone()));
+            assert_eq!(blocks.compressed_offset(), one.compressed_offset);
+        }
+        assert!(blocks.next().is_none());
+
+        // rest of the partially consumed block
+        let mut reader = BGZFReader::new(&data[..])?;
+        reader.read_exact(&mut [0; 10])?;
+        let blocks: Vec<_> = reader.into_block_iter().collect::<Result<_, _>>()?;
+        assert_eq!(blocks.len(), expected.len());
+        assert_eq!(blocks[0], &expected[0].data[10..]);
+        assert_eq!(blocks[1], expected[1].data);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_vectored() -> anyhow::Result<()> {
+        let generated = crate::testutil::SyntheticBGZF::new(23)
+            .with_block_count(3)
+            .generate();
+        let data = &generated.uncompressed;
+        let mut first = vec![0; 100];
+        let mut second = vec![0; 100_000];
+        let mut third = vec![0; data.len()];
+
+        let mut reader = BGZFReader::new(&generated.compressed[..])?;
+        let read_bytes = reader.read_vectored(&mut [
+            io::IoSliceMut::new(&mut first),
+            io::IoSliceMut::new(&mut []),
+            io::IoSliceMut::new(&mut second),
+            io::IoSliceMut::new(&mut third),
+        ])?;
+        assert_eq!(read_bytes, data.len());
+        assert_eq!(first, &data[..100]);
+        assert_eq!(second, &data[100..100_100]);
+        assert_eq!(&third[..(data.len() - 100_100)], &data[100_100..]);
+        assert_eq!(reader.read(&mut first)?, 0);
+
+        #[cfg(feature = "threads")]
+        {
+            let mut reader = BGZFMultiThreadReader::new(&generated.compressed[..])?;
+            third.fill(0);
+            let read_bytes = reader.read_vectored(&mut [
+                io::IoSliceMut::new(&mut first),
+                io::IoSliceMut::new(&mut third),
+            ])?;
+            assert_eq!(read_bytes, data.len());
+            assert_eq!(first, &data[..100]);
+            assert_eq!(&third[..(data.len() - 100)], &data[100..]);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_strict() -> anyhow::Result<()> {
+        // a block holding more than 64 KiB of data
+        let original = vec![b'A'; 70_000];
+        let mut data = Vec::new();
+        crate::write::write_block(
+            &mut data,
+            &original,
+            &mut Compress::new(Compression::default()),
+        )?;
+        data.extend_from_slice(&crate::EOF_MARKER);
+
+        let mut read_data = Vec::new();
+        BGZFReader::new(&data[..])?.read_to_end(&mut read_data)?;
+        assert_eq!(read_data, original);
+
+        let e = BGZFReader::new(&data[..])?
+            .with_strict(true)
+            .read_to_end(&mut Vec::new())
+            .unwrap_err();
+        let e: BGZFError = e.into();
+        assert_eq!(e.compressed_offset(), Some(0));
+        assert!(e.to_string().contains("ISIZE: 70000"));
+
+        let block = &data[..(data.len() - crate::EOF_MARKER.len())];
+        let payload = &block[usize::try_from(BGZFHeader::from_reader(block)?.header_size())?..];
+        let mut decompress = Decompress::new();
+        decompress_block(&mut Vec::new(), payload, &mut decompress)?;
+        assert!(matches!(
+            decompress_block_strict(&mut Vec::new(), payload, &mut decompress),
+            Err(BGZFError::UncompressedBlockTooLarge { size: 70000 })
+        ));
+        assert!(matches!(
+            decompress_block_strict(&mut Vec::new(), &payload[..5], &mut decompress),
+            Err(BGZFError::BlockTooShort { size: 5 })
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_recover() -> anyhow::Result<()> {
+        let data = std::fs::read("testfiles/generated.bed.gz")?;
+        let blocks = RawBlocks::new(&data[..]).collect::<Result<Vec<_>, _>>()?;
+        let decompressed = blocks
+            .iter()
+            .map(|x| {
+                let mut buf = Vec::new();
+                decompress_block(&mut buf, &x.data, &mut Decompress::new())?;
+                Ok(buf)
+            })
+            .collect::<Result<Vec<_>, BGZFError>>()?;
+
+        // broken payload, broken magic bytes and broken last block
+        for (broken_block, broken_byte) in [(10, 100), (20, 0), (blocks.len() - 2, 50)] {
+            let mut broken = data.clone();
+            let broken_offset = blocks[broken_block].compressed_offset;
+            broken[broken_offset as usize + broken_byte] ^= 0xff;
+
+            assert!(BGZFReader::new(&broken[..])?
+                .read_to_end(&mut Vec::new())
+                .is_err());
+
+            let skipped = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+            let skipped_clone = skipped.clone();
+            let mut reader =
+                BGZFReader::new(io::Cursor::new(broken))?.with_recover(move |range, error| {
+                    skipped_clone
+                        .lock()
+                        .unwrap()
+                        .push((range, error.to_string()))
+                });
+            let mut read_data = Vec::new();
+            reader.read_to_end(&mut read_data)?;
+
+            let expected: Vec<u8> = decompressed
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| *i != broken_block)
+                .flat_map(|(_, x)| x.iter().copied())
+                .collect();
+            assert_eq!(read_data, expected);
+            let skipped = skipped.lock().unwrap();
+            assert_eq!(skipped.len(), 1);
+            assert_eq!(
+                skipped[0].0,
+                broken_offset..blocks[broken_block + 1].compressed_offset
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_missing_eof() -> anyhow::Result<()> {
+        let mut data = Vec::new();
+        File::open("testfiles/generated.bed.gz")?.read_to_end(&mut data)?;
+        let mut expected_data = Vec::new();
+        flate2::read::MultiGzDecoder::new(&data[..]).read_to_end(&mut expected_data)?;
+        assert!(data.ends_with(&crate::EOF_MARKER));
+        let without_eof = &data[..(data.len() - crate::EOF_MARKER.len())];
+
+        // read to the end by default
+        let mut reader = BGZFReader::new(without_eof)?;
+        let mut read_data = Vec::new();
+        reader.read_to_end(&mut read_data)?;
+        assert_eq!(read_data, expected_data);
+        assert!(!reader.reached_eof_marker());
+
+        let mut reader = BGZFReader::new(without_eof)?.with_fail_on_missing_eof(true);
+        let e = reader.read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+        let e: BGZFError = e.into();
+        assert_eq!(e.compressed_offset(), Some(without_eof.len() as u64));
+        assert!(e.to_string().contains("without end-of-file marker"));
+
+        // truncated in the middle of a block
+        let mut reader = BGZFReader::new(&without_eof[..(without_eof.len() - 10)])?
+            .with_fail_on_missing_eof(true);
+        let e = reader.read_to_end(&mut Vec::new()).unwrap_err();
+        assert!(e.to_string().contains("without end-of-file marker"));
+
+        let mut reader = BGZFReader::new(&data[..])?.with_fail_on_missing_eof(true);
+        let mut read_data = Vec::new();
+        assert!(!reader.reached_eof_marker());
+        reader.read_to_end(&mut read_data)?;
+        assert_eq!(read_data, expected_data);
+        assert!(reader.reached_eof_marker());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_concatenated() -> anyhow::Result<()> {
+        let mut data = Vec::new();
+        File::open("testfiles/generated.bed.gz")?.read_to_end(&mut data)?;
+        let mut expected_data = Vec::new();
+        BGZFReader::new(&data[..])?.read_to_end(&mut expected_data)?;
+
+        // empty file, two files and a file without end-of-file marker
+        let mut concatenated = crate::EOF_MARKER.to_vec();
+        concatenated.extend_from_slice(&data);
+        concatenated.extend_from_slice(&data);
+        concatenated.extend_from_slice(&data[..(data.len() - crate::EOF_MARKER.len())]);
+        let expected = expected_data.repeat(3);
+
+        let mut reader = BGZFReader::new(&concatenated[..])?;
+        assert_eq!(reader.read_to_end(&mut Vec::new())?, 0);
+
+        let mut reader = BGZFReader::new(&concatenated[..])?.with_concatenated(true);
+        let mut read_data = Vec::new();
+        reader.read_to_end(&mut read_data)?;
+        assert_eq!(read_data, expected);
+        assert!(!reader.reached_eof_marker());
+
+        let mut reader = BGZFReader::new(&concatenated[..])?
+            .with_concatenated(true)
+            .with_fail_on_missing_eof(true);
+        assert!(reader.read_to_end(&mut Vec::new()).is_err());
+
+        concatenated.extend_from_slice(&crate::EOF_MARKER);
+        let mut reader = BGZFReader::new(io::Cursor::new(&concatenated))?
+            .with_concatenated(true)
+            .with_fail_on_missing_eof(true)
+            .with_fail_on_trailing_data(true);
+        let mut read_data = Vec::new();
+        reader.read_to_end(&mut read_data)?;
+        assert_eq!(read_data, expected);
+        assert!(reader.reached_eof_marker());
+        assert_eq!(
+            reader.current_block_file_offset(),
+            (concatenated.len() - crate::EOF_MARKER.len()) as u64
+        );
+
+        // seek into the second file
+        let second = (crate::EOF_MARKER.len() + data.len()) as u64;
+        reader.bgzf_seek(second << 16)?;
+        let mut read_data = Vec::new();
+        reader.read_to_end(&mut read_data)?;
+        assert_eq!(read_data, expected_data.repeat(2));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_trailing_data() -> anyhow::Result<()> {
+        let mut data = Vec::new();
+        File::open("testfiles/generated.bed.gz")?.read_to_end(&mut data)?;
+        let mut expected_data = Vec::new();
+        BGZFReader::new(&data[..])?.read_to_end(&mut expected_data)?;
+        assert!(crate::is_eof_block(&data[(data.len() - 28)..]));
+        assert!(!crate::is_eof_block(&data[..28]));
+
+        let mut with_garbage = data.clone();
+        with_garbage.extend_from_slice(b"garbage");
+
+        // ignored by default
+        let mut reader = BGZFReader::new(&with_garbage[..])?;
+        let mut read_data = Vec::new();
+        reader.read_to_end(&mut read_data)?;
+        assert_eq!(read_data, expected_data);
+        assert!(reader.reached_eof_marker());
+
+        let mut reader = BGZFReader::new(&with_garbage[..])?.with_fail_on_trailing_data(true);
+        let e = reader.read_to_end(&mut Vec::new()).unwrap_err();
+        let e: BGZFError = e.into();
+        assert!(e.to_string().contains("trailing data"));
+        assert!(reader.reached_eof_marker());
+
+        let mut reader = BGZFReader::new(&data[..])?.with_fail_on_trailing_data(true);
+        let mut read_data = Vec::new();
+        reader.read_to_end(&mut read_data)?;
+        assert_eq!(read_data, expected_data);
+
+        // empty file
+        let mut empty = crate::EOF_MARKER.to_vec();
+        let mut reader = BGZFReader::new(&empty[..])?.with_fail_on_trailing_data(true);
+        assert!(reader.reached_eof_marker());
+        assert_eq!(reader.read_to_end(&mut Vec::new())?, 0);
+        empty.push(0);
+        let mut reader = BGZFReader::new(&empty[..])?.with_fail_on_trailing_data(true);
+        assert!(reader.read_to_end(&mut Vec::new()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_block_cache() -> anyhow::Result<()> {
+        let data = crate::testutil::SyntheticBGZF::new(31)
+            .with_block_count(6)
+            .generate();
+        let block_size = crate::write::DEFAULT_COMPRESS_UNIT_SIZE as u64;
+        let mut reader =
+            BGZFReader::new(io::Cursor::new(data.compressed.clone()))?.with_cache_size(2);
+        let mut buffer = vec![0; 100];
+        for block in [1, 4, 1, 4] {
+            reader.bgzf_seek(data.block_offsets[block] << 16 | 10)?;
+            reader.read_exact(&mut buffer)?;
+            let start = block * block_size as usize + 10;
+            assert_eq!(buffer, &data.uncompressed[start..(start + 100)]);
+        }
+        assert!(reader.cache.contains(data.block_offsets[1]));
+        assert!(reader.cache.contains(data.block_offsets[4]));
+
+        // cached blocks are not decompressed again
+        let payload = (data.block_offsets[1] + 100) as usize;
+        reader.reader.get_mut()[payload] ^= 0xff;
+        reader.bgzf_seek(data.block_offsets[1] << 16)?;
+        reader.read_exact(&mut buffer)?;
+        assert_eq!(
+            buffer,
+            &data.uncompressed[(block_size as usize)..(block_size as usize + 100)]
+        );
+
+        // the least recently used block is evicted
+        reader.bgzf_seek(data.block_offsets[2] << 16)?;
+        reader.read_exact(&mut buffer)?;
+        assert!(!reader.cache.contains(data.block_offsets[4]));
+        let mut reader = reader.with_cache_size(0);
+        assert!(!reader.cache.contains(data.block_offsets[1]));
+        assert!(reader.bgzf_seek(data.block_offsets[1] << 16).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_warm() -> anyhow::Result<()> {
+        let mut data = Vec::new();
+        flate2::read::MultiGzDecoder::new(File::open("testfiles/generated.bed.gz")?)
+            .read_to_end(&mut data)?;
+        let mut compressed = Vec::new();
+        let mut writer = BGZFWriter::new(&mut compressed, Compression::default());
+        writer.write_all(&data)?;
+        let index = writer.close()?.unwrap();
+
+        let block_starts: Vec<u64> = std::iter::once(0)
+            .chain(index.entries().iter().map(|x| x.uncompressed_offset))
+            .chain(std::iter::once(data.len() as u64))
+            .collect();
+        let count_blocks = |range: Range<u64>| {
+            block_starts
+                .windows(2)
+                .filter(|x| x[0] < range.end && range.start < x[1])
+                .count()
+        };
+
+        let mut reader =
+            IndexedBGZFReader::new(BGZFReader::new(io::Cursor::new(&compressed[..]))?, index)?;
+        let mut buffer = vec![0; 1000];
+        reader.read_exact(&mut buffer)?;
+
+        assert_eq!(
+            reader.warm(100_000..300_000)?,
+            count_blocks(100_000..300_000)
+        );
+        assert_eq!(reader.warm(150_000..250_000)?, 0);
+        assert_eq!(reader.warm(300_000..300_000)?, 0);
+        assert_eq!(reader.warm(reader.len()..(reader.len() + 100))?, 0);
+
+        // stream position is restored after warming
+        reader.read_exact(&mut buffer)?;
+        assert_eq!(buffer, &data[1000..2000]);
+
+        for pos in [100_000, 150_000, 299_000] {
+            reader.seek(io::SeekFrom::Start(pos as u64))?;
+            reader.read_exact(&mut buffer)?;
+            assert_eq!(buffer, &data[pos..(pos + 1000)]);
+        }
+
+        reader.clear_cache();
+        let len = reader.len();
+        assert_eq!(
+            reader.warm((len - 10)..(len + 10))?,
+            count_blocks((len - 10)..len)
+        );
+        #[cfg(feature = "rayon")]
+        {
+            assert_eq!(reader.par_warm(0..len)?, count_blocks(0..len) - 1);
+        }
+        let mut read_data = Vec::new();
+        reader.seek(io::SeekFrom::Start(0))?;
+        reader.read_to_end(&mut read_data)?;
+        assert_eq!(read_data, data);
 
         Ok(())
     }
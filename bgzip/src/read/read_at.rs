@@ -0,0 +1,204 @@
+use std::io::{self, Read, Seek, SeekFrom};
+use std::sync::Arc;
+
+/// Random access source of data, such as files, memory maps and object storages.
+///
+/// Unlike [`std::io::Read`] and [`std::io::Seek`], reads do not change shared state,
+/// so one source can be shared by many readers. Wrap a source with [`ReadAtReader`] to read BGZF data
+/// from it, or use [`crate::BGZFReader::from_read_at`] and
+/// [`crate::read::IndexedBGZFReader::from_read_at`].
+pub trait ReadAt {
+    /// Read bytes at `offset` into `buf`, and return the number of read bytes.
+    ///
+    /// Zero is returned only if `offset` is at or beyond the end of data or `buf` is empty.
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize>;
+
+    /// Size of data in bytes
+    fn size(&self) -> io::Result<u64>;
+}
+
+impl ReadAt for [u8] {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let start = usize::try_from(offset)
+            .unwrap_or(usize::MAX)
+            .min(self.len());
+        let bytes_to_copy = buf.len().min(self.len() - start);
+        buf[..bytes_to_copy].copy_from_slice(&self[start..(start + bytes_to_copy)]);
+        Ok(bytes_to_copy)
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.len() as u64)
+    }
+}
+
+impl ReadAt for Vec<u8> {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        self[..].read_at(offset, buf)
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        self[..].size()
+    }
+}
+
+#[cfg(any(unix, windows))]
+impl ReadAt for std::fs::File {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        #[cfg(unix)]
+        return std::os::unix::fs::FileExt::read_at(self, buf, offset);
+        // the file position is changed on Windows
+        #[cfg(windows)]
+        std::os::windows::fs::FileExt::seek_read(self, buf, offset)
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.metadata()?.len())
+    }
+}
+
+#[cfg(feature = "mmap")]
+impl ReadAt for memmap2::Mmap {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        self[..].read_at(offset, buf)
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        self[..].size()
+    }
+}
+
+impl<T: ReadAt + ?Sized> ReadAt for &T {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        (**self).read_at(offset, buf)
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        (**self).size()
+    }
+}
+
+impl<T: ReadAt + ?Sized> ReadAt for Box<T> {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        (**self).read_at(offset, buf)
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        (**self).size()
+    }
+}
+
+impl<T: ReadAt + ?Sized> ReadAt for Arc<T> {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        (**self).read_at(offset, buf)
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        (**self).size()
+    }
+}
+
+/// Adapter of [`ReadAt`] implementing [`std::io::Read`] and [`std::io::Seek`] with its own position.
+pub struct ReadAtReader<T: ReadAt> {
+    source: T,
+    position: u64,
+}
+
+impl<T: ReadAt> ReadAtReader<T> {
+    /// Create new reader at the beginning of `source`
+    pub fn new(source: T) -> Self {
+        ReadAtReader {
+            source,
+            position: 0,
+        }
+    }
+
+    /// Get a reference to the source
+    pub fn get_ref(&self) -> &T {
+        &self.source
+    }
+
+    /// Unwrap this reader, returning the source
+    pub fn into_inner(self) -> T {
+        self.source
+    }
+}
+
+impl<T: ReadAt> Read for ReadAtReader<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read_bytes = self.source.read_at(self.position, buf)?;
+        self.position += read_bytes as u64;
+        Ok(read_bytes)
+    }
+}
+
+impl<T: ReadAt> Seek for ReadAtReader<T> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(position) => Some(position),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+            SeekFrom::End(offset) => self.source.size()?.checked_add_signed(offset),
+        };
+        self.position = position.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+        Ok(self.position)
+    }
+
+    fn stream_position(&mut self) -> io::Result<u64> {
+        Ok(self.position)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::index::BGZFIndex;
+    use crate::read::IndexedBGZFReader;
+    use crate::BGZFReader;
+
+    #[test]
+    fn test_read_at() -> anyhow::Result<()> {
+        let path = "testfiles/common_all_20180418_half.vcf.gz";
+        let data = std::fs::read(path)?;
+        let mut expected = Vec::new();
+        BGZFReader::new(&data[..])?.read_to_end(&mut expected)?;
+
+        let file = Arc::new(std::fs::File::open(path)?);
+        assert_eq!(file.size()?, data.len() as u64);
+        let mut buf = [0; 10];
+        assert_eq!(file.read_at(100, &mut buf)?, 10);
+        assert_eq!(buf, data[100..110]);
+
+        // readers sharing one file
+        let mut reader = BGZFReader::from_read_at(file.clone())?;
+        let mut read_data = Vec::new();
+        reader.read_to_end(&mut read_data)?;
+        assert_eq!(read_data, expected);
+
+        let index = BGZFIndex::from_reader(std::fs::File::open(format!("{}.gzi", path))?)?;
+        let mut reader = IndexedBGZFReader::from_read_at(file, index.clone())?;
+        assert_eq!(reader.len(), expected.len() as u64);
+        reader.seek(SeekFrom::Start(1_000_000))?;
+        reader.read_exact(&mut buf)?;
+        assert_eq!(buf, expected[1_000_000..1_000_010]);
+
+        let mut reader = IndexedBGZFReader::from_read_at(&data[..], index)?;
+        reader.seek(SeekFrom::End(-10))?;
+        reader.read_exact(&mut buf)?;
+        assert_eq!(buf, expected[(expected.len() - 10)..]);
+
+        let mut raw = ReadAtReader::new(&data);
+        assert_eq!(raw.seek(SeekFrom::End(-5))?, data.len() as u64 - 5);
+        assert_eq!(raw.read(&mut buf)?, 5);
+        assert_eq!(raw.read(&mut buf)?, 0);
+        assert!(raw
+            .seek(SeekFrom::Current(-(data.len() as i64) - 1))
+            .is_err());
+
+        Ok(())
+    }
+}
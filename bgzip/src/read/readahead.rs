@@ -0,0 +1,245 @@
+use std::io::{self, Read, Seek, SeekFrom};
+use std::sync::mpsc::{channel, sync_channel, Receiver, Sender};
+
+/// Size of data loaded at once. A BGZF block is at most 64 KiB.
+const CHUNK_SIZE: usize = 65536;
+
+enum Command {
+    Seek(u64, SeekFrom),
+}
+
+enum Message {
+    Seeked(u64, io::Result<u64>),
+    Data(u64, io::Result<Vec<u8>>),
+}
+
+/// Reader which loads data ahead in a background thread.
+///
+/// Up to `num_blocks` chunks of 64 KiB, the maximum size of a BGZF block, are loaded ahead of
+/// the current position, so sequential reads over slow storage such as NFS or FUSE mounted object
+/// storages do not stall at each block boundary. Seeking discards loaded data and restarts
+/// loading at the new position, so [`crate::BGZFReader::bgzf_seek`] is still available.
+/// Decompression runs in the reading thread. Use [`crate::read::BGZFMultiThreadReader`] to
+/// decompress blocks in parallel.
+///
+/// ```rust
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use std::io::BufRead;
+///
+/// let file = std::fs::File::open("testfiles/generated.bed.gz")?;
+/// let mut reader = bgzip::BGZFReader::with_readahead(file, 8)?;
+/// let mut line = String::new();
+/// reader.read_line(&mut line)?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Readahead<R> {
+    command_sender: Sender<Command>,
+    receiver: Receiver<Message>,
+    /// Generation of loaded data, incremented at each seek
+    generation: u64,
+    chunk: Vec<u8>,
+    chunk_pos: usize,
+    /// Position of the first byte of `chunk` in the underlying reader
+    chunk_start: u64,
+    eof: bool,
+    /// Kind of the error which stopped loading
+    error_kind: Option<io::ErrorKind>,
+    _reader: std::marker::PhantomData<fn() -> R>,
+}
+
+impl<R: Read + Seek + Send + 'static> Readahead<R> {
+    /// Start loading up to `num_blocks` chunks ahead from the current position of `reader`.
+    pub fn new(mut reader: R, num_blocks: usize) -> io::Result<Self> {
+        let start = reader.stream_position()?;
+        let (command_sender, command_receiver) = channel();
+        let (sender, receiver) = sync_channel(num_blocks.max(1));
+        std::thread::Builder::new()
+            .name("bgzip-readahead".to_string())
+            .spawn(move || {
+                let mut generation = 0;
+                let mut stopped = false;
+                loop {
+                    let command = if stopped {
+                        match command_receiver.recv() {
+                            Ok(command) => Some(command),
+                            Err(_) => return,
+                        }
+                    } else {
+                        command_receiver.try_recv().ok()
+                    };
+                    if let Some(Command::Seek(new_generation, position)) = command {
+                        generation = new_generation;
+                        let result = reader.seek(position);
+                        stopped = result.is_err();
+                        if sender.send(Message::Seeked(generation, result)).is_err() {
+                            return;
+                        }
+                        continue;
+                    }
+
+                    let result = read_chunk(&mut reader);
+                    stopped = !matches!(&result, Ok(x) if !x.is_empty());
+                    // the receiver is dropped if the reader is dropped
+                    if sender.send(Message::Data(generation, result)).is_err() {
+                        return;
+                    }
+                }
+            })?;
+
+        Ok(Readahead {
+            command_sender,
+            receiver,
+            generation: 0,
+            chunk: Vec::new(),
+            chunk_pos: 0,
+            chunk_start: start,
+            eof: false,
+            error_kind: None,
+            _reader: std::marker::PhantomData,
+        })
+    }
+}
+
+fn read_chunk<R: Read>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let mut chunk = Vec::with_capacity(CHUNK_SIZE);
+    reader.take(CHUNK_SIZE as u64).read_to_end(&mut chunk)?;
+    Ok(chunk)
+}
+
+impl<R> Readahead<R> {
+    fn position(&self) -> u64 {
+        self.chunk_start + self.chunk_pos as u64
+    }
+
+    fn disconnected() -> io::Error {
+        io::Error::new(io::ErrorKind::BrokenPipe, "readahead thread is stopped")
+    }
+}
+
+impl<R> Read for Readahead<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some(kind) = self.error_kind {
+            return Err(io::Error::new(kind, "readahead is stopped by an error"));
+        }
+        while self.chunk_pos >= self.chunk.len() && !self.eof {
+            let data = match self.receiver.recv().map_err(|_| Self::disconnected())? {
+                Message::Data(generation, data) if generation == self.generation => data,
+                // data loaded before seek
+                _ => continue,
+            };
+            match data {
+                Ok(data) => {
+                    self.chunk_start += self.chunk.len() as u64;
+                    self.eof = data.is_empty();
+                    self.chunk = data;
+                    self.chunk_pos = 0;
+                }
+                Err(e) => {
+                    self.error_kind = Some(e.kind());
+                    return Err(e);
+                }
+            }
+        }
+        let bytes_to_copy = buf.len().min(self.chunk.len() - self.chunk_pos);
+        buf[..bytes_to_copy]
+            .copy_from_slice(&self.chunk[self.chunk_pos..(self.chunk_pos + bytes_to_copy)]);
+        self.chunk_pos += bytes_to_copy;
+        Ok(bytes_to_copy)
+    }
+}
+
+impl<R> Seek for Readahead<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Current(offset) => SeekFrom::Start(
+                self.position()
+                    .checked_add_signed(offset)
+                    .ok_or_else(|| io::Error::from(io::ErrorKind::InvalidInput))?,
+            ),
+            other => other,
+        };
+        // seek in loaded data without restarting
+        if let SeekFrom::Start(position) = pos {
+            if self.error_kind.is_none()
+                && position >= self.chunk_start
+                && position < self.chunk_start + self.chunk.len() as u64
+            {
+                self.chunk_pos = (position - self.chunk_start) as usize;
+                return Ok(position);
+            }
+        }
+
+        self.generation += 1;
+        self.command_sender
+            .send(Command::Seek(self.generation, pos))
+            .map_err(|_| Self::disconnected())?;
+        loop {
+            match self.receiver.recv().map_err(|_| Self::disconnected())? {
+                Message::Seeked(generation, result) if generation == self.generation => {
+                    // loading is stopped until the next successful seek
+                    let position = result.inspect_err(|e| self.error_kind = Some(e.kind()))?;
+                    self.chunk.clear();
+                    self.chunk_pos = 0;
+                    self.chunk_start = position;
+                    self.eof = false;
+                    self.error_kind = None;
+                    return Ok(position);
+                }
+                _ => continue,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::BGZFReader;
+
+    #[test]
+    fn test_readahead() -> anyhow::Result<()> {
+        let data = std::fs::read("testfiles/common_all_20180418_half.vcf.gz")?;
+        let mut expected = Vec::new();
+        BGZFReader::new(&data[..])?.read_to_end(&mut expected)?;
+
+        let mut reader = BGZFReader::with_readahead(
+            std::fs::File::open("testfiles/common_all_20180418_half.vcf.gz")?,
+            4,
+        )?;
+        let mut read_data = Vec::new();
+        reader.read_to_end(&mut read_data)?;
+        assert_eq!(read_data, expected);
+
+        let index = crate::index::BGZFIndex::from_reader(std::fs::File::open(
+            "testfiles/common_all_20180418_half.vcf.gz.gzi",
+        )?)?;
+        let mut expected_reader = BGZFReader::new(std::io::Cursor::new(&data))?;
+        for entry in [5, 2, 2, 40, 3] {
+            let position =
+                crate::VirtualPosition::new(index.entries()[entry].compressed_offset, 10);
+            reader.bgzf_seek(position)?;
+            expected_reader.bgzf_seek(position)?;
+            let mut buf = vec![0; 100_000];
+            let mut expected_buf = vec![0; 100_000];
+            reader.read_exact(&mut buf)?;
+            expected_reader.read_exact(&mut expected_buf)?;
+            assert_eq!(buf, expected_buf);
+        }
+
+        // raw seek
+        let mut raw = Readahead::new(std::io::Cursor::new(data.clone()), 2)?;
+        let mut buf = vec![0; 10];
+        raw.seek(SeekFrom::End(-10))?;
+        raw.read_exact(&mut buf)?;
+        assert_eq!(buf, &data[(data.len() - 10)..]);
+        assert_eq!(raw.read(&mut buf)?, 0);
+        assert_eq!(raw.seek(SeekFrom::Start(100))?, 100);
+        raw.read_exact(&mut buf)?;
+        assert_eq!(buf, &data[100..110]);
+        assert_eq!(raw.seek(SeekFrom::Current(-5))?, 105);
+        assert_eq!(raw.stream_position()?, 105);
+
+        Ok(())
+    }
+}
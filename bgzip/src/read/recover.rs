@@ -0,0 +1,87 @@
+use super::{decompress_block, load_block_or_end};
+use crate::deflate::Decompress;
+use crate::header::{HeaderLimits, DEFLATE, FLAG_FEXTRA, GZIP_ID1, GZIP_ID2};
+use crate::BGZFError;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::ops::Range;
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Callback called with the compressed range of skipped data and the error of the broken block
+pub(crate) type SkipCallback = Box<dyn FnMut(Range<u64>, &BGZFError) + Send>;
+
+/// State of recovery mode set with [`super::BGZFReader::with_recover`]
+pub(crate) struct Recover<R> {
+    pub(crate) find_next_block: fn(&mut R, u64, &HeaderLimits) -> Result<u64, BGZFError>,
+    pub(crate) callback: SkipCallback,
+}
+
+impl<R: Read + Seek> Recover<R> {
+    pub(crate) fn new(callback: SkipCallback) -> Self {
+        Recover {
+            find_next_block: find_next_block::<R>,
+            callback,
+        }
+    }
+}
+
+/// Return `true` if reading can be resumed after `error` by skipping the broken block.
+///
+/// Failures of the underlying reader other than unexpected end of data are not recovered.
+pub(crate) fn is_recoverable(error: &BGZFError) -> bool {
+    match error {
+        BGZFError::IoError(e) => e.kind() == io::ErrorKind::UnexpectedEof,
+        BGZFError::TruncatedFile => false,
+        _ => true,
+    }
+}
+
+/// Find the first block starting at or after `from` which is decompressed successfully,
+/// and move `reader` to the block.
+///
+/// The end of data is returned if no block is found.
+fn find_next_block<R: Read + Seek>(
+    reader: &mut R,
+    from: u64,
+    limits: &HeaderLimits,
+) -> Result<u64, BGZFError> {
+    let end = reader.seek(SeekFrom::End(0))?;
+    let mut chunk_start = from.min(end);
+    let mut chunk = Vec::with_capacity(CHUNK_SIZE);
+    let mut compressed_data = Vec::new();
+    let mut decompressed_data = Vec::new();
+    let mut decompress = Decompress::new();
+    loop {
+        reader.seek(SeekFrom::Start(chunk_start))?;
+        chunk.clear();
+        reader
+            .by_ref()
+            .take(CHUNK_SIZE as u64)
+            .read_to_end(&mut chunk)?;
+        if chunk.len() < 4 {
+            reader.seek(SeekFrom::Start(end))?;
+            return Ok(end);
+        }
+        for (i, magic) in chunk.windows(4).enumerate() {
+            if magic[..3] != [GZIP_ID1, GZIP_ID2, DEFLATE] || magic[3] & FLAG_FEXTRA == 0 {
+                continue;
+            }
+            let candidate = chunk_start + i as u64;
+            reader.seek(SeekFrom::Start(candidate))?;
+            compressed_data.clear();
+            decompressed_data.clear();
+            let found =
+                matches!(
+                    load_block_or_end(&mut *reader, &mut compressed_data, limits),
+                    Ok(Some(header)) if header.block_size().is_ok()
+                ) && decompress_block(&mut decompressed_data, &compressed_data, &mut decompress)
+                    .is_ok();
+            if found {
+                reader.seek(SeekFrom::Start(candidate))?;
+                return Ok(candidate);
+            }
+        }
+        // keep the last 3 bytes which may be the beginning of a magic
+        chunk_start += (chunk.len() - 3) as u64;
+    }
+}
@@ -0,0 +1,130 @@
+use super::BGZFReader;
+use crate::VirtualPosition;
+use std::io::{self, BufRead, Read};
+
+/// Reader which reaches the end of data at a virtual file offset.
+///
+/// Created by [`BGZFReader::take_until`]. Data is read from the current position of the underlying
+/// reader, and the end of data is reported at `end` even if `end` is in the middle of a block.
+/// Data is read to the end of the underlying reader if `end` is beyond the end of data.
+pub struct TakeUntil<'a, R: Read> {
+    reader: &'a mut BGZFReader<R>,
+    end: VirtualPosition,
+}
+
+impl<'a, R: Read> TakeUntil<'a, R> {
+    pub(crate) fn new(reader: &'a mut BGZFReader<R>, end: VirtualPosition) -> Self {
+        TakeUntil { reader, end }
+    }
+
+    /// Virtual file offset where this reader ends
+    pub fn end(&self) -> VirtualPosition {
+        self.end
+    }
+
+    /// Virtual file offset of the next byte
+    pub fn bgzf_pos(&self) -> VirtualPosition {
+        self.reader.bgzf_pos()
+    }
+}
+
+impl<'a, R: Read> BufRead for TakeUntil<'a, R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        let Some(pos) = self.reader.next_bgzf_pos()? else {
+            return Ok(&[]);
+        };
+        let available = if pos >= self.end {
+            0
+        } else if pos.compressed_offset() == self.end.compressed_offset() {
+            usize::from(
+                self.end.uncompressed_offset_in_block() - pos.uncompressed_offset_in_block(),
+            )
+        } else {
+            // the buffer of the reader holds the rest of the current block only
+            usize::MAX
+        };
+        let buf = self.reader.fill_buf()?;
+        Ok(&buf[..buf.len().min(available)])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.reader.consume(amt);
+    }
+}
+
+impl<'a, R: Read> Read for TakeUntil<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let internal_buf = self.fill_buf()?;
+        let bytes_to_copy = buf.len().min(internal_buf.len());
+        buf[..bytes_to_copy].copy_from_slice(&internal_buf[..bytes_to_copy]);
+        self.consume(bytes_to_copy);
+        Ok(bytes_to_copy)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{BGZFReader, BGZFWriter, Compression, VirtualPosition};
+    use rand::prelude::*;
+    use std::io::{BufRead, Read, Write};
+
+    #[test]
+    fn test_take_until() -> anyhow::Result<()> {
+        let mut rand = rand_pcg::Pcg64Mcg::seed_from_u64(0x27d4eb2f);
+        let mut data = String::new();
+        for i in 0..20_000 {
+            data.push_str(&format!("line{}\t{}\n", i, rand.gen_range(0..1_000_000)));
+        }
+
+        let mut compressed = Vec::new();
+        let mut writer = BGZFWriter::new(&mut compressed, Compression::default());
+        let mut positions = Vec::new();
+        for line in data.split_inclusive('\n') {
+            positions.push(writer.bgzf_pos());
+            writer.write_all(line.as_bytes())?;
+        }
+        positions.push(writer.bgzf_pos());
+        writer.close()?;
+        let lines: Vec<&str> = data.split_inclusive('\n').collect();
+
+        let mut reader = BGZFReader::new(std::io::Cursor::new(&compressed))?;
+        // ranges within a block, across blocks and ending at the end of a block
+        let block_end = positions
+            .iter()
+            .position(|x| x.compressed_offset() != 0)
+            .unwrap();
+        for (start, end) in [(10, 20), (100, 15_000), (0, block_end), (19_990, 20_000)] {
+            reader.bgzf_seek(positions[start])?;
+            let mut read_data = String::new();
+            let mut limited = reader.take_until(positions[end]);
+            assert_eq!(limited.end(), positions[end]);
+            limited.read_to_string(&mut read_data)?;
+            assert_eq!(read_data, lines[start..end].concat());
+            assert!(limited.fill_buf()?.is_empty());
+
+            reader.bgzf_seek(positions[start])?;
+            let mut read_data = Vec::new();
+            reader.read_until_voffset(positions[end], &mut read_data)?;
+            assert_eq!(read_data, lines[start..end].concat().as_bytes());
+        }
+
+        // lines end at the virtual offset
+        reader.bgzf_seek(positions[500])?;
+        let read_lines = reader
+            .take_until(positions[600])
+            .lines()
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(read_lines.len(), 100);
+        assert_eq!(read_lines[0], lines[500].trim_end());
+
+        // the end beyond the end of data
+        reader.bgzf_seek(positions[19_000])?;
+        let mut read_data = Vec::new();
+        reader
+            .take_until(VirtualPosition::new(u64::MAX >> 16, 0))
+            .read_to_end(&mut read_data)?;
+        assert_eq!(read_data, lines[19_000..].concat().as_bytes());
+
+        Ok(())
+    }
+}
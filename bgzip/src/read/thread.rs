@@ -1,12 +1,15 @@
 use std::collections::HashMap;
-use std::io::{BufRead, Read};
+use std::io::{BufRead, Read, Seek, SeekFrom};
 use std::sync::mpsc::{channel, Receiver, Sender};
 
+use super::EOF_BLOCK;
+use crate::cancel::{cancelled_error, CancellationToken};
 use crate::deflate::*;
-use crate::rayon::receive_or_yield;
-use crate::BGZFError;
+use crate::header::HeaderLimits;
+use crate::metrics::{BlockOperation, Recorder};
+use crate::pool::{receive_or_yield, Pool};
+use crate::{BGZFError, VirtualPosition};
 
-const EOF_BLOCK: [u8; 10] = [3, 0, 0, 0, 0, 0, 0, 0, 0, 0];
 const DEFAULT_PROCESS_BLOCK_NUM: usize = 50;
 
 struct ReadBlock {
@@ -14,6 +17,14 @@ struct ReadBlock {
     decompressed_data: Vec<u8>,
     compressed_data: Vec<Vec<u8>>,
     decompress: Decompress,
+    /// Compressed offsets of loaded BGZF blocks
+    block_offsets: Vec<u64>,
+    /// Number of BGZF blocks preceding the first loaded block. Unknown after seek.
+    first_block_index: Option<u64>,
+    /// End positions of BGZF blocks in `decompressed_data`
+    uncompressed_ends: Vec<usize>,
+    /// Compressed offset of the end of the last loaded BGZF block
+    compressed_end: u64,
 }
 
 impl ReadBlock {
@@ -28,6 +39,26 @@ impl ReadBlock {
                 process_block_num
             ],
             decompress,
+            block_offsets: Vec::with_capacity(process_block_num),
+            first_block_index: None,
+            uncompressed_ends: Vec::with_capacity(process_block_num),
+            compressed_end: 0,
+        }
+    }
+
+    /// Virtual file offset of `position` in `decompressed_data`
+    fn bgzf_pos(&self, position: usize) -> VirtualPosition {
+        let i = self.uncompressed_ends.partition_point(|x| *x <= position);
+        match self.block_offsets.get(i) {
+            Some(offset) => {
+                let start = if i == 0 {
+                    0
+                } else {
+                    self.uncompressed_ends[i - 1]
+                };
+                VirtualPosition::from(offset << 16 | (position - start) as u64)
+            }
+            None => VirtualPosition::new(self.compressed_end, 0),
         }
     }
 }
@@ -35,6 +66,7 @@ impl ReadBlock {
 /// A Multi-thread BGZF writer.
 ///
 /// [rayon](https://crates.io/crates/rayon) is used to run decompression in a thread pool.
+/// Without `rayon` feature, decompression runs in a pool of [`std::thread`] workers enabled by `threads` feature.
 pub struct BGZFMultiThreadReader<R: Read> {
     reader: R,
     block_list: Vec<ReadBlock>,
@@ -46,6 +78,26 @@ pub struct BGZFMultiThreadReader<R: Read> {
     next_read_index: u64,
     next_decompress_index: u64,
     eof_read_index: u64,
+    process_block_num: usize,
+    available_blocks: Option<u64>,
+    /// Compressed offset of the next block to load
+    compressed_pos: u64,
+    /// Number of blocks preceding the next block to load. Unknown after seek.
+    block_index: Option<u64>,
+    /// Compressed offset of the end of the last block taken for reading
+    read_compressed_end: u64,
+    /// Compressed offset and index of the missing end-of-file marker
+    missing_eof: Option<(u64, Option<u64>)>,
+    fail_on_missing_eof: bool,
+    /// Skip end-of-file markers followed by more blocks
+    concatenated: bool,
+    /// `true` if the last loaded block is end-of-file marker
+    last_block_eof: bool,
+    backend: Backend,
+    cancel: CancellationToken,
+    cancel_reported: bool,
+    recorder: Recorder,
+    pool: Pool,
 }
 
 impl<R: Read> BGZFMultiThreadReader<R> {
@@ -59,10 +111,58 @@ impl<R: Read> BGZFMultiThreadReader<R> {
     /// `process_block_num` is the number blocks to dispatch a new thread.
     /// Default value is 50. If you have fast CPU, larger value can be improve efficiency.
     pub fn with_process_block_num(reader: R, process_block_num: usize) -> Result<Self, BGZFError> {
+        Self::with_pool(reader, process_block_num, Pool::global()?)
+    }
+
+    /// Create new [`BGZFMultiThreadReader`] which runs decompression in a dedicated thread pool.
+    ///
+    /// [`BGZFMultiThreadReader::new`] uses rayon's global thread pool, which may be configured
+    /// with few threads by other parts of the application. The dedicated pool has as many threads
+    /// as [`std::thread::available_parallelism`], and it is shared by all readers and writers
+    /// created with `with_available_parallelism`.
+    pub fn with_available_parallelism(reader: R) -> Result<Self, BGZFError> {
+        Self::with_pool(
+            reader,
+            DEFAULT_PROCESS_BLOCK_NUM,
+            Pool::available_parallelism()?,
+        )
+    }
+
+    /// Create new [`BGZFMultiThreadReader`] which runs decompression in `num_threads` dedicated
+    /// [`std::thread`] workers.
+    ///
+    /// rayon is not used even if `rayon` feature is enabled. Workers exit when the reader is dropped.
+    pub fn with_std_threads(reader: R, num_threads: usize) -> Result<Self, BGZFError> {
+        Self::with_pool(
+            reader,
+            DEFAULT_PROCESS_BLOCK_NUM,
+            Pool::std_threads(num_threads)?,
+        )
+    }
+
+    /// Create new [`BGZFMultiThreadReader`] which runs decompression in `pool`.
+    #[cfg(feature = "rayon")]
+    pub fn with_thread_pool(
+        reader: R,
+        pool: std::sync::Arc<rayon::ThreadPool>,
+    ) -> Result<Self, BGZFError> {
+        Self::with_pool(reader, DEFAULT_PROCESS_BLOCK_NUM, Pool::Local(pool))
+    }
+
+    /// Same as [`BGZFMultiThreadReader::with_thread_pool`].
+    #[cfg(feature = "rayon")]
+    pub fn new_with_pool(
+        reader: R,
+        pool: std::sync::Arc<rayon::ThreadPool>,
+    ) -> Result<Self, BGZFError> {
+        Self::with_thread_pool(reader, pool)
+    }
+
+    fn with_pool(reader: R, process_block_num: usize, pool: Pool) -> Result<Self, BGZFError> {
         let (tx, rx) = channel();
         let mut reader = BGZFMultiThreadReader {
             reader,
-            block_list: (0..(rayon::current_num_threads() * 2))
+            block_list: (0..(pool.current_num_threads() * 2))
                 .map(|_| ReadBlock::new(process_block_num))
                 .collect(),
             current_read_pos: 0,
@@ -73,64 +173,191 @@ impl<R: Read> BGZFMultiThreadReader<R> {
             next_read_index: 0,
             next_decompress_index: 0,
             eof_read_index: u64::MAX,
+            process_block_num,
+            available_blocks: None,
+            compressed_pos: 0,
+            block_index: Some(0),
+            read_compressed_end: 0,
+            missing_eof: None,
+            fail_on_missing_eof: false,
+            concatenated: false,
+            last_block_eof: false,
+            backend: Backend::default(),
+            cancel: CancellationToken::new(),
+            cancel_reported: false,
+            recorder: Recorder::disabled(),
+            pool,
         };
         reader.dispatch_read_thread()?;
 
         Ok(reader)
     }
 
+    /// Select DEFLATE [`Backend`] to decompress blocks.
+    ///
+    /// Blocks already dispatched before calling this method are decompressed with the default backend.
+    pub fn with_backend(mut self, backend: Backend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// Set [`CancellationToken`] to abort decompression.
+    ///
+    /// After `cancel` is cancelled, dispatched tasks stop decompression of remaining blocks and
+    /// reads fail with [`BGZFError::Cancelled`]. The first failed read returns an error of
+    /// [`std::io::ErrorKind::Interrupted`] kind.
+    /// Blocks already dispatched before calling this method are not aborted.
+    pub fn with_cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Return [`BGZFError::TruncatedFile`] if data ends without end-of-file marker
+    /// or in the middle of a block.
+    ///
+    /// See [`crate::BGZFReader::with_fail_on_missing_eof`] for the default behavior.
+    /// Errors of blocks already loaded in the constructor are returned as is.
+    pub fn with_fail_on_missing_eof(mut self, fail_on_missing_eof: bool) -> Self {
+        self.fail_on_missing_eof = fail_on_missing_eof;
+        self
+    }
+
+    /// Read concatenated BGZF files to the end.
+    ///
+    /// See [`crate::BGZFReader::with_concatenated`] for details.
+    pub fn with_concatenated(mut self, concatenated: bool) -> Self {
+        self.concatenated = concatenated;
+        if concatenated && self.last_block_eof {
+            // reading stopped at end-of-file marker loaded in the constructor
+            self.eof_read_index = u64::MAX;
+        }
+        self
+    }
+
+    /// Record decompression duration of each block into `sink`.
+    ///
+    /// `sink` is called from threads in the thread pool.
+    /// Blocks already dispatched before calling this method are not recorded.
+    #[cfg(feature = "metrics")]
+    pub fn with_histogram(
+        mut self,
+        sink: std::sync::Arc<dyn crate::metrics::HistogramSink>,
+    ) -> Self {
+        self.recorder = Recorder::new(sink);
+        self
+    }
+
     fn dispatch_read_thread(&mut self) -> Result<(), BGZFError> {
-        while !self.block_list.is_empty() && self.next_decompress_index < self.eof_read_index {
+        while !self.block_list.is_empty()
+            && self.next_decompress_index < self.eof_read_index
+            && !self.cancel.is_cancelled()
+        {
             let mut block = self.block_list.pop().unwrap();
             block.index = self.next_decompress_index;
+            if block.decompress.backend() != self.backend {
+                block.decompress = Decompress::with_backend(self.backend.clone());
+            }
             self.next_decompress_index += 1;
 
-            let mut last_index = 0;
+            block
+                .compressed_data
+                .resize(self.process_block_num, Vec::new());
+            block.block_offsets.clear();
+            block.first_block_index = self.block_index;
+            block.compressed_end = self.compressed_pos;
+            let mut loaded_blocks = 0;
 
             for i in 0..block.compressed_data.len() {
+                if self.available_blocks == Some(0) {
+                    self.eof_read_index = self.next_decompress_index;
+                    break;
+                }
                 //eprintln!("load block {}", i);
-                super::load_block(
+                let block_offset = self.compressed_pos;
+                let header = super::load_block_or_end(
                     &mut self.reader,
-                    &mut block.compressed_data.get_mut(i).unwrap(),
+                    block.compressed_data.get_mut(i).unwrap(),
+                    &HeaderLimits::default(),
                 )
-                .map_err(|e| -> std::io::Error {
-                    // eprintln!("load block error: {}", e);
-                    e.into()
-                })?;
-                last_index = i;
-                if block.compressed_data.get(i).unwrap() == &EOF_BLOCK {
+                .map_err(|e| {
+                    if self.fail_on_missing_eof {
+                        super::truncated_error(e)
+                    } else {
+                        e
+                    }
+                })
+                .map_err(|e| e.in_block(block_offset, self.block_index))?;
+                let header = match header {
+                    Some(header) => header,
+                    None => {
+                        // concatenated data ending just after end-of-file marker is complete
+                        if !(self.concatenated && self.last_block_eof) {
+                            #[cfg(feature = "log")]
+                            if !self.fail_on_missing_eof {
+                                log::warn!(
+                                    "BGZF data ends without end-of-file marker at compressed offset {}",
+                                    block_offset
+                                );
+                            }
+                            // checked when reading reaches the end
+                            self.missing_eof = Some((block_offset, self.block_index));
+                        }
+                        self.eof_read_index = self.next_decompress_index;
+                        break;
+                    }
+                };
+                self.compressed_pos += u64::from(header.block_size()?);
+                self.block_index = self.block_index.map(|x| x + 1);
+                if let Some(available_blocks) = self.available_blocks.as_mut() {
+                    *available_blocks -= 1;
+                }
+                self.last_block_eof = block.compressed_data.get(i).unwrap() == &EOF_BLOCK;
+                if self.last_block_eof && !self.concatenated {
                     //self.block_list.clear();
                     // eprintln!("EOF reach: {}", block.index);
                     self.eof_read_index = self.next_decompress_index;
                     break;
                 }
+                block.block_offsets.push(block_offset);
+                block.compressed_end = self.compressed_pos;
+                loaded_blocks += 1;
             }
 
-            if last_index != block.compressed_data.len() - 1 {
-                block
-                    .compressed_data
-                    .drain(last_index..block.compressed_data.len());
-            }
+            block.compressed_data.truncate(loaded_blocks);
 
             let sender = self.reader_sender.clone();
+            let cancel = self.cancel.clone();
+            let recorder = self.recorder.clone();
             // eprintln!("spawn: {}", block.index);
-            rayon::spawn(move || {
+            self.pool.spawn(move || {
                 let _i = block.index;
                 block.decompressed_data.clear();
-                for one_compress_data in &block.compressed_data {
-                    match super::decompress_block(
-                        &mut block.decompressed_data,
-                        &one_compress_data,
-                        &mut block.decompress,
-                    ) {
-                        Ok(_) => (),
+                block.uncompressed_ends.clear();
+                for (i, one_compress_data) in block.compressed_data.iter().enumerate() {
+                    if cancel.is_cancelled() {
+                        // the receiver may be already dropped
+                        let _ = sender.send(Err(BGZFError::Cancelled));
+                        return;
+                    }
+                    match recorder.record(BlockOperation::Decompress, || {
+                        super::decompress_block(
+                            &mut block.decompressed_data,
+                            one_compress_data,
+                            &mut block.decompress,
+                        )
+                    }) {
+                        Ok(_) => block.uncompressed_ends.push(block.decompressed_data.len()),
                         Err(e) => {
                             //eprintln!("send Error: {}", e);
-                            sender.send(Err(e)).expect("reader send error 2")
+                            let block_index = block.first_block_index.map(|x| x + i as u64);
+                            // the receiver may be already dropped
+                            let _ =
+                                sender.send(Err(e.in_block(block.block_offsets[i], block_index)));
                         }
                     }
                 }
-                sender.send(Ok(block)).expect("reader send error 1");
+                // the receiver is dropped if the reader is dropped before reading all data
+                let _ = sender.send(Ok(block));
                 // eprintln!("done: {}", i);
             });
         }
@@ -139,11 +366,157 @@ impl<R: Read> BGZFMultiThreadReader<R> {
     }
 }
 
+impl<R: Read + Seek> BGZFMultiThreadReader<R> {
+    /// Seek BGZF with virtual file offset.
+    ///
+    /// Decompression tasks in flight are discarded, and reading restarts from the block at
+    /// `position`. The underlying reader must be created at the beginning of BGZF data.
+    pub fn bgzf_seek<P: Into<VirtualPosition>>(&mut self, position: P) -> Result<(), BGZFError> {
+        let position = position.into();
+        // results of tasks in flight are sent to the dropped receiver
+        let (tx, rx) = channel();
+        self.reader_sender = tx;
+        self.reader_receiver = rx;
+        self.block_list.extend(self.current_read_buffer.take());
+        self.block_list
+            .extend(self.read_waiting_blocks.drain().map(|(_, x)| x));
+        while self.block_list.len() < self.pool.current_num_threads() * 2 {
+            self.block_list.push(ReadBlock::new(self.process_block_num));
+        }
+        self.next_read_index = 0;
+        self.next_decompress_index = 0;
+        self.eof_read_index = u64::MAX;
+        self.available_blocks = None;
+        self.missing_eof = None;
+        self.last_block_eof = false;
+        self.current_read_pos = 0;
+
+        let block_offset = position.compressed_offset();
+        self.reader.seek(SeekFrom::Start(block_offset))?;
+        self.compressed_pos = block_offset;
+        self.block_index = if block_offset == 0 { Some(0) } else { None };
+        self.read_compressed_end = block_offset;
+        self.dispatch_read_thread()?;
+
+        let position_in_block: usize = position.uncompressed_offset_in_block().into();
+        if position_in_block > 0 {
+            self.fill_buf()?;
+            match self.current_read_buffer.as_ref() {
+                Some(b) if b.uncompressed_ends.first() >= Some(&position_in_block) => {
+                    self.current_read_pos = position_in_block;
+                }
+                _ => return Err(BGZFError::Other("Invalid position in block")),
+            }
+        }
+        Ok(())
+    }
+
+    /// Resume reading blocks appended after the end of file.
+    ///
+    /// This is useful to follow a growing BGZF file like `tail -f`. After this reader reached
+    /// the end of file, this function probes `reader` for complete blocks written after the
+    /// previously observed end of file. Reading is resumed from these blocks without losing
+    /// the current position. Incomplete blocks at the end of file are left for the next call.
+    ///
+    /// Return the number of newly found blocks. An error is returned if end of file is not reached yet.
+    pub fn reset_eof(&mut self) -> Result<u64, BGZFError> {
+        if self.eof_read_index == u64::MAX {
+            return Err(BGZFError::Other("End of file is not reached yet"));
+        }
+
+        let start = self.reader.stream_position()?;
+        let mut buffer = Vec::new();
+        let mut found_blocks = 0;
+        loop {
+            match super::load_block(&mut self.reader, &mut buffer) {
+                Ok(_) => found_blocks += 1,
+                Err(BGZFError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                    break
+                }
+                Err(e) => {
+                    self.reader.seek(SeekFrom::Start(start))?;
+                    return Err(e);
+                }
+            }
+        }
+        self.reader.seek(SeekFrom::Start(start))?;
+
+        if found_blocks > 0 {
+            self.available_blocks = Some(found_blocks);
+            self.missing_eof = None;
+            self.eof_read_index = u64::MAX;
+        }
+
+        Ok(found_blocks)
+    }
+}
+
+impl<R: Read> BGZFMultiThreadReader<R> {
+    /// Get BGZF virtual file offset of the current position.
+    ///
+    /// See [`crate::BGZFReader::bgzf_pos`] for details.
+    pub fn bgzf_pos(&self) -> VirtualPosition {
+        match self.current_read_buffer.as_ref() {
+            Some(b) => b.bgzf_pos(self.current_read_pos),
+            None => VirtualPosition::new(self.read_compressed_end, 0),
+        }
+    }
+
+    /// Get BGZF virtual file offset of the next byte to be read, or `None` at the end of data.
+    ///
+    /// See [`crate::BGZFReader::next_bgzf_pos`] for details.
+    pub fn next_bgzf_pos(&mut self) -> std::io::Result<Option<VirtualPosition>> {
+        if self.fill_buf()?.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(self.bgzf_pos()))
+    }
+
+    /// Iterate lines with virtual file offsets of their first bytes.
+    ///
+    /// See [`crate::BGZFReader::lines_with_pos`] for details.
+    pub fn lines_with_pos(&mut self) -> super::LinesWithPos<'_, Self> {
+        super::LinesWithPos::new(self, BGZFMultiThreadReader::next_bgzf_pos)
+    }
+}
+
+impl<R: Read + Seek> super::BGZFReadSeek for BGZFMultiThreadReader<R> {
+    fn bgzf_pos(&self) -> VirtualPosition {
+        BGZFMultiThreadReader::bgzf_pos(self)
+    }
+
+    fn bgzf_seek(&mut self, position: VirtualPosition) -> Result<(), BGZFError> {
+        BGZFMultiThreadReader::bgzf_seek(self, position)
+    }
+}
+
+impl<R: Read> BGZFMultiThreadReader<R> {
+    fn end_of_data(&self) -> std::io::Result<&'static [u8]> {
+        match self.missing_eof {
+            Some((offset, index)) if self.fail_on_missing_eof => {
+                Err(BGZFError::TruncatedFile.in_block(offset, index).into())
+            }
+            _ => Ok(&[]),
+        }
+    }
+}
+
 impl<R: Read> BufRead for BGZFMultiThreadReader<R> {
     fn consume(&mut self, amt: usize) {
         self.current_read_pos += amt;
     }
     fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        // buffers of empty blocks in the middle of data are skipped
+        while self.fill_next_buffer()?.is_empty() && self.current_read_buffer.is_some() {}
+        match self.current_read_buffer.as_ref() {
+            Some(b) => Ok(&b.decompressed_data[self.current_read_pos..]),
+            None => Ok(&[]),
+        }
+    }
+}
+
+impl<R: Read> BGZFMultiThreadReader<R> {
+    fn fill_next_buffer(&mut self) -> std::io::Result<&[u8]> {
         // eprintln!(
         //     "fill buf start: {} {} {} {}",
         //     self.current_read_pos,
@@ -155,6 +528,10 @@ impl<R: Read> BufRead for BGZFMultiThreadReader<R> {
         //     self.eof_read_index
         // );
 
+        if self.cancel.is_cancelled() {
+            return Err(cancelled_error(&mut self.cancel_reported));
+        }
+
         //eprintln!("fill buf 1");
 
         if let Some(b) = self.current_read_buffer.as_ref() {
@@ -168,7 +545,7 @@ impl<R: Read> BufRead for BGZFMultiThreadReader<R> {
 
         if self.next_read_index > self.eof_read_index {
             //eprintln!("EOF 0 bytes fill");
-            return Ok(&[]);
+            return self.end_of_data();
         }
 
         //eprintln!("fill buf 3");
@@ -180,17 +557,23 @@ impl<R: Read> BufRead for BGZFMultiThreadReader<R> {
         if self.current_read_buffer.is_none() {
             if self.next_read_index >= self.eof_read_index {
                 //eprintln!("EOF 0 bytes fill");
-                return Ok(&[]);
+                return self.end_of_data();
             }
 
             while !self.read_waiting_blocks.contains_key(&self.next_read_index) {
-                let block = receive_or_yield(&self.reader_receiver)
-                    .expect("reader receive error")
-                    .map_err(|e| -> std::io::Error { e.into() })?;
+                let block =
+                    match receive_or_yield(&self.reader_receiver).expect("reader receive error") {
+                        Ok(block) => block,
+                        Err(BGZFError::Cancelled) => {
+                            return Err(cancelled_error(&mut self.cancel_reported))
+                        }
+                        Err(e) => return Err(e.into()),
+                    };
                 // eprintln!("fetch: {}", block.index);
                 self.read_waiting_blocks.insert(block.index, block);
             }
             self.current_read_buffer = self.read_waiting_blocks.remove(&self.next_read_index);
+            self.read_compressed_end = self.current_read_buffer.as_ref().unwrap().compressed_end;
             // eprintln!("read: {}", self.next_read_index);
             self.current_read_pos = 0;
             self.next_read_index += 1;
@@ -220,6 +603,10 @@ impl<R: Read> Read for BGZFMultiThreadReader<R> {
         //eprintln!("read end: {}", bytes_to_copy);
         Ok(bytes_to_copy)
     }
+
+    fn read_vectored(&mut self, bufs: &mut [std::io::IoSliceMut<'_>]) -> std::io::Result<usize> {
+        super::read_vectored_from_buf(self, bufs)
+    }
 }
 
 #[cfg(test)]
@@ -227,6 +614,7 @@ mod test {
     use super::*;
 
     #[test]
+    #[cfg(feature = "rayon")]
     fn test_many_data() -> anyhow::Result<()> {
         let mut expected_reader = flate2::read::MultiGzDecoder::new(std::fs::File::open(
             "testfiles/common_all_20180418_half.vcf.gz",
@@ -312,4 +700,290 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn test_reset_eof() -> anyhow::Result<()> {
+        use std::io::Write;
+
+        let mut data = std::io::Cursor::new(Vec::new());
+        let mut writer = crate::BGZFWriter::with_compress_unit_size(
+            &mut data,
+            crate::Compression::default(),
+            100,
+            false,
+        )?;
+        for i in 0..100 {
+            writeln!(writer, "first {}", i)?;
+        }
+        writer.close()?;
+        data.set_position(0);
+
+        let mut reader = BGZFMultiThreadReader::with_process_block_num(data, 3)?;
+        assert!(reader.reset_eof().is_err());
+        let mut read_buf = String::new();
+        reader.read_to_string(&mut read_buf)?;
+        assert_eq!(read_buf.lines().count(), 100);
+        assert_eq!(reader.reset_eof()?, 0);
+
+        // append a new member and a partial block
+        let mut appended = Vec::new();
+        let mut writer = crate::BGZFWriter::with_compress_unit_size(
+            &mut appended,
+            crate::Compression::default(),
+            100,
+            false,
+        )?;
+        for i in 0..100 {
+            writeln!(writer, "second {}", i)?;
+        }
+        writer.close()?;
+        let appended_len = appended.len();
+        reader.reader.get_mut().extend_from_slice(&appended);
+        reader.reader.get_mut().extend_from_slice(&appended[..10]);
+
+        let found_blocks = reader.reset_eof()?;
+        assert!(found_blocks > 1);
+        let mut read_buf = String::new();
+        reader.read_to_string(&mut read_buf)?;
+        let lines: Vec<_> = read_buf.lines().collect();
+        assert_eq!(lines.len(), 100);
+        assert_eq!(lines[0], "second 0");
+        assert_eq!(lines[99], "second 99");
+
+        // complete the partial block
+        assert_eq!(reader.reset_eof()?, 0);
+        reader
+            .reader
+            .get_mut()
+            .extend_from_slice(&appended[10..appended_len]);
+        assert_eq!(reader.reset_eof()?, found_blocks);
+        let mut read_buf = String::new();
+        reader.read_to_string(&mut read_buf)?;
+        assert_eq!(read_buf.lines().count(), 100);
+        assert_eq!(reader.reset_eof()?, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cancel() -> anyhow::Result<()> {
+        let cancel = CancellationToken::new();
+        let mut reader = BGZFMultiThreadReader::new(std::fs::File::open(
+            "testfiles/common_all_20180418_half.vcf.gz",
+        )?)?
+        .with_cancellation(cancel.clone());
+
+        let mut buf = vec![0; 1000];
+        reader.read_exact(&mut buf)?;
+        cancel.cancel();
+        let e = reader.fill_buf().unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::Interrupted);
+        assert!(matches!(
+            e.into_inner().unwrap().downcast::<BGZFError>().as_deref(),
+            Ok(BGZFError::Cancelled)
+        ));
+        // read_to_end retries interrupted reads, but fails after the first error
+        assert!(reader.read_to_end(&mut Vec::new()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_thread_pool() -> anyhow::Result<()> {
+        let mut expected = Vec::new();
+        crate::BGZFReader::new(std::fs::File::open("testfiles/generated.bed.gz")?)?
+            .read_to_end(&mut expected)?;
+
+        #[cfg(feature = "rayon")]
+        {
+            let pool = std::sync::Arc::new(rayon::ThreadPoolBuilder::new().num_threads(2).build()?);
+            let mut reader = BGZFMultiThreadReader::with_thread_pool(
+                std::fs::File::open("testfiles/generated.bed.gz")?,
+                pool,
+            )?;
+            let mut read_buf = Vec::new();
+            reader.read_to_end(&mut read_buf)?;
+            assert_eq!(read_buf, expected);
+        }
+
+        let mut reader = BGZFMultiThreadReader::with_std_threads(
+            std::fs::File::open("testfiles/generated.bed.gz")?,
+            2,
+        )?;
+        assert_eq!(reader.pool.current_num_threads(), 2);
+        let mut read_buf = Vec::new();
+        reader.read_to_end(&mut read_buf)?;
+        assert_eq!(read_buf, expected);
+
+        let mut reader = BGZFMultiThreadReader::with_available_parallelism(std::fs::File::open(
+            "testfiles/generated.bed.gz",
+        )?)?;
+        let mut read_buf = Vec::new();
+        reader.read_to_end(&mut read_buf)?;
+        assert_eq!(read_buf, expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_block_error_position() -> anyhow::Result<()> {
+        let mut data = Vec::new();
+        std::fs::File::open("testfiles/generated.bed.gz")?.read_to_end(&mut data)?;
+        let blocks: Vec<_> = crate::read::RawBlocks::new(&data[..]).collect::<Result<_, _>>()?;
+        let crc_pos = blocks[7].compressed_offset + blocks[7].compressed_size() - 8;
+        data[crc_pos as usize] ^= 0xff;
+
+        let mut reader = BGZFMultiThreadReader::with_process_block_num(&data[..], 3)?;
+        let e: BGZFError = reader.read_to_end(&mut Vec::new()).unwrap_err().into();
+        assert_eq!(e.compressed_offset(), Some(blocks[7].compressed_offset));
+        assert_eq!(e.block_index(), Some(7));
+
+        // headers may be loaded while the reader is created
+        data[blocks[5].compressed_offset as usize] = 0;
+        let e = match BGZFMultiThreadReader::with_process_block_num(&data[..], 3) {
+            Ok(mut reader) => reader.read_to_end(&mut Vec::new()).unwrap_err().into(),
+            Err(e) => e,
+        };
+        assert_eq!(e.compressed_offset(), Some(blocks[5].compressed_offset));
+        assert_eq!(e.block_index(), Some(5));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_missing_eof() -> anyhow::Result<()> {
+        let mut data = Vec::new();
+        std::fs::File::open("testfiles/generated.bed.gz")?.read_to_end(&mut data)?;
+        let mut expected_data = Vec::new();
+        flate2::read::MultiGzDecoder::new(&data[..]).read_to_end(&mut expected_data)?;
+        let without_eof = &data[..(data.len() - crate::EOF_MARKER.len())];
+        let block_num = crate::read::RawBlocks::new(without_eof).count() as u64;
+
+        let mut reader = BGZFMultiThreadReader::with_process_block_num(without_eof, 3)?;
+        let mut read_data = Vec::new();
+        reader.read_to_end(&mut read_data)?;
+        assert_eq!(read_data, expected_data);
+
+        let mut reader = BGZFMultiThreadReader::with_process_block_num(without_eof, 3)?
+            .with_fail_on_missing_eof(true);
+        let mut read_data = Vec::new();
+        let e = reader.read_to_end(&mut read_data).unwrap_err();
+        assert_eq!(read_data, expected_data);
+        assert_eq!(e.kind(), std::io::ErrorKind::UnexpectedEof);
+        let e: BGZFError = e.into();
+        assert_eq!(e.compressed_offset(), Some(without_eof.len() as u64));
+        assert_eq!(e.block_index(), Some(block_num));
+
+        let mut reader = BGZFMultiThreadReader::with_process_block_num(&data[..], 3)?
+            .with_fail_on_missing_eof(true);
+        let mut read_data = Vec::new();
+        reader.read_to_end(&mut read_data)?;
+        assert_eq!(read_data, expected_data);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_concatenated() -> anyhow::Result<()> {
+        let mut data = Vec::new();
+        std::fs::File::open("testfiles/generated.bed.gz")?.read_to_end(&mut data)?;
+        let mut expected_data = Vec::new();
+        crate::BGZFReader::new(&data[..])?.read_to_end(&mut expected_data)?;
+
+        let mut concatenated = crate::EOF_MARKER.to_vec();
+        for _ in 0..3 {
+            concatenated.extend_from_slice(&data);
+            concatenated.extend_from_slice(&crate::EOF_MARKER);
+        }
+        let expected = expected_data.repeat(3);
+
+        for process_block_num in [1, 3, 50] {
+            let mut reader = BGZFMultiThreadReader::with_process_block_num(
+                &concatenated[..],
+                process_block_num,
+            )?;
+            assert_eq!(reader.read_to_end(&mut Vec::new())?, 0);
+
+            let mut reader = BGZFMultiThreadReader::with_process_block_num(
+                &concatenated[..],
+                process_block_num,
+            )?
+            .with_concatenated(true)
+            .with_fail_on_missing_eof(true);
+            let mut read_data = Vec::new();
+            reader.read_to_end(&mut read_data)?;
+            assert_eq!(read_data, expected);
+
+            // the last file also ends with end-of-file marker
+            let without_eof = &concatenated[..(concatenated.len() - crate::EOF_MARKER.len() * 2)];
+            let mut reader =
+                BGZFMultiThreadReader::with_process_block_num(without_eof, process_block_num)?
+                    .with_concatenated(true)
+                    .with_fail_on_missing_eof(true);
+            assert!(reader.read_to_end(&mut Vec::new()).is_err());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bgzf_seek() -> anyhow::Result<()> {
+        use rand::prelude::*;
+        use std::io::Write;
+
+        let path = "testfiles/common_all_20180418_half.vcf.gz";
+        let mut expected = Vec::new();
+        let mut reader = crate::BGZFReader::new(std::fs::File::open(path)?)?;
+        let mut line = Vec::new();
+        loop {
+            reader.fill_buf()?;
+            let position = reader.bgzf_pos();
+            line.clear();
+            if reader.read_until(b'\n', &mut line)? == 0 {
+                break;
+            }
+            expected.push((position, line.clone()));
+        }
+
+        let mut reader =
+            BGZFMultiThreadReader::with_process_block_num(std::fs::File::open(path)?, 3)?;
+        for one in &expected {
+            reader.fill_buf()?;
+            assert_eq!(reader.bgzf_pos(), one.0);
+            line.clear();
+            reader.read_until(b'\n', &mut line)?;
+            assert_eq!(line, one.1);
+        }
+        assert_eq!(reader.read_until(b'\n', &mut line)?, 0);
+
+        let mut rand = rand_pcg::Pcg64Mcg::seed_from_u64(0x3c1d8e4b2a7f9065);
+        for _ in 0..30 {
+            let i = rand.gen_range(0..expected.len());
+            crate::read::BGZFReadSeek::bgzf_seek(&mut reader, expected[i].0)?;
+            for one in &expected[i..(i + 100).min(expected.len())] {
+                reader.fill_buf()?;
+                assert_eq!(reader.bgzf_pos(), one.0);
+                line.clear();
+                reader.read_until(b'\n', &mut line)?;
+                assert_eq!(line, one.1);
+            }
+        }
+
+        // seek to the end of a block
+        let mut data = Vec::new();
+        {
+            let mut writer = crate::BGZFWriter::new(&mut data, crate::Compression::default());
+            writer.write_all(&[b'a'; 100])?;
+            writer.flush()?;
+            writer.write_all(&[b'b'; 100])?;
+        }
+        let mut reader = BGZFMultiThreadReader::new(std::io::Cursor::new(&data))?;
+        reader.bgzf_seek(100)?;
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf)?;
+        assert_eq!(buf, vec![b'b'; 100]);
+        assert!(reader.bgzf_seek(101).is_err());
+
+        Ok(())
+    }
 }
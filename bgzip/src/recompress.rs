@@ -0,0 +1,130 @@
+//! Recompression of BGZF data
+
+use crate::deflate::{Compress, Decompress};
+use crate::read::{RawBlock, RawBlocks, EOF_BLOCK};
+use crate::{BGZFError, Compression};
+use std::convert::TryInto;
+use std::io::{Read, Write};
+
+/// Number of blocks recompressed at once per thread
+#[cfg(feature = "rayon")]
+const BLOCKS_PER_THREAD: usize = 4;
+
+fn recompress_block(
+    mut block: RawBlock,
+    compress: &mut Compress,
+    decompress: &mut Decompress,
+) -> Result<Vec<u8>, BGZFError> {
+    let mut compressed = Vec::new();
+    if block.data == EOF_BLOCK {
+        block.write(&mut compressed)?;
+        return Ok(compressed);
+    }
+    let mut original = Vec::new();
+    crate::read::decompress_block(&mut original, &block.data, decompress)?;
+    crate::write::write_block_with_header(&mut compressed, &original, compress, &mut block.header)?;
+    Ok(compressed)
+}
+
+/// Decompress each block of BGZF data from `reader` and compress it again at `level` into `writer`.
+///
+/// Block boundaries and block headers, such as comments and extra fields, are preserved exactly,
+/// so .gzi entries and virtual file offsets in other index files must be created again,
+/// but uncompressed offsets are kept. End-of-file markers are copied as is.
+/// Blocks are recompressed in the rayon thread pool if `rayon` feature is enabled.
+/// To change block boundaries, copy data from [`crate::BGZFReader`] to [`crate::BGZFWriter`] instead.
+///
+/// Return the number of written bytes.
+pub fn recompress<R: Read + Send, W: Write>(
+    reader: R,
+    mut writer: W,
+    level: Compression,
+) -> Result<u64, BGZFError> {
+    let mut written_bytes = 0;
+
+    #[cfg(feature = "rayon")]
+    {
+        use rayon::prelude::*;
+
+        let batch_size = rayon::current_num_threads() * BLOCKS_PER_THREAD;
+        let mut blocks = RawBlocks::new(reader);
+        loop {
+            let batch = blocks
+                .by_ref()
+                .take(batch_size)
+                .collect::<Result<Vec<_>, _>>()?;
+            if batch.is_empty() {
+                break;
+            }
+            let compressed = batch
+                .into_par_iter()
+                .map_init(
+                    || (Compress::new(level), Decompress::new()),
+                    |(compress, decompress), block| recompress_block(block, compress, decompress),
+                )
+                .collect::<Result<Vec<_>, _>>()?;
+            for one in compressed {
+                writer.write_all(&one)?;
+                written_bytes += TryInto::<u64>::try_into(one.len()).unwrap();
+            }
+        }
+    }
+
+    #[cfg(not(feature = "rayon"))]
+    {
+        let mut compress = Compress::new(level);
+        let mut decompress = Decompress::new();
+        for block in RawBlocks::new(reader) {
+            let compressed = recompress_block(block?, &mut compress, &mut decompress)?;
+            writer.write_all(&compressed)?;
+            written_bytes += TryInto::<u64>::try_into(compressed.len()).unwrap();
+        }
+    }
+
+    writer.flush()?;
+    Ok(written_bytes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_recompress() -> anyhow::Result<()> {
+        let mut expected_data = Vec::new();
+        flate2::read::MultiGzDecoder::new(fs::File::open("testfiles/generated.bed.gz")?)
+            .read_to_end(&mut expected_data)?;
+
+        let mut fast = Vec::new();
+        let mut writer = crate::BGZFWriter::new(&mut fast, Compression::fast())
+            .with_file_comment(b"recompress test")?;
+        writer.write_all(&expected_data)?;
+        writer.close()?;
+
+        let mut best = Vec::new();
+        let written_bytes = recompress(&fast[..], &mut best, Compression::best())?;
+        assert_eq!(written_bytes, best.len() as u64);
+        assert!(best.len() < fast.len());
+        assert!(best.ends_with(&crate::EOF_MARKER));
+
+        let fast_blocks: Vec<_> = RawBlocks::new(&fast[..]).collect::<Result<_, _>>()?;
+        let best_blocks: Vec<_> = RawBlocks::new(&best[..]).collect::<Result<_, _>>()?;
+        assert_eq!(fast_blocks.len(), best_blocks.len());
+        for (one, two) in fast_blocks.iter().zip(best_blocks.iter()) {
+            assert_eq!(one.uncompressed_offset, two.uncompressed_offset);
+            assert_eq!(one.crc32(), two.crc32());
+            assert_eq!(one.header.comment, two.header.comment);
+        }
+
+        let mut reader = crate::BGZFReader::new(&best[..])?;
+        assert_eq!(reader.file_comment(), Some(&b"recompress test"[..]));
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        assert_eq!(data, expected_data);
+
+        assert!(recompress(&b"not bgzf"[..], Vec::new(), Compression::best()).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,242 @@
+//! Detection of compression format and payload type
+//!
+//! [`sniff`] decompresses only the first non-empty block of BGZF data, or the first 64 KiB of
+//! standard gzip or uncompressed data, and reports what kind of data is stored.
+//!
+//! ```rust
+//! # fn main() -> Result<(), bgzip::BGZFError> {
+//! use bgzip::inspect::Format;
+//! use bgzip::sniff::{sniff, Payload};
+//!
+//! let sniffed = sniff(std::fs::File::open("testfiles/common_all_20180418_half.vcf.gz")?)?;
+//! assert_eq!(sniffed.format, Format::Bgzf);
+//! assert_eq!(sniffed.payload, Payload::Vcf);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::deflate::Decompress;
+use crate::inspect::{detect_format, Format};
+use crate::read::{decompress_block, RawBlocks};
+use crate::write::MAXIMUM_COMPRESS_UNIT_SIZE;
+use crate::BGZFError;
+use std::io::{BufReader, Read};
+
+/// Magic bytes of BAM
+pub const BAM_MAGIC: [u8; 4] = [b'B', b'A', b'M', 1];
+
+/// Magic bytes of BCF version 2
+pub const BCF_MAGIC: [u8; 4] = [b'B', b'C', b'F', 2];
+
+/// Type of data in the compressed stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Payload {
+    /// BAM starting with [`BAM_MAGIC`]
+    Bam,
+    /// BCF starting with [`BCF_MAGIC`]
+    Bcf,
+    /// VCF text starting with `##fileformat=VCF`
+    Vcf,
+    /// Other UTF-8 text without control characters except tab, CR and LF
+    Text,
+    /// Empty data, other binary data, or standard gzip without `flate2` feature
+    Unknown,
+}
+
+/// Result of [`sniff`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Sniffed {
+    /// Compression format
+    pub format: Format,
+    /// Type of decompressed data
+    pub payload: Payload,
+}
+
+/// Detect compression format and payload type of data from `reader`.
+///
+/// Data is read from the current position of `reader`, and the position after this function
+/// is not specified. Errors of the first block, such as CRC32 mismatch, are returned.
+pub fn sniff<R: Read>(reader: R) -> Result<Sniffed, BGZFError> {
+    let mut reader = BufReader::new(reader);
+    let format = detect_format(&mut reader)?;
+    let mut data = Vec::new();
+    match format {
+        Format::Bgzf => {
+            let mut decompress = Decompress::new();
+            for block in RawBlocks::new(&mut reader) {
+                decompress_block(&mut data, &block?.data, &mut decompress)?;
+                if !data.is_empty() {
+                    break;
+                }
+            }
+        }
+        #[cfg(feature = "flate2")]
+        Format::Gzip => {
+            flate2::read::MultiGzDecoder::new(reader)
+                .take(MAXIMUM_COMPRESS_UNIT_SIZE as u64)
+                .read_to_end(&mut data)?;
+        }
+        #[cfg(not(feature = "flate2"))]
+        Format::Gzip => {
+            return Ok(Sniffed {
+                format,
+                payload: Payload::Unknown,
+            });
+        }
+        Format::Plain => {
+            reader
+                .take(MAXIMUM_COMPRESS_UNIT_SIZE as u64)
+                .read_to_end(&mut data)?;
+        }
+    }
+    Ok(Sniffed {
+        format,
+        payload: sniff_payload(&data),
+    })
+}
+
+/// Detect payload type from the beginning of decompressed data.
+///
+/// `data` may be cut in the middle of a UTF-8 character.
+pub fn sniff_payload(data: &[u8]) -> Payload {
+    if data.starts_with(&BAM_MAGIC) {
+        Payload::Bam
+    } else if data.starts_with(&BCF_MAGIC) {
+        Payload::Bcf
+    } else if data.starts_with(b"##fileformat=VCF") {
+        Payload::Vcf
+    } else if is_text(data) {
+        Payload::Text
+    } else {
+        Payload::Unknown
+    }
+}
+
+fn is_text(data: &[u8]) -> bool {
+    if data.is_empty()
+        || data
+            .iter()
+            .any(|x| x.is_ascii_control() && !matches!(x, b'\t' | b'\n' | b'\r'))
+    {
+        return false;
+    }
+    match std::str::from_utf8(data) {
+        Ok(_) => true,
+        // incomplete character at the end
+        Err(e) => e.error_len().is_none(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{BGZFWriter, Compression};
+    use std::fs::File;
+    use std::io::Write;
+
+    fn bgzip(data: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let mut compressed = Vec::new();
+        let mut writer = BGZFWriter::new(&mut compressed, Compression::default());
+        writer.write_all(data)?;
+        writer.close()?;
+        Ok(compressed)
+    }
+
+    #[test]
+    fn test_sniff() -> anyhow::Result<()> {
+        for (path, format, payload) in [
+            (
+                "testfiles/common_all_20180418_half.vcf.gz",
+                Format::Bgzf,
+                Payload::Vcf,
+            ),
+            (
+                "testfiles/common_all_20180418_half.vcf.nobgzip.gz",
+                Format::Gzip,
+                if cfg!(feature = "flate2") {
+                    Payload::Vcf
+                } else {
+                    Payload::Unknown
+                },
+            ),
+            ("testfiles/generated.bed.gz", Format::Bgzf, Payload::Text),
+            (
+                "testfiles/testfile-generate.py",
+                Format::Plain,
+                Payload::Text,
+            ),
+            (
+                "testfiles/generated.bed.gz.tbi",
+                Format::Bgzf,
+                Payload::Unknown,
+            ),
+            (
+                "testfiles/generated.bed.gz.gzi",
+                Format::Plain,
+                Payload::Unknown,
+            ),
+        ] {
+            let sniffed = sniff(File::open(path)?)?;
+            assert_eq!(sniffed, Sniffed { format, payload }, "{}", path);
+        }
+
+        // BAM and BCF headers
+        let mut bam = BAM_MAGIC.to_vec();
+        let header_text = b"@HD\tVN:1.6\tSO:coordinate\n@SQ\tSN:chr1\tLN:248956422\n";
+        bam.extend_from_slice(&(header_text.len() as u32).to_le_bytes());
+        bam.extend_from_slice(header_text);
+        bam.extend_from_slice(&1u32.to_le_bytes());
+        let sniffed = sniff(&bgzip(&bam)?[..])?;
+        assert_eq!(sniffed.format, Format::Bgzf);
+        assert_eq!(sniffed.payload, Payload::Bam);
+
+        let mut bcf = BCF_MAGIC.to_vec();
+        bcf.push(2);
+        let header_text =
+            b"##fileformat=VCFv4.2\n#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n\0";
+        bcf.extend_from_slice(&(header_text.len() as u32).to_le_bytes());
+        bcf.extend_from_slice(header_text);
+        assert_eq!(sniff(&bgzip(&bcf)?[..])?.payload, Payload::Bcf);
+
+        // the first block is empty
+        let mut compressed = crate::EOF_MARKER.to_vec();
+        compressed.extend_from_slice(&bgzip(&bam)?);
+        assert_eq!(sniff(&compressed[..])?.payload, Payload::Bam);
+
+        assert_eq!(
+            sniff(&bgzip(b"")?[..])?,
+            Sniffed {
+                format: Format::Bgzf,
+                payload: Payload::Unknown
+            }
+        );
+        assert_eq!(
+            sniff(&b""[..])?,
+            Sniffed {
+                format: Format::Plain,
+                payload: Payload::Unknown
+            }
+        );
+
+        let mut broken = bgzip(b"##fileformat=VCFv4.2\n")?;
+        broken[20] ^= 0xff;
+        assert!(sniff(&broken[..]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sniff_payload() {
+        assert_eq!(sniff_payload(b"BAM\x01\x00\x00\x00\x00"), Payload::Bam);
+        assert_eq!(sniff_payload(b"BAM\x02"), Payload::Unknown);
+        assert_eq!(sniff_payload(b"BCF\x02\x01"), Payload::Bcf);
+        assert_eq!(sniff_payload(b"##fileformat=VCFv4.3\n"), Payload::Vcf);
+        assert_eq!(sniff_payload(b"chr1\t100\t200\n"), Payload::Text);
+        assert_eq!(sniff_payload("chr1\tあ".as_bytes()), Payload::Text);
+        // cut in the middle of a character
+        assert_eq!(sniff_payload(&"chr1\tあ".as_bytes()[..6]), Payload::Text);
+        assert_eq!(sniff_payload(b"chr1\0"), Payload::Unknown);
+        assert_eq!(sniff_payload(b"\xff\xfe"), Payload::Unknown);
+        assert_eq!(sniff_payload(b""), Payload::Unknown);
+    }
+}
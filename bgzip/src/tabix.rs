@@ -2,20 +2,26 @@ use crate::*;
 use std::collections::HashMap;
 use std::convert::TryInto;
 use std::i32;
-use std::io::{self, Read};
+use std::io::{self, Read, Seek, Write};
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct TabixChunk {
-    pub begin: u64,
-    pub end: u64,
+    pub begin: VirtualPosition,
+    pub end: VirtualPosition,
 }
 
 impl TabixChunk {
-    fn from_reader<R: Read + BinaryReader>(reader: &mut R) -> io::Result<Self> {
-        let begin = reader.read_le_u64()?;
-        let end = reader.read_le_u64()?;
+    pub(crate) fn from_reader<R: Read + BinaryReader>(reader: &mut R) -> io::Result<Self> {
+        let begin = reader.read_le_u64()?.into();
+        let end = reader.read_le_u64()?.into();
         Ok(TabixChunk { begin, end })
     }
+
+    pub(crate) fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&u64::from(self.begin).to_le_bytes())?;
+        writer.write_all(&u64::from(self.end).to_le_bytes())?;
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -40,6 +46,15 @@ impl TabixBin {
             chunks,
         })
     }
+
+    fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&self.bin.to_le_bytes())?;
+        writer.write_all(&self.number_of_chunk.to_le_bytes())?;
+        for one in &self.chunks {
+            one.write(writer)?;
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -47,11 +62,11 @@ pub struct TabixSequence {
     pub number_of_distinct_bin: i32,
     pub bins: HashMap<u32, TabixBin>,
     pub number_of_intervals: i32,
-    pub intervals: Vec<u64>,
+    pub intervals: Vec<VirtualPosition>,
 }
 
 impl TabixSequence {
-    fn from_reader<R: Read + BinaryReader>(reader: &mut R) -> io::Result<Self> {
+    pub(crate) fn from_reader<R: Read + BinaryReader>(reader: &mut R) -> io::Result<Self> {
         let number_of_distinct_bin = reader.read_le_i32()?;
         let mut bins = HashMap::new();
         for _ in 0..number_of_distinct_bin {
@@ -63,7 +78,7 @@ impl TabixSequence {
 
         let mut intervals = Vec::new();
         for _ in 0..number_of_intervals {
-            intervals.push(reader.read_le_u64()?);
+            intervals.push(reader.read_le_u64()?.into());
         }
         Ok(TabixSequence {
             number_of_distinct_bin,
@@ -72,8 +87,69 @@ impl TabixSequence {
             intervals,
         })
     }
+
+    /// Write bins and intervals. Bins are sorted by bin number.
+    pub(crate) fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&self.number_of_distinct_bin.to_le_bytes())?;
+        let mut bins: Vec<_> = self.bins.values().collect();
+        bins.sort_by_key(|x| x.bin);
+        for one in bins {
+            one.write(writer)?;
+        }
+        writer.write_all(&self.number_of_intervals.to_le_bytes())?;
+        for one in &self.intervals {
+            writer.write_all(&u64::from(*one).to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// List chunks which may contain records overlapped with region [begin,end) (zero-based).
+    ///
+    /// Returned chunks are sorted and merged.
+    pub fn query(&self, begin: u64, end: u64) -> Vec<TabixChunk> {
+        let min_offset = if self.intervals.is_empty() {
+            VirtualPosition::default()
+        } else {
+            let i: usize = (begin >> TABIX_MIN_SHIFT).try_into().unwrap_or(usize::MAX);
+            self.intervals[i.min(self.intervals.len() - 1)]
+        };
+
+        let chunks: Vec<TabixChunk> =
+            crate::csi::reg2bins(begin, end, TABIX_MIN_SHIFT, TABIX_DEPTH)
+                .expect("Unreachable")
+                .iter()
+                .filter_map(|x| self.bins.get(x))
+                .flat_map(|x| x.chunks.iter())
+                .filter(|x| x.end > min_offset)
+                .cloned()
+                .collect();
+        merge_chunks(chunks)
+    }
 }
 
+/// Sort chunks and merge overlapped or adjacent chunks.
+///
+/// Chunks of several queries, including [`crate::csi::CSIIndex::query`] and [`crate::bai::BAI::query`],
+/// can be merged to read each block only once.
+pub fn merge_chunks(mut chunks: Vec<TabixChunk>) -> Vec<TabixChunk> {
+    chunks.sort_by_key(|x| x.begin);
+
+    let mut result: Vec<TabixChunk> = Vec::new();
+    for one in chunks {
+        if let Some(last) = result.last_mut() {
+            if one.begin <= last.end {
+                last.end = last.end.max(one.end);
+                continue;
+            }
+        }
+        result.push(one);
+    }
+    result
+}
+
+/// Magic number of tabix index file
+pub const TABIX_MAGIC: [u8; 4] = [b'T', b'B', b'I', 1];
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Tabix {
     pub number_of_references: i32,
@@ -86,6 +162,7 @@ pub struct Tabix {
     pub length_of_concatenated_sequence_names: i32,
     pub names: Vec<Vec<u8>>,
     pub sequences: Vec<TabixSequence>,
+    pub number_of_unplaced_unmapped: Option<u64>,
 }
 
 impl Tabix {
@@ -94,7 +171,7 @@ impl Tabix {
 
         let mut buf: [u8; 4] = [0, 0, 0, 0];
         reader.read_exact(&mut buf)?;
-        if buf != [b'T', b'B', b'I', 1] {
+        if buf != TABIX_MAGIC {
             return Err(BGZFError::Other("Not Tabix format"));
         }
         let number_of_references = reader.read_le_i32()?;
@@ -115,6 +192,11 @@ impl Tabix {
         for _ in 0..number_of_references {
             sequences.push(TabixSequence::from_reader(&mut reader)?);
         }
+        let number_of_unplaced_unmapped = match reader.read_le_u64() {
+            Ok(x) => Some(x),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => None,
+            Err(e) => return Err(e.into()),
+        };
 
         Ok(Tabix {
             number_of_references,
@@ -127,11 +209,741 @@ impl Tabix {
             length_of_concatenated_sequence_names,
             names,
             sequences,
+            number_of_unplaced_unmapped,
+        })
+    }
+
+    /// Write .tbi index file into `writer` in BGZF format.
+    ///
+    /// Number of references and length of sequence names are calculated from `sequences` and `names`.
+    pub fn write<W: Write>(&self, writer: W) -> io::Result<()> {
+        let mut writer = crate::BGZFWriter::new(writer, crate::Compression::default());
+        writer.write_all(&TABIX_MAGIC)?;
+        let number_of_references: i32 = self.sequences.len().try_into().unwrap();
+        writer.write_all(&number_of_references.to_le_bytes())?;
+        for one in [
+            self.format,
+            self.column_for_sequence,
+            self.column_for_begin,
+            self.column_for_end,
+        ] {
+            writer.write_all(&one.to_le_bytes())?;
+        }
+        writer.write_all(&self.meta)?;
+        writer.write_all(&self.skip.to_le_bytes())?;
+        let name_bytes = self.names.concat();
+        let length_of_concatenated_sequence_names: i32 = name_bytes.len().try_into().unwrap();
+        writer.write_all(&length_of_concatenated_sequence_names.to_le_bytes())?;
+        writer.write_all(&name_bytes)?;
+        for one in &self.sequences {
+            one.write(&mut writer)?;
+        }
+        if let Some(unplaced) = self.number_of_unplaced_unmapped {
+            writer.write_all(&unplaced.to_le_bytes())?;
+        }
+        writer.close()?;
+        Ok(())
+    }
+
+    /// Fetch all lines overlapped with `region` from BGZF `data` loaded in memory.
+    ///
+    /// This is same as [`TabixReader::fetch`], but file paths and [`Seek`] are not required.
+    /// Trailing new line characters are removed.
+    pub fn fetch_slice(&self, data: &[u8], region: &Region) -> Result<Vec<Vec<u8>>, BGZFError> {
+        let mut reader = crate::read::BGZFReader::new(io::Cursor::new(data))?;
+        TabixRecords::new(&mut reader, self, region, VirtualPosition::default()).collect()
+    }
+
+    /// Find index of sequence `name`. `name` should not contain a trailing NUL character.
+    pub fn sequence_index(&self, name: &[u8]) -> Option<usize> {
+        self.names
+            .iter()
+            .position(|x| x.strip_suffix(&[0]).unwrap_or(x) == name)
+    }
+
+    /// Parse sequence name and region [begin,end) (zero-based) of a data line.
+    ///
+    /// `None` is returned for meta lines.
+    pub fn record_region<'a>(&self, line: &'a [u8]) -> Result<Option<RecordRegion<'a>>, BGZFError> {
+        if line.is_empty() || line[0] == self.meta[0] {
+            return Ok(None);
+        }
+        let columns: Vec<&[u8]> = line.split(|x| *x == b'\t').collect();
+        let column = |i: i32| -> Result<&'a [u8], BGZFError> {
+            TryInto::<usize>::try_into(i - 1)
+                .ok()
+                .and_then(|x| columns.get(x))
+                .copied()
+                .ok_or(BGZFError::Other("Too few columns in tabix record"))
+        };
+        let number = |data: &[u8]| -> Result<u64, BGZFError> {
+            std::str::from_utf8(data)?
+                .parse()
+                .map_err(|_| BGZFError::Other("Invalid position in tabix record"))
+        };
+
+        let name = column(self.column_for_sequence)?;
+        let mut begin = number(column(self.column_for_begin)?)?;
+        if self.format & TABIX_FORMAT_UCSC == 0 {
+            begin = begin.saturating_sub(1);
+        }
+        let end = match self.format & 0xffff {
+            TABIX_FORMAT_SAM => begin + cigar_reference_length(column(6)?).max(1),
+            TABIX_FORMAT_VCF => {
+                let mut end = begin + TryInto::<u64>::try_into(column(4)?.len()).unwrap();
+                if let Ok(info) = column(8) {
+                    for one in info.split(|x| *x == b';') {
+                        if let Some(value) = one.strip_prefix(b"END=") {
+                            if let Ok(value) = number(value) {
+                                end = value;
+                            }
+                        }
+                    }
+                }
+                end
+            }
+            _ => {
+                if self.column_for_end > 0 {
+                    number(column(self.column_for_end)?)?
+                } else {
+                    begin + 1
+                }
+            }
+        };
+
+        Ok(Some(RecordRegion {
+            sequence: name,
+            begin,
+            end: end.max(begin + 1),
+        }))
+    }
+}
+
+/// Sequence name and region of a tabix record. See [`Tabix::record_region`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecordRegion<'a> {
+    pub sequence: &'a [u8],
+    /// Zero-based begin position
+    pub begin: u64,
+    /// Zero-based end position (exclusive)
+    pub end: u64,
+}
+
+/// Flag of zero-based half-close-half-open coordinates in [`Tabix::format`]
+pub const TABIX_FORMAT_UCSC: i32 = 0x10000;
+/// Generic tab separated format in [`Tabix::format`]
+pub const TABIX_FORMAT_GENERIC: i32 = 0;
+/// SAM format in [`Tabix::format`]. The end position is calculated from CIGAR.
+pub const TABIX_FORMAT_SAM: i32 = 1;
+/// VCF format in [`Tabix::format`]. The end position is calculated from REF and INFO/END.
+pub const TABIX_FORMAT_VCF: i32 = 2;
+
+/// Length of reference sequence consumed by CIGAR string
+fn cigar_reference_length(cigar: &[u8]) -> u64 {
+    let mut length = 0;
+    let mut value = 0;
+    for one in cigar {
+        match one {
+            b'0'..=b'9' => value = value * 10 + u64::from(one - b'0'),
+            b'M' | b'D' | b'N' | b'=' | b'X' => {
+                length += value;
+                value = 0;
+            }
+            _ => value = 0,
+        }
+    }
+    length
+}
+
+/// Genomic region [begin,end) (zero-based, half-close-half-open)
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Region {
+    pub sequence: Vec<u8>,
+    pub begin: u64,
+    pub end: u64,
+}
+
+impl Region {
+    pub fn new<S: Into<Vec<u8>>>(sequence: S, begin: u64, end: u64) -> Self {
+        Region {
+            sequence: sequence.into(),
+            begin,
+            end,
+        }
+    }
+}
+
+/// Position to resume a paginated query of [`TabixReader::fetch_from`].
+///
+/// A token is just a virtual file offset, so it can be passed to clients as a string
+/// and a query can be resumed by another reader instance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ResumeToken {
+    pub bgzf_pos: VirtualPosition,
+}
+
+impl std::fmt::Display for ResumeToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", u64::from(self.bgzf_pos))
+    }
+}
+
+impl std::str::FromStr for ResumeToken {
+    type Err = BGZFError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse::<u64>()
+            .map(|bgzf_pos| ResumeToken {
+                bgzf_pos: bgzf_pos.into(),
+            })
+            .map_err(|_| BGZFError::Other("Invalid resume token"))
+    }
+}
+
+/// Default number of records in a page of [`TabixReader::fetch_from`]
+pub const DEFAULT_PAGE_SIZE: usize = 1000;
+
+/// Region query of tabix indexed BGZF text.
+pub struct TabixReader<R: Read + Seek> {
+    reader: crate::read::BGZFReader<R>,
+    tabix: Tabix,
+    page_size: usize,
+}
+
+impl<R: Read + Seek> TabixReader<R> {
+    pub fn new(reader: crate::read::BGZFReader<R>, tabix: Tabix) -> Self {
+        TabixReader {
+            reader,
+            tabix,
+            page_size: DEFAULT_PAGE_SIZE,
+        }
+    }
+
+    /// Set maximum number of records returned from [`TabixReader::fetch_from`].
+    pub fn with_page_size(mut self, page_size: usize) -> Self {
+        self.page_size = page_size.max(1);
+        self
+    }
+
+    /// Get tabix index of this reader.
+    pub fn tabix(&self) -> &Tabix {
+        &self.tabix
+    }
+
+    /// Fetch all lines overlapped with `region`. Trailing new line characters are removed.
+    pub fn fetch(&mut self, region: &Region) -> Result<Vec<Vec<u8>>, BGZFError> {
+        self.query(region).collect()
+    }
+
+    /// Iterate lines overlapped with `region` without loading all of them into memory.
+    /// Trailing new line characters are removed.
+    pub fn query(&mut self, region: &Region) -> TabixRecords<'_, R> {
+        TabixRecords::new(
+            &mut self.reader,
+            &self.tabix,
+            region,
+            VirtualPosition::default(),
+        )
+    }
+
+    /// Fetch one page of lines overlapped with `region`.
+    ///
+    /// Pass `None` as `resume_token` to fetch the first page, and pass the returned token to fetch the next page.
+    /// `None` is returned as the next token if no more lines are available.
+    pub fn fetch_from(
+        &mut self,
+        region: &Region,
+        resume_token: Option<ResumeToken>,
+    ) -> Result<(Vec<Vec<u8>>, Option<ResumeToken>), BGZFError> {
+        let start = resume_token.map(|x| x.bgzf_pos).unwrap_or_default();
+        let mut iter = TabixRecords::new(&mut self.reader, &self.tabix, region, start);
+        let mut records = Vec::new();
+        while let Some((pos, line)) = iter.next_record()? {
+            if records.len() >= self.page_size {
+                return Ok((records, Some(ResumeToken { bgzf_pos: pos })));
+            }
+            records.push(line);
+        }
+        Ok((records, None))
+    }
+
+    /// Fetch all lines overlapped with any of `regions` in a single sweep over the file.
+    ///
+    /// Chunks of all regions are merged with [`merge_chunks`], so each block is read at most once
+    /// even if regions are close to each other. Lines are returned once in file order, with indexes
+    /// of overlapped regions.
+    pub fn fetch_regions(&mut self, regions: &[Region]) -> Result<Vec<RegionRecord>, BGZFError> {
+        let mut regions_by_sequence: HashMap<&[u8], Vec<usize>> = HashMap::new();
+        let mut chunks = Vec::new();
+        for (i, region) in regions.iter().enumerate() {
+            if let Some(sequence_index) = self.tabix.sequence_index(&region.sequence) {
+                chunks.extend(self.tabix.sequences[sequence_index].query(region.begin, region.end));
+                regions_by_sequence
+                    .entry(&region.sequence)
+                    .or_default()
+                    .push(i);
+            }
+        }
+        for one in regions_by_sequence.values_mut() {
+            one.sort_by_key(|x| regions[*x].begin);
+        }
+
+        let mut records = Vec::new();
+        let mut line = Vec::new();
+        for chunk in merge_chunks(chunks) {
+            self.reader.bgzf_seek(chunk.begin)?;
+            loop {
+                match self.reader.next_bgzf_pos()? {
+                    Some(pos) if pos < chunk.end => (),
+                    _ => break,
+                }
+                line.clear();
+                io::BufRead::read_until(&mut self.reader, b'\n', &mut line)?;
+                while line.last() == Some(&b'\n') || line.last() == Some(&b'\r') {
+                    line.pop();
+                }
+                let record = match self.tabix.record_region(&line)? {
+                    Some(x) => x,
+                    None => continue,
+                };
+                let candidates = match regions_by_sequence.get(record.sequence) {
+                    Some(x) => x,
+                    None => continue,
+                };
+                let candidates =
+                    &candidates[..candidates.partition_point(|x| regions[*x].begin < record.end)];
+                let mut overlapped: Vec<usize> = candidates
+                    .iter()
+                    .filter(|x| regions[**x].end > record.begin)
+                    .copied()
+                    .collect();
+                if !overlapped.is_empty() {
+                    overlapped.sort_unstable();
+                    records.push(RegionRecord {
+                        regions: overlapped,
+                        line: line.clone(),
+                    });
+                }
+            }
+        }
+        Ok(records)
+    }
+}
+
+/// A line returned by [`TabixReader::fetch_regions`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegionRecord {
+    /// Indexes of all regions overlapped with this line, in ascending order
+    pub regions: Vec<usize>,
+    /// Line without trailing new line characters
+    pub line: Vec<u8>,
+}
+
+/// Iterator over lines overlapped with a region. Created by [`TabixReader::query`].
+pub struct TabixRecords<'a, R: Read + Seek> {
+    reader: &'a mut crate::read::BGZFReader<R>,
+    tabix: &'a Tabix,
+    region: Region,
+    chunks: std::vec::IntoIter<TabixChunk>,
+    chunk_end: Option<VirtualPosition>,
+    start: VirtualPosition,
+    sorted: bool,
+    line: Vec<u8>,
+}
+
+impl<'a, R: Read + Seek> TabixRecords<'a, R> {
+    fn new(
+        reader: &'a mut crate::read::BGZFReader<R>,
+        tabix: &'a Tabix,
+        region: &Region,
+        start: VirtualPosition,
+    ) -> Self {
+        let chunks: Vec<TabixChunk> = match tabix.sequence_index(&region.sequence) {
+            Some(i) => tabix.sequences[i]
+                .query(region.begin, region.end)
+                .into_iter()
+                .filter(|x| x.end > start)
+                .collect(),
+            None => Vec::new(),
+        };
+        TabixRecords {
+            reader,
+            tabix,
+            region: region.clone(),
+            chunks: chunks.into_iter(),
+            chunk_end: None,
+            start,
+            sorted: true,
+            line: Vec::new(),
+        }
+    }
+
+    /// Iterate lines overlapped with `region` in `chunks` calculated with another index, such as
+    /// [`crate::csi::CSIIndex::query`]. `tabix` is used to parse records.
+    ///
+    /// Records are assumed to be sorted like [`TabixReader::query`], so the iteration stops at the
+    /// first record beginning after `region`. Use [`TabixRecords::with_sorted`] to scan unsorted
+    /// records, for example a whole file without index.
+    pub fn with_chunks(
+        reader: &'a mut crate::read::BGZFReader<R>,
+        tabix: &'a Tabix,
+        region: &Region,
+        chunks: Vec<TabixChunk>,
+    ) -> Self {
+        TabixRecords {
+            reader,
+            tabix,
+            region: region.clone(),
+            chunks: chunks.into_iter(),
+            chunk_end: None,
+            start: VirtualPosition::default(),
+            sorted: true,
+            line: Vec::new(),
+        }
+    }
+
+    /// Set whether records are sorted. If `sorted` is `false`, all records in the chunks are
+    /// checked.
+    pub fn with_sorted(mut self, sorted: bool) -> Self {
+        self.sorted = sorted;
+        self
+    }
+
+    /// Read next overlapped line and its virtual file offset
+    fn next_record(&mut self) -> Result<Option<(VirtualPosition, Vec<u8>)>, BGZFError> {
+        loop {
+            let chunk_end = match self.chunk_end {
+                Some(x) => x,
+                None => match self.chunks.next() {
+                    Some(chunk) => {
+                        self.reader.bgzf_seek(chunk.begin.max(self.start))?;
+                        self.chunk_end = Some(chunk.end);
+                        chunk.end
+                    }
+                    None => return Ok(None),
+                },
+            };
+
+            let pos = match self.reader.next_bgzf_pos()? {
+                Some(pos) if pos < chunk_end => pos,
+                _ => {
+                    self.chunk_end = None;
+                    continue;
+                }
+            };
+            self.line.clear();
+            io::BufRead::read_until(self.reader, b'\n', &mut self.line)?;
+            while self.line.last() == Some(&b'\n') || self.line.last() == Some(&b'\r') {
+                self.line.pop();
+            }
+            if let Some(record) = self.tabix.record_region(&self.line)? {
+                if record.sequence != &self.region.sequence[..] {
+                    continue;
+                }
+                if record.begin >= self.region.end {
+                    if !self.sorted {
+                        continue;
+                    }
+                    self.chunks = Vec::new().into_iter();
+                    self.chunk_end = None;
+                    return Ok(None);
+                }
+                if record.end > self.region.begin {
+                    return Ok(Some((pos, self.line.clone())));
+                }
+            }
+        }
+    }
+}
+
+impl<'a, R: Read + Seek> Iterator for TabixRecords<'a, R> {
+    type Item = Result<Vec<u8>, BGZFError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_record()
+            .transpose()
+            .map(|x| x.map(|(_, line)| line))
+    }
+}
+
+impl TabixReader<std::fs::File> {
+    /// Open tabix indexed file. Index is loaded from `path` + `.tbi`.
+    pub fn from_path<P: AsRef<std::path::Path>>(path: P) -> Result<Self, BGZFError> {
+        let reader = crate::read::BGZFReader::new(std::fs::File::open(path.as_ref())?)?;
+        let mut index_path = path.as_ref().as_os_str().to_os_string();
+        index_path.push(".tbi");
+        let tabix = Tabix::from_reader(std::fs::File::open(index_path)?)?;
+        Ok(TabixReader::new(reader, tabix))
+    }
+}
+
+/// Configuration of tabix index, same as `tbx_conf_t` of htslib
+///
+/// Columns are one-based. Set `column_for_end` to zero if the text does not have end column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TabixConf {
+    /// [`TABIX_FORMAT_GENERIC`], [`TABIX_FORMAT_SAM`] or [`TABIX_FORMAT_VCF`], combined with
+    /// [`TABIX_FORMAT_UCSC`] for zero-based coordinates
+    pub format: i32,
+    pub column_for_sequence: i32,
+    pub column_for_begin: i32,
+    pub column_for_end: i32,
+    /// Lines starting with this character are skipped
+    pub meta_char: u8,
+    /// Number of first lines to skip
+    pub skip: i32,
+}
+
+/// Preset configurations of `tabix -p`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TabixPreset {
+    Gff,
+    Bed,
+    Sam,
+    Vcf,
+    Custom(TabixConf),
+}
+
+impl TabixPreset {
+    /// Configuration of this preset
+    pub fn conf(&self) -> TabixConf {
+        let conf =
+            |format, column_for_sequence, column_for_begin, column_for_end, meta_char| TabixConf {
+                format,
+                column_for_sequence,
+                column_for_begin,
+                column_for_end,
+                meta_char,
+                skip: 0,
+            };
+        match self {
+            TabixPreset::Gff => conf(TABIX_FORMAT_GENERIC, 1, 4, 5, b'#'),
+            TabixPreset::Bed => conf(TABIX_FORMAT_GENERIC | TABIX_FORMAT_UCSC, 1, 2, 3, b'#'),
+            TabixPreset::Sam => conf(TABIX_FORMAT_SAM, 3, 4, 0, b'@'),
+            TabixPreset::Vcf => conf(TABIX_FORMAT_VCF, 1, 2, 0, b'#'),
+            TabixPreset::Custom(conf) => *conf,
+        }
+    }
+}
+
+/// Builder of tabix index for BGZF compressed text
+///
+/// Lines must be grouped by sequence and sorted by begin position within each sequence.
+/// Pass each line with [`TabixIndexBuilder::write_line`] to write it into [`BGZFWriter`], or with
+/// [`TabixIndexBuilder::add_line`] if virtual file offsets are known.
+///
+/// ```rust
+/// use bgzip::tabix::TabixIndexBuilder;
+/// use bgzip::{BGZFWriter, Compression};
+///
+/// let mut data = Vec::new();
+/// let mut writer = BGZFWriter::new(&mut data, Compression::default());
+/// let mut builder = TabixIndexBuilder::bed();
+/// builder.write_line(&mut writer, b"chr1\t100\t200\tfeature1")?;
+/// builder.write_line(&mut writer, b"chr2\t10\t20\tfeature2")?;
+/// writer.close()?;
+///
+/// let tabix = builder.finish();
+/// assert_eq!(tabix.sequence_index(b"chr2"), Some(1));
+/// let mut index = Vec::new();
+/// tabix.write(&mut index)?;
+/// # Ok::<(), bgzip::BGZFError>(())
+/// ```
+#[derive(Debug)]
+pub struct TabixIndexBuilder {
+    tabix: Tabix,
+    builder: crate::bai::BAIBuilder,
+    current_sequence: Option<usize>,
+    last_begin: u64,
+    number_of_lines: u64,
+}
+
+impl TabixIndexBuilder {
+    /// Create a builder for tab separated text with one-based sequence, begin and end columns.
+    ///
+    /// Set `column_for_end` to zero if the text does not have end column.
+    /// Coordinates are one-based and closed, and lines starting with `#` are skipped by default.
+    pub fn new(column_for_sequence: i32, column_for_begin: i32, column_for_end: i32) -> Self {
+        Self::with_conf(TabixConf {
+            format: TABIX_FORMAT_GENERIC,
+            column_for_sequence,
+            column_for_begin,
+            column_for_end,
+            meta_char: b'#',
+            skip: 0,
         })
     }
+
+    /// Create a builder with configuration of `preset`
+    pub fn with_preset(preset: TabixPreset) -> Self {
+        Self::with_conf(preset.conf())
+    }
+
+    /// Create a builder with `conf`
+    pub fn with_conf(conf: TabixConf) -> Self {
+        TabixIndexBuilder {
+            tabix: Tabix {
+                number_of_references: 0,
+                format: conf.format,
+                column_for_sequence: conf.column_for_sequence,
+                column_for_begin: conf.column_for_begin,
+                column_for_end: conf.column_for_end,
+                meta: [conf.meta_char, 0, 0, 0],
+                skip: conf.skip,
+                length_of_concatenated_sequence_names: 0,
+                names: Vec::new(),
+                sequences: Vec::new(),
+                number_of_unplaced_unmapped: None,
+            },
+            builder: crate::bai::BAIBuilder::new(0),
+            current_sequence: None,
+            last_begin: 0,
+            number_of_lines: 0,
+        }
+    }
+
+    /// Same configuration as `tabix -p gff`
+    pub fn gff() -> Self {
+        Self::with_preset(TabixPreset::Gff)
+    }
+
+    /// Same configuration as `tabix -p bed`
+    pub fn bed() -> Self {
+        Self::with_preset(TabixPreset::Bed)
+    }
+
+    /// Same configuration as `tabix -p sam`
+    pub fn sam() -> Self {
+        Self::with_preset(TabixPreset::Sam)
+    }
+
+    /// Same configuration as `tabix -p vcf`
+    pub fn vcf() -> Self {
+        Self::with_preset(TabixPreset::Vcf)
+    }
+
+    /// Use zero-based half-close-half-open coordinates if `true`, or one-based closed coordinates if `false`.
+    pub fn with_zero_based(mut self, zero_based: bool) -> Self {
+        if zero_based {
+            self.tabix.format |= TABIX_FORMAT_UCSC;
+        } else {
+            self.tabix.format &= !TABIX_FORMAT_UCSC;
+        }
+        self
+    }
+
+    /// Skip lines starting with `meta_char`
+    pub fn with_meta_char(mut self, meta_char: u8) -> Self {
+        self.tabix.meta = [meta_char, 0, 0, 0];
+        self
+    }
+
+    /// Skip first `skip` lines
+    pub fn with_skip_lines(mut self, skip: i32) -> Self {
+        self.tabix.skip = skip;
+        self
+    }
+
+    /// Write `line` and a new line character into `writer`, and add the line to the index.
+    ///
+    /// `line` should not contain a trailing new line character. If the line is out of order,
+    /// [`BGZFError::UnsortedRecord`] is returned after the line is written.
+    pub fn write_line<W: Write>(
+        &mut self,
+        writer: &mut crate::BGZFWriter<W>,
+        line: &[u8],
+    ) -> Result<(), BGZFError> {
+        let begin_voffset = writer.bgzf_pos();
+        writer.write_all(line)?;
+        writer.write_all(b"\n")?;
+        self.add_line(line, begin_voffset, writer.bgzf_pos())
+    }
+
+    /// Add a line located between `begin_voffset` and `end_voffset` (BGZF virtual file offsets).
+    ///
+    /// A trailing new line character in `line` is ignored. [`BGZFError::UnsortedRecord`] is returned
+    /// if the sequence of the line appeared before another sequence, or the begin position is
+    /// smaller than the previous line of the same sequence.
+    pub fn add_line(
+        &mut self,
+        line: &[u8],
+        begin_voffset: VirtualPosition,
+        end_voffset: VirtualPosition,
+    ) -> Result<(), BGZFError> {
+        self.number_of_lines += 1;
+        if self.number_of_lines <= self.tabix.skip.try_into().unwrap_or(0) {
+            return Ok(());
+        }
+        let mut line = line;
+        while let Some(x) = line
+            .strip_suffix(b"\n")
+            .or_else(|| line.strip_suffix(b"\r"))
+        {
+            line = x;
+        }
+        let record = match self.tabix.record_region(line)? {
+            Some(x) => x,
+            None => return Ok(()),
+        };
+
+        let unsorted = || BGZFError::UnsortedRecord {
+            line_number: self.number_of_lines,
+            sequence: String::from_utf8_lossy(record.sequence).into_owned(),
+            begin: record.begin,
+        };
+        let reference_id = match self.current_sequence {
+            Some(i) if self.tabix.names[i].strip_suffix(&[0]) == Some(record.sequence) => {
+                if record.begin < self.last_begin {
+                    return Err(unsorted());
+                }
+                i
+            }
+            _ => {
+                let i = match self.tabix.sequence_index(record.sequence) {
+                    Some(_) => return Err(unsorted()),
+                    None => {
+                        let mut name = record.sequence.to_vec();
+                        name.push(0);
+                        self.tabix.names.push(name);
+                        self.builder.add_reference()
+                    }
+                };
+                self.current_sequence = Some(i);
+                i
+            }
+        };
+        self.last_begin = record.begin;
+        self.builder.add_record(
+            &crate::bai::Coordinate {
+                reference_id: Some(reference_id),
+                begin: record.begin,
+                end: record.end,
+                mapped: true,
+            },
+            begin_voffset,
+            end_voffset,
+        )
+    }
+
+    /// Finish building and create [`Tabix`] index.
+    pub fn finish(self) -> Tabix {
+        let bai = self.builder.finish();
+        let mut tabix = self.tabix;
+        tabix.number_of_references = tabix.names.len().try_into().unwrap();
+        tabix.length_of_concatenated_sequence_names = tabix
+            .names
+            .iter()
+            .map(|x| x.len())
+            .sum::<usize>()
+            .try_into()
+            .unwrap();
+        tabix.sequences = bai.sequences;
+        tabix.number_of_unplaced_unmapped = bai.number_of_unplaced_unmapped;
+        tabix
+    }
 }
 
-fn split_names(data: &[u8]) -> Vec<Vec<u8>> {
+pub(crate) fn split_names(data: &[u8]) -> Vec<Vec<u8>> {
     let mut reader = io::BufReader::new(data);
     let mut result = Vec::new();
 
@@ -147,17 +959,16 @@ fn split_names(data: &[u8]) -> Vec<Vec<u8>> {
     result
 }
 
-const MIN_SHIFT: u32 = 14;
-const DEPTH: u32 = 5;
+use crate::csi::{TABIX_DEPTH, TABIX_MIN_SHIFT};
 
-/// calculate the list of bins that may overlap with region [beg,end) (zero-based)
+/// calculate bin given an alignment covering [beg,end) (zero-based, half-close-half-open)
 pub fn reg2bin(beg: u32, end: u32) -> u32 {
-    crate::csi::reg2bin(beg.into(), end.into(), MIN_SHIFT, DEPTH)
+    crate::csi::reg2bin(beg.into(), end.into(), TABIX_MIN_SHIFT, TABIX_DEPTH).expect("Unreachable")
 }
 
 /// calculate the list of bins that may overlap with region [beg,end) (zero-based)
 pub fn reg2bins(beg: u32, end: u32) -> Vec<u32> {
-    crate::csi::reg2bins(beg.into(), end.into(), MIN_SHIFT, DEPTH)
+    crate::csi::reg2bins(beg.into(), end.into(), TABIX_MIN_SHIFT, TABIX_DEPTH).expect("Unreachable")
 }
 
 #[cfg(test)]
@@ -202,6 +1013,154 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_tabix_write() -> anyhow::Result<()> {
+        for path in [
+            "testfiles/generated.bed.gz.tbi",
+            "testfiles/common_all_20180418_half.vcf.gz.tbi",
+        ] {
+            let tabix = Tabix::from_reader(File::open(path)?)?;
+            assert_eq!(tabix.number_of_unplaced_unmapped, Some(0));
+            let mut data = Vec::new();
+            tabix.write(&mut data)?;
+            assert_eq!(Tabix::from_reader(&data[..])?, tabix);
+
+            let mut expected = Vec::new();
+            flate2::read::MultiGzDecoder::new(File::open(path)?).read_to_end(&mut expected)?;
+            let mut decompressed = Vec::new();
+            flate2::read::MultiGzDecoder::new(&data[..]).read_to_end(&mut decompressed)?;
+            // bins are sorted in written data, but htslib does not sort them
+            let header_len = 36 + tabix.names.concat().len();
+            assert_eq!(decompressed.len(), expected.len());
+            assert!(decompressed[..header_len] == expected[..header_len]);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_tabix_builder() -> anyhow::Result<()> {
+        // same index as htslib
+        for (path, mut builder) in [
+            ("testfiles/generated.bed.gz", TabixIndexBuilder::bed()),
+            (
+                "testfiles/common_all_20180418_half.vcf.gz",
+                TabixIndexBuilder::vcf(),
+            ),
+        ] {
+            let mut reader = crate::read::BGZFReader::new(File::open(path)?)?;
+            let mut line = Vec::new();
+            loop {
+                io::BufRead::fill_buf(&mut reader)?;
+                let begin_voffset = reader.bgzf_pos();
+                line.clear();
+                if io::BufRead::read_until(&mut reader, b'\n', &mut line)? == 0 {
+                    break;
+                }
+                io::BufRead::fill_buf(&mut reader)?;
+                builder.add_line(&line, begin_voffset, reader.bgzf_pos())?;
+            }
+            let expected = Tabix::from_reader(File::open(format!("{}.tbi", path))?)?;
+            assert_eq!(builder.finish(), expected);
+        }
+
+        // write text and index
+        let original = Tabix::from_reader(File::open("testfiles/generated.bed.gz.tbi")?)?;
+        let mut writer = crate::BGZFWriter::new(
+            File::create("tmp/tabix-builder.bed.gz")?,
+            crate::Compression::default(),
+        );
+        let mut builder = TabixIndexBuilder::bed();
+        builder.write_line(&mut writer, b"#header")?;
+        let reader = io::BufReader::new(flate2::read::MultiGzDecoder::new(File::open(
+            "testfiles/generated.bed.gz",
+        )?));
+        let mut lines = Vec::new();
+        for line in io::BufRead::split(reader, b'\n') {
+            let line = line?;
+            builder.write_line(&mut writer, &line)?;
+            lines.push(line);
+        }
+        writer.close()?;
+        let tabix = builder.finish();
+        assert_eq!(tabix.names, original.names);
+        tabix.write(File::create("tmp/tabix-builder.bed.gz.tbi")?)?;
+
+        let mut reader = TabixReader::from_path("tmp/tabix-builder.bed.gz")?;
+        for region in [
+            Region::new("chr1", 100_000, 400_000),
+            Region::new("chr2", 0, 30_000),
+        ] {
+            let expected = brute_force_fetch("tmp/tabix-builder.bed.gz", &tabix, &region)?;
+            assert!(!expected.is_empty());
+            assert_eq!(reader.fetch(&region)?, expected);
+        }
+
+        // unsorted sequences and positions
+        let mut data = Vec::new();
+        let mut writer = crate::BGZFWriter::new(&mut data, crate::Compression::default());
+        let mut builder = TabixIndexBuilder::new(1, 2, 3).with_skip_lines(1);
+        builder.write_line(&mut writer, b"seq\tbegin\tend")?;
+        builder.write_line(&mut writer, &lines[0])?;
+        builder.write_line(&mut writer, &lines[2])?;
+        let e = builder.write_line(&mut writer, &lines[1]).unwrap_err();
+        assert!(matches!(
+            e,
+            BGZFError::UnsortedRecord { line_number: 4, ref sequence, .. } if sequence == "chr1"
+        ));
+        assert!(e.to_string().starts_with("line 4 is out of order (chr1:"));
+        let last = lines.last().unwrap();
+        builder.write_line(&mut writer, last)?;
+        assert!(matches!(
+            builder.write_line(&mut writer, &lines[0]),
+            Err(BGZFError::UnsortedRecord { line_number: 6, .. })
+        ));
+
+        // presets and custom configuration
+        for (preset, path) in [
+            (TabixPreset::Bed, "testfiles/generated.bed.gz.tbi"),
+            (
+                TabixPreset::Vcf,
+                "testfiles/common_all_20180418_half.vcf.gz.tbi",
+            ),
+        ] {
+            let expected = Tabix::from_reader(File::open(path)?)?;
+            let conf = preset.conf();
+            assert_eq!(conf.format, expected.format);
+            assert_eq!(conf.column_for_sequence, expected.column_for_sequence);
+            assert_eq!(conf.column_for_begin, expected.column_for_begin);
+            assert_eq!(conf.column_for_end, expected.column_for_end);
+            assert_eq!(conf.meta_char, expected.meta[0]);
+            assert_eq!(conf.skip, expected.skip);
+        }
+        assert_eq!(TabixPreset::Gff.conf().column_for_end, 5);
+        let conf = TabixConf {
+            column_for_sequence: 2,
+            column_for_begin: 3,
+            column_for_end: 0,
+            ..TabixPreset::Bed.conf()
+        };
+        let mut builder = TabixIndexBuilder::with_preset(TabixPreset::Custom(conf));
+        let mut data = Vec::new();
+        let mut writer = crate::BGZFWriter::new(&mut data, crate::Compression::default());
+        builder.write_line(&mut writer, b"a\tseq1\t10")?;
+        builder.write_line(&mut writer, b"b\tseq1\t20")?;
+        builder.write_line(&mut writer, b"c\tseq2\t5")?;
+        writer.close()?;
+        let tabix = builder.finish();
+        assert_eq!(tabix.format, TABIX_FORMAT_UCSC);
+        assert_eq!(tabix.sequence_index(b"seq2"), Some(1));
+        assert_eq!(
+            tabix.record_region(b"c\tseq2\t5")?,
+            Some(RecordRegion {
+                sequence: b"seq2",
+                begin: 5,
+                end: 6
+            })
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn test_bins() -> anyhow::Result<()> {
         let mut reader = csv::ReaderBuilder::new()
@@ -246,4 +1205,170 @@ mod test {
         }
         Ok(())
     }
+
+    fn brute_force_fetch(
+        path: &str,
+        tabix: &Tabix,
+        region: &Region,
+    ) -> anyhow::Result<Vec<Vec<u8>>> {
+        let reader = io::BufReader::new(flate2::read::MultiGzDecoder::new(File::open(path)?));
+        let mut result = Vec::new();
+        for line in io::BufRead::split(reader, b'\n') {
+            let mut line = line?;
+            if line.last() == Some(&b'\r') {
+                line.pop();
+            }
+            if let Some(record) = tabix.record_region(&line)? {
+                if record.sequence == &region.sequence[..]
+                    && record.begin < region.end
+                    && record.end > region.begin
+                {
+                    result.push(line);
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    #[test]
+    fn test_tabix_fetch() -> anyhow::Result<()> {
+        for (path, region) in [
+            (
+                "testfiles/generated.bed.gz",
+                Region::new("chr1", 100_000, 400_000),
+            ),
+            ("testfiles/generated.bed.gz", Region::new("chr2", 0, 30_000)),
+            (
+                "testfiles/common_all_20180418_half.vcf.gz",
+                Region::new("1", 1_000_000, 1_500_000),
+            ),
+            (
+                "testfiles/common_all_20180418_half.vcf.gz",
+                Region::new("3", 10_342_311, 10_342_312),
+            ),
+        ] {
+            let mut reader = TabixReader::from_path(path)?.with_page_size(7);
+            let expected = brute_force_fetch(path, reader.tabix(), &region)?;
+            assert!(!expected.is_empty());
+            assert_eq!(reader.fetch(&region)?, expected);
+            let tabix = Tabix::from_reader(&std::fs::read(format!("{}.tbi", path))?[..])?;
+            assert_eq!(tabix.fetch_slice(&std::fs::read(path)?, &region)?, expected);
+            let first_lines = reader
+                .query(&region)
+                .take(3)
+                .collect::<Result<Vec<_>, _>>()?;
+            assert_eq!(first_lines, &expected[..expected.len().min(3)]);
+
+            // scan whole file with precomputed chunk
+            let mut bgzf_reader = crate::read::BGZFReader::new(File::open(path)?)?;
+            let whole_file = vec![TabixChunk {
+                begin: VirtualPosition::default(),
+                end: VirtualPosition::from(u64::MAX),
+            }];
+            let scanned =
+                TabixRecords::with_chunks(&mut bgzf_reader, reader.tabix(), &region, whole_file)
+                    .with_sorted(false)
+                    .collect::<Result<Vec<_>, _>>()?;
+            assert_eq!(scanned, expected);
+
+            // resume with token string in new reader
+            let mut records = Vec::new();
+            let mut token: Option<ResumeToken> = None;
+            loop {
+                let mut reader = TabixReader::from_path(path)?.with_page_size(7);
+                let (page, next_token) = reader.fetch_from(&region, token)?;
+                assert!(page.len() <= 7);
+                records.extend(page);
+                match next_token {
+                    Some(t) => token = Some(t.to_string().parse()?),
+                    None => break,
+                }
+            }
+            assert_eq!(records, expected);
+        }
+
+        let mut reader = TabixReader::from_path("testfiles/generated.bed.gz")?;
+        assert!(reader.fetch(&Region::new("chrUnknown", 0, 100))?.is_empty());
+
+        // batched fetch of overlapped regions
+        let regions = vec![
+            Region::new("chr2", 0, 30_000),
+            Region::new("chr1", 100_000, 400_000),
+            Region::new("chrUnknown", 0, 100),
+            Region::new("chr1", 300_000, 500_000),
+            Region::new("chr1", 350_000, 360_000),
+        ];
+        let mut expected: Vec<(Vec<u8>, Vec<usize>)> = Vec::new();
+        for (i, region) in regions.iter().enumerate() {
+            for line in reader.fetch(region)? {
+                match expected.iter_mut().find(|x| x.0 == line) {
+                    Some(x) => x.1.push(i),
+                    None => expected.push((line, vec![i])),
+                }
+            }
+        }
+        let records = reader.fetch_regions(&regions)?;
+        assert_eq!(records.len(), expected.len());
+        for one in &records {
+            let found = expected.iter().find(|x| x.0 == one.line).unwrap();
+            assert_eq!(one.regions, found.1);
+        }
+        let chr1_records: Vec<_> = records
+            .iter()
+            .filter(|x| x.line.starts_with(b"chr1\t"))
+            .map(|x| x.line.clone())
+            .collect();
+        assert_eq!(
+            chr1_records,
+            reader.fetch(&Region::new("chr1", 100_000, 500_000))?
+        );
+        assert!(reader.fetch_regions(&[])?.is_empty());
+        assert_eq!(reader.query(&Region::new("chrUnknown", 0, 100)).count(), 0);
+        assert!("abc".parse::<ResumeToken>().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_record_region() -> anyhow::Result<()> {
+        let mut tabix =
+            Tabix::from_reader(File::open("testfiles/common_all_20180418_half.vcf.gz.tbi")?)?;
+        assert_eq!(tabix.sequence_index(b"2"), Some(1));
+        assert_eq!(tabix.sequence_index(b"chr2"), None);
+        assert_eq!(tabix.record_region(b"##fileformat=VCFv4.0")?, None);
+        assert_eq!(
+            tabix.record_region(b"1\t100\t.\tACG\tA\t.\t.\t.")?,
+            Some(RecordRegion {
+                sequence: b"1",
+                begin: 99,
+                end: 102
+            })
+        );
+        assert_eq!(
+            tabix.record_region(b"1\t100\t.\tA\t<DEL>\t.\t.\tSVTYPE=DEL;END=500")?,
+            Some(RecordRegion {
+                sequence: b"1",
+                begin: 99,
+                end: 500
+            })
+        );
+        assert!(tabix.record_region(b"1\tabc\t.\tA").is_err());
+
+        tabix.format = TABIX_FORMAT_SAM;
+        tabix.column_for_sequence = 3;
+        tabix.column_for_begin = 4;
+        tabix.column_for_end = 0;
+        tabix.meta = [b'@', 0, 0, 0];
+        assert_eq!(
+            tabix.record_region(b"r1\t0\tchr1\t100\t60\t5S10M2I3D4N2=1X\t*\t0\t0\tA\tA")?,
+            Some(RecordRegion {
+                sequence: b"chr1",
+                begin: 99,
+                end: 119
+            })
+        );
+        assert_eq!(tabix.record_region(b"@HD\tVN:1.6")?, None);
+
+        Ok(())
+    }
 }
@@ -0,0 +1,318 @@
+//! Deterministic generator of synthetic BGZF data for tests.
+//!
+//! This module is available with `testutil` feature. Downstream crates can create BGZF files with
+//! configurable number and size of blocks and intentional corruptions instead of shipping large
+//! binary fixtures.
+//!
+//! ```rust
+//! use bgzip::testutil::{Corruption, SyntheticBGZF};
+//! use std::io::Read;
+//!
+//! let data = SyntheticBGZF::new(42).with_block_count(3).generate();
+//! let mut decompressed = Vec::new();
+//! bgzip::BGZFReader::new(&data.compressed[..])
+//!     .unwrap()
+//!     .read_to_end(&mut decompressed)
+//!     .unwrap();
+//! assert_eq!(decompressed, data.uncompressed);
+//!
+//! let broken = SyntheticBGZF::new(42)
+//!     .with_corruption(Corruption::BadCrc { block: 1 })
+//!     .generate();
+//! assert!(bgzip::BGZFReader::new(&broken.compressed[..])
+//!     .unwrap()
+//!     .read_to_end(&mut Vec::new())
+//!     .is_err());
+//! ```
+
+use crate::deflate::{Compress, Compression};
+use crate::index::{BGZFIndex, BGZFIndexEntry};
+use std::convert::TryInto;
+
+/// Intentional corruption applied to generated data
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Corruption {
+    /// Flip bits of CRC32 of `block`
+    BadCrc { block: usize },
+    /// Flip bits of ISIZE of `block`
+    BadIsize { block: usize },
+    /// Flip bits in the middle of deflate payload of `block`
+    BadPayload { block: usize },
+    /// Break gzip magic number of `block`
+    BadHeader { block: usize },
+    /// Cut the data in the middle of `block`. Following blocks and end-of-file marker are removed.
+    Truncate { block: usize },
+}
+
+/// Generated data
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntheticData {
+    /// BGZF data with corruptions
+    pub compressed: Vec<u8>,
+    /// Original data
+    pub uncompressed: Vec<u8>,
+    /// Index of `compressed` before corruptions are applied
+    pub index: BGZFIndex,
+    /// Compressed offsets of blocks. End-of-file marker is not included.
+    pub block_offsets: Vec<u64>,
+}
+
+/// Builder of synthetic BGZF data
+///
+/// Same data is generated from the same configuration.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntheticBGZF {
+    seed: u64,
+    block_count: usize,
+    block_size: usize,
+    text: bool,
+    level: Compression,
+    eof_marker: bool,
+    corruptions: Vec<Corruption>,
+}
+
+impl SyntheticBGZF {
+    /// Create new generator with `seed`. Ten text blocks of 65280 bytes are generated by default.
+    pub fn new(seed: u64) -> Self {
+        SyntheticBGZF {
+            seed,
+            block_count: 10,
+            block_size: crate::write::DEFAULT_COMPRESS_UNIT_SIZE,
+            text: true,
+            level: Compression::default(),
+            eof_marker: true,
+            corruptions: Vec::new(),
+        }
+    }
+
+    /// Set number of data blocks
+    pub fn with_block_count(mut self, block_count: usize) -> Self {
+        self.block_count = block_count;
+        self
+    }
+
+    /// Set uncompressed size of each block. The size is truncated to [`crate::write::DEFAULT_COMPRESS_UNIT_SIZE`].
+    pub fn with_block_size(mut self, block_size: usize) -> Self {
+        self.block_size = block_size.min(crate::write::DEFAULT_COMPRESS_UNIT_SIZE);
+        self
+    }
+
+    /// Generate tab separated text lines if `true`, or random binary data if `false`.
+    pub fn with_text(mut self, text: bool) -> Self {
+        self.text = text;
+        self
+    }
+
+    /// Set compression level
+    pub fn with_compression(mut self, level: Compression) -> Self {
+        self.level = level;
+        self
+    }
+
+    /// Append end-of-file marker if `true`
+    pub fn with_eof_marker(mut self, eof_marker: bool) -> Self {
+        self.eof_marker = eof_marker;
+        self
+    }
+
+    /// Add a corruption. Corruptions for blocks which do not exist are ignored.
+    pub fn with_corruption(mut self, corruption: Corruption) -> Self {
+        self.corruptions.push(corruption);
+        self
+    }
+
+    /// Generate data
+    pub fn generate(&self) -> SyntheticData {
+        let mut random = SplitMix64(self.seed);
+        let uncompressed = if self.text {
+            random_text(&mut random, self.block_count * self.block_size)
+        } else {
+            (0..(self.block_count * self.block_size))
+                .map(|_| random.next() as u8)
+                .collect()
+        };
+
+        let mut compress = Compress::new(self.level);
+        let mut compressed = Vec::new();
+        let mut index = BGZFIndex::new();
+        let mut block_offsets: Vec<u64> = Vec::new();
+        let mut block_ends = Vec::new();
+        for (i, one) in uncompressed.chunks(self.block_size.max(1)).enumerate() {
+            block_offsets.push(compressed.len().try_into().unwrap());
+            crate::write::write_block(&mut compressed, one, &mut compress)
+                .expect("Unreachable (SyntheticBGZF)");
+            block_ends.push(compressed.len());
+            if i + 1 < self.block_count {
+                index.entries.push(BGZFIndexEntry {
+                    compressed_offset: compressed.len().try_into().unwrap(),
+                    uncompressed_offset: ((i + 1) * self.block_size).try_into().unwrap(),
+                });
+            }
+        }
+        if self.eof_marker {
+            compressed.extend_from_slice(&crate::EOF_MARKER);
+        }
+
+        let mut truncate_at = None;
+        for corruption in &self.corruptions {
+            let block = match corruption {
+                Corruption::BadCrc { block }
+                | Corruption::BadIsize { block }
+                | Corruption::BadPayload { block }
+                | Corruption::BadHeader { block }
+                | Corruption::Truncate { block } => *block,
+            };
+            let (start, end): (usize, usize) = match block_offsets.get(block) {
+                Some(start) => ((*start).try_into().unwrap(), block_ends[block]),
+                None => continue,
+            };
+            match corruption {
+                Corruption::BadCrc { .. } => compressed[end - 8] ^= 0xff,
+                Corruption::BadIsize { .. } => compressed[end - 4] ^= 0x01,
+                Corruption::BadPayload { .. } => {
+                    let header_size = usize::from(crate::header::BGZIP_HEADER_SIZE) - 8;
+                    compressed[(start + header_size + end - 8) / 2] ^= 0xff
+                }
+                Corruption::BadHeader { .. } => compressed[start] ^= 0xff,
+                Corruption::Truncate { .. } => {
+                    let position = (start + end) / 2;
+                    truncate_at = Some(truncate_at.map_or(position, |x: usize| x.min(position)));
+                }
+            }
+        }
+        if let Some(position) = truncate_at {
+            compressed.truncate(position);
+        }
+
+        SyntheticData {
+            compressed,
+            uncompressed,
+            index,
+            block_offsets,
+        }
+    }
+}
+
+/// Small deterministic random number generator
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+}
+
+/// Generate BED like text of `length` bytes
+fn random_text(random: &mut SplitMix64, length: usize) -> Vec<u8> {
+    const BASES: &[u8] = b"ACGT";
+    let mut data = Vec::with_capacity(length + 100);
+    let mut position = 0;
+    let mut line = 0;
+    while data.len() < length {
+        position += random.next() % 1000;
+        let width = random.next() % 500 + 1;
+        data.extend_from_slice(
+            format!("chr1\t{}\t{}\tentry{}\t", position, position + width, line).as_bytes(),
+        );
+        for _ in 0..(random.next() % 40 + 1) {
+            data.push(BASES[(random.next() % 4) as usize]);
+        }
+        data.push(b'\n');
+        line += 1;
+    }
+    data.truncate(length);
+    data
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::BGZFReader;
+    use std::io::{Read, Seek};
+
+    fn read_all(data: &[u8]) -> Result<Vec<u8>, crate::BGZFError> {
+        let mut result = Vec::new();
+        BGZFReader::new(data)?.read_to_end(&mut result)?;
+        Ok(result)
+    }
+
+    #[test]
+    fn test_generate() -> anyhow::Result<()> {
+        let data = SyntheticBGZF::new(1)
+            .with_block_count(5)
+            .with_block_size(10_000)
+            .generate();
+        assert_eq!(
+            data,
+            SyntheticBGZF::new(1)
+                .with_block_count(5)
+                .with_block_size(10_000)
+                .generate()
+        );
+        assert_ne!(
+            data.uncompressed,
+            SyntheticBGZF::new(2)
+                .with_block_count(5)
+                .with_block_size(10_000)
+                .generate()
+                .uncompressed
+        );
+        assert_eq!(data.uncompressed.len(), 50_000);
+        assert_eq!(data.block_offsets.len(), 5);
+        assert_eq!(data.index.entries().len(), 4);
+        assert!(data.compressed.ends_with(&crate::EOF_MARKER));
+        assert_eq!(read_all(&data.compressed)?, data.uncompressed);
+
+        let mut reader = crate::read::IndexedBGZFReader::new(
+            BGZFReader::new(std::io::Cursor::new(&data.compressed))?,
+            data.index.clone(),
+        )?;
+        reader.seek(std::io::SeekFrom::Start(25_000))?;
+        let mut buf = vec![0; 100];
+        reader.read_exact(&mut buf)?;
+        assert_eq!(buf, &data.uncompressed[25_000..25_100]);
+
+        let binary = SyntheticBGZF::new(1)
+            .with_text(false)
+            .with_block_count(1)
+            .generate();
+        assert_eq!(read_all(&binary.compressed)?, binary.uncompressed);
+
+        let empty = SyntheticBGZF::new(1).with_block_count(0).generate();
+        assert_eq!(empty.compressed, crate::EOF_MARKER);
+
+        let no_eof = SyntheticBGZF::new(1).with_eof_marker(false).generate();
+        assert!(!no_eof.compressed.ends_with(&crate::EOF_MARKER));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_corruptions() -> anyhow::Result<()> {
+        for corruption in [
+            Corruption::BadCrc { block: 2 },
+            Corruption::BadIsize { block: 2 },
+            Corruption::BadPayload { block: 2 },
+            Corruption::BadHeader { block: 2 },
+            Corruption::Truncate { block: 2 },
+        ] {
+            let data = SyntheticBGZF::new(3)
+                .with_block_count(4)
+                .with_corruption(corruption)
+                .generate();
+            assert!(read_all(&data.compressed).is_err(), "{:?}", corruption);
+        }
+
+        let data = SyntheticBGZF::new(3)
+            .with_block_count(4)
+            .with_corruption(Corruption::Truncate { block: 10 })
+            .generate();
+        assert_eq!(read_all(&data.compressed)?, data.uncompressed);
+
+        Ok(())
+    }
+}
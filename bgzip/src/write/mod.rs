@@ -1,21 +1,22 @@
 //! BGZF writer
 
-#[cfg(feature = "rayon")]
+#[cfg(feature = "threads")]
 mod thread;
 
-#[cfg(feature = "rayon")]
+#[cfg(feature = "threads")]
 pub use thread::BGZFMultiThreadWriter;
 
-use crate::header::BGZFHeader;
+use crate::header::{BGZFHeader, ExtraField};
 use crate::index::{BGZFIndex, BGZFIndexEntry};
-use crate::{deflate::*, BGZFError};
+use crate::metrics::{BlockOperation, Recorder};
+use crate::{deflate::*, BGZFError, VirtualPosition};
 use std::convert::TryInto;
-use std::io::{self, Write};
+use std::io::{self, Seek, Write};
 use std::path::Path;
 
 enum AdaptiveWriter<W: Write> {
     Plain(io::BufWriter<W>),
-    BGZF(BGZFWriter<W>),
+    BGZF(Box<BGZFWriter<W>>),
 }
 
 impl<W: Write> Write for AdaptiveWriter<W> {
@@ -44,10 +45,10 @@ pub fn create<P: AsRef<Path>>(path: P) -> io::Result<impl Write> {
         .map(|s| s == "gz")
         .unwrap_or(false)
     {
-        Ok(AdaptiveWriter::BGZF(BGZFWriter::new(
+        Ok(AdaptiveWriter::BGZF(Box::new(BGZFWriter::new(
             std::fs::File::create(path)?,
             Compression::default(),
-        )))
+        ))))
     } else {
         Ok(AdaptiveWriter::Plain(io::BufWriter::new(
             std::fs::File::create(path)?,
@@ -55,6 +56,148 @@ pub fn create<P: AsRef<Path>>(path: P) -> io::Result<impl Write> {
     }
 }
 
+/// Write an empty BGZF file, which contains only [`crate::EOF_MARKER`], into `writer`.
+pub fn write_empty<W: Write>(mut writer: W) -> io::Result<()> {
+    writer.write_all(&crate::EOF_MARKER)?;
+    writer.flush()
+}
+
+/// Output of a writer closed without writing any data
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum EmptyOutput {
+    /// Write only end-of-file marker (default)
+    #[default]
+    EofMarkerOnly,
+    /// Write an empty data block followed by end-of-file marker.
+    ///
+    /// Some readers reject a file which consists of end-of-file marker only.
+    EmptyBlock,
+}
+
+/// Behavior of [`std::io::Write::flush`] of [`BGZFWriter`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum FlushMode {
+    /// Write buffered data as a block and flush the underlying writer (default)
+    #[default]
+    FinishBlock,
+    /// Flush the underlying writer only, and keep buffered data until the compress unit is filled.
+    ///
+    /// Use [`BGZFWriter::finish_block`] to write a block boundary explicitly.
+    Defer,
+}
+
+/// Statistics of blocks written by [`BGZFWriter`] or [`BGZFMultiThreadWriter`]
+///
+/// The end-of-file marker written on close is not counted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct WriterStats {
+    /// Length of uncompressed data in written blocks
+    pub uncompressed_bytes: u64,
+    /// Length of written blocks including headers and footers
+    pub compressed_bytes: u64,
+    /// Number of written blocks
+    pub blocks: u64,
+    /// Total duration of compression. Durations in worker threads of [`BGZFMultiThreadWriter`]
+    /// are summed up. Blocks copied without recompression are not counted.
+    #[cfg(feature = "metrics")]
+    pub compress_time: std::time::Duration,
+}
+
+impl WriterStats {
+    /// `compressed_bytes / uncompressed_bytes`, or `None` if no data is written
+    pub fn compression_ratio(&self) -> Option<f64> {
+        if self.uncompressed_bytes == 0 {
+            return None;
+        }
+        Some(self.compressed_bytes as f64 / self.uncompressed_bytes as f64)
+    }
+
+    pub(crate) fn add_block(&mut self, uncompressed_size: u64, compressed_size: u64) {
+        self.uncompressed_bytes += uncompressed_size;
+        self.compressed_bytes += compressed_size;
+        self.blocks += 1;
+    }
+}
+
+/// Compress unit target adjusted with observed record sizes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct AdaptiveUnitSize {
+    average_record_size: usize,
+    target: usize,
+}
+
+impl AdaptiveUnitSize {
+    fn update(&mut self, record_size: usize, compress_unit_size: usize) {
+        self.average_record_size = if self.average_record_size == 0 {
+            record_size
+        } else {
+            (self.average_record_size * 7 + record_size) / 8
+        };
+        let average = self.average_record_size.max(1);
+        // a block is written when the next record of average size does not fit
+        self.target = if average >= compress_unit_size {
+            compress_unit_size
+        } else {
+            compress_unit_size - compress_unit_size % average
+        };
+    }
+}
+
+trait IndexSink: Write + Seek + Send {}
+
+impl<T: Write + Seek + Send> IndexSink for T {}
+
+/// .gzi index written into a file while compressing
+struct IndexStream {
+    writer: Box<dyn IndexSink>,
+    /// Position of the number of entries
+    start_pos: u64,
+    entry_count: u64,
+    /// The last entry, which is dropped on close unless the writer is a fragment writer
+    pending: Option<BGZFIndexEntry>,
+}
+
+impl IndexStream {
+    fn new(mut writer: Box<dyn IndexSink>) -> io::Result<Self> {
+        let start_pos = writer.stream_position()?;
+        writer.write_all(&0u64.to_le_bytes())?;
+        Ok(IndexStream {
+            writer,
+            start_pos,
+            entry_count: 0,
+            pending: None,
+        })
+    }
+
+    fn write_entry(&mut self, entry: BGZFIndexEntry) -> io::Result<()> {
+        self.writer
+            .write_all(&entry.compressed_offset.to_le_bytes())?;
+        self.writer
+            .write_all(&entry.uncompressed_offset.to_le_bytes())?;
+        self.entry_count += 1;
+        Ok(())
+    }
+
+    fn push(&mut self, entry: BGZFIndexEntry) -> io::Result<()> {
+        if let Some(pending) = self.pending.replace(entry) {
+            self.write_entry(pending)?;
+        }
+        Ok(())
+    }
+
+    /// Write the number of entries
+    fn finish(&mut self, keep_last_entry: bool) -> io::Result<()> {
+        if let (true, Some(pending)) = (keep_last_entry, self.pending.take()) {
+            self.write_entry(pending)?;
+        }
+        let end_pos = self.writer.stream_position()?;
+        self.writer.seek(io::SeekFrom::Start(self.start_pos))?;
+        self.writer.write_all(&self.entry_count.to_le_bytes())?;
+        self.writer.seek(io::SeekFrom::Start(end_pos))?;
+        self.writer.flush()
+    }
+}
+
 /// A BGZF writer
 pub struct BGZFWriter<W: io::Write> {
     writer: W,
@@ -63,9 +206,23 @@ pub struct BGZFWriter<W: io::Write> {
     compress: Compress,
     compress_unit_size: usize,
     closed: bool,
+    fragment: bool,
+    empty_output: EmptyOutput,
+    flush_mode: FlushMode,
+    file_comment: Option<Vec<u8>>,
+    /// Template of block headers
+    header: BGZFHeader,
+    adaptive_unit_size: Option<AdaptiveUnitSize>,
+    verify: Option<BlockVerifier>,
+    strict: bool,
+    /// Forbid time-dependent header fields
+    deterministic: bool,
+    recorder: Recorder,
+    stats: WriterStats,
     current_compressed_pos: u64,
     current_uncompressed_pos: u64,
     bgzf_index: Option<BGZFIndex>,
+    index_stream: Option<IndexStream>,
 }
 
 /// Default BGZF compress unit size
@@ -76,6 +233,84 @@ pub const MAXIMUM_COMPRESS_UNIT_SIZE: usize = 64 * 1024;
 
 pub(crate) const EXTRA_COMPRESS_BUFFER_SIZE: usize = 200;
 
+/// Bytes of a compressed block buffer in addition to uncompressed data.
+///
+/// A buffer of `original_data.len() + COMPRESSED_BLOCK_OVERHEAD` bytes is always large enough for
+/// [`write_block_into`], and [`write_block`] does not reallocate a buffer with this capacity.
+pub const COMPRESSED_BLOCK_OVERHEAD: usize =
+    EXTRA_COMPRESS_BUFFER_SIZE + crate::header::BGZIP_HEADER_SIZE as usize;
+
+/// Default number of compress units processed in one job of [`BGZFMultiThreadWriter`]
+pub(crate) const DEFAULT_WRITE_BLOCK_UNIT_NUM: usize = 50;
+
+/// Preset of compress unit size and number of compress units per job of [`BGZFMultiThreadWriter`]
+///
+/// Compress unit size of every preset is smaller than [`MAXIMUM_COMPRESS_UNIT_SIZE`], because
+/// uncompressed data of a block must be addressable by 16 bit offsets of virtual file offsets,
+/// and incompressible data must fit in a block after compression.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum BlockSizeProfile {
+    /// 65280 bytes per block, same as htslib. This is default.
+    #[default]
+    Htslib64K,
+    /// 16KiB per block. Random access decompresses less data, but compression ratio is slightly worse.
+    SmallLatency16K,
+    /// Same block size as htslib, but more blocks are compressed in one job of
+    /// [`BGZFMultiThreadWriter`] to reduce synchronization overhead on large inputs.
+    LargeThroughput,
+}
+
+impl BlockSizeProfile {
+    /// Size of uncompressed data in a block
+    pub fn compress_unit_size(self) -> usize {
+        match self {
+            BlockSizeProfile::Htslib64K | BlockSizeProfile::LargeThroughput => {
+                DEFAULT_COMPRESS_UNIT_SIZE
+            }
+            BlockSizeProfile::SmallLatency16K => 16 * 1024,
+        }
+    }
+
+    /// Number of compress units processed in one job of [`BGZFMultiThreadWriter`]
+    pub fn write_block_num(self) -> usize {
+        match self {
+            BlockSizeProfile::Htslib64K => DEFAULT_WRITE_BLOCK_UNIT_NUM,
+            BlockSizeProfile::SmallLatency16K => DEFAULT_WRITE_BLOCK_UNIT_NUM * 4,
+            BlockSizeProfile::LargeThroughput => DEFAULT_WRITE_BLOCK_UNIT_NUM * 4,
+        }
+    }
+}
+
+/// Check that `compress_unit_size` is between 1 and [`MAXIMUM_COMPRESS_UNIT_SIZE`] (exclusive)
+pub(crate) fn check_compress_unit_size(compress_unit_size: usize) -> Result<(), BGZFError> {
+    if compress_unit_size >= MAXIMUM_COMPRESS_UNIT_SIZE {
+        return Err(BGZFError::TooLargeCompressUnit);
+    }
+    if compress_unit_size == 0 {
+        return Err(BGZFError::Other("Compress unit size must not be zero"));
+    }
+    Ok(())
+}
+
+/// Maximum length of a file comment.
+///
+/// The comment is stored in the header of the first block, so the length is limited to keep
+/// the block smaller than 64KiB.
+pub const MAX_FILE_COMMENT_LEN: usize = 200;
+
+/// Validate a file comment and convert it into NUL terminated string
+pub(crate) fn file_comment(comment: &[u8]) -> Result<Vec<u8>, BGZFError> {
+    if comment.len() > MAX_FILE_COMMENT_LEN {
+        return Err(BGZFError::Other("Too long file comment"));
+    }
+    if comment.contains(&0) {
+        return Err(BGZFError::Other("File comment must not contain NUL"));
+    }
+    let mut result = comment.to_vec();
+    result.push(0);
+    Ok(result)
+}
+
 impl<W: io::Write> BGZFWriter<W> {
     /// Create new BGZF writer from [`std::io::Write`]
     pub fn new(writer: W, level: Compression) -> Self {
@@ -83,26 +318,42 @@ impl<W: io::Write> BGZFWriter<W> {
             .expect("Unreachable (BGZFWriter)")
     }
 
+    /// Create new BGZF writer with compress unit size of `profile`.
+    pub fn with_profile(writer: W, level: Compression, profile: BlockSizeProfile) -> Self {
+        Self::with_compress_unit_size(writer, level, profile.compress_unit_size(), true)
+            .expect("Unreachable (BGZFWriter)")
+    }
+
     /// Cerate new BGZF writer with compress unit size.
     ///
-    /// Default value of compress unit size is 65280.
+    /// Default value of compress unit size is 65280. [`BGZFError::TooLargeCompressUnit`] is
+    /// returned if `compress_unit_size` is not smaller than [`MAXIMUM_COMPRESS_UNIT_SIZE`].
     pub fn with_compress_unit_size(
         writer: W,
         level: Compression,
         compress_unit_size: usize,
         create_index: bool,
     ) -> Result<Self, BGZFError> {
-        if compress_unit_size >= crate::write::MAXIMUM_COMPRESS_UNIT_SIZE {
-            return Err(BGZFError::TooLargeCompressUnit);
-        }
+        check_compress_unit_size(compress_unit_size)?;
 
         Ok(BGZFWriter {
             writer,
             original_data: Vec::with_capacity(compress_unit_size),
-            compressed_buffer: Vec::with_capacity(compress_unit_size + EXTRA_COMPRESS_BUFFER_SIZE),
+            compressed_buffer: Vec::with_capacity(compress_unit_size + COMPRESSED_BLOCK_OVERHEAD),
             compress_unit_size,
             compress: Compress::new(level),
             closed: false,
+            fragment: false,
+            empty_output: EmptyOutput::default(),
+            flush_mode: FlushMode::default(),
+            file_comment: None,
+            header: BGZFHeader::new(false, 0, 0),
+            adaptive_unit_size: None,
+            verify: None,
+            strict: false,
+            deterministic: false,
+            recorder: Recorder::disabled(),
+            stats: WriterStats::default(),
             current_uncompressed_pos: 0,
             current_compressed_pos: 0,
             bgzf_index: if create_index {
@@ -110,14 +361,246 @@ impl<W: io::Write> BGZFWriter<W> {
             } else {
                 None
             },
+            index_stream: None,
         })
     }
 
+    /// Configure this writer as a fragment writer.
+    ///
+    /// A fragment writer does not write end-of-file marker and keeps the last index entry on close,
+    /// so outputs of several fragment writers can be concatenated and terminated with a single
+    /// [`crate::EOF_MARKER`] later. The last entry of returned index points to the end of the fragment.
+    pub fn with_fragment(mut self, fragment: bool) -> Self {
+        self.fragment = fragment;
+        self
+    }
+
+    /// Adjust compress unit target with sizes of records written with [`BGZFWriter::write_record`].
+    ///
+    /// The target is kept a multiple of the average record size and at most the compress unit
+    /// size, and a block is written when the target is reached. Blocks are kept close to full
+    /// while records rarely straddle block boundaries.
+    pub fn with_adaptive_unit_size(mut self, adaptive: bool) -> Self {
+        self.adaptive_unit_size = if adaptive {
+            Some(AdaptiveUnitSize {
+                average_record_size: 0,
+                target: self.compress_unit_size,
+            })
+        } else {
+            None
+        };
+        self
+    }
+
+    /// Current compress unit target. This value is equal to the compress unit size unless
+    /// [`BGZFWriter::with_adaptive_unit_size`] is enabled.
+    pub fn compress_unit_target(&self) -> usize {
+        self.adaptive_unit_size
+            .map(|x| x.target)
+            .unwrap_or(self.compress_unit_size)
+    }
+
+    /// Write `record` without splitting it across blocks if possible, and return the virtual
+    /// file offset of the start of the record.
+    ///
+    /// If `record` does not fit in the current block, [`BGZFWriter::finish_block`] is called first,
+    /// so a record either fits in one block or starts at a block boundary.
+    /// Records larger than the compress unit size are split into several blocks.
+    pub fn write_record(&mut self, record: &[u8]) -> io::Result<VirtualPosition> {
+        if let Some(adaptive) = self.adaptive_unit_size.as_mut() {
+            adaptive.update(record.len(), self.compress_unit_size);
+        }
+        if self.original_data.len() + record.len() > self.compress_unit_size {
+            self.finish_block()?;
+        }
+        let record_pos = self.bgzf_pos();
+        io::Write::write_all(self, record)?;
+        if !self.original_data.is_empty() && self.original_data.len() >= self.compress_unit_target()
+        {
+            self.write_block()?;
+        }
+        Ok(record_pos)
+    }
+
+    /// Write `comment` into FCOMMENT field of the first block.
+    ///
+    /// The comment can be read with [`crate::BGZFReader::file_comment`]. It must be at most
+    /// [`MAX_FILE_COMMENT_LEN`] bytes and must not contain NUL. If no data is written, an empty
+    /// block is written to hold the comment.
+    pub fn with_file_comment(mut self, comment: &[u8]) -> Result<Self, BGZFError> {
+        self.file_comment = Some(file_comment(comment)?);
+        self.check_header_size()?;
+        Ok(self)
+    }
+
+    /// Write `modified_time` (unix epoch) into MTIME field of every block header. Default is `0`.
+    ///
+    /// Writing data fails if non-zero `modified_time` is set in [`BGZFWriter::deterministic`] mode.
+    pub fn with_modified_time(mut self, modified_time: u32) -> Self {
+        self.header.modified_time = modified_time;
+        self
+    }
+
+    /// Write `operation_system` into OS field of every block header.
+    /// Default is [`crate::header::FILESYSTEM_UNKNOWN`].
+    pub fn with_operation_system(mut self, operation_system: u8) -> Self {
+        self.header.operation_system = operation_system;
+        self
+    }
+
+    /// Write `comment` into FCOMMENT field of every block header.
+    ///
+    /// A comment set with [`BGZFWriter::with_file_comment`] replaces this comment in the first block.
+    /// Comments and extra fields must fit in [`MAX_FILE_COMMENT_LEN`] bytes in total.
+    pub fn with_block_comment(mut self, comment: &[u8]) -> Result<Self, BGZFError> {
+        self.header.flags |= crate::header::FLAG_FCOMMENT;
+        self.header.comment = Some(file_comment(comment)?);
+        self.check_header_size()?;
+        Ok(self)
+    }
+
+    /// Add a gzip extra field written after BC field of every block header.
+    ///
+    /// Comments and extra fields must fit in [`MAX_FILE_COMMENT_LEN`] bytes in total.
+    pub fn with_extra_field(mut self, field: ExtraField) -> Result<Self, BGZFError> {
+        if field.id1() == 66 && field.id2() == 67 {
+            return Err(BGZFError::Other(
+                "BC extra field is reserved for block size",
+            ));
+        }
+        self.header.extra_field.push(field);
+        self.header.extra_field_len =
+            Some(self.header.extra_field.iter().map(|x| x.field_len()).sum());
+        self.check_header_size()?;
+        Ok(self)
+    }
+
+    /// Check that the header of the first block is small enough
+    fn check_header_size(&self) -> Result<(), BGZFError> {
+        let default_size = BGZFHeader::new(false, 0, 0).header_size();
+        let mut header_size = self.header.header_size();
+        if let Some(comment) = self.file_comment.as_ref() {
+            let block_comment_len = self.header.comment.as_ref().map(|x| x.len()).unwrap_or(0);
+            header_size = header_size + comment.len() as u64 - block_comment_len as u64;
+        }
+        // comments are stored with a terminating NUL
+        if header_size - default_size > (MAX_FILE_COMMENT_LEN + 1) as u64 {
+            return Err(BGZFError::Other("Too large block header"));
+        }
+        Ok(())
+    }
+
+    /// Pre-size internal buffers and index for `input_size` bytes of uncompressed data.
+    ///
+    /// This is only a hint to avoid reallocation. Writing more or less data is allowed.
+    pub fn with_size_hint(mut self, input_size: u64) -> Self {
+        let buffer_size = input_size.min(self.compress_unit_size.try_into().unwrap());
+        let buffer_size: usize = buffer_size.try_into().unwrap();
+        self.original_data.shrink_to(buffer_size);
+        self.compressed_buffer
+            .shrink_to(buffer_size + COMPRESSED_BLOCK_OVERHEAD);
+        if let Some(index) = self.bgzf_index.as_mut() {
+            index
+                .entries
+                .reserve(index_entries_for_size(input_size, self.compress_unit_size));
+        }
+        self
+    }
+
+    /// Select output when the writer is closed without writing any data.
+    ///
+    /// [`crate::write::BGZFMultiThreadWriter`] writes the same output with the same option.
+    pub fn with_empty_output(mut self, empty_output: EmptyOutput) -> Self {
+        self.empty_output = empty_output;
+        self
+    }
+
+    /// Write .gzi index entries into `index_writer` while blocks are written, instead of keeping them in memory.
+    ///
+    /// The number of entries at the beginning of .gzi is written on close, so `index_writer` must be seekable.
+    /// [`BGZFWriter::close`] returns `None` and [`Checkpoint::index`] is `None` with this option.
+    pub fn with_index_writer<I: Write + Seek + Send + 'static>(
+        mut self,
+        index_writer: I,
+    ) -> Result<Self, BGZFError> {
+        self.index_stream = Some(IndexStream::new(Box::new(index_writer))?);
+        self.bgzf_index = None;
+        Ok(self)
+    }
+
+    /// Select behavior of [`std::io::Write::flush`].
+    ///
+    /// With [`FlushMode::Defer`], frequent flushes, e.g. from [`std::io::BufWriter`] or line based protocols,
+    /// do not produce tiny blocks.
+    pub fn with_flush_mode(mut self, flush_mode: FlushMode) -> Self {
+        self.flush_mode = flush_mode;
+        self
+    }
+
+    /// Record compression duration of each block into `sink`.
+    #[cfg(feature = "metrics")]
+    pub fn with_histogram(
+        mut self,
+        sink: std::sync::Arc<dyn crate::metrics::HistogramSink>,
+    ) -> Self {
+        self.recorder = Recorder::new(sink);
+        self
+    }
+
+    /// Select DEFLATE [`Backend`] to compress blocks.
+    pub fn with_backend(mut self, backend: Backend) -> Self {
+        self.compress = Compress::with_backend(self.compress.level(), backend);
+        self
+    }
+
+    /// Verify each block before writing it.
+    ///
+    /// Compressed blocks are immediately decompressed and compared with original data with
+    /// [`verify_block`]. This option slows down compression, but it is useful to detect
+    /// backend bugs or memory corruption in critical archival jobs.
+    pub fn with_verify(mut self, verify: bool) -> Self {
+        self.verify = if verify {
+            Some(BlockVerifier::new())
+        } else {
+            None
+        };
+        self
+    }
+
+    /// Enforce block size constraints of the SAM specification.
+    ///
+    /// In strict mode, [`BGZFError::UncompressedBlockTooLarge`] is returned if a block holds more
+    /// than 65536 bytes of data, and [`BGZFError::BlockTooLarge`] is returned if a compressed block,
+    /// including header and footer, does not fit in 65536 bytes, for example because incompressible
+    /// data is written with a long file comment. Errors are wrapped in [`std::io::Error`] by
+    /// [`std::io::Write`] methods.
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Forbid time-dependent header fields for reproducible output.
+    ///
+    /// Output of BGZF writers only depends on written data, compression level, compress unit size,
+    /// DEFLATE backend and header options, and [`crate::write::BGZFMultiThreadWriter`] writes the
+    /// same bytes as this writer regardless of the number of threads. In deterministic mode, MTIME
+    /// is reset to `0` in every block including blocks copied with [`BGZFWriter::append_bgzf`],
+    /// and writing data fails with [`BGZFError::Other`] if non-zero MTIME is set with
+    /// [`BGZFWriter::with_modified_time`] later. [`Backend::Custom`] backends must be
+    /// deterministic by themselves.
+    pub fn deterministic(mut self) -> Self {
+        self.deterministic = true;
+        self.header.modified_time = 0;
+        self
+    }
+
     /// Get BGZF virtual file offset. This position is not equal to real file offset,
     /// but equal to virtual file offset described in [BGZF format](https://samtools.github.io/hts-specs/SAMv1.pdf).
     /// Please read "4.1.1 Random access" to learn more.       
-    pub fn bgzf_pos(&self) -> u64 {
-        self.current_compressed_pos << 16 | (self.original_data.len() & 0xffff) as u64
+    pub fn bgzf_pos(&self) -> VirtualPosition {
+        VirtualPosition::from(
+            self.current_compressed_pos << 16 | (self.original_data.len() & 0xffff) as u64,
+        )
     }
 
     /// Current write position.
@@ -125,31 +608,182 @@ impl<W: io::Write> BGZFWriter<W> {
         self.current_uncompressed_pos + TryInto::<u64>::try_into(self.original_data.len()).unwrap()
     }
 
+    /// Statistics of blocks written by this writer. Buffered data is not counted yet.
+    pub fn stats(&self) -> WriterStats {
+        self.stats
+    }
+
+    /// Write buffered data as a block, so the next data starts at a new block.
+    ///
+    /// Nothing is written if no data is buffered. The underlying writer is not flushed.
+    pub fn finish_block(&mut self) -> io::Result<()> {
+        if !self.original_data.is_empty() {
+            self.write_block()?;
+        }
+        Ok(())
+    }
+
     fn write_block(&mut self) -> io::Result<()> {
+        let original_data = std::mem::take(&mut self.original_data);
+        let result = self.compress_and_write(&original_data);
+        self.original_data = original_data;
+        result?;
+        self.original_data.clear();
+        Ok(())
+    }
+
+    /// Compress `original_data` as one block and write it
+    fn compress_and_write(&mut self, original_data: &[u8]) -> io::Result<()> {
+        if self.strict && original_data.len() > MAXIMUM_COMPRESS_UNIT_SIZE {
+            return Err(BGZFError::UncompressedBlockTooLarge {
+                size: original_data.len() as u64,
+            }
+            .into_io_error());
+        }
+        if self.deterministic && self.header.modified_time != 0 {
+            return Err(
+                BGZFError::Other("MTIME is not allowed in deterministic mode").into_io_error(),
+            );
+        }
         self.compressed_buffer.clear();
-        write_block(
-            &mut self.compressed_buffer,
-            &self.original_data,
-            &mut self.compress,
-        )
-        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+        // the template is used as is except for the first block with a file comment, so that
+        // extra fields are not cloned for every block
+        let mut first_header = None;
+        if let (0, Some(comment)) = (self.current_compressed_pos, self.file_comment.as_ref()) {
+            let mut header = self.header.clone();
+            header.flags |= crate::header::FLAG_FCOMMENT;
+            header.comment = Some(comment.clone());
+            first_header = Some(header);
+        }
+        let header = first_header.as_mut().unwrap_or(&mut self.header);
+        #[cfg(feature = "metrics")]
+        let start = std::time::Instant::now();
+        self.recorder
+            .record(BlockOperation::Compress, || {
+                write_block_with_header(
+                    &mut self.compressed_buffer,
+                    original_data,
+                    &mut self.compress,
+                    header,
+                )
+            })
+            .map_err(|e| match e {
+                CompressError::InsufficientSpace if self.strict => {
+                    BGZFError::BlockTooLarge.into_io_error()
+                }
+                e => std::io::Error::new(std::io::ErrorKind::Other, e),
+            })?;
+        #[cfg(feature = "metrics")]
+        {
+            self.stats.compress_time += start.elapsed();
+        }
+        if let Some(verifier) = self.verify.as_mut() {
+            verifier
+                .verify(&self.compressed_buffer, original_data)
+                .map_err(|e| e.into_io_error())?;
+        }
         self.writer.write_all(&self.compressed_buffer)?;
+        self.stats.add_block(
+            original_data.len().try_into().unwrap(),
+            self.compressed_buffer.len().try_into().unwrap(),
+        );
 
-        self.current_uncompressed_pos +=
-            TryInto::<u64>::try_into(self.original_data.len()).unwrap();
+        self.current_uncompressed_pos += TryInto::<u64>::try_into(original_data.len()).unwrap();
         self.current_compressed_pos +=
             TryInto::<u64>::try_into(self.compressed_buffer.len()).unwrap();
 
-        if let Some(index) = self.bgzf_index.as_mut() {
-            index.entries.push(BGZFIndexEntry {
-                compressed_offset: self.current_compressed_pos,
-                uncompressed_offset: self.current_uncompressed_pos,
-            });
+        self.push_index_entry()
+    }
+
+    /// Copy blocks of another BGZF stream read from `reader` without recompression.
+    ///
+    /// Buffered data is written as a block before copying. End-of-file markers and other empty
+    /// blocks in `reader` are skipped, so several BGZF files can be merged into one file.
+    /// Entries of the .gzi index are added for copied blocks. If nothing is written yet, a file comment
+    /// set with [`BGZFWriter::with_file_comment`] replaces the comment of the first copied block.
+    pub fn append_bgzf<R: io::Read>(&mut self, reader: R) -> Result<(), BGZFError> {
+        self.finish_block()?;
+        for block in crate::read::RawBlocks::new(reader) {
+            let block = block?;
+            if block.uncompressed_size() == 0 {
+                continue;
+            }
+            self.append_raw_block(block)?;
+        }
+        Ok(())
+    }
+
+    /// Write `block` as is after buffered data is written as a block.
+    pub(crate) fn append_raw_block(
+        &mut self,
+        mut block: crate::read::RawBlock,
+    ) -> Result<(), BGZFError> {
+        self.finish_block()?;
+        if self.deterministic {
+            block.header.modified_time = 0;
         }
+        if let (0, Some(comment)) = (self.current_compressed_pos, self.file_comment.as_ref()) {
+            // keep the file comment in the first block
+            block.header.flags |= crate::header::FLAG_FCOMMENT;
+            block.header.comment = Some(comment.clone());
+            let block_size = block
+                .compressed_size()
+                .try_into()
+                .map_err(|_| BGZFError::Other("File comment does not fit in the first block"))?;
+            block.header.update_block_size(block_size)?;
+        }
+        block.write(&mut self.writer)?;
+        self.stats
+            .add_block(block.uncompressed_size(), block.compressed_size());
+        self.current_uncompressed_pos += block.uncompressed_size();
+        self.current_compressed_pos += block.compressed_size();
+        self.push_index_entry()?;
+        Ok(())
+    }
 
+    fn push_index_entry(&mut self) -> io::Result<()> {
+        let entry = BGZFIndexEntry {
+            compressed_offset: self.current_compressed_pos,
+            uncompressed_offset: self.current_uncompressed_pos,
+        };
+        if let Some(index) = self.bgzf_index.as_mut() {
+            index.entries.push(entry);
+        }
+        if let Some(index_stream) = self.index_stream.as_mut() {
+            index_stream.push(entry)?;
+        }
         Ok(())
     }
 
+    /// Write buffered data, and an empty block if no data is written and it is requested with
+    /// [`BGZFWriter::with_empty_output`] or a file comment is set.
+    fn flush_last_block(&mut self) -> io::Result<()> {
+        let empty_block =
+            self.empty_output == EmptyOutput::EmptyBlock || self.file_comment.is_some();
+        if empty_block && self.pos() == 0 {
+            self.write_block()
+        } else {
+            self.finish_block()
+        }
+    }
+
+    /// Write buffered data as a block and return current state of the writer.
+    ///
+    /// The underlying writer is flushed before returning, so the output up to
+    /// [`Checkpoint::compressed_pos`] is complete. Pass the checkpoint to
+    /// [`BGZFWriter::resume_from_checkpoint`] to continue writing after a crash.
+    pub fn checkpoint(&mut self) -> io::Result<Checkpoint> {
+        self.finish_block()?;
+        self.writer.flush()?;
+        Ok(Checkpoint {
+            compressed_pos: self.current_compressed_pos,
+            uncompressed_pos: self.current_uncompressed_pos,
+            index: self.bgzf_index.clone(),
+            compress_unit_size: self.compress_unit_size,
+            backend: self.compress.backend(),
+        })
+    }
+
     /// Write end-of-file marker and close BGZF.
     ///
     /// Explicitly call of this method is not required unless you need .gzi index.
@@ -157,24 +791,150 @@ impl<W: io::Write> BGZFWriter<W> {
     /// If you need to handle I/O errors while closing, please use this method.
     pub fn close(mut self) -> io::Result<Option<BGZFIndex>> {
         if !self.closed {
-            self.flush()?;
-            self.writer.write_all(&crate::EOF_MARKER)?;
+            self.flush_last_block()?;
+            if !self.fragment {
+                self.writer.write_all(&crate::EOF_MARKER)?;
+            }
+            if let Some(index_stream) = self.index_stream.as_mut() {
+                index_stream.finish(self.fragment)?;
+            }
             self.closed = true;
         }
 
-        if let Some(index) = self.bgzf_index.as_mut() {
-            index.entries.pop();
+        if !self.fragment {
+            if let Some(index) = self.bgzf_index.as_mut() {
+                index.entries.pop();
+            }
         }
 
         Ok(self.bgzf_index.take())
     }
 }
 
+impl BGZFWriter<std::fs::File> {
+    /// Resume writing from [`Checkpoint`].
+    ///
+    /// `file` is truncated to [`Checkpoint::compressed_pos`], and new blocks are appended after it.
+    /// Only [`std::fs::File`] is supported, because the output written after the checkpoint must be
+    /// truncated. A .gzi index is created if the checkpoint has an index.
+    ///
+    /// Compress unit size and DEFLATE backend are restored from the checkpoint. Other options, such
+    /// as [`BGZFWriter::with_verify`] or [`BGZFWriter::with_file_comment`], are not restored.
+    /// Set them again on the returned writer if needed.
+    pub fn resume_from_checkpoint(
+        mut file: std::fs::File,
+        level: Compression,
+        checkpoint: &Checkpoint,
+    ) -> Result<Self, BGZFError> {
+        file.set_len(checkpoint.compressed_pos)?;
+        file.seek(io::SeekFrom::Start(checkpoint.compressed_pos))?;
+        let mut writer = Self::with_compress_unit_size(
+            file,
+            level,
+            checkpoint.compress_unit_size,
+            checkpoint.index.is_some(),
+        )?
+        .with_backend(checkpoint.backend.clone());
+        writer.current_compressed_pos = checkpoint.compressed_pos;
+        writer.current_uncompressed_pos = checkpoint.uncompressed_pos;
+        writer.bgzf_index = checkpoint.index.clone();
+        Ok(writer)
+    }
+
+    /// Open BGZF `file` to append data after existing data without rewriting it.
+    ///
+    /// `file` must be opened for both reading and writing. The end-of-file marker at the end of
+    /// `file` is verified and removed, and new blocks are written in its place.
+    /// [`BGZFError::TruncatedFile`] is returned if `file` does not end with end-of-file marker.
+    ///
+    /// If `index` of `file` is given, the length of existing data is restored by scanning only
+    /// blocks after the last entry, and the updated index is returned by [`BGZFWriter::close`].
+    /// Otherwise, all blocks are scanned and no index is created.
+    pub fn append(
+        mut file: std::fs::File,
+        level: Compression,
+        index: Option<BGZFIndex>,
+    ) -> Result<Self, BGZFError> {
+        let eof_marker_len = crate::EOF_MARKER.len() as u64;
+        let file_len = file.metadata()?.len();
+        if file_len < eof_marker_len {
+            return Err(BGZFError::TruncatedFile);
+        }
+        let mut eof_marker = [0; crate::EOF_MARKER.len()];
+        file.seek(io::SeekFrom::Start(file_len - eof_marker_len))?;
+        io::Read::read_exact(&mut file, &mut eof_marker)?;
+        if eof_marker != crate::EOF_MARKER {
+            return Err(BGZFError::TruncatedFile);
+        }
+        let data_len = file_len - eof_marker_len;
+
+        let mut entries = index.map(|x| x.entries);
+        let (mut compressed_pos, mut uncompressed_pos) = entries
+            .as_ref()
+            .and_then(|x| x.last())
+            .map(|x| (x.compressed_offset, x.uncompressed_offset))
+            .unwrap_or((0, 0));
+        if compressed_pos > data_len {
+            return Err(BGZFError::Other("index does not match the file"));
+        }
+        file.seek(io::SeekFrom::Start(compressed_pos))?;
+        let remaining = io::Read::take(&mut file, data_len - compressed_pos);
+        for block in crate::read::RawBlocks::new(remaining) {
+            let block = block?;
+            compressed_pos += block.compressed_size();
+            uncompressed_pos += block.uncompressed_size();
+            // entries at the end of each block, same as written by `push_index_entry`
+            if let Some(entries) = entries.as_mut() {
+                entries.push(BGZFIndexEntry {
+                    compressed_offset: compressed_pos,
+                    uncompressed_offset: uncompressed_pos,
+                });
+            }
+        }
+
+        Self::resume_from_checkpoint(
+            file,
+            level,
+            &Checkpoint {
+                compressed_pos,
+                uncompressed_pos,
+                index: entries.map(|entries| BGZFIndex { entries }),
+                compress_unit_size: DEFAULT_COMPRESS_UNIT_SIZE,
+                backend: Backend::default(),
+            },
+        )
+    }
+}
+
+/// State of [`BGZFWriter`] returned by [`BGZFWriter::checkpoint`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Checkpoint {
+    /// Length of compressed output
+    pub compressed_pos: u64,
+    /// Length of uncompressed data
+    pub uncompressed_pos: u64,
+    /// Snapshot of .gzi index
+    pub index: Option<BGZFIndex>,
+    /// Compress unit size of the writer
+    pub compress_unit_size: usize,
+    /// DEFLATE backend of the writer
+    pub backend: Backend,
+}
+
 impl<W: io::Write> io::Write for BGZFWriter<W> {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
         let mut process_start_pos = 0;
         loop {
             //eprintln!("process start pos: {}", process_start_pos);
+            if self.original_data.is_empty()
+                && buf.len() - process_start_pos >= self.compress_unit_size
+            {
+                // compress a whole unit directly from `buf` without copying it into the buffer
+                let process_end_pos = process_start_pos + self.compress_unit_size;
+                self.compress_and_write(&buf[process_start_pos..process_end_pos])?;
+                process_start_pos = process_end_pos;
+                continue;
+            }
             let to_write_bytes = (buf.len() - process_start_pos)
                 .min(self.compress_unit_size - self.original_data.len());
             if to_write_bytes == 0 {
@@ -184,32 +944,53 @@ impl<W: io::Write> io::Write for BGZFWriter<W> {
                 .extend_from_slice(&buf[process_start_pos..(process_start_pos + to_write_bytes)]);
             if self.original_data.len() >= self.compress_unit_size {
                 self.write_block()?;
-                self.original_data.clear();
             }
             process_start_pos += to_write_bytes;
         }
 
         Ok(buf.len())
     }
+    fn write_vectored(&mut self, bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
+        let mut wrote_bytes = 0;
+        for buf in bufs {
+            wrote_bytes += self.write(buf)?;
+        }
+        Ok(wrote_bytes)
+    }
     fn flush(&mut self) -> io::Result<()> {
-        if !self.original_data.is_empty() {
-            self.write_block()?;
+        if self.flush_mode == FlushMode::FinishBlock {
+            self.finish_block()?;
         }
-        Ok(())
+        self.writer.flush()
     }
 }
 
 impl<W: io::Write> Drop for BGZFWriter<W> {
     fn drop(&mut self) {
         if !self.closed {
-            self.flush().unwrap();
-            self.writer.write_all(&crate::EOF_MARKER).unwrap();
+            self.flush_last_block().unwrap();
+            if !self.fragment {
+                self.writer.write_all(&crate::EOF_MARKER).unwrap();
+            }
+            if let Some(index_stream) = self.index_stream.as_mut() {
+                index_stream.finish(self.fragment).unwrap();
+            }
             self.closed = true;
         }
     }
 }
 
-const FOOTER_SIZE: usize = 8;
+/// Number of index entries created while writing `input_size` bytes
+pub(crate) fn index_entries_for_size(input_size: u64, compress_unit_size: usize) -> usize {
+    let compress_unit_size: u64 = compress_unit_size.try_into().unwrap();
+    input_size
+        .div_ceil(compress_unit_size)
+        .try_into()
+        .unwrap_or(usize::MAX)
+}
+
+/// Length of CRC32 and ISIZE fields at the end of a block
+pub(crate) const FOOTER_SIZE: usize = 8;
 
 /// Write single BGZF block to writer.
 ///
@@ -219,9 +1000,46 @@ pub fn write_block(
     original_data: &[u8],
     compress: &mut Compress,
 ) -> Result<usize, CompressError> {
-    //eprintln!("write block : {} ", original_data.len());
-    let original_compressed_data_size = compressed_data.len();
+    write_block_with_comment(compressed_data, original_data, compress, None)
+}
+
+/// Write single BGZF block with NUL terminated FCOMMENT field.
+pub(crate) fn write_block_with_comment(
+    compressed_data: &mut Vec<u8>,
+    original_data: &[u8],
+    compress: &mut Compress,
+    comment: Option<&[u8]>,
+) -> Result<usize, CompressError> {
     let mut header = BGZFHeader::new(false, 0, 0);
+    if let Some(comment) = comment {
+        header.flags |= crate::header::FLAG_FCOMMENT;
+        header.comment = Some(comment.to_vec());
+    }
+    write_block_with_header(compressed_data, original_data, compress, &mut header)
+}
+
+/// Write single BGZF block into `compressed_data`, and return the size of the block.
+///
+/// Unlike [`write_block`], no memory is allocated. [`CompressError::InsufficientSpace`] is returned
+/// if `compressed_data` is too small to hold the block. See [`COMPRESSED_BLOCK_OVERHEAD`] for
+/// the size of a large enough buffer.
+pub fn write_block_into(
+    compressed_data: &mut [u8],
+    original_data: &[u8],
+    compress: &mut Compress,
+) -> Result<usize, CompressError> {
+    let mut header = BGZFHeader::new(false, 0, 0);
+    write_block_with_header_into(compressed_data, original_data, compress, &mut header)
+}
+
+/// Write single BGZF block with `header`. Block size in BC field of `header` is updated.
+pub(crate) fn write_block_with_header(
+    compressed_data: &mut Vec<u8>,
+    original_data: &[u8],
+    compress: &mut Compress,
+    header: &mut BGZFHeader,
+) -> Result<usize, CompressError> {
+    let original_compressed_data_size = compressed_data.len();
     let header_size: usize = header.header_size().try_into().unwrap();
     compressed_data.resize(
         original_compressed_data_size
@@ -231,39 +1049,136 @@ pub fn write_block(
             + FOOTER_SIZE,
         0,
     );
+    match write_block_with_header_into(
+        &mut compressed_data[original_compressed_data_size..],
+        original_data,
+        compress,
+        header,
+    ) {
+        Ok(block_size) => {
+            compressed_data.truncate(original_compressed_data_size + block_size);
+            Ok(block_size)
+        }
+        Err(e) => {
+            compressed_data.truncate(original_compressed_data_size);
+            Err(e)
+        }
+    }
+}
+
+/// Write single BGZF block with `header` at the beginning of `compressed_data`.
+fn write_block_with_header_into(
+    compressed_data: &mut [u8],
+    original_data: &[u8],
+    compress: &mut Compress,
+    header: &mut BGZFHeader,
+) -> Result<usize, CompressError> {
+    let header_size: usize = header.header_size().try_into().unwrap();
+    let Some(payload_end) = compressed_data
+        .len()
+        .checked_sub(FOOTER_SIZE)
+        .filter(|x| *x >= header_size)
+    else {
+        return Err(CompressError::InsufficientSpace);
+    };
 
     let compressed_len = compress.compress(
         original_data,
-        &mut compressed_data[(original_compressed_data_size + header_size)..],
+        &mut compressed_data[header_size..payload_end],
     )?;
-    compressed_data.truncate(original_compressed_data_size + header_size + compressed_len);
+    let footer_start = header_size + compressed_len;
+    let block_size = footer_start + FOOTER_SIZE;
+    // too large to be stored in BC field
+    let bc_block_size = block_size
+        .try_into()
+        .map_err(|_| CompressError::InsufficientSpace)?;
 
     let mut crc = Crc::new();
     crc.update(original_data);
+    compressed_data[footer_start..(footer_start + 4)].copy_from_slice(&crc.sum().to_le_bytes());
+    compressed_data[(footer_start + 4)..block_size]
+        .copy_from_slice(&(original_data.len() as u32).to_le_bytes());
 
-    compressed_data.extend_from_slice(&crc.sum().to_le_bytes());
-    compressed_data.extend_from_slice(&(original_data.len() as u32).to_le_bytes());
-
-    let block_size = compressed_data.len() - original_compressed_data_size;
-    //eprintln!("block size: {} / {}", block_size, original_data.len());
     header
-        .update_block_size(block_size.try_into().unwrap())
+        .update_block_size(bc_block_size)
         .expect("Unreachable");
-
     header
-        .write(
-            &mut compressed_data
-                [original_compressed_data_size..(header_size + original_compressed_data_size)],
-        )
+        .write(&mut compressed_data[..header_size])
         .expect("Failed to write header");
 
     Ok(block_size)
 }
 
-#[cfg(test)]
-mod test {
-    use crate::BGZFReader;
-    use crate::{deflate::Compression, BinaryReader};
+/// Decompress a block written by [`write_block`] and verify it with `original_data`.
+///
+/// CRC32 and ISIZE in the footer are checked while decompression.
+pub fn verify_block(
+    compressed_block: &[u8],
+    original_data: &[u8],
+    decompress: &mut Decompress,
+) -> Result<(), BGZFError> {
+    verify_block_with_buffers(
+        compressed_block,
+        original_data,
+        decompress,
+        &mut Vec::new(),
+        &mut Vec::with_capacity(original_data.len()),
+    )
+}
+
+fn verify_block_with_buffers(
+    compressed_block: &[u8],
+    original_data: &[u8],
+    decompress: &mut Decompress,
+    payload: &mut Vec<u8>,
+    decompressed_data: &mut Vec<u8>,
+) -> Result<(), BGZFError> {
+    crate::read::load_block(compressed_block, payload)?;
+    decompressed_data.clear();
+    crate::read::decompress_block(decompressed_data, payload, decompress)?;
+    if decompressed_data != original_data {
+        return Err(BGZFError::Other(
+            "Written block does not match original data",
+        ));
+    }
+    Ok(())
+}
+
+/// [`verify_block`] with buffers reused across blocks
+pub(crate) struct BlockVerifier {
+    decompress: Decompress,
+    payload: Vec<u8>,
+    decompressed_data: Vec<u8>,
+}
+
+impl BlockVerifier {
+    pub(crate) fn new() -> Self {
+        BlockVerifier {
+            decompress: Decompress::new(),
+            payload: Vec::new(),
+            decompressed_data: Vec::new(),
+        }
+    }
+
+    pub(crate) fn verify(
+        &mut self,
+        compressed_block: &[u8],
+        original_data: &[u8],
+    ) -> Result<(), BGZFError> {
+        verify_block_with_buffers(
+            compressed_block,
+            original_data,
+            &mut self.decompress,
+            &mut self.payload,
+            &mut self.decompressed_data,
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::BGZFReader;
+    use crate::{deflate::Compression, BinaryReader};
     use rand::prelude::*;
 
     use super::*;
@@ -307,6 +1222,65 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_empty() -> anyhow::Result<()> {
+        let mut data = Vec::new();
+        write_empty(&mut data)?;
+        assert_eq!(data, crate::EOF_MARKER);
+
+        let mut data = Vec::new();
+        let mut writer = BGZFWriter::new(&mut data, Compression::default());
+        writer.write_all(b"")?;
+        writer.flush()?;
+        let index = writer.close()?.unwrap();
+        assert!(index.entries().is_empty());
+        assert_eq!(data, crate::EOF_MARKER);
+
+        let mut data = Vec::new();
+        std::mem::drop(BGZFWriter::new(&mut data, Compression::default()));
+        assert_eq!(data, crate::EOF_MARKER);
+
+        let mut reader = BGZFReader::new(&data[..])?;
+        let mut read_data = Vec::new();
+        reader.read_to_end(&mut read_data)?;
+        assert!(read_data.is_empty());
+        reader.read_to_end(&mut read_data)?;
+        assert!(read_data.is_empty());
+
+        // an empty block before EOF marker
+        let mut data = Vec::new();
+        let writer = BGZFWriter::new(&mut data, Compression::default())
+            .with_empty_output(EmptyOutput::EmptyBlock);
+        let index = writer.close()?.unwrap();
+        assert!(index.entries().is_empty());
+        assert!(data.len() > crate::EOF_MARKER.len());
+        assert!(data.ends_with(&crate::EOF_MARKER));
+        let blocks = crate::read::RawBlocks::new(&data[..]).collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0].uncompressed_size(), 0);
+
+        let mut dropped = Vec::new();
+        std::mem::drop(
+            BGZFWriter::new(&mut dropped, Compression::default())
+                .with_empty_output(EmptyOutput::EmptyBlock),
+        );
+        assert_eq!(dropped, data);
+
+        let mut read_data = Vec::new();
+        BGZFReader::new(&data[..])?.read_to_end(&mut read_data)?;
+        assert!(read_data.is_empty());
+
+        // the option is ignored if any data is written
+        let mut data = Vec::new();
+        let mut writer = BGZFWriter::new(&mut data, Compression::default())
+            .with_empty_output(EmptyOutput::EmptyBlock);
+        writer.write_all(b"hello")?;
+        writer.close()?;
+        assert_eq!(crate::read::RawBlocks::new(&data[..]).count(), 2);
+
+        Ok(())
+    }
+
     #[test]
     fn test_write_bed() -> anyhow::Result<()> {
         const TEST_OUTPUT_PATH: &str = "tmp/test.bed.gz";
@@ -420,4 +1394,826 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn test_append() -> anyhow::Result<()> {
+        let mut data = Vec::new();
+        flate2::read::MultiGzDecoder::new(fs::File::open("testfiles/generated.bed.gz")?)
+            .read_to_end(&mut data)?;
+        let (first, second) = data.split_at(data.len() / 3);
+        let open = |path: &str| fs::OpenOptions::new().read(true).write(true).open(path);
+
+        for with_index in [false, true] {
+            let output_path = format!("tmp/test_append_{}.bed.gz", with_index);
+            let mut writer = BGZFWriter::with_compress_unit_size(
+                fs::File::create(&output_path)?,
+                Compression::default(),
+                DEFAULT_COMPRESS_UNIT_SIZE,
+                true,
+            )?;
+            writer.write_all(first)?;
+            let index = writer.close()?;
+
+            let mut writer = BGZFWriter::append(
+                open(&output_path)?,
+                Compression::default(),
+                index.filter(|_| with_index),
+            )?;
+            assert_eq!(writer.pos(), first.len() as u64);
+            writer.write_all(second)?;
+            let index = writer.close()?;
+
+            let mut wrote_data = Vec::new();
+            BGZFReader::new(fs::File::open(&output_path)?)?
+                .with_fail_on_missing_eof(true)
+                .read_to_end(&mut wrote_data)?;
+            assert_eq!(wrote_data, data);
+            if with_index {
+                let expected = BGZFIndex::from_bgzf_reader(fs::File::open(&output_path)?)?;
+                assert_eq!(index, Some(expected));
+            } else {
+                assert_eq!(index, None);
+            }
+        }
+
+        // append to an empty file
+        let output_path = "tmp/test_append_empty.bed.gz";
+        write_empty(fs::File::create(output_path)?)?;
+        let mut writer = BGZFWriter::append(
+            open(output_path)?,
+            Compression::default(),
+            Some(BGZFIndex::default()),
+        )?;
+        writer.write_all(first)?;
+        let index = writer.close()?;
+        assert_eq!(
+            index,
+            Some(BGZFIndex::from_bgzf_reader(fs::File::open(output_path)?)?)
+        );
+
+        // no end-of-file marker
+        let output_path = "tmp/test_append_fragment.bed.gz";
+        let mut writer = BGZFWriter::new(fs::File::create(output_path)?, Compression::default())
+            .with_fragment(true);
+        writer.write_all(first)?;
+        writer.close()?;
+        let result = BGZFWriter::append(open(output_path)?, Compression::default(), None);
+        assert!(matches!(result, Err(BGZFError::TruncatedFile)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_checkpoint() -> anyhow::Result<()> {
+        let mut data = Vec::new();
+        flate2::read::MultiGzDecoder::new(fs::File::open("testfiles/generated.bed.gz")?)
+            .read_to_end(&mut data)?;
+        let (first, second) = data.split_at(data.len() / 3);
+
+        let output_path = "tmp/test_checkpoint.bed.gz";
+        let mut writer = BGZFWriter::new(fs::File::create(output_path)?, Compression::default());
+        writer.write_all(first)?;
+        let checkpoint = writer.checkpoint()?;
+        assert_eq!(checkpoint.uncompressed_pos, first.len() as u64);
+        assert_eq!(checkpoint.compressed_pos, fs::metadata(output_path)?.len());
+        // simulate crash after checkpoint
+        writer.write_all(&second[..100_000])?;
+        std::mem::drop(writer);
+
+        let mut writer = BGZFWriter::resume_from_checkpoint(
+            fs::OpenOptions::new().write(true).open(output_path)?,
+            Compression::default(),
+            &checkpoint,
+        )?;
+        assert_eq!(writer.pos(), first.len() as u64);
+        writer.write_all(second)?;
+        let index = writer.close()?.unwrap();
+
+        let mut reader = flate2::read::MultiGzDecoder::new(fs::File::open(output_path)?);
+        let mut wrote_data = Vec::new();
+        reader.read_to_end(&mut wrote_data)?;
+        assert_eq!(wrote_data, data);
+
+        let mut reader = crate::read::IndexedBGZFReader::new(
+            BGZFReader::new(fs::File::open(output_path)?)?,
+            index,
+        )?;
+        let mut buffer = [0; 100];
+        for pos in [0, first.len() - 50, first.len(), data.len() - 100] {
+            reader.seek(io::SeekFrom::Start(pos as u64))?;
+            reader.read_exact(&mut buffer)?;
+            assert_eq!(&buffer[..], &data[pos..(pos + 100)]);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "flate2")]
+    fn test_checkpoint_settings() -> anyhow::Result<()> {
+        let mut data = Vec::new();
+        flate2::read::MultiGzDecoder::new(fs::File::open("testfiles/generated.bed.gz")?)
+            .read_to_end(&mut data)?;
+        let (first, second) = data.split_at(data.len() / 3);
+
+        // a custom backend is never selected by default
+        let backend = Backend::custom(Flate2Backend);
+        let mut expected = Vec::new();
+        let mut writer =
+            BGZFWriter::with_compress_unit_size(&mut expected, Compression::default(), 1000, true)?
+                .with_backend(backend.clone());
+        writer.write_all(first)?;
+        writer.finish_block()?;
+        writer.write_all(second)?;
+        let expected_index = writer.close()?;
+
+        let output_path = "tmp/test_checkpoint_settings.bed.gz";
+        let mut writer = BGZFWriter::with_compress_unit_size(
+            fs::File::create(output_path)?,
+            Compression::default(),
+            1000,
+            true,
+        )?
+        .with_backend(backend.clone());
+        writer.write_all(first)?;
+        let checkpoint = writer.checkpoint()?;
+        assert_eq!(checkpoint.compress_unit_size, 1000);
+        assert_eq!(checkpoint.backend, backend);
+        std::mem::drop(writer);
+        let mut writer = BGZFWriter::resume_from_checkpoint(
+            fs::OpenOptions::new().write(true).open(output_path)?,
+            Compression::default(),
+            &checkpoint,
+        )?;
+        assert_eq!(writer.compress.backend(), backend);
+        writer.write_all(second)?;
+        assert_eq!(writer.close()?, expected_index);
+        assert_eq!(fs::read(output_path)?, expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_writer() -> anyhow::Result<()> {
+        let mut data = Vec::new();
+        flate2::read::MultiGzDecoder::new(fs::File::open("testfiles/generated.bed.gz")?)
+            .read_to_end(&mut data)?;
+
+        for fragment in [false, true] {
+            let mut expected_output = Vec::new();
+            let mut writer = BGZFWriter::new(&mut expected_output, Compression::default())
+                .with_fragment(fragment);
+            writer.write_all(&data)?;
+            let expected_index = writer.close()?.unwrap();
+
+            let index_path = format!("tmp/test_index_writer_{}.gzi", fragment);
+            let mut output = Vec::new();
+            let mut writer = BGZFWriter::new(&mut output, Compression::default())
+                .with_fragment(fragment)
+                .with_index_writer(fs::File::create(&index_path)?)?;
+            writer.write_all(&data)?;
+            assert!(writer.checkpoint()?.index.is_none());
+            assert!(writer.close()?.is_none());
+            assert_eq!(output, expected_output);
+            let index = BGZFIndex::from_reader(fs::File::open(&index_path)?)?;
+            assert_eq!(index, expected_index);
+        }
+
+        // index is written on drop
+        let index_path = "tmp/test_index_writer_drop.gzi";
+        let mut output = Vec::new();
+        let mut writer =
+            BGZFWriter::with_compress_unit_size(&mut output, Compression::default(), 1000, false)?
+                .with_index_writer(fs::File::create(index_path)?)?;
+        writer.write_all(&data[..10_500])?;
+        std::mem::drop(writer);
+        let index = BGZFIndex::from_reader(fs::File::open(index_path)?)?;
+        assert_eq!(index.entries().len(), 10);
+        assert_eq!(index.uncompressed_size(io::Cursor::new(&output))?, 10_500);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_block_size_profile() -> anyhow::Result<()> {
+        let data = crate::testutil::SyntheticBGZF::new(3)
+            .with_block_count(3)
+            .generate()
+            .uncompressed;
+
+        for profile in [
+            BlockSizeProfile::Htslib64K,
+            BlockSizeProfile::SmallLatency16K,
+            BlockSizeProfile::LargeThroughput,
+        ] {
+            assert!(profile.compress_unit_size() < MAXIMUM_COMPRESS_UNIT_SIZE);
+            let mut compressed = Vec::new();
+            let mut writer =
+                BGZFWriter::with_profile(&mut compressed, Compression::fast(), profile);
+            writer.write_all(&data)?;
+            writer.close()?;
+            let blocks: Vec<_> =
+                crate::read::RawBlocks::new(&compressed[..]).collect::<Result<_, _>>()?;
+            assert_eq!(
+                blocks.len() - 1,
+                data.len().div_ceil(profile.compress_unit_size())
+            );
+            assert!(blocks
+                .iter()
+                .all(|x| x.uncompressed_size() <= profile.compress_unit_size() as u64));
+        }
+
+        assert!(matches!(
+            BGZFWriter::with_compress_unit_size(
+                Vec::new(),
+                Compression::fast(),
+                MAXIMUM_COMPRESS_UNIT_SIZE,
+                true
+            ),
+            Err(BGZFError::TooLargeCompressUnit)
+        ));
+        assert!(
+            BGZFWriter::with_compress_unit_size(Vec::new(), Compression::fast(), 0, true).is_err()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_stats() -> anyhow::Result<()> {
+        let data = crate::testutil::SyntheticBGZF::new(21)
+            .with_block_count(3)
+            .generate()
+            .uncompressed;
+
+        let mut compressed = Vec::new();
+        let mut writer = BGZFWriter::new(&mut compressed, Compression::default());
+        assert_eq!(writer.stats(), WriterStats::default());
+        assert_eq!(writer.stats().compression_ratio(), None);
+        writer.write_all(&data)?;
+        writer.flush()?;
+        let stats = writer.stats();
+        writer.close()?;
+
+        let blocks: Vec<_> =
+            crate::read::RawBlocks::new(&compressed[..]).collect::<Result<_, _>>()?;
+        assert_eq!(stats.blocks, blocks.len() as u64 - 1);
+        assert_eq!(stats.uncompressed_bytes, data.len() as u64);
+        assert_eq!(
+            stats.compressed_bytes,
+            (compressed.len() - crate::EOF_MARKER.len()) as u64
+        );
+        assert_eq!(
+            stats.compression_ratio(),
+            Some(stats.compressed_bytes as f64 / data.len() as f64)
+        );
+        #[cfg(feature = "metrics")]
+        assert!(stats.compress_time > std::time::Duration::ZERO);
+
+        // copied blocks are counted
+        let mut merged = Vec::new();
+        let mut writer = BGZFWriter::new(&mut merged, Compression::default());
+        writer.append_bgzf(&compressed[..])?;
+        let merged_stats = writer.stats();
+        assert_eq!(merged_stats.blocks, stats.blocks);
+        assert_eq!(merged_stats.uncompressed_bytes, stats.uncompressed_bytes);
+        assert_eq!(merged_stats.compressed_bytes, stats.compressed_bytes);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_vectored() -> anyhow::Result<()> {
+        let data = crate::testutil::SyntheticBGZF::new(22)
+            .with_block_count(4)
+            .generate()
+            .uncompressed;
+        let slices = [
+            &data[..100],
+            &data[100..100],
+            &data[100..(DEFAULT_COMPRESS_UNIT_SIZE * 2 + 100)],
+            &data[(DEFAULT_COMPRESS_UNIT_SIZE * 2 + 100)..],
+        ];
+
+        let mut expected = Vec::new();
+        let mut writer = BGZFWriter::new(&mut expected, Compression::default());
+        writer.write_all(&data)?;
+        writer.close()?;
+
+        let mut compressed = Vec::new();
+        let mut writer = BGZFWriter::new(&mut compressed, Compression::default());
+        let io_slices: Vec<_> = slices.iter().map(|x| io::IoSlice::new(x)).collect();
+        assert_eq!(writer.write_vectored(&io_slices)?, data.len());
+        writer.close()?;
+        assert_eq!(compressed, expected);
+
+        // whole compress units written directly from the input
+        let mut compressed = Vec::new();
+        let mut writer = BGZFWriter::new(&mut compressed, Compression::default());
+        let io_slices: Vec<_> = slices[2..].iter().map(|x| io::IoSlice::new(x)).collect();
+        assert_eq!(writer.write_vectored(&io_slices)?, data.len() - 100);
+        assert_eq!(writer.stats().blocks, 3);
+        writer.close()?;
+        let mut decompressed = Vec::new();
+        crate::BGZFReader::new(&compressed[..])?.read_to_end(&mut decompressed)?;
+        assert_eq!(decompressed, &data[100..]);
+
+        #[cfg(feature = "threads")]
+        {
+            let mut compressed = Vec::new();
+            let mut writer = BGZFMultiThreadWriter::new(&mut compressed, Compression::default());
+            let io_slices: Vec<_> = slices.iter().map(|x| io::IoSlice::new(x)).collect();
+            assert_eq!(writer.write_vectored(&io_slices)?, data.len());
+            writer.close()?;
+            assert_eq!(compressed, expected);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_flush_mode() -> anyhow::Result<()> {
+        let lines: Vec<String> = (0..1000)
+            .map(|i| format!("chr1\t{}\t{}\tline{}\n", i * 10, i * 10 + 5, i))
+            .collect();
+        let expected = lines.concat();
+
+        for (flush_mode, expected_blocks) in [(FlushMode::FinishBlock, 1001), (FlushMode::Defer, 2)]
+        {
+            let mut data = Vec::new();
+            let mut writer =
+                BGZFWriter::new(&mut data, Compression::default()).with_flush_mode(flush_mode);
+            for line in &lines {
+                writer.write_all(line.as_bytes())?;
+                writer.flush()?;
+            }
+            writer.close()?;
+
+            let blocks: Vec<_> =
+                crate::read::RawBlocks::new(&data[..]).collect::<Result<_, _>>()?;
+            assert_eq!(blocks.len(), expected_blocks);
+            let mut wrote_data = String::new();
+            flate2::read::MultiGzDecoder::new(&data[..]).read_to_string(&mut wrote_data)?;
+            assert_eq!(wrote_data, expected);
+        }
+
+        // explicit block boundaries
+        let mut data = Vec::new();
+        let mut writer =
+            BGZFWriter::new(&mut data, Compression::default()).with_flush_mode(FlushMode::Defer);
+        writer.write_all(lines[0].as_bytes())?;
+        writer.finish_block()?;
+        writer.finish_block()?;
+        let second_block = writer.bgzf_pos();
+        assert_eq!(second_block.uncompressed_offset_in_block(), 0);
+        writer.write_all(lines[1].as_bytes())?;
+        writer.flush()?;
+        assert_eq!(
+            writer.bgzf_pos().compressed_offset(),
+            second_block.compressed_offset()
+        );
+        writer.close()?;
+        let blocks: Vec<_> = crate::read::RawBlocks::new(&data[..]).collect::<Result<_, _>>()?;
+        assert_eq!(blocks.len(), 3);
+        assert_eq!(
+            blocks[1].compressed_offset,
+            second_block.compressed_offset()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_append_bgzf() -> anyhow::Result<()> {
+        let mut compressed_bed = Vec::new();
+        fs::File::open("testfiles/generated.bed.gz")?.read_to_end(&mut compressed_bed)?;
+        let mut bed = Vec::new();
+        flate2::read::MultiGzDecoder::new(&compressed_bed[..]).read_to_end(&mut bed)?;
+        let mut compressed_hello = Vec::new();
+        BGZFWriter::new(&mut compressed_hello, Compression::default())
+            .with_empty_output(EmptyOutput::EmptyBlock)
+            .close()?;
+        let mut writer = BGZFWriter::new(&mut compressed_hello, Compression::default());
+        writer.write_all(b"hello\n")?;
+        writer.close()?;
+
+        let mut compressed = Vec::new();
+        let mut writer = BGZFWriter::new(&mut compressed, Compression::default())
+            .with_file_comment(b"merged")?;
+        writer.append_bgzf(&compressed_bed[..])?;
+        writer.write_all(b"middle\n")?;
+        writer.append_bgzf(&compressed_hello[..])?;
+        writer.append_bgzf(&crate::EOF_MARKER[..])?;
+        writer.write_all(b"last\n")?;
+        let index = writer.close()?.unwrap();
+
+        let expected = [&bed[..], b"middle\n", b"hello\n", b"last\n"].concat();
+        let mut reader = BGZFReader::new(&compressed[..])?;
+        assert_eq!(reader.file_comment(), Some(&b"merged"[..]));
+        let mut read_data = Vec::new();
+        reader.read_to_end(&mut read_data)?;
+        assert_eq!(read_data, expected);
+        assert_eq!(crate::read::member_offsets(&compressed[..])?, vec![]);
+        assert_eq!(index, BGZFIndex::from_bgzf_reader(&compressed[..])?);
+
+        let mut reader = crate::read::IndexedBGZFReader::new(
+            BGZFReader::new(io::Cursor::new(&compressed))?,
+            index,
+        )?;
+        let mut buffer = [0; 12];
+        for pos in [0, bed.len() - 5, bed.len() + 2, expected.len() - 12] {
+            reader.seek(io::SeekFrom::Start(pos as u64))?;
+            reader.read_exact(&mut buffer)?;
+            assert_eq!(&buffer[..], &expected[pos..(pos + 12)]);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify() -> anyhow::Result<()> {
+        let data = include_bytes!("../../testfiles/reg2bin.c").repeat(30);
+        let mut compressed = Vec::new();
+        let mut writer = BGZFWriter::new(&mut compressed, Compression::default()).with_verify(true);
+        writer.write_all(&data)?;
+        writer.close()?;
+
+        let mut read_data = Vec::new();
+        BGZFReader::new(&compressed[..])?.read_to_end(&mut read_data)?;
+        assert_eq!(read_data, data);
+
+        let original_data = &data[..1000];
+        let mut block = Vec::new();
+        let mut compress = Compress::new(Compression::default());
+        let mut decompress = Decompress::new();
+        write_block(&mut block, original_data, &mut compress)?;
+        verify_block(&block, original_data, &mut decompress)?;
+        assert!(verify_block(&block, &data[1..1001], &mut decompress).is_err());
+
+        let block_len = block.len();
+        let mut broken_crc = block.clone();
+        broken_crc[block_len - 8] ^= 0xff;
+        assert!(verify_block(&broken_crc, original_data, &mut decompress).is_err());
+        let mut broken_size = block.clone();
+        broken_size[block_len - 4] ^= 0x01;
+        assert!(verify_block(&broken_size, original_data, &mut decompress).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_block_into() -> anyhow::Result<()> {
+        let mut rand = rand_pcg::Pcg64Mcg::seed_from_u64(0x85ebca6b);
+        let mut random_data = vec![0u8; DEFAULT_COMPRESS_UNIT_SIZE];
+        rand.fill_bytes(&mut random_data);
+        let text_data = b"chr1\t100\t200\n".repeat(4000);
+
+        let mut compress = Compress::new(Compression::fast());
+        let mut buffer = vec![0u8; DEFAULT_COMPRESS_UNIT_SIZE + COMPRESSED_BLOCK_OVERHEAD];
+        for original_data in [&random_data[..], &text_data[..], &[]] {
+            let mut expected = Vec::new();
+            write_block(&mut expected, original_data, &mut compress)?;
+
+            let block_size = write_block_into(&mut buffer, original_data, &mut compress)?;
+            assert_eq!(&buffer[..block_size], &expected[..]);
+
+            let block_size = write_block_into(
+                &mut buffer[..(original_data.len() + COMPRESSED_BLOCK_OVERHEAD)],
+                original_data,
+                &mut compress,
+            )?;
+            assert_eq!(&buffer[..block_size], &expected[..]);
+
+            let result = write_block_into(
+                &mut buffer[..(expected.len() - 1)],
+                original_data,
+                &mut compress,
+            );
+            assert!(matches!(result, Err(CompressError::InsufficientSpace)));
+        }
+        assert!(matches!(
+            write_block_into(&mut [0u8; 25], &[], &mut compress),
+            Err(CompressError::InsufficientSpace)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_size_hint() -> anyhow::Result<()> {
+        let data = crate::testutil::SyntheticBGZF::new(11)
+            .with_block_count(3)
+            .generate()
+            .uncompressed;
+
+        let mut expected = Vec::new();
+        let mut writer = BGZFWriter::new(&mut expected, Compression::default());
+        writer.write_all(&data)?;
+        let expected_index = writer.close()?;
+
+        for hint in [0, 100, data.len() as u64, 10_000_000] {
+            let mut compressed = Vec::new();
+            let mut writer =
+                BGZFWriter::new(&mut compressed, Compression::default()).with_size_hint(hint);
+            assert!(
+                writer.bgzf_index.as_ref().unwrap().entries.capacity()
+                    >= index_entries_for_size(hint, DEFAULT_COMPRESS_UNIT_SIZE)
+            );
+            writer.write_all(&data)?;
+            assert_eq!(writer.close()?, expected_index);
+            assert_eq!(compressed, expected);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_buffer_reuse() -> anyhow::Result<()> {
+        // incompressible data produces the largest blocks
+        let mut rand = rand_pcg::Pcg64Mcg::seed_from_u64(0x9e3779b9);
+        let mut data = vec![0u8; DEFAULT_COMPRESS_UNIT_SIZE * 5 + 123];
+        rand.fill_bytes(&mut data);
+
+        let mut compressed = Vec::new();
+        let mut writer = BGZFWriter::new(&mut compressed, Compression::fast()).with_verify(true);
+        let original_capacity = writer.original_data.capacity();
+        let compressed_capacity = writer.compressed_buffer.capacity();
+        for chunk in data.chunks(1000) {
+            writer.write_all(chunk)?;
+        }
+        writer.finish_block()?;
+        assert_eq!(writer.stats().blocks, 6);
+        assert_eq!(writer.original_data.capacity(), original_capacity);
+        assert_eq!(writer.compressed_buffer.capacity(), compressed_capacity);
+        writer.close()?;
+
+        let mut decompressed = Vec::new();
+        BGZFReader::new(&compressed[..])?.read_to_end(&mut decompressed)?;
+        assert_eq!(decompressed, data);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_comment() -> anyhow::Result<()> {
+        let data = crate::testutil::SyntheticBGZF::new(17)
+            .with_block_count(3)
+            .with_text(false)
+            .generate()
+            .uncompressed;
+        let comment = [b'x'; MAX_FILE_COMMENT_LEN];
+
+        let mut compressed = Vec::new();
+        let mut writer =
+            BGZFWriter::new(&mut compressed, Compression::default()).with_file_comment(&comment)?;
+        writer.write_all(&data)?;
+        writer.close()?;
+
+        let mut reader = BGZFReader::new(&compressed[..])?;
+        assert_eq!(reader.file_comment(), Some(&comment[..]));
+        let mut read_data = Vec::new();
+        reader.read_to_end(&mut read_data)?;
+        assert_eq!(read_data, data);
+        assert!(crate::inspect::strict_check(&compressed[..])?
+            .iter()
+            .all(|x| x.message == "FLG must be FEXTRA only"));
+
+        let mut decoder = flate2::read::GzDecoder::new(&compressed[..]);
+        decoder.read_exact(&mut [0; 10])?;
+        assert_eq!(decoder.header().unwrap().comment(), Some(&comment[..]));
+        let mut read_data = Vec::new();
+        flate2::read::MultiGzDecoder::new(&compressed[..]).read_to_end(&mut read_data)?;
+        assert_eq!(read_data, data);
+
+        // the comment is kept without data
+        let mut compressed = Vec::new();
+        BGZFWriter::new(&mut compressed, Compression::default())
+            .with_file_comment(b"empty")?
+            .close()?;
+        assert!(compressed.ends_with(&crate::EOF_MARKER));
+        assert_eq!(
+            BGZFReader::new(&compressed[..])?.file_comment(),
+            Some(&b"empty"[..])
+        );
+
+        let mut compressed = Vec::new();
+        BGZFWriter::new(&mut compressed, Compression::default()).close()?;
+        assert_eq!(BGZFReader::new(&compressed[..])?.file_comment(), None);
+
+        assert!(BGZFWriter::new(Vec::new(), Compression::default())
+            .with_file_comment(&[b'x'; MAX_FILE_COMMENT_LEN + 1])
+            .is_err());
+        assert!(BGZFWriter::new(Vec::new(), Compression::default())
+            .with_file_comment(b"a\0b")
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_custom_header() -> anyhow::Result<()> {
+        let mut rand = rand_pcg::Pcg64Mcg::seed_from_u64(0x7e3c0a9d21f4b865);
+        // incompressible data makes the largest blocks
+        let mut data = vec![0u8; DEFAULT_COMPRESS_UNIT_SIZE * 3];
+        rand.fill_bytes(&mut data);
+        let field = ExtraField::new(b'X', b'Y', vec![1, 2, 3]);
+
+        let mut compressed = Vec::new();
+        let mut writer = BGZFWriter::new(&mut compressed, Compression::default())
+            .with_modified_time(1_600_000_000)
+            .with_operation_system(crate::header::FILESYSTEM_UNIX)
+            .with_block_comment(b"block")?
+            .with_extra_field(field.clone())?
+            .with_file_comment(&[b'f'; MAX_FILE_COMMENT_LEN - 20])?;
+        writer.write_all(&data)?;
+        writer.close()?;
+
+        let mut block_count = 0;
+        for block in crate::read::RawBlocks::new(&compressed[..]) {
+            let block = block?;
+            if block.data == crate::read::EOF_BLOCK {
+                // end-of-file marker is not changed
+                assert_eq!(block.header.modified_time, 0);
+                continue;
+            }
+            assert_eq!(block.header.modified_time, 1_600_000_000);
+            assert_eq!(
+                block.header.operation_system,
+                crate::header::FILESYSTEM_UNIX
+            );
+            assert_eq!(block.header.extra_field[1], field);
+            let expected_comment = if block_count == 0 {
+                [&[b'f'; MAX_FILE_COMMENT_LEN - 20][..], b"\0"].concat()
+            } else {
+                b"block\0".to_vec()
+            };
+            assert_eq!(block.header.comment, Some(expected_comment));
+            block_count += 1;
+        }
+        assert_eq!(block_count, 3);
+
+        let mut read_data = Vec::new();
+        BGZFReader::new(&compressed[..])?.read_to_end(&mut read_data)?;
+        assert_eq!(read_data, data);
+        let mut read_data = Vec::new();
+        flate2::read::MultiGzDecoder::new(&compressed[..]).read_to_end(&mut read_data)?;
+        assert_eq!(read_data, data);
+
+        assert!(BGZFWriter::new(Vec::new(), Compression::default())
+            .with_extra_field(ExtraField::new(66, 67, vec![0, 0]))
+            .is_err());
+        assert!(BGZFWriter::new(Vec::new(), Compression::default())
+            .with_block_comment(b"comment")?
+            .with_extra_field(field.clone())?
+            .with_file_comment(&[b'x'; MAX_FILE_COMMENT_LEN])
+            .is_err());
+        assert!(BGZFWriter::new(Vec::new(), Compression::default())
+            .with_extra_field(ExtraField::new(b'X', b'Y', vec![0; MAX_FILE_COMMENT_LEN]))
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_strict() -> anyhow::Result<()> {
+        let mut rand = rand_pcg::Pcg64Mcg::seed_from_u64(0x85ebca6b);
+        let mut data = vec![0; 200_000];
+        rand.fill_bytes(&mut data);
+        let comment = [b'x'; MAX_FILE_COMMENT_LEN];
+
+        let mut compressed = Vec::new();
+        let mut writer = BGZFWriter::new(&mut compressed, Compression::default()).with_strict(true);
+        writer.write_all(&data)?;
+        writer.close()?;
+        let mut read_data = Vec::new();
+        BGZFReader::new(&compressed[..])?
+            .with_strict(true)
+            .read_to_end(&mut read_data)?;
+        assert_eq!(read_data, data);
+
+        // incompressible data with a long comment does not fit in a block
+        for strict in [false, true] {
+            let mut writer = BGZFWriter::with_compress_unit_size(
+                Vec::new(),
+                Compression::default(),
+                MAXIMUM_COMPRESS_UNIT_SIZE - 1,
+                false,
+            )?
+            .with_file_comment(&comment)?
+            .with_strict(strict);
+            let e = writer.write_all(&data).unwrap_err();
+            let e = e.get_ref().and_then(|x| x.downcast_ref::<BGZFError>());
+            assert_eq!(matches!(e, Some(BGZFError::BlockTooLarge)), strict);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_deterministic() -> anyhow::Result<()> {
+        let mut data = Vec::new();
+        flate2::read::MultiGzDecoder::new(File::open("testfiles/generated.bed.gz")?)
+            .read_to_end(&mut data)?;
+        let compress = |chunk_size: usize| -> anyhow::Result<Vec<u8>> {
+            let mut compressed = Vec::new();
+            let mut writer =
+                BGZFWriter::new(&mut compressed, Compression::default()).deterministic();
+            for chunk in data.chunks(chunk_size) {
+                writer.write_all(chunk)?;
+            }
+            writer.close()?;
+            Ok(compressed)
+        };
+        let expected = compress(1000)?;
+        assert_eq!(compress(1000)?, expected);
+        assert_eq!(compress(77_777)?, expected);
+
+        #[cfg(feature = "threads")]
+        for num_threads in [1, 2, 5] {
+            let mut compressed = Vec::new();
+            let mut writer = crate::write::BGZFMultiThreadWriter::with_std_threads(
+                &mut compressed,
+                Compression::default(),
+                num_threads,
+            )?;
+            writer.write_all(&data)?;
+            writer.close()?;
+            assert_eq!(compressed, expected);
+        }
+
+        // time-dependent fields
+        let mut writer = BGZFWriter::new(Vec::new(), Compression::default())
+            .deterministic()
+            .with_modified_time(1_600_000_000);
+        assert!(writer.write_all(&data).is_err());
+
+        let mut timestamped = Vec::new();
+        let mut writer = BGZFWriter::new(&mut timestamped, Compression::default())
+            .with_modified_time(1_600_000_000);
+        writer.write_all(&data)?;
+        writer.close()?;
+        assert_ne!(timestamped, expected);
+        let mut merged = Vec::new();
+        let mut writer = BGZFWriter::new(&mut merged, Compression::default()).deterministic();
+        writer.append_bgzf(&timestamped[..])?;
+        writer.close()?;
+        assert_eq!(merged, expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_record() -> anyhow::Result<()> {
+        let mut rand = rand_pcg::Pcg64Mcg::seed_from_u64(0x1b873593cc9e2d51);
+        let records: Vec<Vec<u8>> = (0..5000)
+            .map(|i| {
+                let mut record = format!("record{}\t", i).into_bytes();
+                record.resize(rand.gen_range(20..200), b'A');
+                record.push(b'\n');
+                record
+            })
+            .collect();
+        let expected = records.concat();
+
+        for adaptive in [false, true] {
+            let mut compressed = Vec::new();
+            let mut writer = BGZFWriter::new(&mut compressed, Compression::default())
+                .with_adaptive_unit_size(adaptive);
+            let mut positions = Vec::new();
+            for one in &records {
+                positions.push(writer.write_record(one)?);
+            }
+            if adaptive {
+                assert!(writer.compress_unit_target() < DEFAULT_COMPRESS_UNIT_SIZE);
+            }
+            let large_record_pos = writer.write_record(&[b'x'; 100_000])?;
+            assert_eq!(large_record_pos.uncompressed_offset_in_block(), 0);
+            writer.close()?;
+
+            let mut reader = BGZFReader::new(io::Cursor::new(&compressed))?;
+            let mut line = Vec::new();
+            for (pos, one) in positions.iter().zip(&records).step_by(101) {
+                reader.bgzf_seek(*pos)?;
+                line.clear();
+                io::BufRead::read_until(&mut reader, b'\n', &mut line)?;
+                assert_eq!(&line, one);
+            }
+
+            let mut decompress = Decompress::new();
+            let mut read_data = Vec::new();
+            for block in crate::read::RawBlocks::new(&compressed[..]) {
+                let block = block?;
+                let start = read_data.len();
+                crate::read::decompress_block(&mut read_data, &block.data, &mut decompress)?;
+                // records do not straddle blocks
+                if read_data.len() <= expected.len() {
+                    assert!(read_data[start..].is_empty() || read_data.ends_with(b"\n"));
+                }
+            }
+            assert_eq!(&read_data[..expected.len()], &expected[..]);
+            assert_eq!(read_data.len(), expected.len() + 100_000);
+        }
+
+        Ok(())
+    }
 }
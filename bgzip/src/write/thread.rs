@@ -1,12 +1,16 @@
+use crate::cancel::{cancelled_error, CancellationToken};
 use crate::index::BGZFIndexEntry;
-use crate::rayon::receive_or_yield;
+use crate::metrics::{BlockOperation, Recorder};
+use crate::pool::{receive_or_yield, Pool};
+use crate::write::{
+    BlockSizeProfile, BlockVerifier, EmptyOutput, WriterStats, DEFAULT_WRITE_BLOCK_UNIT_NUM,
+};
 use crate::{deflate::*, index::BGZFIndex, BGZFError};
 use std::collections::HashMap;
 use std::convert::TryInto;
 use std::io::{self, Error, ErrorKind, Write};
-use std::sync::mpsc::{channel, Receiver, Sender};
-
-const DEFAULT_WRITE_BLOCK_UNIT_NUM: usize = 50;
+use std::sync::mpsc::{channel, sync_channel, Receiver, Sender, SyncSender};
+use std::thread::JoinHandle;
 
 #[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
 struct BlockSize {
@@ -20,6 +24,10 @@ struct WriteBlock {
     compressed_buffer: Vec<u8>,
     raw_buffer: Vec<u8>,
     block_sizes: Vec<BlockSize>,
+    verify: Option<BlockVerifier>,
+    error: Option<BGZFError>,
+    #[cfg(feature = "metrics")]
+    compress_time: std::time::Duration,
 }
 
 impl WriteBlock {
@@ -30,10 +38,14 @@ impl WriteBlock {
             index: 0,
             compress,
             compressed_buffer: Vec::with_capacity(
-                (compress_unit_size + crate::write::EXTRA_COMPRESS_BUFFER_SIZE) * write_block_num,
+                (compress_unit_size + crate::write::COMPRESSED_BLOCK_OVERHEAD) * write_block_num,
             ),
             raw_buffer: Vec::with_capacity(compress_unit_size * write_block_num),
             block_sizes: Vec::new(),
+            verify: None,
+            error: None,
+            #[cfg(feature = "metrics")]
+            compress_time: std::time::Duration::ZERO,
         }
     }
 
@@ -42,14 +54,100 @@ impl WriteBlock {
         self.compressed_buffer.clear();
         self.raw_buffer.clear();
         self.block_sizes.clear();
+        self.error = None;
+        #[cfg(feature = "metrics")]
+        {
+            self.compress_time = std::time::Duration::ZERO;
+        }
+    }
+}
+
+/// Dedicated thread to write compressed data into the output.
+struct WriterThread {
+    sender: Option<SyncSender<Vec<u8>>>,
+    recycle_receiver: Receiver<Vec<u8>>,
+    handle: Option<JoinHandle<io::Result<()>>>,
+}
+
+impl WriterThread {
+    fn new<W: Write + Send + 'static>(mut writer: W, queue_size: usize) -> Self {
+        let (tx, rx) = sync_channel::<Vec<u8>>(queue_size);
+        let (recycle_tx, recycle_rx) = channel();
+        let handle = std::thread::spawn(move || {
+            for mut data in rx {
+                writer.write_all(&data)?;
+                data.clear();
+                // the receiver may be already dropped
+                let _ = recycle_tx.send(data);
+            }
+            writer.flush()
+        });
+        WriterThread {
+            sender: Some(tx),
+            recycle_receiver: recycle_rx,
+            handle: Some(handle),
+        }
+    }
+
+    /// Wait until the writer thread finishes and return its error if exists.
+    fn join(&mut self) -> io::Result<()> {
+        self.sender.take();
+        match self.handle.take() {
+            Some(handle) => handle
+                .join()
+                .map_err(|_| BGZFError::Other("Writer thread panicked").into_io_error())?,
+            None => Ok(()),
+        }
+    }
+}
+
+enum Output<W: Write> {
+    Direct(W),
+    Thread(WriterThread),
+    Taken,
+}
+
+impl<W: Write> Output<W> {
+    /// Write `data` into the output. `data` may be replaced with a recycled buffer.
+    fn write_all(&mut self, data: &mut Vec<u8>) -> io::Result<()> {
+        match self {
+            Output::Direct(writer) => writer.write_all(data),
+            Output::Thread(thread) => {
+                let recycled = thread.recycle_receiver.try_recv().unwrap_or_default();
+                let data = std::mem::replace(data, recycled);
+                let sent = thread
+                    .sender
+                    .as_ref()
+                    .map(|x| x.send(data).is_ok())
+                    .unwrap_or(false);
+                if sent {
+                    return Ok(());
+                }
+                // the error is reported only once, and following writes fail with `Taken`
+                let result = thread.join();
+                *self = Output::Taken;
+                result?;
+                Err(BGZFError::Other("Writer thread is stopped").into_io_error())
+            }
+            Output::Taken => Err(BGZFError::Other("Writer is already closed").into_io_error()),
+        }
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        match self {
+            Output::Thread(thread) => thread.join(),
+            _ => Ok(()),
+        }
     }
 }
 
 /// A Multi-thread BGZF writer
 ///
 /// [rayon](https://crates.io/crates/rayon) is used to run compression in a thread pool.
+/// Without `rayon` feature, compression runs in a pool of [`std::thread`] workers enabled by `threads` feature.
+/// Output does not depend on the number of threads. See [`crate::BGZFWriter::deterministic`].
 pub struct BGZFMultiThreadWriter<W: Write> {
-    writer: W,
+    writer: Output<W>,
     compress_unit_size: usize,
     write_block_num: usize,
     block_list: Vec<WriteBlock>,
@@ -59,6 +157,14 @@ pub struct BGZFMultiThreadWriter<W: Write> {
     next_write_index: u64,
     next_compress_index: u64,
     closed: bool,
+    fragment: bool,
+    empty_output: EmptyOutput,
+    file_comment: Option<Vec<u8>>,
+    cancel: CancellationToken,
+    cancel_reported: bool,
+    recorder: Recorder,
+    pool: Pool,
+    stats: WriterStats,
 
     current_compressed_pos: u64,
     current_uncompressed_pos: u64,
@@ -78,6 +184,24 @@ impl<W: Write> BGZFMultiThreadWriter<W> {
         .expect("Unreachable (BGZFMultiThreadWriter)")
     }
 
+    /// Create new [`BGZFMultiThreadWriter`] with compress unit size and number of compress
+    /// units per job of `profile`.
+    pub fn with_profile(writer: W, level: Compression, profile: BlockSizeProfile) -> Self {
+        Self::with_compress_unit_size(
+            writer,
+            profile.compress_unit_size(),
+            profile.write_block_num(),
+            level,
+            true,
+        )
+        .expect("Unreachable (BGZFMultiThreadWriter)")
+    }
+
+    /// Create new [`BGZFMultiThreadWriter`] with compress unit size and number of compress units
+    /// per job.
+    ///
+    /// [`BGZFError::TooLargeCompressUnit`] is returned if `compress_unit_size` is not smaller than
+    /// [`crate::write::MAXIMUM_COMPRESS_UNIT_SIZE`]. `write_block_num` is rounded up to 1.
     pub fn with_compress_unit_size(
         writer: W,
         compress_unit_size: usize,
@@ -85,17 +209,121 @@ impl<W: Write> BGZFMultiThreadWriter<W> {
         level: Compression,
         create_index: bool,
     ) -> Result<Self, BGZFError> {
-        if compress_unit_size >= crate::write::MAXIMUM_COMPRESS_UNIT_SIZE {
-            return Err(BGZFError::TooLargeCompressUnit);
-        }
+        Self::with_pool(
+            writer,
+            compress_unit_size,
+            write_block_num,
+            level,
+            create_index,
+            Pool::global()?,
+        )
+    }
+
+    /// Create new [`BGZFMultiThreadWriter`] which runs compression in a dedicated thread pool.
+    ///
+    /// See [`crate::read::BGZFMultiThreadReader::with_available_parallelism`] for details.
+    pub fn with_available_parallelism(writer: W, level: Compression) -> Result<Self, BGZFError> {
+        Self::with_pool(
+            writer,
+            crate::write::DEFAULT_COMPRESS_UNIT_SIZE,
+            DEFAULT_WRITE_BLOCK_UNIT_NUM,
+            level,
+            true,
+            Pool::available_parallelism()?,
+        )
+    }
+
+    /// Create new [`BGZFMultiThreadWriter`] which runs compression in `num_threads` dedicated
+    /// [`std::thread`] workers.
+    ///
+    /// rayon is not used even if `rayon` feature is enabled. Workers exit when the writer is dropped.
+    pub fn with_std_threads(
+        writer: W,
+        level: Compression,
+        num_threads: usize,
+    ) -> Result<Self, BGZFError> {
+        Self::with_pool(
+            writer,
+            crate::write::DEFAULT_COMPRESS_UNIT_SIZE,
+            DEFAULT_WRITE_BLOCK_UNIT_NUM,
+            level,
+            true,
+            Pool::std_threads(num_threads)?,
+        )
+    }
+
+    /// Create new [`BGZFMultiThreadWriter`] which runs compression in `pool`.
+    #[cfg(feature = "rayon")]
+    pub fn with_thread_pool(
+        writer: W,
+        level: Compression,
+        pool: std::sync::Arc<rayon::ThreadPool>,
+    ) -> Self {
+        Self::with_pool(
+            writer,
+            crate::write::DEFAULT_COMPRESS_UNIT_SIZE,
+            DEFAULT_WRITE_BLOCK_UNIT_NUM,
+            level,
+            true,
+            Pool::Local(pool),
+        )
+        .expect("Unreachable (BGZFMultiThreadWriter)")
+    }
+
+    /// Same as [`BGZFMultiThreadWriter::with_thread_pool`].
+    #[cfg(feature = "rayon")]
+    pub fn new_with_pool(
+        writer: W,
+        level: Compression,
+        pool: std::sync::Arc<rayon::ThreadPool>,
+    ) -> Self {
+        Self::with_thread_pool(writer, level, pool)
+    }
+
+    /// Create new [`BGZFMultiThreadWriter`] which passes compression jobs to `executor` instead of
+    /// spawning them in a thread pool.
+    ///
+    /// `executor` is called from the thread writing data, and must run the job eventually on any
+    /// thread, for example with `rayon::spawn_fifo` of a specific pool or
+    /// `tokio::task::spawn_blocking`. Jobs are CPU-bound and do not wait for each other, and
+    /// the writer blocks until their results are available. Up to `num_jobs` jobs are in flight
+    /// at a time.
+    pub fn with_executor<F>(writer: W, level: Compression, num_jobs: usize, executor: F) -> Self
+    where
+        F: Fn(Box<dyn FnOnce() + Send>) + Send + Sync + 'static,
+    {
+        Self::with_pool(
+            writer,
+            crate::write::DEFAULT_COMPRESS_UNIT_SIZE,
+            DEFAULT_WRITE_BLOCK_UNIT_NUM,
+            level,
+            true,
+            Pool::Executor {
+                executor: std::sync::Arc::new(executor),
+                num_jobs: num_jobs.max(1),
+            },
+        )
+        .expect("Unreachable (BGZFMultiThreadWriter)")
+    }
+
+    fn with_pool(
+        writer: W,
+        compress_unit_size: usize,
+        write_block_num: usize,
+        level: Compression,
+        create_index: bool,
+        pool: Pool,
+    ) -> Result<Self, BGZFError> {
+        crate::write::check_compress_unit_size(compress_unit_size)?;
+        let write_block_num = write_block_num.max(1);
 
         let (tx, rx) = channel();
 
         Ok(BGZFMultiThreadWriter {
-            writer,
+            writer: Output::Direct(writer),
             compress_unit_size,
             write_block_num,
-            block_list: (0..(rayon::current_num_threads() * 2))
+            block_list: (0..(pool.current_num_threads() * 2))
                 .map(|_| WriteBlock::new(level, compress_unit_size, write_block_num))
                 .collect(),
             write_waiting_blocks: HashMap::new(),
@@ -104,6 +332,14 @@ impl<W: Write> BGZFMultiThreadWriter<W> {
             next_write_index: 0,
             next_compress_index: 0,
             closed: false,
+            fragment: false,
+            empty_output: EmptyOutput::default(),
+            file_comment: None,
+            cancel: CancellationToken::new(),
+            cancel_reported: false,
+            recorder: Recorder::disabled(),
+            pool,
+            stats: WriterStats::default(),
             current_uncompressed_pos: 0,
             current_compressed_pos: 0,
             bgzf_index: if create_index {
@@ -114,9 +350,182 @@ impl<W: Write> BGZFMultiThreadWriter<W> {
         })
     }
 
+    /// Configure this writer as a fragment writer.
+    ///
+    /// A fragment writer does not write end-of-file marker and keeps the last index entry on close,
+    /// so outputs of several fragment writers can be concatenated and terminated with a single
+    /// [`crate::EOF_MARKER`] later. The last entry of returned index points to the end of the fragment.
+    pub fn with_fragment(mut self, fragment: bool) -> Self {
+        self.fragment = fragment;
+        self
+    }
+
+    /// Write `comment` into FCOMMENT field of the first block.
+    ///
+    /// See [`crate::write::BGZFWriter::with_file_comment`] for details.
+    pub fn with_file_comment(mut self, comment: &[u8]) -> Result<Self, BGZFError> {
+        self.file_comment = Some(crate::write::file_comment(comment)?);
+        Ok(self)
+    }
+
+    /// Pre-size internal buffers and index for `input_size` bytes of uncompressed data.
+    ///
+    /// See [`crate::write::BGZFWriter::with_size_hint`] for details.
+    pub fn with_size_hint(mut self, input_size: u64) -> Self {
+        let buffer_size = input_size.min(
+            (self.compress_unit_size * self.write_block_num)
+                .try_into()
+                .unwrap(),
+        );
+        let buffer_size: usize = buffer_size.try_into().unwrap();
+        let compressed_size = buffer_size
+            + crate::write::COMPRESSED_BLOCK_OVERHEAD
+                * buffer_size.div_ceil(self.compress_unit_size).max(1);
+        for block in self.block_list.iter_mut() {
+            block.raw_buffer.shrink_to(buffer_size);
+            block.compressed_buffer.shrink_to(compressed_size);
+        }
+        if let Some(index) = self.bgzf_index.as_mut() {
+            index.entries.reserve(crate::write::index_entries_for_size(
+                input_size,
+                self.compress_unit_size,
+            ));
+        }
+        self
+    }
+
+    /// Select output when the writer is closed without writing any data.
+    ///
+    /// See [`crate::write::BGZFWriter::with_empty_output`] for details.
+    pub fn with_empty_output(mut self, empty_output: EmptyOutput) -> Self {
+        self.empty_output = empty_output;
+        self
+    }
+
+    /// Write compressed data in a dedicated thread.
+    ///
+    /// By default, compressed blocks are written in the caller thread, so compression stalls while
+    /// the output is slow (e.g. network storage). With this option, compressed blocks are passed to
+    /// a writer thread via a bounded queue which holds up to `queue_size` buffers, and compression
+    /// and output I/O overlap. Errors of the writer thread are returned from following writes or
+    /// [`BGZFMultiThreadWriter::close`].
+    pub fn with_writer_thread(mut self, queue_size: usize) -> Self
+    where
+        W: Send + 'static,
+    {
+        if let Output::Direct(writer) = std::mem::replace(&mut self.writer, Output::Taken) {
+            self.writer = Output::Thread(WriterThread::new(writer, queue_size));
+        }
+        self
+    }
+
+    /// Set [`CancellationToken`] to abort compression.
+    ///
+    /// After `cancel` is cancelled, dispatched tasks stop compression of remaining blocks and
+    /// writes fail with [`BGZFError::Cancelled`]. The first failed write returns an error of
+    /// [`std::io::ErrorKind::Interrupted`] kind. End-of-file marker is not written on drop.
+    pub fn with_cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Record compression duration of each block into `sink`.
+    ///
+    /// `sink` is called from threads in the thread pool.
+    #[cfg(feature = "metrics")]
+    pub fn with_histogram(
+        mut self,
+        sink: std::sync::Arc<dyn crate::metrics::HistogramSink>,
+    ) -> Self {
+        self.recorder = Recorder::new(sink);
+        self
+    }
+
+    /// Verify each block before writing it.
+    ///
+    /// See [`crate::write::BGZFWriter::with_verify`] for details.
+    pub fn with_verify(mut self, verify: bool) -> Self {
+        for block in self.block_list.iter_mut() {
+            block.verify = if verify {
+                Some(BlockVerifier::new())
+            } else {
+                None
+            };
+        }
+        self
+    }
+
+    /// Set maximum number of blocks being compressed or waiting to be written.
+    ///
+    /// Each block holds up to `compress_unit_size * write_block_num` bytes of uncompressed data
+    /// and its compressed data. When all blocks are in flight, [`Write::write`] waits until the
+    /// oldest block is written, so memory usage stays bounded. Two blocks per thread are used by
+    /// default. `max_pending_blocks` is rounded up to 1.
+    pub fn with_max_pending_blocks(mut self, max_pending_blocks: usize) -> Self {
+        let max_pending_blocks = max_pending_blocks.max(1);
+        let first = &self.block_list[0];
+        let level = first.compress.level();
+        let backend = first.compress.backend();
+        let verify = first.verify.is_some();
+        while self.block_list.len() < max_pending_blocks {
+            let mut block = WriteBlock::new(level, self.compress_unit_size, self.write_block_num);
+            block.compress = Compress::with_backend(level, backend.clone());
+            if verify {
+                block.verify = Some(BlockVerifier::new());
+            }
+            self.block_list.push(block);
+        }
+        self.block_list.truncate(max_pending_blocks);
+        self
+    }
+
+    /// Select DEFLATE [`Backend`] to compress blocks.
+    pub fn with_backend(mut self, backend: Backend) -> Self {
+        for block in self.block_list.iter_mut() {
+            block.compress = Compress::with_backend(block.compress.level(), backend.clone());
+        }
+        self
+    }
+
+    /// Number of compress units processed in one job
+    pub fn write_block_num(&self) -> usize {
+        self.write_block_num
+    }
+
+    /// Change number of compress units processed in one job.
+    ///
+    /// The new value is applied to data written after this call, so it can be tuned with
+    /// throughput observed with [`BGZFMultiThreadWriter::stats`]. Larger value reduces
+    /// synchronization overhead, and smaller value reduces latency and memory usage.
+    /// `write_block_num` is rounded up to 1.
+    pub fn set_write_block_num(&mut self, write_block_num: usize) {
+        self.write_block_num = write_block_num.max(1);
+    }
+
+    /// Statistics of blocks written to the output.
+    ///
+    /// Blocks being compressed in the thread pool are not counted yet. Call [`Write::flush`]
+    /// before this method to count all written data.
+    pub fn stats(&self) -> WriterStats {
+        self.stats
+    }
+
     fn write_blocks(&mut self, mut next_data: WriteBlock) -> io::Result<()> {
-        self.writer.write_all(&next_data.compressed_buffer)?;
+        match next_data.error.take() {
+            Some(BGZFError::Cancelled) => return Err(cancelled_error(&mut self.cancel_reported)),
+            Some(e) => return Err(e.into_io_error()),
+            None => (),
+        }
+        self.writer.write_all(&mut next_data.compressed_buffer)?;
+        #[cfg(feature = "metrics")]
+        {
+            self.stats.compress_time += next_data.compress_time;
+        }
         for one in &next_data.block_sizes {
+            self.stats.add_block(
+                one.uncompressed_size.try_into().unwrap(),
+                one.compressed_size.try_into().unwrap(),
+            );
             self.current_compressed_pos += TryInto::<u64>::try_into(one.compressed_size).unwrap();
             self.current_uncompressed_pos +=
                 TryInto::<u64>::try_into(one.uncompressed_size).unwrap();
@@ -135,6 +544,9 @@ impl<W: Write> BGZFMultiThreadWriter<W> {
     }
 
     fn process_buffer(&mut self, block: bool, block_all: bool) -> io::Result<()> {
+        if self.cancel.is_cancelled() {
+            return Err(cancelled_error(&mut self.cancel_reported));
+        }
         let mut current_block = block;
         while self.next_compress_index != self.next_write_index {
             let next_data = if current_block {
@@ -178,25 +590,57 @@ impl<W: Write> BGZFMultiThreadWriter<W> {
         let sender = self.writer_sender.clone();
         // eprintln!("spawn thread: {}", block.index);
         let compress_unit_size = self.compress_unit_size;
-        rayon::spawn_fifo(move || {
+        let cancel = self.cancel.clone();
+        let recorder = self.recorder.clone();
+        let mut comment = if block.index == 0 {
+            self.file_comment.clone()
+        } else {
+            None
+        };
+        self.pool.spawn_fifo(move || {
             // eprintln!("started thread: {}", block.index);
             block.compressed_buffer.clear();
             let mut wrote_bytes = 0;
 
-            while wrote_bytes < block.raw_buffer.len() {
+            // an empty buffer is compressed into an empty block
+            while wrote_bytes < block.raw_buffer.len() || block.block_sizes.is_empty() {
                 // eprintln!(
                 //     "write block: {} / {}, {}",
                 //     block.index,
                 //     wrote_bytes,
                 //     String::from_utf8_lossy(&block.raw_buffer[wrote_bytes..(wrote_bytes + 10)])
                 // );
+                if cancel.is_cancelled() {
+                    block.error = Some(BGZFError::Cancelled);
+                    break;
+                }
                 let bytes_to_write = (block.raw_buffer.len() - wrote_bytes).min(compress_unit_size);
-                let compressed_size = crate::write::write_block(
-                    &mut block.compressed_buffer,
-                    &block.raw_buffer[wrote_bytes..(wrote_bytes + bytes_to_write)],
-                    &mut block.compress,
-                )
-                .expect("Failed to write block");
+                let compressed_start = block.compressed_buffer.len();
+                #[cfg(feature = "metrics")]
+                let start = std::time::Instant::now();
+                let compressed_size = recorder
+                    .record(BlockOperation::Compress, || {
+                        crate::write::write_block_with_comment(
+                            &mut block.compressed_buffer,
+                            &block.raw_buffer[wrote_bytes..(wrote_bytes + bytes_to_write)],
+                            &mut block.compress,
+                            comment.take().as_deref(),
+                        )
+                    })
+                    .expect("Failed to write block");
+                #[cfg(feature = "metrics")]
+                {
+                    block.compress_time += start.elapsed();
+                }
+                if let Some(verifier) = block.verify.as_mut() {
+                    if let Err(e) = verifier.verify(
+                        &block.compressed_buffer[compressed_start..],
+                        &block.raw_buffer[wrote_bytes..(wrote_bytes + bytes_to_write)],
+                    ) {
+                        block.error = Some(e);
+                        break;
+                    }
+                }
                 wrote_bytes += bytes_to_write;
                 block.block_sizes.push(BlockSize {
                     uncompressed_size: bytes_to_write,
@@ -205,22 +649,43 @@ impl<W: Write> BGZFMultiThreadWriter<W> {
             }
 
             //eprintln!("finished thread: {}", block.index);
-            sender.send(block).expect("failed to send write result");
+            // the receiver may be dropped after cancellation
+            if sender.send(block).is_err() && !cancel.is_cancelled() {
+                panic!("failed to send write result");
+            }
         });
     }
 
+    /// Write buffered data, and an empty block if no data is written and it is requested with
+    /// [`BGZFMultiThreadWriter::with_empty_output`] or a file comment is set.
+    fn flush_last_block(&mut self) -> io::Result<()> {
+        self.flush()?;
+        let empty_block =
+            self.empty_output == EmptyOutput::EmptyBlock || self.file_comment.is_some();
+        if empty_block && self.next_compress_index == 0 {
+            self.dispatch_current_block();
+            self.process_buffer(true, true)?;
+        }
+        Ok(())
+    }
+
     /// Write end-of-file marker and close BGZF.
     ///
     /// Explicitly call of this method is not required unless you need .gzi index.
     /// Drop trait will write end-of-file marker automatically.
     /// If you need to handle I/O errors while closing, please use this method.    
     pub fn close(mut self) -> io::Result<Option<BGZFIndex>> {
-        self.flush()?;
-        self.writer.write_all(&crate::EOF_MARKER)?;
+        self.flush_last_block()?;
+        if !self.fragment {
+            self.writer.write_all(&mut crate::EOF_MARKER.to_vec())?;
+        }
         self.closed = true;
+        self.writer.finish()?;
 
-        if let Some(index) = self.bgzf_index.as_mut() {
-            index.entries.pop();
+        if !self.fragment {
+            if let Some(index) = self.bgzf_index.as_mut() {
+                index.entries.pop();
+            }
         }
 
         Ok(self.bgzf_index.take())
@@ -233,8 +698,15 @@ impl<W: Write> Write for BGZFMultiThreadWriter<W> {
         while wrote_bytes < buf.len() {
             self.process_buffer(self.block_list.is_empty(), false)?;
             let current_buffer = self.block_list.get_mut(0).unwrap();
-            let remain_buffer =
-                (self.compress_unit_size * self.write_block_num) - current_buffer.raw_buffer.len();
+            let capacity = self.compress_unit_size * self.write_block_num;
+            let buffered = current_buffer.raw_buffer.len();
+            // fill the last compress unit if more data is buffered after write_block_num is decreased
+            let remain_buffer = if buffered < capacity {
+                capacity - buffered
+            } else {
+                (self.compress_unit_size - buffered % self.compress_unit_size)
+                    % self.compress_unit_size
+            };
             let bytes_to_write = remain_buffer.min(buf.len() - wrote_bytes);
             current_buffer
                 .raw_buffer
@@ -248,6 +720,14 @@ impl<W: Write> Write for BGZFMultiThreadWriter<W> {
         Ok(wrote_bytes)
     }
 
+    fn write_vectored(&mut self, bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
+        let mut wrote_bytes = 0;
+        for buf in bufs {
+            wrote_bytes += self.write(buf)?;
+        }
+        Ok(wrote_bytes)
+    }
+
     fn flush(&mut self) -> io::Result<()> {
         self.process_buffer(self.block_list.is_empty(), false)?;
         if self.block_list[0].raw_buffer.len() > 0 {
@@ -267,11 +747,17 @@ impl<W: Write> Write for BGZFMultiThreadWriter<W> {
 
 impl<W: Write> Drop for BGZFMultiThreadWriter<W> {
     fn drop(&mut self) {
-        if !self.closed {
-            self.flush().expect("BGZF: Flash Error");
+        // output is already taken if the writer thread is stopped with an error
+        if !self.closed && !matches!(self.writer, Output::Taken) && !self.cancel.is_cancelled() {
+            self.flush_last_block().expect("BGZF: Flash Error");
+            if !self.fragment {
+                self.writer
+                    .write_all(&mut crate::EOF_MARKER.to_vec())
+                    .expect("BGZF: Cannot write EOF marker");
+            }
             self.writer
-                .write_all(&crate::EOF_MARKER)
-                .expect("BGZF: Cannot write EOF marker");
+                .finish()
+                .expect("BGZF: Cannot finish writer thread");
         }
     }
 }
@@ -287,6 +773,7 @@ mod test {
     const BUF_SIZE: usize = 1000 * 1000 * 10;
 
     #[test]
+    #[cfg(feature = "rayon")]
     fn test_write_many() -> anyhow::Result<()> {
         let mut reader = flate2::read::MultiGzDecoder::new(std::fs::File::open(
             "testfiles/common_all_20180418_half.vcf.gz",
@@ -371,4 +858,454 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn test_empty_writer() -> anyhow::Result<()> {
+        let mut data = Vec::new();
+        let mut writer = BGZFMultiThreadWriter::new(&mut data, Compression::default());
+        writer.flush()?;
+        let index = writer.close()?.unwrap();
+        assert!(index.entries().is_empty());
+        assert_eq!(data, crate::EOF_MARKER);
+
+        let mut reader = crate::read::BGZFMultiThreadReader::new(&data[..])?;
+        let mut read_data = Vec::new();
+        reader.read_to_end(&mut read_data)?;
+        assert!(read_data.is_empty());
+
+        // same output as the single thread writer
+        let mut expected = Vec::new();
+        crate::write::BGZFWriter::new(&mut expected, Compression::default())
+            .with_empty_output(EmptyOutput::EmptyBlock)
+            .close()?;
+        let mut data = Vec::new();
+        let writer = BGZFMultiThreadWriter::new(&mut data, Compression::default())
+            .with_empty_output(EmptyOutput::EmptyBlock);
+        let index = writer.close()?.unwrap();
+        assert!(index.entries().is_empty());
+        assert_eq!(data, expected);
+
+        let mut data = Vec::new();
+        std::mem::drop(
+            BGZFMultiThreadWriter::new(&mut data, Compression::default())
+                .with_empty_output(EmptyOutput::EmptyBlock),
+        );
+        assert_eq!(data, expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fragment_writer() -> anyhow::Result<()> {
+        let mut rand = rand_pcg::Pcg64Mcg::seed_from_u64(0x2f9a8c2b41d0e153);
+        let mut data = vec![0; 300_000];
+        rand.fill_bytes(&mut data);
+
+        let mut concatenated = Vec::new();
+        for one in data.chunks(100_000) {
+            let mut fragment = Vec::new();
+            let mut writer = BGZFMultiThreadWriter::with_compress_unit_size(
+                &mut fragment,
+                10_000,
+                3,
+                Compression::fast(),
+                true,
+            )?
+            .with_fragment(true);
+            writer.write_all(one)?;
+            let index = writer.close()?.unwrap();
+            assert!(!fragment.ends_with(&crate::EOF_MARKER));
+            assert_eq!(
+                index.entries().last().unwrap().compressed_offset,
+                fragment.len() as u64
+            );
+            assert_eq!(
+                index.entries().last().unwrap().uncompressed_offset,
+                one.len() as u64
+            );
+            concatenated.extend_from_slice(&fragment);
+        }
+        concatenated.extend_from_slice(&crate::EOF_MARKER);
+
+        let mut read_data = Vec::new();
+        crate::BGZFReader::new(&concatenated[..])?.read_to_end(&mut read_data)?;
+        assert_eq!(read_data, data);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_writer() -> anyhow::Result<()> {
+        let mut rand = rand_pcg::Pcg64Mcg::seed_from_u64(0x51c4e0a9d7b3f218);
+        let mut data = vec![0; 300_000];
+        rand.fill_bytes(&mut data);
+
+        let mut compressed = Vec::new();
+        let mut writer = BGZFMultiThreadWriter::with_compress_unit_size(
+            &mut compressed,
+            10_000,
+            3,
+            Compression::default(),
+            false,
+        )?
+        .with_verify(true);
+        writer.write_all(&data)?;
+        writer.close()?;
+
+        let mut read_data = Vec::new();
+        crate::BGZFReader::new(&compressed[..])?.read_to_end(&mut read_data)?;
+        assert_eq!(read_data, data);
+
+        Ok(())
+    }
+
+    #[derive(Clone, Default)]
+    struct SharedWriter(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl Write for SharedWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    struct FailWriter(usize);
+
+    impl Write for FailWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.0 < buf.len() {
+                return Err(Error::new(ErrorKind::WriteZero, "Disk full"));
+            }
+            self.0 -= buf.len();
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_writer_thread() -> anyhow::Result<()> {
+        let mut rand = rand_pcg::Pcg64Mcg::seed_from_u64(0x7c1d3e5f9a2b4c60);
+        let mut data = vec![0; 1_000_000];
+        rand.fill_bytes(&mut data[..500_000]);
+
+        let output = SharedWriter::default();
+        let mut writer = BGZFMultiThreadWriter::with_compress_unit_size(
+            output.clone(),
+            10_000,
+            3,
+            Compression::fast(),
+            true,
+        )?
+        .with_writer_thread(2);
+        for one in data.chunks(WRITE_UNIT) {
+            writer.write_all(one)?;
+        }
+        let index = writer.close()?.unwrap();
+
+        let compressed = output.0.lock().unwrap().clone();
+        assert!(compressed.ends_with(&crate::EOF_MARKER));
+        assert_eq!(
+            index.entries().last().unwrap().uncompressed_offset,
+            (data.len() - 10_000) as u64
+        );
+        let mut read_data = Vec::new();
+        crate::BGZFReader::new(&compressed[..])?.read_to_end(&mut read_data)?;
+        assert_eq!(read_data, data);
+
+        let mut writer = BGZFMultiThreadWriter::new(FailWriter(100_000), Compression::default())
+            .with_writer_thread(1);
+        let result = data
+            .chunks(WRITE_UNIT)
+            .try_for_each(|x| writer.write_all(x))
+            .and_then(|_| writer.close().map(|_| ()));
+        assert_eq!(result.unwrap_err().to_string(), "Disk full");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cancel() -> anyhow::Result<()> {
+        let mut rand = rand_pcg::Pcg64Mcg::seed_from_u64(0x3a6f0d2c81b94e57);
+        let mut data = vec![0; 1_000_000];
+        rand.fill_bytes(&mut data);
+
+        let cancel = CancellationToken::new();
+        let output = SharedWriter::default();
+        let mut writer = BGZFMultiThreadWriter::new(output.clone(), Compression::default())
+            .with_cancellation(cancel.clone());
+        writer.write_all(&data[..100_000])?;
+        cancel.cancel();
+        let e = writer.write(&data[100_000..]).unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::Interrupted);
+        assert!(writer.write_all(&data[100_000..]).is_err());
+        assert!(writer.flush().is_err());
+        std::mem::drop(writer);
+        assert!(!output.0.lock().unwrap().ends_with(&crate::EOF_MARKER));
+
+        let cancel = CancellationToken::new();
+        let mut writer = BGZFMultiThreadWriter::new(Vec::new(), Compression::default())
+            .with_cancellation(cancel.clone());
+        writer.write_all(&data)?;
+        cancel.cancel();
+        assert!(writer.close().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_thread_pool() -> anyhow::Result<()> {
+        let mut rand = rand_pcg::Pcg64Mcg::seed_from_u64(0x5be1c04a7d2f9368);
+        let mut data = vec![0; 500_000];
+        rand.fill_bytes(&mut data[..250_000]);
+
+        let mut expected = Vec::new();
+        let mut writer = BGZFMultiThreadWriter::new(&mut expected, Compression::default());
+        writer.write_all(&data)?;
+        let expected_index = writer.close()?;
+
+        #[cfg(feature = "rayon")]
+        {
+            let pool = std::sync::Arc::new(rayon::ThreadPoolBuilder::new().num_threads(2).build()?);
+            let mut compressed = Vec::new();
+            let mut writer = BGZFMultiThreadWriter::with_thread_pool(
+                &mut compressed,
+                Compression::default(),
+                pool,
+            );
+            assert_eq!(writer.block_list.len(), 4);
+            writer.write_all(&data)?;
+            assert_eq!(writer.close()?, expected_index);
+            assert_eq!(compressed, expected);
+        }
+
+        let mut compressed = Vec::new();
+        let mut writer =
+            BGZFMultiThreadWriter::with_std_threads(&mut compressed, Compression::default(), 2)?;
+        assert_eq!(writer.block_list.len(), 4);
+        writer.write_all(&data)?;
+        assert_eq!(writer.close()?, expected_index);
+        assert_eq!(compressed, expected);
+
+        let mut compressed = Vec::new();
+        let mut writer = BGZFMultiThreadWriter::with_available_parallelism(
+            &mut compressed,
+            Compression::default(),
+        )?;
+        writer.write_all(&data)?;
+        assert_eq!(writer.close()?, expected_index);
+        assert_eq!(compressed, expected);
+
+        // user-supplied executors
+        let spawned = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let spawned_clone = spawned.clone();
+        let mut compressed = Vec::new();
+        let mut writer = BGZFMultiThreadWriter::with_executor(
+            &mut compressed,
+            Compression::default(),
+            3,
+            move |job| {
+                spawned_clone.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                std::thread::spawn(job);
+            },
+        );
+        assert_eq!(writer.block_list.len(), 6);
+        writer.write_all(&data)?;
+        assert_eq!(writer.close()?, expected_index);
+        assert_eq!(compressed, expected);
+        assert!(spawned.load(std::sync::atomic::Ordering::SeqCst) > 0);
+
+        let mut compressed = Vec::new();
+        let mut writer = BGZFMultiThreadWriter::with_executor(
+            &mut compressed,
+            Compression::default(),
+            1,
+            |job| job(),
+        );
+        writer.write_all(&data)?;
+        assert_eq!(writer.close()?, expected_index);
+        assert_eq!(compressed, expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_stats() -> anyhow::Result<()> {
+        let data = crate::testutil::SyntheticBGZF::new(17)
+            .with_block_count(7)
+            .generate()
+            .uncompressed;
+
+        let mut expected = Vec::new();
+        let mut writer = crate::BGZFWriter::new(&mut expected, Compression::default());
+        writer.write_all(&data)?;
+        writer.flush()?;
+        let expected_stats = writer.stats();
+
+        let mut compressed = Vec::new();
+        let mut writer = BGZFMultiThreadWriter::with_compress_unit_size(
+            &mut compressed,
+            crate::write::DEFAULT_COMPRESS_UNIT_SIZE,
+            2,
+            Compression::default(),
+            true,
+        )?;
+        writer.write_all(&data)?;
+        writer.flush()?;
+        let stats = writer.stats();
+        assert_eq!(stats.blocks, expected_stats.blocks);
+        assert_eq!(stats.uncompressed_bytes, expected_stats.uncompressed_bytes);
+        assert_eq!(stats.compressed_bytes, expected_stats.compressed_bytes);
+        #[cfg(feature = "metrics")]
+        assert!(stats.compress_time > std::time::Duration::ZERO);
+        writer.close()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_write_block_num() -> anyhow::Result<()> {
+        let data = crate::testutil::SyntheticBGZF::new(29)
+            .with_block_count(20)
+            .generate()
+            .uncompressed;
+
+        let mut expected = Vec::new();
+        let mut writer = crate::BGZFWriter::with_profile(
+            &mut expected,
+            Compression::default(),
+            BlockSizeProfile::SmallLatency16K,
+        );
+        writer.write_all(&data)?;
+        let expected_index = writer.close()?;
+
+        let mut compressed = Vec::new();
+        let mut writer = BGZFMultiThreadWriter::with_profile(
+            &mut compressed,
+            Compression::default(),
+            BlockSizeProfile::SmallLatency16K,
+        );
+        assert_eq!(
+            writer.write_block_num(),
+            BlockSizeProfile::SmallLatency16K.write_block_num()
+        );
+        let chunks: Vec<_> = data.chunks(100_000).collect();
+        for (i, chunk) in chunks.iter().enumerate() {
+            // shrink below buffered data, then grow again
+            writer.set_write_block_num([1, 0, 7, 3][i % 4]);
+            writer.write_all(chunk)?;
+        }
+        assert_eq!(
+            writer.write_block_num(),
+            [1, 1, 7, 3][(chunks.len() - 1) % 4]
+        );
+        assert_eq!(writer.close()?, expected_index);
+        assert_eq!(compressed, expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_size_hint() -> anyhow::Result<()> {
+        let data = crate::testutil::SyntheticBGZF::new(13)
+            .with_block_count(5)
+            .generate()
+            .uncompressed;
+
+        let mut expected = Vec::new();
+        let mut writer = BGZFMultiThreadWriter::new(&mut expected, Compression::default());
+        writer.write_all(&data)?;
+        let expected_index = writer.close()?;
+
+        for hint in [0, 100, data.len() as u64, 100_000_000] {
+            let mut compressed = Vec::new();
+            let mut writer = BGZFMultiThreadWriter::new(&mut compressed, Compression::default())
+                .with_size_hint(hint);
+            assert!(
+                writer.bgzf_index.as_ref().unwrap().entries.capacity()
+                    >= crate::write::index_entries_for_size(
+                        hint,
+                        crate::write::DEFAULT_COMPRESS_UNIT_SIZE
+                    )
+            );
+            writer.write_all(&data)?;
+            assert_eq!(writer.close()?, expected_index);
+            assert_eq!(compressed, expected);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_comment() -> anyhow::Result<()> {
+        let data = crate::testutil::SyntheticBGZF::new(19)
+            .with_block_count(4)
+            .generate()
+            .uncompressed;
+
+        for data in [&data[..], &[]] {
+            let mut expected = Vec::new();
+            let mut writer = crate::write::BGZFWriter::new(&mut expected, Compression::default())
+                .with_file_comment(b"source: test")?;
+            writer.write_all(data)?;
+            writer.close()?;
+
+            let mut compressed = Vec::new();
+            let mut writer = BGZFMultiThreadWriter::new(&mut compressed, Compression::default())
+                .with_file_comment(b"source: test")?;
+            writer.write_all(data)?;
+            writer.close()?;
+            assert_eq!(compressed, expected);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_pending_blocks() -> anyhow::Result<()> {
+        let data = crate::testutil::SyntheticBGZF::new(23)
+            .with_block_count(2)
+            .generate()
+            .uncompressed;
+
+        let mut expected = Vec::new();
+        let mut writer = crate::write::BGZFWriter::with_compress_unit_size(
+            &mut expected,
+            Compression::default(),
+            1000,
+            true,
+        )?;
+        writer.write_all(&data)?;
+        let expected_index = writer.close()?;
+
+        for max_pending_blocks in [0, 1, 3, 100] {
+            let mut compressed = Vec::new();
+            let mut writer = BGZFMultiThreadWriter::with_compress_unit_size(
+                &mut compressed,
+                1000,
+                2,
+                Compression::default(),
+                true,
+            )?
+            .with_verify(true)
+            .with_max_pending_blocks(max_pending_blocks);
+            assert_eq!(writer.block_list.len(), max_pending_blocks.max(1));
+            assert!(writer.block_list.iter().all(|x| x.verify.is_some()));
+            for chunk in data.chunks(1500) {
+                writer.write_all(chunk)?;
+                let pending = writer.next_compress_index - writer.next_write_index;
+                assert!(pending <= max_pending_blocks.max(1) as u64);
+            }
+            assert_eq!(writer.close()?, expected_index);
+            assert_eq!(compressed, expected);
+        }
+
+        Ok(())
+    }
 }